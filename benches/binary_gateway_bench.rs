@@ -0,0 +1,73 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use quantumflow::{
+    engine::matching::MatchingEngine,
+    gateway::binary::{
+        client::BinaryClient,
+        codec::{ClientMessage, EnterOrder, ServerMessage},
+        BinaryServer,
+    },
+    OrderType, Side,
+};
+use rust_decimal::Decimal;
+use std::sync::Arc;
+use tokio::net::TcpListener;
+
+fn codec_benchmark(c: &mut Criterion) {
+    let message = ClientMessage::EnterOrder(EnterOrder {
+        token: 1,
+        side: Side::Buy,
+        order_type: OrderType::Limit,
+        symbol: "BTCUSD".to_string(),
+        quantity: Decimal::new(125, 3),
+        price: Decimal::new(5000050, 2),
+    });
+
+    c.bench_function("binary_codec_enter_order_round_trip", |b| {
+        let mut buf = Vec::with_capacity(64);
+        b.iter(|| {
+            buf.clear();
+            black_box(&message).encode(&mut buf).unwrap();
+            black_box(ClientMessage::decode(&buf).unwrap());
+        });
+    });
+}
+
+async fn connect() -> BinaryClient {
+    let engine = Arc::new(MatchingEngine::new());
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(Arc::new(BinaryServer::new(engine)).serve(listener));
+    BinaryClient::connect(addr).await.unwrap()
+}
+
+/// Enters a resting order and waits for it to be accepted, then cancels it
+/// and waits for the cancel, over loopback TCP.
+fn tcp_round_trip_benchmark(c: &mut Criterion) {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let mut client = runtime.block_on(connect());
+
+    c.bench_function("binary_tcp_enter_accept_cancel_round_trip", |b| {
+        b.iter(|| {
+            runtime.block_on(async {
+                let token = client
+                    .enter_order(
+                        "BTCUSD",
+                        Side::Buy,
+                        OrderType::Limit,
+                        Decimal::from(1),
+                        Decimal::from(100),
+                    )
+                    .await
+                    .unwrap();
+                let accepted = client.recv().await.unwrap();
+                assert!(matches!(accepted, Some(ServerMessage::Accepted(_))));
+                client.cancel_order(token).await.unwrap();
+                let cancelled = client.recv().await.unwrap();
+                assert!(matches!(cancelled, Some(ServerMessage::Cancelled(_))));
+            })
+        });
+    });
+}
+
+criterion_group!(benches, codec_benchmark, tcp_round_trip_benchmark);
+criterion_main!(benches);
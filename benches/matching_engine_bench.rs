@@ -1,15 +1,18 @@
-use criterion::{black_box, criterion_group, criterion_main, Criterion};
-use quantumflow::{engine::matching::MatchingEngine, Order, OrderType, Side};
+use chrono::{DateTime, Utc};
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
+use quantumflow::{
+    engine::{matching::MatchingEngine, orderbook::OrderBook},
+    Order, OrderType, Side, Trade,
+};
 use rust_decimal::Decimal;
-use tokio::sync::mpsc;
+use std::collections::BTreeMap;
 
 fn matching_engine_submit_benchmark(c: &mut Criterion) {
     let runtime = tokio::runtime::Runtime::new().unwrap();
 
     c.bench_function("matching_engine_submit_100_orders", |b| {
         b.to_async(&runtime).iter(|| async {
-            let (tx, _rx) = mpsc::unbounded_channel();
-            let engine = MatchingEngine::new(tx);
+            let engine = MatchingEngine::new();
 
             for i in 0..100 {
                 let order = Order::new(
@@ -27,5 +30,173 @@ fn matching_engine_submit_benchmark(c: &mut Criterion) {
     });
 }
 
-criterion_group!(benches, matching_engine_submit_benchmark);
+/// The previous `Decimal`-keyed book, kept as a baseline for the tick representation.
+struct DecimalBook {
+    asks: BTreeMap<Decimal, Vec<Order>>,
+}
+
+impl DecimalBook {
+    fn add_order(&mut self, order: Order) {
+        self.asks.entry(order.price).or_default().push(order);
+    }
+
+    fn match_buy(&mut self, mut order: Order, now: DateTime<Utc>) -> (Order, Vec<Trade>) {
+        let mut trades = Vec::new();
+        let prices: Vec<Decimal> = self
+            .asks
+            .keys()
+            .filter(|price| **price <= order.price)
+            .copied()
+            .collect();
+
+        for price in prices {
+            if order.is_fully_filled() {
+                break;
+            }
+            if let Some(orders) = self.asks.get_mut(&price) {
+                let mut i = 0;
+                while i < orders.len() && !order.is_fully_filled() {
+                    let resting = &mut orders[i];
+                    let quantity = order.remaining_quantity().min(resting.remaining_quantity());
+                    trades.push(Trade::new(
+                        "BTCUSD".to_string(),
+                        price,
+                        quantity,
+                        order.id,
+                        resting.id,
+                        now,
+                    ));
+                    order.filled_quantity += quantity;
+                    resting.filled_quantity += quantity;
+                    if resting.is_fully_filled() {
+                        orders.remove(i);
+                    } else {
+                        i += 1;
+                    }
+                }
+                if orders.is_empty() {
+                    self.asks.remove(&price);
+                }
+            }
+        }
+
+        (order, trades)
+    }
+}
+
+fn resting_asks(levels: i64) -> Vec<Order> {
+    (0..levels)
+        .flat_map(|i| {
+            (0..4).map(move |_| {
+                Order::new(
+                    "BTCUSD".to_string(),
+                    Side::Sell,
+                    OrderType::Limit,
+                    Decimal::new(5_000_000 + i * 5, 2),
+                    Decimal::new(25, 2),
+                )
+            })
+        })
+        .collect()
+}
+
+fn sweep_order(levels: i64) -> Order {
+    Order::new(
+        "BTCUSD".to_string(),
+        Side::Buy,
+        OrderType::Limit,
+        Decimal::new(5_000_000 + levels * 5, 2),
+        Decimal::from(levels),
+    )
+}
+
+fn price_representation_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("sweep_levels");
+
+    for levels in [10i64, 100] {
+        let asks = resting_asks(levels);
+
+        group.bench_with_input(
+            BenchmarkId::new("decimal_keys", levels),
+            &asks,
+            |b, asks| {
+                b.iter_batched(
+                    || {
+                        let mut book = DecimalBook {
+                            asks: BTreeMap::new(),
+                        };
+                        for order in asks {
+                            book.add_order(order.clone());
+                        }
+                        (book, sweep_order(levels))
+                    },
+                    |(mut book, order)| black_box(book.match_buy(order, Utc::now())),
+                    BatchSize::SmallInput,
+                );
+            },
+        );
+
+        group.bench_with_input(BenchmarkId::new("tick_keys", levels), &asks, |b, asks| {
+            b.iter_batched(
+                || {
+                    let mut book = OrderBook::new("BTCUSD".to_string());
+                    for order in asks {
+                        book.add_order(order.clone());
+                    }
+                    (book, sweep_order(levels))
+                },
+                |(mut book, order)| black_box(book.match_order(order, Utc::now())),
+                BatchSize::SmallInput,
+            );
+        });
+    }
+
+    group.finish();
+}
+
+/// Submits a passive order through the engine into books of growing depth;
+/// the per-order cost should not grow with the number of resting levels.
+fn engine_submit_depth_benchmark(c: &mut Criterion) {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let mut group = c.benchmark_group("engine_submit_into_book");
+
+    for levels in [10i64, 100, 1000] {
+        let asks = resting_asks(levels);
+
+        group.bench_with_input(BenchmarkId::new("levels", levels), &asks, |b, asks| {
+            b.iter_batched(
+                || {
+                    let engine = MatchingEngine::new();
+                    runtime.block_on(async {
+                        for order in asks {
+                            engine.submit_order(order.clone()).await.unwrap();
+                        }
+                    });
+                    let bid = Order::new(
+                        "BTCUSD".to_string(),
+                        Side::Buy,
+                        OrderType::Limit,
+                        Decimal::new(4_900_000, 2),
+                        Decimal::new(25, 2),
+                    );
+                    (engine, bid)
+                },
+                |(engine, bid)| {
+                    black_box(runtime.block_on(engine.submit_order(bid)).unwrap());
+                    engine
+                },
+                BatchSize::SmallInput,
+            );
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    matching_engine_submit_benchmark,
+    price_representation_benchmark,
+    engine_submit_depth_benchmark
+);
 criterion_main!(benches);
@@ -47,7 +47,7 @@ fn orderbook_match_benchmark(c: &mut Criterion) {
                 Decimal::from(50),
             );
 
-            let (_, trades) = book.match_order(sell_order);
+            let (_, trades) = book.match_order(sell_order, chrono::Utc::now());
             black_box(trades);
         });
     });
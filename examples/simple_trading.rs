@@ -1,19 +1,18 @@
 use quantumflow::{
-    engine::matching::MatchingEngine,
+    engine::{bus::BackpressurePolicy, matching::MatchingEngine},
     Order, OrderType, Side,
 };
 use rust_decimal::Decimal;
-use tokio::sync::mpsc;
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     // Create matching engine
-    let (trade_tx, mut trade_rx) = mpsc::unbounded_channel();
-    let engine = MatchingEngine::new(trade_tx);
+    let engine = MatchingEngine::new();
+    let mut trade_rx = engine.subscribe_trades(1024, BackpressurePolicy::DropOldest);
 
     // Spawn task to handle trades
     tokio::spawn(async move {
-        while let Some(trade) = trade_rx.recv().await {
+        while let Ok(trade) = trade_rx.recv().await {
             println!(
                 "Trade executed: {} @ {} qty {}",
                 trade.symbol, trade.price, trade.quantity
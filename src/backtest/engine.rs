@@ -1,10 +1,12 @@
-use crate::utils::types::{Side, Trade};
+use crate::engine::stops::{self, MarketPrices, StopBook};
+use crate::engine::ticks::InstrumentSpec;
+use crate::utils::types::{Order, OrderType, Side, Trade};
 use chrono::{DateTime, Utc};
 use rust_decimal::Decimal;
 use serde::{Deserialize, Serialize};
 use tracing::info;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct OHLCV {
     pub timestamp: DateTime<Utc>,
     pub open: Decimal,
@@ -33,6 +35,10 @@ pub struct BacktestEngine {
     position_price: Decimal,
     trades: Vec<Trade>,
     equity_curve: Vec<Decimal>,
+    spec: InstrumentSpec,
+    stops: StopBook,
+    /// Triggered stop-limits that could not fill when they triggered.
+    working: Vec<Order>,
 }
 
 impl BacktestEngine {
@@ -44,9 +50,89 @@ impl BacktestEngine {
             position_price: Decimal::ZERO,
             trades: Vec::new(),
             equity_curve: vec![initial_capital],
+            spec: InstrumentSpec::default(),
+            stops: StopBook::new(),
+            working: Vec::new(),
         }
     }
 
+    /// Parks a stop, stop-limit or trailing stop until a bar reaches it.
+    pub fn submit_stop(&mut self, order: Order) {
+        self.stops.add(order);
+    }
+
+    pub fn pending_stops(&self) -> &StopBook {
+        &self.stops
+    }
+
+    /// Replays one bar against pending stops and triggered stop-limits.
+    ///
+    /// The bar is walked open, nearer extreme, farther extreme, close. Trailing
+    /// stops ratchet at every point. A stop triggered at the open fills there,
+    /// since the market gapped through it; one triggered later fills at its
+    /// stop price. Stop-limits that cannot fill at that price keep working as
+    /// limit orders on later points and bars.
+    pub fn process_bar(&mut self, symbol: &str, bar: &OHLCV) -> Vec<Trade> {
+        let (near, far) = if bar.high - bar.open <= bar.open - bar.low {
+            (bar.high, bar.low)
+        } else {
+            (bar.low, bar.high)
+        };
+
+        let mut fills = Vec::new();
+        for (i, price) in [bar.open, near, far, bar.close].into_iter().enumerate() {
+            let market = MarketPrices::at(price);
+            self.stops.update_trailing(&market, &self.spec);
+
+            for stop in self.stops.take_triggered(&market) {
+                let trigger = match stop.stop_price {
+                    Some(stop_price) if i > 0 => stop_price,
+                    _ => price,
+                };
+                let order = stops::activate(stop);
+                let marketable = match (order.order_type, order.side) {
+                    (OrderType::Limit, Side::Buy) => trigger <= order.price,
+                    (OrderType::Limit, Side::Sell) => trigger >= order.price,
+                    _ => true,
+                };
+                if marketable {
+                    fills.extend(self.fill(symbol, &order, trigger, bar.timestamp));
+                } else {
+                    self.working.push(order);
+                }
+            }
+
+            let (filled, working): (Vec<Order>, Vec<Order>) = std::mem::take(&mut self.working)
+                .into_iter()
+                .partition(|o| match o.side {
+                    Side::Buy => price <= o.price,
+                    Side::Sell => price >= o.price,
+                });
+            self.working = working;
+            for order in filled {
+                let fill_price = if i == 0 { price } else { order.price };
+                fills.extend(self.fill(symbol, &order, fill_price, bar.timestamp));
+            }
+        }
+        fills
+    }
+
+    fn fill(
+        &mut self,
+        symbol: &str,
+        order: &Order,
+        price: Decimal,
+        timestamp: DateTime<Utc>,
+    ) -> Option<Trade> {
+        self.execute_signal(
+            symbol,
+            order.side,
+            price,
+            order.remaining_quantity(),
+            timestamp,
+        )
+    }
+
     pub fn execute_signal(
         &mut self,
         symbol: &str,
@@ -281,4 +367,53 @@ mod tests {
         let results = engine.get_results();
         assert!(results.total_pnl > Decimal::ZERO);
     }
+
+    #[test]
+    fn test_backtest_trailing_stop_exits_on_pullback() {
+        use crate::utils::types::{TrailReference, TrailingOffset, TrailingStop};
+
+        let mut engine = BacktestEngine::new(Decimal::from(100000));
+        engine.execute_signal(
+            "BTCUSD",
+            Side::Buy,
+            Decimal::from(100),
+            Decimal::from(1),
+            Utc::now(),
+        );
+        engine.submit_stop(Order {
+            trailing: Some(TrailingStop {
+                offset: TrailingOffset::Amount(Decimal::from(5)),
+                reference: TrailReference::LastTrade,
+            }),
+            ..Order::new(
+                "BTCUSD".to_string(),
+                Side::Sell,
+                OrderType::StopMarket,
+                Decimal::ZERO,
+                Decimal::from(1),
+            )
+        });
+
+        let bar = |open: i64, high: i64, low: i64, close: i64| OHLCV {
+            timestamp: Utc::now(),
+            open: Decimal::from(open),
+            high: Decimal::from(high),
+            low: Decimal::from(low),
+            close: Decimal::from(close),
+            volume: Decimal::ONE,
+        };
+
+        assert!(engine
+            .process_bar("BTCUSD", &bar(100, 112, 99, 110))
+            .is_empty());
+        assert_eq!(
+            engine.pending_stops().iter().next().unwrap().stop_price,
+            Some(Decimal::from(107))
+        );
+
+        let fills = engine.process_bar("BTCUSD", &bar(110, 111, 104, 105));
+        assert_eq!(fills.len(), 1);
+        assert_eq!(fills[0].price, Decimal::from(107));
+        assert!(engine.pending_stops().is_empty());
+    }
 }
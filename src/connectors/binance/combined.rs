@@ -0,0 +1,279 @@
+//! Combined streams: many symbols and channels over one connection.
+//!
+//! Payloads arrive wrapped as `{"stream": "<name>", "data": {...}}` and the
+//! set of streams can be changed on a live connection with `SUBSCRIBE` and
+//! `UNSUBSCRIBE` requests. Every event is routed into [`MarketEvent`].
+
+use super::depth::{DepthSync, DepthUpdate};
+use super::klines::KlineEvent;
+use super::trades::{AggTradeEvent, TradeEvent};
+use super::BinanceTickerUpdate;
+pub use crate::connectors::market::{
+    subscription_channel, Channel, MarketEvent, Subscription, SubscriptionCommands,
+    SubscriptionHandle,
+};
+use crate::connectors::market::{Command, SymbolSilence};
+use anyhow::Result;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::{BTreeSet, HashMap};
+use tracing::warn;
+
+/// Binance rejects connections carrying more streams than this.
+pub const MAX_STREAMS: usize = 1024;
+
+fn channel_suffix(channel: Channel) -> String {
+    match channel {
+        Channel::Depth => "depth@100ms".to_string(),
+        Channel::Ticker => "ticker".to_string(),
+        Channel::Trade => "trade".to_string(),
+        Channel::AggTrade => "aggTrade".to_string(),
+        Channel::Kline(interval) => format!("kline_{}", interval),
+    }
+}
+
+fn channel_from_suffix(suffix: &str) -> Option<Channel> {
+    if let Some(interval) = suffix.strip_prefix("kline_") {
+        return interval.parse().ok().map(Channel::Kline);
+    }
+    [
+        Channel::Depth,
+        Channel::Ticker,
+        Channel::Trade,
+        Channel::AggTrade,
+    ]
+    .into_iter()
+    .find(|channel| channel_suffix(*channel) == suffix)
+}
+
+/// Stream name of a subscription as used in URLs and subscription
+/// requests, e.g. `btcusdt@ticker`.
+pub fn stream_name(subscription: &Subscription) -> String {
+    format!(
+        "{}@{}",
+        subscription.symbol.to_lowercase(),
+        channel_suffix(subscription.channel)
+    )
+}
+
+pub fn subscription_for(name: &str) -> Option<Subscription> {
+    let (symbol, suffix) = name.split_once('@')?;
+    let channel = channel_from_suffix(suffix)?;
+    Some(Subscription::new(symbol, channel))
+}
+
+/// A payload on a combined connection.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub(super) enum CombinedMessage {
+    Event { stream: String, data: Value },
+    Response { id: u64, error: Option<Value> },
+}
+
+/// Subscriptions that outlive connections, plus the per-connection state
+/// derived from them.
+#[derive(Debug)]
+pub(super) struct CombinedState {
+    pub(super) subscriptions: BTreeSet<Subscription>,
+    pub(super) commands: Option<SubscriptionCommands>,
+    next_id: u64,
+    /// Books for depth subscriptions, rebuilt on every connection.
+    pub(super) books: HashMap<String, DepthSync>,
+    pub(super) silence: SymbolSilence,
+}
+
+impl CombinedState {
+    pub(super) fn new(subscriptions: &[Subscription], commands: SubscriptionCommands) -> Self {
+        Self {
+            subscriptions: subscriptions.iter().cloned().collect(),
+            commands: Some(commands),
+            next_id: 1,
+            books: HashMap::new(),
+            silence: SymbolSilence::default(),
+        }
+    }
+
+    /// Resets per-connection state and returns the request subscribing to
+    /// everything, if there is anything to subscribe to.
+    pub(super) fn reconnected(&mut self) -> Option<String> {
+        self.books.clear();
+        self.silence.clear();
+        self.silence.track(&self.subscriptions);
+        let all: Vec<_> = self.subscriptions.iter().cloned().collect();
+        self.track(&all);
+        self.request("SUBSCRIBE", &all)
+    }
+
+    /// Applies a command and returns the request to send for it.
+    pub(super) fn apply(&mut self, command: Command) -> Option<String> {
+        let request = self.change(command);
+        self.silence.track(&self.subscriptions);
+        request
+    }
+
+    fn change(&mut self, command: Command) -> Option<String> {
+        match command {
+            Command::Subscribe(subscriptions) => {
+                let added: Vec<_> = subscriptions
+                    .into_iter()
+                    .filter(|s| self.subscriptions.insert(s.clone()))
+                    .collect();
+                if self.subscriptions.len() > MAX_STREAMS {
+                    warn!(
+                        "{} streams exceed Binance's limit of {} per connection",
+                        self.subscriptions.len(),
+                        MAX_STREAMS
+                    );
+                }
+                self.track(&added);
+                self.request("SUBSCRIBE", &added)
+            }
+            Command::Unsubscribe(subscriptions) => {
+                let removed: Vec<_> = subscriptions
+                    .into_iter()
+                    .filter(|s| self.subscriptions.remove(s))
+                    .collect();
+                for subscription in &removed {
+                    if subscription.channel == Channel::Depth {
+                        self.books.remove(&subscription.symbol);
+                    }
+                }
+                self.request("UNSUBSCRIBE", &removed)
+            }
+        }
+    }
+
+    fn track(&mut self, subscriptions: &[Subscription]) {
+        for subscription in subscriptions {
+            if subscription.channel == Channel::Depth {
+                self.books.insert(
+                    subscription.symbol.clone(),
+                    DepthSync::new(&subscription.symbol),
+                );
+            }
+        }
+    }
+
+    fn request(&mut self, method: &str, subscriptions: &[Subscription]) -> Option<String> {
+        if subscriptions.is_empty() {
+            return None;
+        }
+        let params: Vec<_> = subscriptions.iter().map(stream_name).collect();
+        let id = self.next_id;
+        self.next_id += 1;
+        Some(json!({"method": method, "params": params, "id": id}).to_string())
+    }
+
+    /// Routes an event payload. Depth diffs go to their book; the returned
+    /// event is whatever should be emitted. Payloads for streams no longer
+    /// subscribed are dropped.
+    pub(super) fn route(&mut self, stream: &str, data: Value) -> Result<Option<MarketEvent>> {
+        let Some(subscription) = subscription_for(stream)
+            .filter(|subscription| self.subscriptions.contains(subscription))
+        else {
+            return Ok(None);
+        };
+        self.silence.seen(&subscription.symbol);
+        match subscription.channel {
+            Channel::Depth => {
+                let update: DepthUpdate = serde_json::from_value(data)?;
+                let Some(sync) = self.books.get_mut(&subscription.symbol) else {
+                    return Ok(None);
+                };
+                match sync.on_update(update) {
+                    Ok(true) => Ok(sync
+                        .book()
+                        .map(|book| MarketEvent::Book(book.snapshot(usize::MAX)))),
+                    Ok(false) => Ok(None),
+                    Err(e) => {
+                        warn!("Resynchronising {} order book: {}", subscription.symbol, e);
+                        Ok(None)
+                    }
+                }
+            }
+            Channel::Ticker => {
+                let update: BinanceTickerUpdate = serde_json::from_value(data)?;
+                Ok(Some(MarketEvent::Ticker(update.into_ticker())))
+            }
+            Channel::Trade => {
+                let event: TradeEvent = serde_json::from_value(data)?;
+                Ok(Some(MarketEvent::Trade(event.into_trade()?)))
+            }
+            Channel::AggTrade => {
+                let event: AggTradeEvent = serde_json::from_value(data)?;
+                Ok(Some(MarketEvent::Trade(event.into_trade()?)))
+            }
+            Channel::Kline(_) => {
+                let event: KlineEvent = serde_json::from_value(data)?;
+                Ok(Some(MarketEvent::Kline(event.into_kline()?)))
+            }
+        }
+    }
+
+    /// Symbols whose books are waiting for a snapshot.
+    pub(super) fn snapshots_needed(&self) -> Vec<String> {
+        self.books
+            .iter()
+            .filter(|(_, sync)| sync.needs_snapshot())
+            .map(|(symbol, _)| symbol.clone())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connectors::market::KlineInterval;
+
+    #[test]
+    fn test_stream_names_round_trip() {
+        let depth = Subscription::depth("btcusdt");
+        assert_eq!(stream_name(&depth), "btcusdt@depth@100ms");
+        assert_eq!(subscription_for("btcusdt@depth@100ms"), Some(depth));
+        assert_eq!(
+            subscription_for("ETHUSDT@ticker"),
+            Some(Subscription::ticker("ethusdt"))
+        );
+        assert_eq!(
+            subscription_for("solusdt@aggTrade"),
+            Some(Subscription::agg_trade("SOLUSDT"))
+        );
+        assert_eq!(
+            subscription_for("btcusdt@kline_1h"),
+            Some(Subscription::kline("BTCUSDT", KlineInterval::Hour1))
+        );
+        assert_eq!(subscription_for("btcusdt@kline_7m"), None);
+    }
+
+    #[test]
+    fn test_tracks_subscriptions_and_drops_unsubscribed_streams() {
+        let (_handle, commands) = subscription_channel();
+        let mut state = CombinedState::new(&[Subscription::ticker("BTCUSDT")], commands);
+        let subscribe = state.reconnected().unwrap();
+        assert_eq!(
+            serde_json::from_str::<Value>(&subscribe).unwrap(),
+            json!({"method": "SUBSCRIBE", "params": ["btcusdt@ticker"], "id": 1})
+        );
+
+        // Subscribing twice sends nothing new
+        let request = state.apply(Command::Subscribe(vec![
+            Subscription::ticker("BTCUSDT"),
+            Subscription::depth("ETHUSDT"),
+        ]));
+        assert!(request
+            .unwrap()
+            .contains(r#""params":["ethusdt@depth@100ms"]"#));
+        assert!(state.books.contains_key("ETHUSDT"));
+
+        let ticker = json!({
+            "e": "24hrTicker", "E": 1_700_000_000_000u64, "s": "BTCUSDT",
+            "b": "99", "a": "101", "c": "100", "v": "10",
+        });
+        assert!(state
+            .route("btcusdt@ticker", ticker.clone())
+            .unwrap()
+            .is_some());
+        state.apply(Command::Unsubscribe(vec![Subscription::ticker("BTCUSDT")]));
+        assert!(state.route("btcusdt@ticker", ticker).unwrap().is_none());
+    }
+}
@@ -0,0 +1,343 @@
+//! Local order book maintenance from Binance depth snapshots and diffs.
+//!
+//! Binance publishes `<symbol>@depth` events as diffs numbered by update id.
+//! A book is rebuilt by buffering the diffs, fetching a REST snapshot and
+//! replaying the buffered diffs that follow the snapshot's `lastUpdateId`.
+//! From then on every diff must start right after the previous one; a gap
+//! means the book is no longer trustworthy and has to be resynchronised.
+
+use crate::utils::types::{OrderBookLevel, OrderBookSnapshot};
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::str::FromStr;
+use thiserror::Error;
+
+/// Response of `GET /api/v3/depth`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DepthSnapshot {
+    pub last_update_id: u64,
+    pub bids: Vec<[String; 2]>,
+    pub asks: Vec<[String; 2]>,
+}
+
+/// A `<symbol>@depth` diff event. A zero quantity removes the level.
+#[derive(Debug, Clone, Deserialize)]
+pub struct DepthUpdate {
+    /// Event time in milliseconds since the epoch.
+    #[serde(rename = "E")]
+    pub event_time: i64,
+    #[serde(rename = "s")]
+    pub symbol: String,
+    #[serde(rename = "U")]
+    pub first_update_id: u64,
+    #[serde(rename = "u")]
+    pub final_update_id: u64,
+    #[serde(rename = "b")]
+    pub bids: Vec<[String; 2]>,
+    #[serde(rename = "a")]
+    pub asks: Vec<[String; 2]>,
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum SyncError {
+    #[error("depth update {first}..={last} does not follow update {previous}")]
+    Gap {
+        previous: u64,
+        first: u64,
+        last: u64,
+    },
+    #[error("snapshot at update {snapshot} predates the first buffered update {first}")]
+    StaleSnapshot { snapshot: u64, first: u64 },
+    #[error("invalid level {price} x {quantity}")]
+    InvalidLevel { price: String, quantity: String },
+}
+
+/// Full depth for one symbol, kept in step with the diff stream.
+#[derive(Debug, Clone)]
+pub struct LocalOrderBook {
+    symbol: String,
+    last_update_id: u64,
+    bids: BTreeMap<Decimal, Decimal>,
+    asks: BTreeMap<Decimal, Decimal>,
+    timestamp: DateTime<Utc>,
+}
+
+impl LocalOrderBook {
+    pub fn from_snapshot(symbol: &str, snapshot: &DepthSnapshot) -> Result<Self, SyncError> {
+        let mut book = Self {
+            symbol: symbol.to_uppercase(),
+            last_update_id: snapshot.last_update_id,
+            bids: BTreeMap::new(),
+            asks: BTreeMap::new(),
+            timestamp: Utc::now(),
+        };
+        set_levels(&mut book.bids, parse_levels(&snapshot.bids)?);
+        set_levels(&mut book.asks, parse_levels(&snapshot.asks)?);
+        Ok(book)
+    }
+
+    pub fn symbol(&self) -> &str {
+        &self.symbol
+    }
+
+    /// Id of the last update reflected in the book.
+    pub fn last_update_id(&self) -> u64 {
+        self.last_update_id
+    }
+
+    pub fn best_bid(&self) -> Option<Decimal> {
+        self.bids.keys().next_back().copied()
+    }
+
+    pub fn best_ask(&self) -> Option<Decimal> {
+        self.asks.keys().next().copied()
+    }
+
+    /// Applies a diff. Returns `false` for a diff the book already contains
+    /// and fails without touching the book if the diff leaves a gap.
+    pub fn apply(&mut self, update: &DepthUpdate) -> Result<bool, SyncError> {
+        if update.final_update_id <= self.last_update_id {
+            return Ok(false);
+        }
+        if update.first_update_id > self.last_update_id + 1 {
+            return Err(SyncError::Gap {
+                previous: self.last_update_id,
+                first: update.first_update_id,
+                last: update.final_update_id,
+            });
+        }
+        let bids = parse_levels(&update.bids)?;
+        let asks = parse_levels(&update.asks)?;
+        set_levels(&mut self.bids, bids);
+        set_levels(&mut self.asks, asks);
+        self.last_update_id = update.final_update_id;
+        self.timestamp =
+            DateTime::from_timestamp_millis(update.event_time).unwrap_or_else(Utc::now);
+        Ok(true)
+    }
+
+    /// Best-first view of up to `depth` levels per side.
+    pub fn snapshot(&self, depth: usize) -> OrderBookSnapshot {
+        let level = |(&price, &quantity): (&Decimal, &Decimal)| OrderBookLevel {
+            price,
+            quantity,
+            order_count: 0,
+        };
+        OrderBookSnapshot {
+            symbol: self.symbol.clone(),
+            bids: self.bids.iter().rev().take(depth).map(level).collect(),
+            asks: self.asks.iter().take(depth).map(level).collect(),
+            timestamp: self.timestamp,
+        }
+    }
+}
+
+fn parse_levels(levels: &[[String; 2]]) -> Result<Vec<(Decimal, Decimal)>, SyncError> {
+    levels
+        .iter()
+        .map(
+            |[price, quantity]| match (Decimal::from_str(price), Decimal::from_str(quantity)) {
+                (Ok(p), Ok(q)) if q >= Decimal::ZERO => Ok((p, q)),
+                _ => Err(SyncError::InvalidLevel {
+                    price: price.clone(),
+                    quantity: quantity.clone(),
+                }),
+            },
+        )
+        .collect()
+}
+
+fn set_levels(side: &mut BTreeMap<Decimal, Decimal>, levels: Vec<(Decimal, Decimal)>) {
+    for (price, quantity) in levels {
+        if quantity.is_zero() {
+            side.remove(&price);
+        } else {
+            side.insert(price, quantity);
+        }
+    }
+}
+
+/// Snapshot-plus-diffs synchronisation for one symbol, independent of the
+/// socket. Diffs received without a book are buffered until
+/// [`DepthSync::on_snapshot`] supplies one.
+#[derive(Debug)]
+pub struct DepthSync {
+    symbol: String,
+    book: Option<LocalOrderBook>,
+    buffer: Vec<DepthUpdate>,
+}
+
+impl DepthSync {
+    pub fn new(symbol: &str) -> Self {
+        Self {
+            symbol: symbol.to_uppercase(),
+            book: None,
+            buffer: Vec::new(),
+        }
+    }
+
+    /// The maintained book, once synchronised.
+    pub fn book(&self) -> Option<&LocalOrderBook> {
+        self.book.as_ref()
+    }
+
+    /// Whether diffs are waiting for a REST snapshot. Binance requires at
+    /// least one buffered diff before fetching it.
+    pub fn needs_snapshot(&self) -> bool {
+        self.book.is_none() && !self.buffer.is_empty()
+    }
+
+    /// Feeds a diff. Returns `true` when the book changed. On a gap the book
+    /// is dropped and the diff becomes the first one buffered for the next
+    /// snapshot.
+    pub fn on_update(&mut self, update: DepthUpdate) -> Result<bool, SyncError> {
+        let Some(book) = &mut self.book else {
+            self.buffer.push(update);
+            return Ok(false);
+        };
+        match book.apply(&update) {
+            Ok(changed) => Ok(changed),
+            Err(e) => {
+                self.book = None;
+                self.buffer.clear();
+                if matches!(e, SyncError::Gap { .. }) {
+                    self.buffer.push(update);
+                }
+                Err(e)
+            }
+        }
+    }
+
+    /// Builds the book from a snapshot and replays the buffered diffs. A
+    /// snapshot older than the buffer must be fetched again; a gap inside
+    /// the buffer keeps the diffs from the gap onwards for the next one.
+    pub fn on_snapshot(&mut self, snapshot: DepthSnapshot) -> Result<(), SyncError> {
+        if let Some(first) = self.buffer.first() {
+            if snapshot.last_update_id + 1 < first.first_update_id {
+                return Err(SyncError::StaleSnapshot {
+                    snapshot: snapshot.last_update_id,
+                    first: first.first_update_id,
+                });
+            }
+        }
+        let mut book = LocalOrderBook::from_snapshot(&self.symbol, &snapshot)?;
+        let buffered = std::mem::take(&mut self.buffer);
+        for (i, update) in buffered.iter().enumerate() {
+            if let Err(e) = book.apply(update) {
+                if matches!(e, SyncError::Gap { .. }) {
+                    self.buffer = buffered[i..].to_vec();
+                }
+                return Err(e);
+            }
+        }
+        self.book = Some(book);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn levels(levels: &[(&str, &str)]) -> Vec<[String; 2]> {
+        levels
+            .iter()
+            .map(|(p, q)| [p.to_string(), q.to_string()])
+            .collect()
+    }
+
+    fn update(first: u64, last: u64, bids: &[(&str, &str)], asks: &[(&str, &str)]) -> DepthUpdate {
+        DepthUpdate {
+            event_time: 1_700_000_000_000,
+            symbol: "BTCUSDT".to_string(),
+            first_update_id: first,
+            final_update_id: last,
+            bids: levels(bids),
+            asks: levels(asks),
+        }
+    }
+
+    fn snapshot(last_update_id: u64) -> DepthSnapshot {
+        DepthSnapshot {
+            last_update_id,
+            bids: levels(&[("100", "1"), ("99", "2")]),
+            asks: levels(&[("101", "1")]),
+        }
+    }
+
+    type Levels = Vec<(Decimal, Decimal)>;
+
+    fn sides(book: &LocalOrderBook) -> (Levels, Levels) {
+        let snapshot = book.snapshot(usize::MAX);
+        let pairs = |levels: &[OrderBookLevel]| {
+            levels
+                .iter()
+                .map(|l| (l.price, l.quantity))
+                .collect::<Vec<_>>()
+        };
+        (pairs(&snapshot.bids), pairs(&snapshot.asks))
+    }
+
+    fn d(s: &str) -> Decimal {
+        Decimal::from_str(s).unwrap()
+    }
+
+    #[test]
+    fn test_replays_buffered_diffs_after_snapshot() {
+        let mut sync = DepthSync::new("btcusdt");
+        assert!(!sync.needs_snapshot());
+
+        // Already in the snapshot, straddling it, then following on
+        sync.on_update(update(95, 100, &[("100", "5")], &[]))
+            .unwrap();
+        sync.on_update(update(
+            99,
+            102,
+            &[("98", "3")],
+            &[("101", "0"), ("102", "4")],
+        ))
+        .unwrap();
+        assert!(sync.needs_snapshot());
+        sync.on_snapshot(snapshot(100)).unwrap();
+        assert!(sync
+            .on_update(update(103, 105, &[("99", "0")], &[]))
+            .unwrap());
+
+        let book = sync.book().unwrap();
+        assert_eq!(book.last_update_id(), 105);
+        let (bids, asks) = sides(book);
+        assert_eq!(bids, vec![(d("100"), d("1")), (d("98"), d("3"))]);
+        assert_eq!(asks, vec![(d("102"), d("4"))]);
+    }
+
+    #[test]
+    fn test_gap_and_stale_snapshot_force_resync() {
+        let mut sync = DepthSync::new("BTCUSDT");
+        sync.on_update(update(101, 101, &[], &[])).unwrap();
+        sync.on_snapshot(snapshot(100)).unwrap();
+
+        let gap = sync.on_update(update(110, 112, &[("97", "1")], &[]));
+        assert_eq!(
+            gap,
+            Err(SyncError::Gap {
+                previous: 101,
+                first: 110,
+                last: 112
+            })
+        );
+        assert!(sync.book().is_none());
+        assert!(sync.needs_snapshot());
+
+        // The snapshot has to reach the diff that exposed the gap
+        assert!(matches!(
+            sync.on_snapshot(snapshot(105)),
+            Err(SyncError::StaleSnapshot { .. })
+        ));
+        sync.on_snapshot(snapshot(111)).unwrap();
+        let (bids, _) = sides(sync.book().unwrap());
+        assert_eq!(bids[2], (d("97"), d("1")));
+    }
+}
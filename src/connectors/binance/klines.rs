@@ -0,0 +1,174 @@
+//! Candlesticks: the `<symbol>@kline_<interval>` stream and historical bars
+//! from `GET /api/v3/klines`, both as backtester [`OHLCV`] bars.
+
+pub use crate::connectors::market::{Kline, KlineInterval};
+
+use crate::backtest::engine::OHLCV;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use serde_json::Value;
+use std::io;
+use std::str::FromStr;
+
+/// A `<symbol>@kline_<interval>` event.
+#[derive(Debug, Clone, Deserialize)]
+pub struct KlineEvent {
+    #[serde(rename = "E")]
+    pub event_time: i64,
+    #[serde(rename = "s")]
+    pub symbol: String,
+    #[serde(rename = "k")]
+    pub kline: KlineData,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct KlineData {
+    #[serde(rename = "t")]
+    pub open_time: i64,
+    #[serde(rename = "T")]
+    pub close_time: i64,
+    #[serde(rename = "i")]
+    pub interval: String,
+    #[serde(rename = "o")]
+    pub open: String,
+    #[serde(rename = "h")]
+    pub high: String,
+    #[serde(rename = "l")]
+    pub low: String,
+    #[serde(rename = "c")]
+    pub close: String,
+    #[serde(rename = "v")]
+    pub volume: String,
+    #[serde(rename = "x")]
+    pub closed: bool,
+}
+
+impl KlineEvent {
+    pub fn into_kline(self) -> Result<Kline> {
+        let k = self.kline;
+        Ok(Kline {
+            symbol: self.symbol,
+            interval: k.interval.parse()?,
+            bar: OHLCV {
+                timestamp: millis(k.open_time)?,
+                open: decimal(&k.open)?,
+                high: decimal(&k.high)?,
+                low: decimal(&k.low)?,
+                close: decimal(&k.close)?,
+                volume: decimal(&k.volume)?,
+            },
+            close_time: millis(k.close_time)?,
+            closed: k.closed,
+            event_time: millis(self.event_time)?,
+        })
+    }
+}
+
+/// Parses a row of `GET /api/v3/klines`: open time, open, high, low, close
+/// and volume come first, followed by fields the backtester does not use.
+pub fn parse_rest_row(row: &[Value]) -> Result<OHLCV> {
+    let open_time = row
+        .first()
+        .and_then(Value::as_i64)
+        .context("kline row without an open time")?;
+    let field = |index: usize| {
+        row.get(index)
+            .and_then(Value::as_str)
+            .with_context(|| format!("kline row at {} missing field {}", open_time, index))
+            .and_then(decimal)
+    };
+    Ok(OHLCV {
+        timestamp: millis(open_time)?,
+        open: field(1)?,
+        high: field(2)?,
+        low: field(3)?,
+        close: field(4)?,
+        volume: field(5)?,
+    })
+}
+
+/// Writes bars in the layout `run_backtest` reads: a header, then
+/// `timestamp,open,high,low,close,volume` with RFC 3339 timestamps.
+pub fn write_csv<W: io::Write>(writer: W, bars: &[OHLCV]) -> Result<()> {
+    let mut writer = csv::Writer::from_writer(writer);
+    writer.write_record(["timestamp", "open", "high", "low", "close", "volume"])?;
+    for bar in bars {
+        writer.write_record([
+            bar.timestamp.to_rfc3339(),
+            bar.open.to_string(),
+            bar.high.to_string(),
+            bar.low.to_string(),
+            bar.close.to_string(),
+            bar.volume.to_string(),
+        ])?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+fn decimal(s: &str) -> Result<Decimal> {
+    Decimal::from_str(s).with_context(|| format!("invalid decimal {}", s))
+}
+
+fn millis(ms: i64) -> Result<DateTime<Utc>> {
+    DateTime::from_timestamp_millis(ms).with_context(|| format!("invalid timestamp {}", ms))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parses_stream_and_rest_klines_alike() {
+        let event: KlineEvent = serde_json::from_value(json!({
+            "e": "kline", "E": 1_700_000_030_000i64, "s": "BTCUSDT",
+            "k": {
+                "t": 1_700_000_000_000i64, "T": 1_700_000_059_999i64, "s": "BTCUSDT",
+                "i": "1m", "f": 1, "L": 9, "o": "100.0", "c": "101.5", "h": "102",
+                "l": "99.5", "v": "12.5", "n": 9, "x": false, "q": "1265",
+                "V": "6", "Q": "608", "B": "0",
+            },
+        }))
+        .unwrap();
+        let kline = event.into_kline().unwrap();
+        assert_eq!(kline.interval, KlineInterval::Minute1);
+        assert!(!kline.closed);
+        assert_eq!(kline.close_time.timestamp_millis(), 1_700_000_059_999);
+
+        let row = json!([
+            1_700_000_000_000i64,
+            "100.0",
+            "102",
+            "99.5",
+            "101.5",
+            "12.5",
+            1_700_000_059_999i64,
+            "1265",
+            9,
+            "6",
+            "608",
+            "0",
+        ]);
+        assert_eq!(parse_rest_row(row.as_array().unwrap()).unwrap(), kline.bar);
+        assert!(parse_rest_row(&[json!(1), json!("x")]).is_err());
+    }
+
+    #[test]
+    fn test_interval_names_round_trip() {
+        for interval in KlineInterval::ALL {
+            assert_eq!(
+                interval.as_str().parse::<KlineInterval>().unwrap(),
+                interval
+            );
+        }
+        // Minutes and months differ only by case
+        assert_eq!(
+            "1M".parse::<KlineInterval>().unwrap(),
+            KlineInterval::Month1
+        );
+        assert!("2m".parse::<KlineInterval>().is_err());
+    }
+}
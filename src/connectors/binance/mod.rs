@@ -0,0 +1,708 @@
+pub mod combined;
+pub mod depth;
+pub mod klines;
+pub mod trades;
+pub mod trading;
+pub mod user_data;
+
+use super::exchange::{
+    check_subscriptions, Balance, Capabilities, ExchangeConnector, MarketDataCallback, OrderRef,
+    VenueOrder,
+};
+use super::market::{next_command, wait_until};
+use super::reconnect::{
+    next_text, supervise, Backoff, ReconnectPolicy, SessionSender, StreamEvent, WsStream,
+};
+use crate::backtest::engine::OHLCV;
+use crate::utils::types::{MarketTrade, Order, OrderBookSnapshot, Ticker};
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use combined::{CombinedMessage, CombinedState, MarketEvent, Subscription, SubscriptionCommands};
+use depth::{DepthSnapshot, DepthSync, DepthUpdate};
+use futures::stream::FuturesUnordered;
+use futures::{SinkExt, StreamExt};
+use klines::{Kline, KlineEvent, KlineInterval};
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Duration;
+use tokio_tungstenite::connect_async;
+use tracing::{error, info, warn};
+use trades::{AggTradeEvent, TradeEvent};
+use trading::{Credentials, TradingClient};
+
+#[derive(Debug, Deserialize)]
+struct BinanceTickerUpdate {
+    #[serde(rename = "e")]
+    #[allow(dead_code)]
+    event_type: String,
+    #[serde(rename = "E")]
+    event_time: i64,
+    #[serde(rename = "s")]
+    symbol: String,
+    #[serde(rename = "b")]
+    bid_price: String,
+    #[serde(rename = "a")]
+    ask_price: String,
+    #[serde(rename = "c")]
+    last_price: String,
+    #[serde(rename = "v")]
+    volume: String,
+}
+
+/// Endpoints and limits for [`BinanceConnector`].
+#[derive(Debug, Clone)]
+pub struct BinanceConfig {
+    /// Raw stream base; stream names are appended as `/<stream>`.
+    pub ws_url: String,
+    /// Combined stream endpoint, for many streams over one connection.
+    pub combined_ws_url: String,
+    /// REST API base, e.g. the testnet or a local stand-in.
+    pub rest_url: String,
+    /// Levels per side requested in depth snapshots.
+    pub depth_limit: u32,
+    /// Bars requested per page of historical klines.
+    pub kline_limit: u32,
+    /// How long after its timestamp a signed request stays valid.
+    pub recv_window: Duration,
+    /// How often a user-data listen key is kept alive; Binance expires
+    /// keys after an hour without one.
+    pub listen_key_keepalive: Duration,
+    /// Reconnection of streams that drop.
+    pub reconnect: ReconnectPolicy,
+}
+
+impl BinanceConfig {
+    pub fn new() -> Self {
+        Self {
+            ws_url: "wss://stream.binance.com:9443/ws".to_string(),
+            combined_ws_url: "wss://stream.binance.com:9443/stream".to_string(),
+            rest_url: "https://api.binance.com".to_string(),
+            depth_limit: 1000,
+            kline_limit: 1000,
+            recv_window: Duration::from_secs(5),
+            listen_key_keepalive: Duration::from_secs(30 * 60),
+            reconnect: ReconnectPolicy::new(),
+        }
+    }
+}
+
+impl Default for BinanceConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub struct BinanceConnector {
+    config: BinanceConfig,
+    http: reqwest::Client,
+    /// Order entry and account queries, given credentials.
+    trading: Option<TradingClient>,
+}
+
+impl BinanceConnector {
+    pub fn new() -> Self {
+        Self::with_config(BinanceConfig::new())
+    }
+
+    pub fn with_config(config: BinanceConfig) -> Self {
+        Self {
+            config,
+            http: reqwest::Client::new(),
+            trading: None,
+        }
+    }
+
+    /// A connector that can also trade on the account of `credentials`.
+    pub fn with_credentials(config: BinanceConfig, credentials: Credentials) -> Self {
+        Self {
+            trading: Some(TradingClient::new(&config, credentials)),
+            ..Self::with_config(config)
+        }
+    }
+
+    fn trading(&self) -> Result<&TradingClient> {
+        self.trading
+            .as_ref()
+            .context("binance connector has no API credentials")
+    }
+
+    fn stream_url(&self, symbol: &str, stream: &str) -> String {
+        format!(
+            "{}/{}@{}",
+            self.config.ws_url,
+            symbol.to_lowercase(),
+            stream
+        )
+    }
+
+    pub async fn connect_orderbook(&self, symbol: &str) -> Result<WsStream> {
+        let url = self.stream_url(symbol, "depth@100ms");
+        info!("Connecting to Binance orderbook stream: {}", url);
+
+        let (ws_stream, _) = connect_async(&url)
+            .await
+            .context("Failed to connect to Binance WebSocket")?;
+
+        info!("Connected to Binance orderbook stream for {}", symbol);
+        Ok(ws_stream)
+    }
+
+    pub async fn connect_ticker(&self, symbol: &str) -> Result<WsStream> {
+        let url = self.stream_url(symbol, "ticker");
+        info!("Connecting to Binance ticker stream: {}", url);
+
+        let (ws_stream, _) = connect_async(&url)
+            .await
+            .context("Failed to connect to Binance WebSocket")?;
+
+        info!("Connected to Binance ticker stream for {}", symbol);
+        Ok(ws_stream)
+    }
+
+    /// Fetches the REST depth snapshot a local book is synchronised from.
+    pub async fn fetch_depth_snapshot(&self, symbol: &str) -> Result<DepthSnapshot> {
+        let url = format!("{}/api/v3/depth", self.config.rest_url);
+        let snapshot = self
+            .http
+            .get(&url)
+            .query(&[
+                ("symbol", symbol.to_uppercase()),
+                ("limit", self.config.depth_limit.to_string()),
+            ])
+            .send()
+            .await
+            .context("Failed to request Binance depth snapshot")?
+            .error_for_status()?
+            .json()
+            .await
+            .context("Failed to parse Binance depth snapshot")?;
+        Ok(snapshot)
+    }
+
+    /// Fetches historical bars opening between `start` and `end`, paging
+    /// through `GET /api/v3/klines` `kline_limit` bars at a time.
+    pub async fn fetch_klines(
+        &self,
+        symbol: &str,
+        interval: KlineInterval,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Result<Vec<OHLCV>> {
+        let url = format!("{}/api/v3/klines", self.config.rest_url);
+        let end = end.timestamp_millis();
+        let mut from = start.timestamp_millis();
+        let mut bars = Vec::new();
+        while from <= end {
+            let rows: Vec<Vec<serde_json::Value>> = self
+                .http
+                .get(&url)
+                .query(&[
+                    ("symbol", symbol.to_uppercase()),
+                    ("interval", interval.to_string()),
+                    ("startTime", from.to_string()),
+                    ("endTime", end.to_string()),
+                    ("limit", self.config.kline_limit.to_string()),
+                ])
+                .send()
+                .await
+                .context("Failed to request Binance klines")?
+                .error_for_status()?
+                .json()
+                .await
+                .context("Failed to parse Binance klines")?;
+            let page: Vec<OHLCV> = rows
+                .iter()
+                .map(|row| klines::parse_rest_row(row))
+                .collect::<Result<_>>()?;
+            let full = page.len() >= self.config.kline_limit as usize;
+            let Some(last) = page.last() else {
+                break;
+            };
+            from = last.timestamp.timestamp_millis() + 1;
+            bars.extend(page);
+            if !full {
+                break;
+            }
+        }
+        info!("Fetched {} {} klines for {}", bars.len(), interval, symbol);
+        Ok(bars)
+    }
+
+    /// Fetches historical bars into a CSV file `run_backtest` can read and
+    /// returns how many were written.
+    pub async fn download_klines(
+        &self,
+        symbol: &str,
+        interval: KlineInterval,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        path: impl AsRef<Path>,
+    ) -> Result<usize> {
+        let bars = self.fetch_klines(symbol, interval, start, end).await?;
+        let file = std::fs::File::create(path.as_ref())
+            .with_context(|| format!("Failed to create {}", path.as_ref().display()))?;
+        klines::write_csv(file, &bars)?;
+        Ok(bars.len())
+    }
+
+    /// Maintains a local order book from the diff stream and a REST
+    /// snapshot, calling `callback` with the full book after every change.
+    /// Gaps in the update ids trigger a resync from a fresh snapshot, and
+    /// the stream reconnects under the configured policy, resynchronising
+    /// the book on every new connection. Returns only when the policy gives
+    /// up.
+    pub async fn stream_orderbook<F>(&self, symbol: &str, mut callback: F) -> Result<()>
+    where
+        F: FnMut(StreamEvent<OrderBookSnapshot>) + Send + 'static,
+    {
+        let url = self.stream_url(symbol, "depth@100ms");
+        supervise(
+            &url,
+            &self.config.reconnect,
+            &mut callback,
+            |ws_stream, tx| self.depth_session(ws_stream, symbol, tx),
+        )
+        .await
+    }
+
+    /// Streams 24h ticker updates, reconnecting under the configured policy.
+    pub async fn stream_ticker<F>(&self, symbol: &str, mut callback: F) -> Result<()>
+    where
+        F: FnMut(StreamEvent<Ticker>) + Send + 'static,
+    {
+        let url = self.stream_url(symbol, "ticker");
+        supervise(
+            &url,
+            &self.config.reconnect,
+            &mut callback,
+            |ws_stream, tx| self.ticker_session(ws_stream, tx),
+        )
+        .await
+    }
+
+    /// Streams candlesticks: every update of the open bar, then the bar
+    /// once closed.
+    pub async fn stream_klines<F>(
+        &self,
+        symbol: &str,
+        interval: KlineInterval,
+        mut callback: F,
+    ) -> Result<()>
+    where
+        F: FnMut(StreamEvent<Kline>) + Send + 'static,
+    {
+        let url = self.stream_url(symbol, &format!("kline_{}", interval));
+        supervise(
+            &url,
+            &self.config.reconnect,
+            &mut callback,
+            |ws_stream, tx| self.kline_session(ws_stream, tx),
+        )
+        .await
+    }
+
+    /// Streams every trade on the public tape, reconnecting under the
+    /// configured policy.
+    pub async fn stream_trades<F>(&self, symbol: &str, mut callback: F) -> Result<()>
+    where
+        F: FnMut(StreamEvent<MarketTrade>) + Send + 'static,
+    {
+        let url = self.stream_url(symbol, "trade");
+        supervise(
+            &url,
+            &self.config.reconnect,
+            &mut callback,
+            |ws_stream, tx| self.trade_session(ws_stream, false, tx),
+        )
+        .await
+    }
+
+    /// Streams aggregate trades, each merging the fills of one taker order
+    /// at one price; the trade id is the aggregate id.
+    pub async fn stream_agg_trades<F>(&self, symbol: &str, mut callback: F) -> Result<()>
+    where
+        F: FnMut(StreamEvent<MarketTrade>) + Send + 'static,
+    {
+        let url = self.stream_url(symbol, "aggTrade");
+        supervise(
+            &url,
+            &self.config.reconnect,
+            &mut callback,
+            |ws_stream, tx| self.trade_session(ws_stream, true, tx),
+        )
+        .await
+    }
+
+    async fn depth_session(
+        &self,
+        mut ws_stream: WsStream,
+        symbol: &str,
+        tx: SessionSender<OrderBookSnapshot>,
+    ) -> Result<()> {
+        let mut sync = DepthSync::new(symbol);
+        // A snapshot that fails to synchronise is fetched again with backoff
+        let mut backoff = Backoff::new(self.config.reconnect.clone());
+        let mut delay = Duration::ZERO;
+
+        loop {
+            if sync.needs_snapshot() {
+                let wait = tokio::time::sleep(delay);
+                let fetch = async {
+                    wait.await;
+                    self.fetch_depth_snapshot(symbol).await
+                };
+                tokio::pin!(fetch);
+                // Diffs keep arriving while the snapshot is in flight
+                let snapshot = loop {
+                    tokio::select! {
+                        snapshot = &mut fetch => break snapshot?,
+                        text = next_text(&mut ws_stream) => match text? {
+                            Some(text) => on_depth_text(&mut sync, &text, &tx),
+                            None => return Ok(()),
+                        },
+                    }
+                };
+                match sync.on_snapshot(snapshot) {
+                    Ok(()) => {
+                        backoff.reset();
+                        delay = Duration::ZERO;
+                        let book = sync.book().expect("synchronised");
+                        info!(
+                            "Synchronised {} order book at update {}",
+                            book.symbol(),
+                            book.last_update_id()
+                        );
+                        let _ = tx.send(book.snapshot(usize::MAX));
+                    }
+                    Err(e) => {
+                        let Some(next) = backoff.next_delay() else {
+                            anyhow::bail!(
+                                "Giving up on {} depth snapshots after {} attempts: {}",
+                                symbol,
+                                backoff.failures(),
+                                e
+                            );
+                        };
+                        delay = next;
+                        warn!(
+                            "Resynchronising {} order book in {:?}: {}",
+                            symbol, delay, e
+                        );
+                    }
+                }
+                continue;
+            }
+
+            match next_text(&mut ws_stream).await? {
+                Some(text) => on_depth_text(&mut sync, &text, &tx),
+                None => return Ok(()),
+            }
+        }
+    }
+
+    async fn ticker_session(
+        &self,
+        mut ws_stream: WsStream,
+        tx: SessionSender<Ticker>,
+    ) -> Result<()> {
+        while let Some(text) = next_text(&mut ws_stream).await? {
+            match serde_json::from_str::<BinanceTickerUpdate>(&text) {
+                Ok(update) => {
+                    let ticker = update.into_ticker();
+                    let _ = tx.send(ticker);
+                }
+                Err(e) => {
+                    error!("Failed to parse ticker update: {}", e);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn kline_session(&self, mut ws_stream: WsStream, tx: SessionSender<Kline>) -> Result<()> {
+        while let Some(text) = next_text(&mut ws_stream).await? {
+            match serde_json::from_str::<KlineEvent>(&text)
+                .map_err(anyhow::Error::from)
+                .and_then(KlineEvent::into_kline)
+            {
+                Ok(kline) => {
+                    let _ = tx.send(kline);
+                }
+                Err(e) => {
+                    error!("Failed to parse kline: {}", e);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn trade_session(
+        &self,
+        mut ws_stream: WsStream,
+        aggregate: bool,
+        tx: SessionSender<MarketTrade>,
+    ) -> Result<()> {
+        while let Some(text) = next_text(&mut ws_stream).await? {
+            let trade = if aggregate {
+                serde_json::from_str::<AggTradeEvent>(&text)
+                    .map_err(anyhow::Error::from)
+                    .and_then(AggTradeEvent::into_trade)
+            } else {
+                serde_json::from_str::<TradeEvent>(&text)
+                    .map_err(anyhow::Error::from)
+                    .and_then(TradeEvent::into_trade)
+            };
+            match trade {
+                Ok(trade) => {
+                    let _ = tx.send(trade);
+                }
+                Err(e) => {
+                    error!("Failed to parse trade: {}", e);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Streams every subscription over one combined connection, routing
+    /// books, tickers and trades through [`MarketEvent`]. Subscriptions can be
+    /// changed while running through the handle paired with `commands`
+    /// (see [`combined::subscription_channel`]) and are restored, with
+    /// books resynchronised, after every reconnect.
+    pub async fn stream_combined<F>(
+        &self,
+        subscriptions: &[Subscription],
+        commands: SubscriptionCommands,
+        mut callback: F,
+    ) -> Result<()>
+    where
+        F: FnMut(StreamEvent<MarketEvent>) + Send + 'static,
+    {
+        let state = tokio::sync::Mutex::new(CombinedState::new(subscriptions, commands));
+        supervise(
+            &self.config.combined_ws_url,
+            &self.config.reconnect,
+            &mut callback,
+            |ws_stream, tx| self.combined_session(ws_stream, &state, tx),
+        )
+        .await
+    }
+
+    async fn combined_session(
+        &self,
+        mut ws_stream: WsStream,
+        state: &tokio::sync::Mutex<CombinedState>,
+        tx: SessionSender<MarketEvent>,
+    ) -> Result<()> {
+        let mut state = state.lock().await;
+        let stale_after = self.config.reconnect.stale_after;
+        if let Some(request) = state.reconnected() {
+            ws_stream.send(tungstenite::Message::Text(request)).await?;
+        }
+        let mut fetches = FuturesUnordered::new();
+        let mut fetching = HashSet::new();
+        // A failed snapshot is retried with backoff for that symbol alone
+        let mut retries: HashMap<String, Backoff> = HashMap::new();
+        let mut delays: HashMap<String, Duration> = HashMap::new();
+
+        loop {
+            let quiet = state.silence.deadline(stale_after);
+            for symbol in state.snapshots_needed() {
+                if fetching.insert(symbol.clone()) {
+                    let delay = delays.remove(&symbol).unwrap_or_default();
+                    fetches.push(async move {
+                        tokio::time::sleep(delay).await;
+                        let snapshot = self.fetch_depth_snapshot(&symbol).await;
+                        (symbol, snapshot)
+                    });
+                }
+            }
+
+            tokio::select! {
+                text = next_text(&mut ws_stream) => {
+                    let Some(text) = text? else {
+                        return Ok(());
+                    };
+                    match serde_json::from_str::<CombinedMessage>(&text) {
+                        Ok(CombinedMessage::Event { stream, data }) => {
+                            match state.route(&stream, data) {
+                                Ok(Some(event)) => {
+                                    let _ = tx.send(event);
+                                }
+                                Ok(None) => {}
+                                Err(e) => error!("Failed to parse {} event: {}", stream, e),
+                            }
+                        }
+                        Ok(CombinedMessage::Response { id, error: Some(error) }) => {
+                            warn!("Subscription request {} failed: {}", id, error);
+                        }
+                        Ok(CombinedMessage::Response { .. }) => {}
+                        Err(e) => error!("Failed to parse combined stream message: {}", e),
+                    }
+                }
+                command = next_command(&mut state.commands) => match command {
+                    Some(command) => {
+                        if let Some(request) = state.apply(command) {
+                            ws_stream
+                                .send(tungstenite::Message::Text(request))
+                                .await?;
+                        }
+                    }
+                    // Every handle is gone; keep streaming the current set
+                    None => state.commands = None,
+                },
+                _ = wait_until(quiet) => {
+                    for (symbol, silent_for) in state.silence.silent(stale_after) {
+                        tx.stale(&symbol, silent_for);
+                    }
+                }
+                Some((symbol, snapshot)) = fetches.next() => {
+                    fetching.remove(&symbol);
+                    // Skipped if the symbol was unsubscribed meanwhile
+                    let Some(sync) = state.books.get_mut(&symbol) else {
+                        retries.remove(&symbol);
+                        continue;
+                    };
+                    let synced = snapshot.and_then(|snapshot| Ok(sync.on_snapshot(snapshot)?));
+                    if let Err(e) = synced {
+                        let backoff = retries
+                            .entry(symbol.clone())
+                            .or_insert_with(|| Backoff::new(self.config.reconnect.clone()));
+                        let Some(delay) = backoff.next_delay() else {
+                            anyhow::bail!(
+                                "Giving up on {} depth snapshots after {} attempts: {:#}",
+                                symbol,
+                                backoff.failures(),
+                                e
+                            );
+                        };
+                        warn!("Resynchronising {} order book in {:?}: {:#}", symbol, delay, e);
+                        delays.insert(symbol, delay);
+                        continue;
+                    }
+                    retries.remove(&symbol);
+                    let book = sync.book().expect("synchronised");
+                    let _ = tx.send(MarketEvent::Book(book.snapshot(usize::MAX)));
+                }
+            }
+        }
+    }
+}
+
+impl BinanceTickerUpdate {
+    fn into_ticker(self) -> Ticker {
+        Ticker {
+            symbol: self.symbol,
+            bid: Decimal::from_str(&self.bid_price).unwrap_or_default(),
+            ask: Decimal::from_str(&self.ask_price).unwrap_or_default(),
+            last: Decimal::from_str(&self.last_price).unwrap_or_default(),
+            volume_24h: Decimal::from_str(&self.volume).unwrap_or_default(),
+            timestamp: DateTime::from_timestamp_millis(self.event_time).unwrap_or_else(Utc::now),
+        }
+    }
+}
+
+impl Default for BinanceConnector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl ExchangeConnector for BinanceConnector {
+    fn name(&self) -> &'static str {
+        "binance"
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            order_book: true,
+            ticker: true,
+            trades: true,
+            agg_trades: true,
+            klines: true,
+            live_subscriptions: true,
+            order_entry: self.trading.is_some(),
+            account: self.trading.is_some(),
+        }
+    }
+
+    async fn stream_market_data(
+        &self,
+        subscriptions: &[Subscription],
+        commands: SubscriptionCommands,
+        callback: MarketDataCallback,
+    ) -> Result<()> {
+        check_subscriptions(self, subscriptions)?;
+        self.stream_combined(subscriptions, commands, callback)
+            .await
+    }
+
+    async fn place_order(&self, order: &Order) -> Result<VenueOrder> {
+        let placed = self.trading()?.place_order(order).await?;
+        Ok(placed.into_venue_order())
+    }
+
+    async fn cancel_order(&self, symbol: &str, order: &OrderRef) -> Result<VenueOrder> {
+        let cancelled = self
+            .trading()?
+            .cancel_order(symbol, &binance_order_ref(order)?)
+            .await?;
+        Ok(cancelled.into_venue_order())
+    }
+
+    async fn query_order(&self, symbol: &str, order: &OrderRef) -> Result<VenueOrder> {
+        let queried = self
+            .trading()?
+            .query_order(symbol, &binance_order_ref(order)?)
+            .await?;
+        Ok(queried.into_venue_order())
+    }
+
+    async fn open_orders(&self, symbol: Option<&str>) -> Result<Vec<VenueOrder>> {
+        let orders = self.trading()?.open_orders(symbol).await?;
+        Ok(orders
+            .into_iter()
+            .map(trading::ExchangeOrder::into_venue_order)
+            .collect())
+    }
+
+    async fn balances(&self) -> Result<Vec<Balance>> {
+        Ok(self.trading()?.balances().await?)
+    }
+}
+
+/// Binance order ids are numeric.
+fn binance_order_ref(order: &OrderRef) -> Result<trading::OrderRef> {
+    match order {
+        OrderRef::Id(id) => id
+            .parse()
+            .map(trading::OrderRef::Id)
+            .with_context(|| format!("invalid binance order id {}", id)),
+        OrderRef::ClientId(id) => Ok(trading::OrderRef::ClientId(id.clone())),
+    }
+}
+
+fn on_depth_text(sync: &mut DepthSync, text: &str, tx: &SessionSender<OrderBookSnapshot>) {
+    let update = match serde_json::from_str::<DepthUpdate>(text) {
+        Ok(update) => update,
+        Err(e) => {
+            error!("Failed to parse depth update: {}", e);
+            return;
+        }
+    };
+    match sync.on_update(update) {
+        Ok(true) => {
+            let _ = tx.send(sync.book().expect("synchronised").snapshot(usize::MAX));
+        }
+        Ok(false) => {}
+        Err(e) => warn!("Resynchronising order book: {}", e),
+    }
+}
@@ -0,0 +1,138 @@
+//! Public trade tape: `<symbol>@trade` prints every fill, `<symbol>@aggTrade`
+//! merges the fills of one taker order at one price.
+
+use crate::utils::types::MarketTrade;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use std::str::FromStr;
+
+/// A `<symbol>@trade` event.
+#[derive(Debug, Clone, Deserialize)]
+pub struct TradeEvent {
+    /// Event time in milliseconds since the epoch.
+    #[serde(rename = "E")]
+    pub event_time: i64,
+    #[serde(rename = "s")]
+    pub symbol: String,
+    #[serde(rename = "t")]
+    pub trade_id: u64,
+    #[serde(rename = "p")]
+    pub price: String,
+    #[serde(rename = "q")]
+    pub quantity: String,
+    #[serde(rename = "m")]
+    pub buyer_maker: bool,
+}
+
+/// A `<symbol>@aggTrade` event covering trades `first_trade_id..=last_trade_id`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct AggTradeEvent {
+    #[serde(rename = "E")]
+    pub event_time: i64,
+    #[serde(rename = "s")]
+    pub symbol: String,
+    #[serde(rename = "a")]
+    pub aggregate_id: u64,
+    #[serde(rename = "p")]
+    pub price: String,
+    #[serde(rename = "q")]
+    pub quantity: String,
+    #[serde(rename = "f")]
+    pub first_trade_id: u64,
+    #[serde(rename = "l")]
+    pub last_trade_id: u64,
+    #[serde(rename = "m")]
+    pub buyer_maker: bool,
+}
+
+impl TradeEvent {
+    pub fn into_trade(self) -> Result<MarketTrade> {
+        market_trade(
+            self.symbol,
+            self.trade_id,
+            &self.price,
+            &self.quantity,
+            self.buyer_maker,
+            self.event_time,
+        )
+    }
+}
+
+impl AggTradeEvent {
+    pub fn into_trade(self) -> Result<MarketTrade> {
+        market_trade(
+            self.symbol,
+            self.aggregate_id,
+            &self.price,
+            &self.quantity,
+            self.buyer_maker,
+            self.event_time,
+        )
+    }
+}
+
+fn market_trade(
+    symbol: String,
+    trade_id: u64,
+    price: &str,
+    quantity: &str,
+    buyer_maker: bool,
+    event_time: i64,
+) -> Result<MarketTrade> {
+    Ok(MarketTrade {
+        trade_id,
+        price: Decimal::from_str(price)
+            .with_context(|| format!("invalid price {} in trade {}", price, trade_id))?,
+        quantity: Decimal::from_str(quantity)
+            .with_context(|| format!("invalid quantity {} in trade {}", quantity, trade_id))?,
+        buyer_maker,
+        timestamp: DateTime::from_timestamp_millis(event_time).unwrap_or_else(Utc::now),
+        symbol,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::types::Side;
+
+    #[test]
+    fn test_parses_trade_and_agg_trade_events() {
+        let trade: TradeEvent = serde_json::from_str(
+            r#"{"e":"trade","E":1700000000123,"s":"BTCUSDT","t":12345,"p":"43000.10",
+                "q":"0.250","T":1700000000120,"m":true,"M":true}"#,
+        )
+        .unwrap();
+        let trade = trade.into_trade().unwrap();
+        assert_eq!(trade.trade_id, 12345);
+        assert_eq!(trade.price, Decimal::from_str("43000.10").unwrap());
+        assert_eq!(trade.quantity, Decimal::from_str("0.25").unwrap());
+        assert_eq!(trade.aggressor(), Side::Sell);
+        assert_eq!(trade.timestamp.timestamp_millis(), 1_700_000_000_123);
+
+        let agg: AggTradeEvent = serde_json::from_str(
+            r#"{"e":"aggTrade","E":1700000000456,"s":"ETHUSDT","a":777,"p":"2200",
+                "q":"3","f":100,"l":104,"T":1700000000450,"m":false,"M":true}"#,
+        )
+        .unwrap();
+        assert_eq!(agg.last_trade_id - agg.first_trade_id, 4);
+        let agg = agg.into_trade().unwrap();
+        assert_eq!((agg.symbol.as_str(), agg.trade_id), ("ETHUSDT", 777));
+        assert_eq!(agg.aggressor(), Side::Buy);
+    }
+
+    #[test]
+    fn test_rejects_malformed_prices() {
+        let trade = TradeEvent {
+            event_time: 0,
+            symbol: "BTCUSDT".to_string(),
+            trade_id: 1,
+            price: "not-a-price".to_string(),
+            quantity: "1".to_string(),
+            buyer_maker: false,
+        };
+        assert!(trade.into_trade().is_err());
+    }
+}
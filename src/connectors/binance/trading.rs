@@ -0,0 +1,580 @@
+//! Signed REST client for spot trading.
+//!
+//! Signed endpoints take a `timestamp` and `recvWindow` and an HMAC-SHA256
+//! signature of the query string under the secret key. Timestamps are
+//! corrected by the offset to Binance's clock measured with
+//! [`TradingClient::sync_time`], and a request rejected for its timestamp is
+//! retried once after measuring the offset again.
+
+use super::BinanceConfig;
+use crate::connectors::exchange::{Balance, VenueOrder};
+use crate::utils::types::{Order, OrderStatus, OrderType, Side};
+use chrono::Utc;
+use dashmap::DashMap;
+use hmac::{Hmac, Mac};
+use reqwest::{Method, Response, StatusCode};
+use rust_decimal::Decimal;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Deserializer};
+use sha2::Sha256;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::time::Duration;
+use thiserror::Error;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+/// API key pair for signed endpoints.
+#[derive(Clone)]
+pub struct Credentials {
+    pub api_key: String,
+    pub secret_key: String,
+}
+
+impl Credentials {
+    pub fn new(api_key: &str, secret_key: &str) -> Self {
+        Self {
+            api_key: api_key.to_string(),
+            secret_key: secret_key.to_string(),
+        }
+    }
+
+    /// Hex HMAC-SHA256 of `payload` under the secret key.
+    pub fn sign(&self, payload: &str) -> String {
+        let mut mac = Hmac::<Sha256>::new_from_slice(self.secret_key.as_bytes())
+            .expect("HMAC accepts keys of any length");
+        mac.update(payload.as_bytes());
+        hex::encode(mac.finalize().into_bytes())
+    }
+}
+
+impl std::fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("Credentials")
+            .field("api_key", &self.api_key)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Failures of a REST request, with Binance's error codes mapped to the
+/// cases callers handle differently.
+#[derive(Debug, Error)]
+pub enum BinanceError {
+    /// -1021: the local clock is too far from Binance's.
+    #[error("timestamp outside the receive window: {0}")]
+    TimestampOutsideRecvWindow(String),
+    /// -1022
+    #[error("invalid signature: {0}")]
+    InvalidSignature(String),
+    /// -2014, -2015 and HTTP 401: bad key, IP or permissions.
+    #[error("unauthorized: {0}")]
+    Unauthorized(String),
+    /// HTTP 429 or 418 (banned for ignoring 429s), or -1003.
+    #[error("rate limited, retry after {retry_after:?}")]
+    RateLimited { retry_after: Option<Duration> },
+    /// -2010, e.g. insufficient balance or a filter failure.
+    #[error("order rejected: {0}")]
+    OrderRejected(String),
+    /// -2011
+    #[error("cancel rejected: {0}")]
+    CancelRejected(String),
+    /// -2013
+    #[error("order does not exist: {0}")]
+    UnknownOrder(String),
+    /// -1100 to -1199: malformed or missing parameters.
+    #[error("invalid request ({code}): {msg}")]
+    InvalidRequest { code: i64, msg: String },
+    #[error("binance error {code}: {msg}")]
+    Api { code: i64, msg: String },
+    #[error("http {status}: {body}")]
+    Http { status: u16, body: String },
+    #[error("request failed: {0}")]
+    Transport(#[from] reqwest::Error),
+    #[error("unexpected response: {0}")]
+    Decode(String),
+    /// Refused locally, before anything is sent.
+    #[error("invalid order: {0}")]
+    InvalidOrder(String),
+}
+
+impl BinanceError {
+    /// Maps a `{"code": .., "msg": ..}` error body.
+    pub fn from_code(code: i64, msg: String) -> Self {
+        match code {
+            -1003 => BinanceError::RateLimited { retry_after: None },
+            -1021 => BinanceError::TimestampOutsideRecvWindow(msg),
+            -1022 => BinanceError::InvalidSignature(msg),
+            -2010 => BinanceError::OrderRejected(msg),
+            -2011 => BinanceError::CancelRejected(msg),
+            -2013 => BinanceError::UnknownOrder(msg),
+            -2015 | -2014 => BinanceError::Unauthorized(msg),
+            -1199..=-1100 => BinanceError::InvalidRequest { code, msg },
+            _ => BinanceError::Api { code, msg },
+        }
+    }
+}
+
+/// How an existing order is identified.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OrderRef {
+    /// Binance's order id.
+    Id(u64),
+    /// Our `newClientOrderId`.
+    ClientId(String),
+}
+
+impl OrderRef {
+    fn param(&self) -> (&'static str, String) {
+        match self {
+            OrderRef::Id(id) => ("orderId", id.to_string()),
+            OrderRef::ClientId(id) => ("origClientOrderId", id.clone()),
+        }
+    }
+}
+
+/// An order as Binance reports it.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExchangeOrder {
+    pub symbol: String,
+    pub order_id: u64,
+    /// The id we assigned.
+    pub client_order_id: String,
+    #[serde(deserialize_with = "de_decimal")]
+    pub price: Decimal,
+    #[serde(rename = "origQty", deserialize_with = "de_decimal")]
+    pub quantity: Decimal,
+    #[serde(rename = "executedQty", deserialize_with = "de_decimal")]
+    pub executed_quantity: Decimal,
+    #[serde(deserialize_with = "de_status")]
+    pub status: OrderStatus,
+    #[serde(deserialize_with = "de_side")]
+    pub side: Side,
+    /// Binance order type, e.g. `LIMIT` or `STOP_LOSS_LIMIT`.
+    #[serde(rename = "type")]
+    pub order_type: String,
+}
+
+impl ExchangeOrder {
+    pub fn into_venue_order(self) -> VenueOrder {
+        VenueOrder {
+            order_type: parse_order_type(&self.order_type),
+            symbol: self.symbol,
+            order_id: self.order_id.to_string(),
+            client_id: Some(self.client_order_id),
+            side: self.side,
+            price: self.price,
+            quantity: self.quantity,
+            filled_quantity: self.executed_quantity,
+            status: self.status,
+        }
+    }
+}
+
+/// Holdings of one asset, from the account endpoint or a user-data
+/// `outboundAccountPosition` event.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub(super) struct AccountBalance {
+    #[serde(alias = "a")]
+    pub asset: String,
+    #[serde(alias = "f", deserialize_with = "de_decimal")]
+    pub free: Decimal,
+    /// Held by open orders.
+    #[serde(alias = "l", deserialize_with = "de_decimal")]
+    pub locked: Decimal,
+}
+
+impl AccountBalance {
+    pub(super) fn into_balance(self) -> Balance {
+        Balance {
+            asset: self.asset,
+            free: self.free,
+            locked: self.locked,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct ApiErrorBody {
+    code: i64,
+    msg: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ServerTime {
+    server_time: i64,
+}
+
+#[derive(Deserialize)]
+struct Account {
+    balances: Vec<AccountBalance>,
+}
+
+/// Signed spot trading over REST.
+pub struct TradingClient {
+    http: reqwest::Client,
+    config: BinanceConfig,
+    credentials: Credentials,
+    /// Binance's clock minus ours, in milliseconds.
+    time_offset: AtomicI64,
+    /// Ids of orders placed under a caller's client id, by that client id,
+    /// since Binance reports them under it rather than the order's own id.
+    client_orders: DashMap<String, Uuid>,
+}
+
+impl TradingClient {
+    pub fn new(config: &BinanceConfig, credentials: Credentials) -> Self {
+        Self {
+            http: reqwest::Client::new(),
+            config: config.clone(),
+            credentials,
+            time_offset: AtomicI64::new(0),
+            client_orders: DashMap::new(),
+        }
+    }
+
+    /// Offset applied to request timestamps, in milliseconds.
+    pub fn time_offset(&self) -> i64 {
+        self.time_offset.load(Ordering::Relaxed)
+    }
+
+    /// Measures the offset to Binance's clock, taking the server time as of
+    /// the midpoint of the round trip.
+    pub async fn sync_time(&self) -> Result<i64, BinanceError> {
+        let sent = Utc::now().timestamp_millis();
+        let response = self
+            .http
+            .get(format!("{}/api/v3/time", self.config.rest_url))
+            .send()
+            .await?;
+        let time: ServerTime = parse_response(response).await?;
+        let received = Utc::now().timestamp_millis();
+        let offset = time.server_time - (sent + received) / 2;
+        self.time_offset.store(offset, Ordering::Relaxed);
+        info!("Binance clock offset is {}ms", offset);
+        Ok(offset)
+    }
+
+    /// Places `order`, identified to Binance by its `client_id` or else its
+    /// id. Limit orders rest good-till-cancelled. Either way the user-data
+    /// stream reports it under the order's id. Pegged, trailing and expiring
+    /// orders have no Binance spot equivalent and are refused.
+    pub async fn place_order(&self, order: &Order) -> Result<ExchangeOrder, BinanceError> {
+        let unsupported = if order.peg.is_some() {
+            Some("pegged orders")
+        } else if order.trailing.is_some() {
+            Some("trailing stops")
+        } else if order.expires_at.is_some() {
+            Some("orders with an expiry time")
+        } else {
+            None
+        };
+        if let Some(unsupported) = unsupported {
+            return Err(BinanceError::InvalidOrder(format!(
+                "binance does not support {}",
+                unsupported
+            )));
+        }
+        let client_id = order
+            .client_id
+            .clone()
+            .unwrap_or_else(|| order.id.simple().to_string());
+        let mut params = vec![
+            ("symbol", order.symbol.to_uppercase()),
+            ("side", side_name(order.side).to_string()),
+            ("quantity", order.quantity.normalize().to_string()),
+            ("newClientOrderId", client_id),
+            ("newOrderRespType", "RESULT".to_string()),
+        ];
+        let stop_price = || {
+            order
+                .stop_price
+                .map(|price| price.normalize().to_string())
+                .ok_or_else(|| {
+                    BinanceError::InvalidOrder("stop order without a stop price".to_string())
+                })
+        };
+        match order.order_type {
+            OrderType::Market => params.push(("type", "MARKET".to_string())),
+            OrderType::Limit => params.extend([
+                ("type", "LIMIT".to_string()),
+                ("timeInForce", "GTC".to_string()),
+                ("price", order.price.normalize().to_string()),
+            ]),
+            OrderType::StopMarket => params.extend([
+                ("type", "STOP_LOSS".to_string()),
+                ("stopPrice", stop_price()?),
+            ]),
+            OrderType::StopLimit => params.extend([
+                ("type", "STOP_LOSS_LIMIT".to_string()),
+                ("timeInForce", "GTC".to_string()),
+                ("price", order.price.normalize().to_string()),
+                ("stopPrice", stop_price()?),
+            ]),
+        }
+        if let Some(client_id) = &order.client_id {
+            // Recorded first, as the stream may report the order before the
+            // placement returns
+            self.client_orders.insert(client_id.clone(), order.id);
+        }
+        let placed = self.signed(Method::POST, "/api/v3/order", &params).await;
+        if placed.is_err() {
+            if let Some(client_id) = &order.client_id {
+                self.client_orders.remove(client_id);
+            }
+        }
+        placed
+    }
+
+    /// Id of the order placed under `client_id`, forgetting it once the
+    /// order is `done`.
+    pub(super) fn client_order(&self, client_id: &str, done: bool) -> Option<Uuid> {
+        if done {
+            self.client_orders.remove(client_id).map(|(_, id)| id)
+        } else {
+            self.client_orders.get(client_id).map(|id| *id)
+        }
+    }
+
+    pub async fn cancel_order(
+        &self,
+        symbol: &str,
+        order: &OrderRef,
+    ) -> Result<ExchangeOrder, BinanceError> {
+        let params = [("symbol", symbol.to_uppercase()), order.param()];
+        let mut response: serde_json::Value = self
+            .signed(Method::DELETE, "/api/v3/order", &params)
+            .await?;
+        // `clientOrderId` names the cancel request; report the order's own
+        if let Some(id) = response.get("origClientOrderId").cloned() {
+            response["clientOrderId"] = id;
+        }
+        serde_json::from_value(response).map_err(|e| BinanceError::Decode(e.to_string()))
+    }
+
+    pub async fn query_order(
+        &self,
+        symbol: &str,
+        order: &OrderRef,
+    ) -> Result<ExchangeOrder, BinanceError> {
+        let params = [("symbol", symbol.to_uppercase()), order.param()];
+        self.signed(Method::GET, "/api/v3/order", &params).await
+    }
+
+    /// Open orders for `symbol`, or for every symbol when `None`.
+    pub async fn open_orders(
+        &self,
+        symbol: Option<&str>,
+    ) -> Result<Vec<ExchangeOrder>, BinanceError> {
+        let params: Vec<_> = symbol
+            .map(|symbol| ("symbol", symbol.to_uppercase()))
+            .into_iter()
+            .collect();
+        self.signed(Method::GET, "/api/v3/openOrders", &params)
+            .await
+    }
+
+    /// Assets with a non-zero free or locked amount.
+    pub async fn balances(&self) -> Result<Vec<Balance>, BinanceError> {
+        let account: Account = self.signed(Method::GET, "/api/v3/account", &[]).await?;
+        Ok(account
+            .balances
+            .into_iter()
+            .filter(|balance| !balance.free.is_zero() || !balance.locked.is_zero())
+            .map(AccountBalance::into_balance)
+            .collect())
+    }
+
+    pub(super) fn config(&self) -> &BinanceConfig {
+        &self.config
+    }
+
+    /// Sends a request that needs the API key but no signature.
+    pub(super) async fn keyed<T: DeserializeOwned>(
+        &self,
+        method: Method,
+        path: &str,
+        params: &[(&str, String)],
+    ) -> Result<T, BinanceError> {
+        let response = self
+            .http
+            .request(method, format!("{}{}", self.config.rest_url, path))
+            .query(params)
+            .header("X-MBX-APIKEY", &self.credentials.api_key)
+            .send()
+            .await?;
+        parse_response(response).await
+    }
+
+    async fn signed<T: DeserializeOwned>(
+        &self,
+        method: Method,
+        path: &str,
+        params: &[(&str, String)],
+    ) -> Result<T, BinanceError> {
+        match self.send_signed(method.clone(), path, params).await {
+            Err(BinanceError::TimestampOutsideRecvWindow(msg)) => {
+                warn!("Binance rejected our timestamp ({}), resyncing clock", msg);
+                self.sync_time().await?;
+                self.send_signed(method, path, params).await
+            }
+            result => result,
+        }
+    }
+
+    async fn send_signed<T: DeserializeOwned>(
+        &self,
+        method: Method,
+        path: &str,
+        params: &[(&str, String)],
+    ) -> Result<T, BinanceError> {
+        let timestamp = Utc::now().timestamp_millis() + self.time_offset();
+        // The serializer is not `Send`, so it must not live across the request
+        let query = {
+            let mut query = url::form_urlencoded::Serializer::new(String::new());
+            for (key, value) in params {
+                query.append_pair(key, value);
+            }
+            query
+                .append_pair(
+                    "recvWindow",
+                    &self.config.recv_window.as_millis().to_string(),
+                )
+                .append_pair("timestamp", &timestamp.to_string())
+                .finish()
+        };
+        let signature = self.credentials.sign(&query);
+        let url = format!(
+            "{}{}?{}&signature={}",
+            self.config.rest_url, path, query, signature
+        );
+        let response = self
+            .http
+            .request(method, url)
+            .header("X-MBX-APIKEY", &self.credentials.api_key)
+            .send()
+            .await?;
+        parse_response(response).await
+    }
+}
+
+async fn parse_response<T: DeserializeOwned>(response: Response) -> Result<T, BinanceError> {
+    let status = response.status();
+    if status == StatusCode::TOO_MANY_REQUESTS || status == StatusCode::IM_A_TEAPOT {
+        let retry_after = response
+            .headers()
+            .get(reqwest::header::RETRY_AFTER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse().ok())
+            .map(Duration::from_secs);
+        return Err(BinanceError::RateLimited { retry_after });
+    }
+    let body = response.text().await?;
+    if status.is_success() {
+        return serde_json::from_str(&body).map_err(|e| BinanceError::Decode(e.to_string()));
+    }
+    match serde_json::from_str::<ApiErrorBody>(&body) {
+        Ok(error) => Err(BinanceError::from_code(error.code, error.msg)),
+        Err(_) if status == StatusCode::UNAUTHORIZED => Err(BinanceError::Unauthorized(body)),
+        Err(_) => Err(BinanceError::Http {
+            status: status.as_u16(),
+            body,
+        }),
+    }
+}
+
+pub(super) fn side_name(side: Side) -> &'static str {
+    match side {
+        Side::Buy => "BUY",
+        Side::Sell => "SELL",
+    }
+}
+
+pub(super) fn parse_side(side: &str) -> Option<Side> {
+    match side {
+        "BUY" => Some(Side::Buy),
+        "SELL" => Some(Side::Sell),
+        _ => None,
+    }
+}
+
+pub(super) fn parse_order_type(order_type: &str) -> Option<OrderType> {
+    match order_type {
+        "LIMIT" | "LIMIT_MAKER" => Some(OrderType::Limit),
+        "MARKET" => Some(OrderType::Market),
+        // Take-profit orders are stops triggering on the other side
+        "STOP_LOSS" | "TAKE_PROFIT" => Some(OrderType::StopMarket),
+        "STOP_LOSS_LIMIT" | "TAKE_PROFIT_LIMIT" => Some(OrderType::StopLimit),
+        _ => None,
+    }
+}
+
+pub(super) fn parse_status(status: &str) -> Option<OrderStatus> {
+    match status {
+        "NEW" | "PENDING_CANCEL" => Some(OrderStatus::Open),
+        "PENDING_NEW" => Some(OrderStatus::Pending),
+        "PARTIALLY_FILLED" => Some(OrderStatus::PartiallyFilled),
+        "FILLED" => Some(OrderStatus::Filled),
+        "CANCELED" => Some(OrderStatus::Cancelled),
+        "REJECTED" => Some(OrderStatus::Rejected),
+        "EXPIRED" | "EXPIRED_IN_MATCH" => Some(OrderStatus::Expired),
+        _ => None,
+    }
+}
+
+/// Binance sends prices and quantities as strings.
+pub(super) fn de_decimal<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Decimal, D::Error> {
+    let s = String::deserialize(deserializer)?;
+    Decimal::from_str(&s).map_err(serde::de::Error::custom)
+}
+
+fn de_side<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Side, D::Error> {
+    let s = String::deserialize(deserializer)?;
+    parse_side(&s).ok_or_else(|| serde::de::Error::custom(format!("unknown side {}", s)))
+}
+
+fn de_status<'de, D: Deserializer<'de>>(deserializer: D) -> Result<OrderStatus, D::Error> {
+    let s = String::deserialize(deserializer)?;
+    parse_status(&s).ok_or_else(|| serde::de::Error::custom(format!("unknown order status {}", s)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_signs_like_the_binance_documentation() {
+        let credentials = Credentials::new(
+            "vmPUZE6mv9SD5VNHk4HlWFsOr6aKE2zvsw0MuIgwCIPy6utIco14y7Ju91duEh8A",
+            "NhqPtmdSJYdKjVHjA7PZj4Mge3R5YNiP1e3UZjInClVN65XAbvqqM6A7H5fATj0j",
+        );
+        assert_eq!(
+            credentials.sign(
+                "symbol=LTCBTC&side=BUY&type=LIMIT&timeInForce=GTC&quantity=1&price=0.1\
+                 &recvWindow=5000&timestamp=1499827319559"
+            ),
+            "c8db56825ae71d6d79447849e617115f4a920fa2acdcab2b053c4b2838bd6b71"
+        );
+        assert!(!format!("{:?}", credentials).contains("NhqPtmdSJ"));
+    }
+
+    #[test]
+    fn test_maps_error_codes() {
+        let error = |code| BinanceError::from_code(code, "msg".to_string());
+        assert!(matches!(
+            error(-1021),
+            BinanceError::TimestampOutsideRecvWindow(_)
+        ));
+        assert!(matches!(error(-2010), BinanceError::OrderRejected(_)));
+        assert!(matches!(error(-2015), BinanceError::Unauthorized(_)));
+        assert!(matches!(
+            error(-1102),
+            BinanceError::InvalidRequest { code: -1102, .. }
+        ));
+        assert!(matches!(
+            error(-9999),
+            BinanceError::Api { code: -9999, .. }
+        ));
+    }
+}
@@ -0,0 +1,385 @@
+//! User-data stream: live execution reports and balances for the account,
+//! including orders placed outside the engine.
+//!
+//! The stream is addressed by a listen key created over REST, which expires
+//! an hour after the last keepalive. Every connection obtains a key first,
+//! so a key that expired while disconnected is simply replaced.
+
+use super::trading::{
+    parse_order_type, parse_side, parse_status, AccountBalance, BinanceError, TradingClient,
+};
+use crate::connectors::exchange::Balance;
+use crate::connectors::reconnect::{
+    next_text, supervise_endpoint, ReconnectPolicy, SessionSender, StreamEvent, Timestamped,
+    WsStream,
+};
+use crate::risk::manager::RiskManager;
+use crate::utils::types::{ExecType, ExecutionReport, OrderStatus};
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use parking_lot::Mutex;
+use reqwest::Method;
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use serde_json::Value;
+use std::str::FromStr;
+use std::sync::Arc;
+use thiserror::Error;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+/// Something that happened to the account.
+#[derive(Debug, Clone)]
+pub enum UserDataEvent {
+    Execution(ExecutionReport),
+    /// Balances of the assets that changed.
+    Account {
+        balances: Vec<Balance>,
+        timestamp: DateTime<Utc>,
+    },
+}
+
+impl Timestamped for UserDataEvent {
+    fn timestamp(&self) -> DateTime<Utc> {
+        match self {
+            UserDataEvent::Execution(report) => report.timestamp,
+            UserDataEvent::Account { timestamp, .. } => *timestamp,
+        }
+    }
+}
+
+impl UserDataEvent {
+    /// Feeds fills into positions and balances into holdings.
+    pub fn apply(&self, risk: &RiskManager) {
+        match self {
+            UserDataEvent::Execution(report) => risk.on_execution(report),
+            UserDataEvent::Account { balances, .. } => {
+                for balance in balances {
+                    risk.set_balance(&balance.asset, balance.total());
+                }
+            }
+        }
+    }
+}
+
+/// Binance ended the stream because its listen key expired.
+#[derive(Debug, Error)]
+#[error("listen key expired")]
+pub struct ListenKeyExpired;
+
+/// An `executionReport` event.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ExecutionEvent {
+    #[serde(rename = "s")]
+    pub symbol: String,
+    #[serde(rename = "c")]
+    pub client_order_id: String,
+    /// On cancels, the client id of the order cancelled.
+    #[serde(rename = "C", default)]
+    pub original_client_order_id: String,
+    #[serde(rename = "S")]
+    pub side: String,
+    #[serde(rename = "o")]
+    pub order_type: String,
+    #[serde(rename = "q")]
+    pub quantity: String,
+    #[serde(rename = "p")]
+    pub price: String,
+    #[serde(rename = "x")]
+    pub exec_type: String,
+    #[serde(rename = "X")]
+    pub status: String,
+    #[serde(rename = "i")]
+    pub order_id: u64,
+    #[serde(rename = "l")]
+    pub last_quantity: String,
+    #[serde(rename = "z")]
+    pub filled_quantity: String,
+    #[serde(rename = "L")]
+    pub last_price: String,
+    /// Transaction time in milliseconds since the epoch.
+    #[serde(rename = "T")]
+    pub transaction_time: i64,
+    /// `-1` unless the event is a trade.
+    #[serde(rename = "t")]
+    pub trade_id: i64,
+}
+
+/// An `outboundAccountPosition` event.
+#[derive(Debug, Clone, Deserialize)]
+pub struct AccountPositionEvent {
+    #[serde(rename = "E")]
+    pub event_time: i64,
+    #[serde(rename = "B")]
+    balances: Vec<AccountBalance>,
+}
+
+impl ExecutionEvent {
+    /// The report in the engine's terms. Orders placed through
+    /// [`TradingClient::place_order`] without a client id keep the id of the
+    /// `Order` they came from; others get an id derived from Binance's order
+    /// id, which [`TradingClient::stream_user_data`] replaces with the
+    /// `Order`'s for orders it placed under a client id.
+    pub fn into_report(self) -> Result<ExecutionReport> {
+        let client_id = if self.original_client_order_id.is_empty() {
+            self.client_order_id
+        } else {
+            self.original_client_order_id
+        };
+        let order_id = Uuid::try_parse(&client_id).unwrap_or(Uuid::from_u64_pair(0, self.order_id));
+        let exec_type = parse_exec_type(&self.exec_type)
+            .with_context(|| format!("unknown execution type {}", self.exec_type))?;
+        let (last_price, last_quantity) = match exec_type {
+            ExecType::Trade => (
+                Some(decimal(&self.last_price)?),
+                Some(decimal(&self.last_quantity)?),
+            ),
+            _ => (None, None),
+        };
+        Ok(ExecutionReport {
+            order_id,
+            client_id: Some(client_id),
+            side: parse_side(&self.side).with_context(|| format!("unknown side {}", self.side))?,
+            order_type: parse_order_type(&self.order_type)
+                .with_context(|| format!("unsupported order type {}", self.order_type))?,
+            exec_type,
+            status: parse_status(&self.status)
+                .with_context(|| format!("unknown order status {}", self.status))?,
+            price: decimal(&self.price)?,
+            quantity: decimal(&self.quantity)?,
+            filled_quantity: decimal(&self.filled_quantity)?,
+            last_price,
+            last_quantity,
+            trade_id: u64::try_from(self.trade_id)
+                .ok()
+                .map(|id| Uuid::from_u64_pair(0, id)),
+            timestamp: DateTime::from_timestamp_millis(self.transaction_time)
+                .unwrap_or_else(Utc::now),
+            symbol: self.symbol,
+        })
+    }
+}
+
+/// Parses a user-data payload; `None` for events this stream does not use.
+pub fn parse_event(text: &str) -> Result<Option<UserDataEvent>> {
+    let payload: Value = serde_json::from_str(text)?;
+    match payload.get("e").and_then(Value::as_str) {
+        Some("executionReport") => {
+            let event: ExecutionEvent = serde_json::from_value(payload)?;
+            Ok(Some(UserDataEvent::Execution(event.into_report()?)))
+        }
+        Some("outboundAccountPosition") => {
+            let event: AccountPositionEvent = serde_json::from_value(payload)?;
+            Ok(Some(UserDataEvent::Account {
+                balances: event
+                    .balances
+                    .into_iter()
+                    .map(AccountBalance::into_balance)
+                    .collect(),
+                timestamp: DateTime::from_timestamp_millis(event.event_time)
+                    .unwrap_or_else(Utc::now),
+            }))
+        }
+        Some("listenKeyExpired") => Err(ListenKeyExpired.into()),
+        _ => Ok(None),
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ListenKey {
+    listen_key: String,
+}
+
+impl TradingClient {
+    /// Creates a listen key, or returns the account's active one with its
+    /// validity extended.
+    pub async fn create_listen_key(&self) -> Result<String, BinanceError> {
+        let key: ListenKey = self
+            .keyed(Method::POST, "/api/v3/userDataStream", &[])
+            .await?;
+        Ok(key.listen_key)
+    }
+
+    /// Extends a listen key's validity by an hour.
+    pub async fn keepalive_listen_key(&self, listen_key: &str) -> Result<(), BinanceError> {
+        let params = [("listenKey", listen_key.to_string())];
+        let _: Value = self
+            .keyed(Method::PUT, "/api/v3/userDataStream", &params)
+            .await?;
+        Ok(())
+    }
+
+    /// Closes a listen key, ending its stream.
+    pub async fn close_listen_key(&self, listen_key: &str) -> Result<(), BinanceError> {
+        let params = [("listenKey", listen_key.to_string())];
+        let _: Value = self
+            .keyed(Method::DELETE, "/api/v3/userDataStream", &params)
+            .await?;
+        Ok(())
+    }
+
+    /// Streams the account's execution reports and balance changes, applying
+    /// each to `risk` before passing it to `callback`. Listen keys are
+    /// created per connection and kept alive while connected. Returns only
+    /// when the reconnect policy gives up.
+    pub async fn stream_user_data<F>(&self, risk: Arc<RiskManager>, mut callback: F) -> Result<()>
+    where
+        F: FnMut(StreamEvent<UserDataEvent>) + Send + 'static,
+    {
+        let mut callback = move |event: StreamEvent<UserDataEvent>| {
+            if let StreamEvent::Data { data, .. } = &event {
+                data.apply(&risk);
+            }
+            callback(event);
+        };
+        let config = self.config();
+        // A quiet account is not an outage, so silence is only reported
+        // after as long as a listen key goes without a keepalive
+        let policy = ReconnectPolicy {
+            stale_after: config.listen_key_keepalive,
+            ..config.reconnect.clone()
+        };
+        let listen_key = &Mutex::new(String::new());
+        let endpoint = move || async move {
+            let key = self.create_listen_key().await?;
+            *listen_key.lock() = key.clone();
+            Ok(format!("{}/{}", config.ws_url, key))
+        };
+        supervise_endpoint(endpoint, &policy, &mut callback, |ws_stream, tx| {
+            let key = listen_key.lock().clone();
+            self.user_data_session(ws_stream, key, tx)
+        })
+        .await
+    }
+
+    /// Gives a report on an order placed under a caller's client id the id
+    /// of the `Order` it came from.
+    fn restore_order_id(&self, report: &mut ExecutionReport) {
+        let Some(client_id) = &report.client_id else {
+            return;
+        };
+        let done = matches!(
+            report.status,
+            OrderStatus::Filled
+                | OrderStatus::Cancelled
+                | OrderStatus::Rejected
+                | OrderStatus::Expired
+        );
+        if let Some(id) = self.client_order(client_id, done) {
+            report.order_id = id;
+        }
+    }
+
+    async fn user_data_session(
+        &self,
+        mut ws_stream: WsStream,
+        listen_key: String,
+        tx: SessionSender<UserDataEvent>,
+    ) -> Result<()> {
+        let mut keepalive = tokio::time::interval(self.config().listen_key_keepalive);
+        keepalive.tick().await;
+        loop {
+            tokio::select! {
+                text = next_text(&mut ws_stream) => {
+                    let Some(text) = text? else {
+                        return Ok(());
+                    };
+                    match parse_event(&text) {
+                        Ok(Some(mut event)) => {
+                            if let UserDataEvent::Execution(report) = &mut event {
+                                self.restore_order_id(report);
+                            }
+                            let _ = tx.send(event);
+                        }
+                        Ok(None) => {}
+                        Err(e) if e.is::<ListenKeyExpired>() => return Err(e),
+                        Err(e) => warn!("Failed to parse user-data event: {}", e),
+                    }
+                }
+                _ = keepalive.tick() => {
+                    self.keepalive_listen_key(&listen_key)
+                        .await
+                        .context("Failed to keep listen key alive")?;
+                    info!("Kept user-data listen key alive");
+                }
+            }
+        }
+    }
+}
+
+fn parse_exec_type(exec_type: &str) -> Option<ExecType> {
+    match exec_type {
+        "NEW" | "REPLACED" => Some(ExecType::New),
+        "TRADE" => Some(ExecType::Trade),
+        "CANCELED" => Some(ExecType::Cancelled),
+        "REJECTED" => Some(ExecType::Rejected),
+        // Self-trade prevention expires the order like a time limit would
+        "EXPIRED" | "TRADE_PREVENTION" => Some(ExecType::Expired),
+        _ => None,
+    }
+}
+
+fn decimal(s: &str) -> Result<Decimal> {
+    Decimal::from_str(s).with_context(|| format!("invalid decimal {}", s))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::types::{OrderStatus, Side};
+    use serde_json::json;
+
+    fn execution(exec_type: &str, status: &str, client_id: &str) -> String {
+        json!({
+            "e": "executionReport", "E": 1_700_000_000_100i64, "s": "ETHUSDT",
+            "c": client_id, "S": "SELL", "o": "LIMIT", "f": "GTC", "q": "2.00000000",
+            "p": "2000.00000000", "P": "0.00000000", "x": exec_type, "X": status,
+            "r": "NONE", "i": 4293153, "l": "0.50000000", "z": "0.50000000",
+            "L": "2001.00000000", "n": "0", "N": null, "T": 1_700_000_000_090i64,
+            "t": 87, "w": false, "m": true, "O": 1_700_000_000_000i64,
+        })
+        .to_string()
+    }
+
+    #[test]
+    fn test_parses_execution_reports_and_balances() {
+        let Some(UserDataEvent::Execution(fill)) =
+            parse_event(&execution("TRADE", "PARTIALLY_FILLED", "manual-web")).unwrap()
+        else {
+            panic!("expected an execution report");
+        };
+        // Placed outside the engine, so the id comes from Binance's
+        assert_eq!(fill.order_id, Uuid::from_u64_pair(0, 4293153));
+        assert_eq!(fill.side, Side::Sell);
+        assert_eq!(fill.status, OrderStatus::PartiallyFilled);
+        assert_eq!(fill.last_price, Some(Decimal::from(2001)));
+        assert_eq!(fill.leaves_quantity(), Decimal::from_str("1.5").unwrap());
+
+        let ours = Uuid::new_v4();
+        let Some(UserDataEvent::Execution(new)) =
+            parse_event(&execution("NEW", "NEW", &ours.simple().to_string())).unwrap()
+        else {
+            panic!("expected an execution report");
+        };
+        assert_eq!(new.order_id, ours);
+        assert_eq!((new.exec_type, new.last_price), (ExecType::New, None));
+
+        let account = json!({
+            "e": "outboundAccountPosition", "E": 1_700_000_000_200i64, "u": 1_700_000_000_199i64,
+            "B": [{"a": "ETH", "f": "8.5", "l": "1.5"}, {"a": "USDT", "f": "1001", "l": "0"}],
+        });
+        let Some(UserDataEvent::Account { balances, .. }) =
+            parse_event(&account.to_string()).unwrap()
+        else {
+            panic!("expected balances");
+        };
+        assert_eq!(balances[0].free + balances[0].locked, Decimal::from(10));
+
+        let expired = parse_event(r#"{"e":"listenKeyExpired","E":1,"listenKey":"k"}"#);
+        assert!(expired.unwrap_err().is::<ListenKeyExpired>());
+        assert!(parse_event(r#"{"e":"balanceUpdate","E":1}"#)
+            .unwrap()
+            .is_none());
+    }
+}
@@ -0,0 +1,473 @@
+//! The Coinbase Exchange WebSocket feed: `level2`, `ticker` and `matches`.
+//!
+//! A `level2` subscription opens with a `snapshot` of the whole book,
+//! followed by `l2update` messages carrying the new size of every changed
+//! level, a size of zero removing it. The feed carries no sequence numbers
+//! for level2, so a book is only trusted from its snapshot onwards and is
+//! rebuilt from the snapshot that follows every (re)subscription.
+
+use crate::connectors::market::{
+    Channel, Command, MarketEvent, Subscription, SubscriptionCommands, SymbolSilence,
+};
+use crate::utils::types::{MarketTrade, OrderBookLevel, OrderBookSnapshot, Side, Ticker, Trade};
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::str::FromStr;
+use tracing::warn;
+use uuid::Uuid;
+
+/// A message on the feed, by its `type`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum FeedMessage {
+    /// Acknowledges a subscribe or unsubscribe with the full current set.
+    Subscriptions {
+        channels: Vec<Value>,
+    },
+    Snapshot(Level2Snapshot),
+    L2update(Level2Update),
+    Ticker(TickerMessage),
+    Match(MatchMessage),
+    /// The last trade before a `matches` subscription started.
+    LastMatch(MatchMessage),
+    Heartbeat {},
+    Error {
+        message: String,
+        #[serde(default)]
+        reason: Option<String>,
+    },
+    #[serde(other)]
+    Other,
+}
+
+/// The whole book for one product.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Level2Snapshot {
+    pub product_id: String,
+    pub bids: Vec<[String; 2]>,
+    pub asks: Vec<[String; 2]>,
+    #[serde(default)]
+    pub time: Option<DateTime<Utc>>,
+}
+
+/// New sizes of changed levels as `[side, price, size]`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Level2Update {
+    pub product_id: String,
+    pub changes: Vec<[String; 3]>,
+    pub time: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct TickerMessage {
+    pub product_id: String,
+    pub price: String,
+    pub best_bid: String,
+    pub best_ask: String,
+    pub volume_24h: String,
+    pub time: DateTime<Utc>,
+}
+
+/// A trade between a resting maker order and an incoming taker order.
+#[derive(Debug, Clone, Deserialize)]
+pub struct MatchMessage {
+    pub trade_id: u64,
+    pub product_id: String,
+    pub maker_order_id: Uuid,
+    pub taker_order_id: Uuid,
+    pub price: String,
+    pub size: String,
+    /// Side of the maker order.
+    pub side: String,
+    pub time: DateTime<Utc>,
+}
+
+impl TickerMessage {
+    pub fn into_ticker(self) -> Result<Ticker> {
+        Ok(Ticker {
+            bid: decimal(&self.best_bid)?,
+            ask: decimal(&self.best_ask)?,
+            last: decimal(&self.price)?,
+            volume_24h: decimal(&self.volume_24h)?,
+            symbol: self.product_id,
+            timestamp: self.time,
+        })
+    }
+}
+
+impl MatchMessage {
+    /// The print as it appears on the public tape.
+    pub fn into_market_trade(self) -> Result<MarketTrade> {
+        Ok(MarketTrade {
+            trade_id: self.trade_id,
+            price: decimal(&self.price)?,
+            quantity: decimal(&self.size)?,
+            buyer_maker: parse_side(&self.side)? == Side::Buy,
+            symbol: self.product_id,
+            timestamp: self.time,
+        })
+    }
+
+    /// The trade between the two orders, in the engine's terms.
+    pub fn into_trade(self) -> Result<Trade> {
+        let (buy_order_id, sell_order_id) = match parse_side(&self.side)? {
+            Side::Buy => (self.maker_order_id, self.taker_order_id),
+            Side::Sell => (self.taker_order_id, self.maker_order_id),
+        };
+        Ok(Trade {
+            id: Uuid::from_u64_pair(0, self.trade_id),
+            symbol: self.product_id,
+            price: decimal(&self.price)?,
+            quantity: decimal(&self.size)?,
+            buy_order_id,
+            sell_order_id,
+            timestamp: self.time,
+        })
+    }
+}
+
+/// Full depth for one product, kept from a level2 snapshot and its updates.
+#[derive(Debug, Clone)]
+pub struct Level2Book {
+    product_id: String,
+    bids: BTreeMap<Decimal, Decimal>,
+    asks: BTreeMap<Decimal, Decimal>,
+    timestamp: DateTime<Utc>,
+}
+
+impl Level2Book {
+    pub fn from_snapshot(snapshot: &Level2Snapshot) -> Result<Self> {
+        let mut book = Self {
+            product_id: snapshot.product_id.clone(),
+            bids: BTreeMap::new(),
+            asks: BTreeMap::new(),
+            timestamp: snapshot.time.unwrap_or_else(Utc::now),
+        };
+        for [price, size] in &snapshot.bids {
+            set_level(&mut book.bids, price, size)?;
+        }
+        for [price, size] in &snapshot.asks {
+            set_level(&mut book.asks, price, size)?;
+        }
+        Ok(book)
+    }
+
+    /// Applies every change of an update, or none if any is malformed.
+    pub fn apply(&mut self, update: &Level2Update) -> Result<()> {
+        let mut next = self.clone();
+        for [side, price, size] in &update.changes {
+            let levels = match parse_side(side)? {
+                Side::Buy => &mut next.bids,
+                Side::Sell => &mut next.asks,
+            };
+            set_level(levels, price, size)?;
+        }
+        next.timestamp = update.time;
+        *self = next;
+        Ok(())
+    }
+
+    pub fn best_bid(&self) -> Option<Decimal> {
+        self.bids.keys().next_back().copied()
+    }
+
+    pub fn best_ask(&self) -> Option<Decimal> {
+        self.asks.keys().next().copied()
+    }
+
+    /// Best-first view of up to `depth` levels per side.
+    pub fn snapshot(&self, depth: usize) -> OrderBookSnapshot {
+        let level = |(&price, &quantity): (&Decimal, &Decimal)| OrderBookLevel {
+            price,
+            quantity,
+            order_count: 0,
+        };
+        OrderBookSnapshot {
+            symbol: self.product_id.clone(),
+            bids: self.bids.iter().rev().take(depth).map(level).collect(),
+            asks: self.asks.iter().take(depth).map(level).collect(),
+            timestamp: self.timestamp,
+        }
+    }
+}
+
+fn set_level(levels: &mut BTreeMap<Decimal, Decimal>, price: &str, size: &str) -> Result<()> {
+    let price = decimal(price)?;
+    let size = decimal(size)?;
+    if size.is_zero() {
+        levels.remove(&price);
+    } else {
+        levels.insert(price, size);
+    }
+    Ok(())
+}
+
+/// Feed channel carrying a subscription's channel, if Coinbase has one.
+/// Level2 requires an authenticated subscription; `level2_batch` carries
+/// the same messages in 50ms batches to anyone.
+pub(super) fn channel_name(channel: Channel, authenticated: bool) -> Option<&'static str> {
+    match channel {
+        Channel::Depth if authenticated => Some("level2"),
+        Channel::Depth => Some("level2_batch"),
+        Channel::Ticker => Some("ticker"),
+        Channel::Trade => Some("matches"),
+        Channel::AggTrade | Channel::Kline(_) => None,
+    }
+}
+
+/// Subscriptions that outlive connections, plus the books derived from
+/// them on the current connection.
+#[derive(Debug)]
+pub(super) struct FeedState {
+    pub(super) subscriptions: BTreeSet<Subscription>,
+    pub(super) commands: Option<SubscriptionCommands>,
+    authenticated: bool,
+    /// Books of depth subscriptions whose snapshot has arrived.
+    books: HashMap<String, Level2Book>,
+    pub(super) silence: SymbolSilence,
+}
+
+impl FeedState {
+    pub(super) fn new(
+        subscriptions: &[Subscription],
+        commands: SubscriptionCommands,
+        authenticated: bool,
+    ) -> Self {
+        Self {
+            subscriptions: subscriptions.iter().cloned().collect(),
+            commands: Some(commands),
+            authenticated,
+            books: HashMap::new(),
+            silence: SymbolSilence::default(),
+        }
+    }
+
+    /// Resets per-connection state and returns the request subscribing to
+    /// everything, if there is anything to subscribe to.
+    pub(super) fn reconnected(&mut self) -> Option<Value> {
+        self.books.clear();
+        self.silence.clear();
+        self.silence.track(&self.subscriptions);
+        let all: Vec<_> = self.subscriptions.iter().cloned().collect();
+        self.request("subscribe", &all)
+    }
+
+    /// Applies a command and returns the request to send for it.
+    pub(super) fn apply(&mut self, command: Command) -> Option<Value> {
+        let request = self.change(command);
+        self.silence.track(&self.subscriptions);
+        request
+    }
+
+    fn change(&mut self, command: Command) -> Option<Value> {
+        match command {
+            Command::Subscribe(subscriptions) => {
+                let added: Vec<_> = subscriptions
+                    .into_iter()
+                    .filter(|s| self.subscriptions.insert(s.clone()))
+                    .collect();
+                self.request("subscribe", &added)
+            }
+            Command::Unsubscribe(subscriptions) => {
+                let removed: Vec<_> = subscriptions
+                    .into_iter()
+                    .filter(|s| self.subscriptions.remove(s))
+                    .collect();
+                for subscription in &removed {
+                    if subscription.channel == Channel::Depth {
+                        self.books.remove(&subscription.symbol);
+                    }
+                }
+                self.request("unsubscribe", &removed)
+            }
+        }
+    }
+
+    /// A request naming each feed channel with its products.
+    fn request(&self, kind: &str, subscriptions: &[Subscription]) -> Option<Value> {
+        let mut channels: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+        for subscription in subscriptions {
+            match channel_name(subscription.channel, self.authenticated) {
+                Some(name) => channels.entry(name).or_default().push(&subscription.symbol),
+                None => warn!(
+                    "Coinbase has no {:?} channel for {}",
+                    subscription.channel, subscription.symbol
+                ),
+            }
+        }
+        if channels.is_empty() {
+            return None;
+        }
+        let channels: Vec<_> = channels
+            .into_iter()
+            .map(|(name, product_ids)| json!({"name": name, "product_ids": product_ids}))
+            .collect();
+        Some(json!({"type": kind, "channels": channels}))
+    }
+
+    fn subscribed(&self, product_id: &str, channel: Channel) -> bool {
+        self.subscriptions
+            .contains(&Subscription::new(product_id, channel))
+    }
+
+    /// Routes a feed message; the returned event is whatever should be
+    /// emitted. Messages for subscriptions since dropped are ignored.
+    pub(super) fn route(&mut self, message: FeedMessage) -> Result<Option<MarketEvent>> {
+        match message {
+            FeedMessage::Snapshot(snapshot) => {
+                if !self.subscribed(&snapshot.product_id, Channel::Depth) {
+                    return Ok(None);
+                }
+                self.silence.seen(&snapshot.product_id);
+                let book = Level2Book::from_snapshot(&snapshot)?;
+                let event = MarketEvent::Book(book.snapshot(usize::MAX));
+                self.books.insert(snapshot.product_id, book);
+                Ok(Some(event))
+            }
+            FeedMessage::L2update(update) => {
+                let Some(book) = self.books.get_mut(&update.product_id) else {
+                    return Ok(None);
+                };
+                self.silence.seen(&update.product_id);
+                if let Err(e) = book.apply(&update) {
+                    // Without sequence numbers the book cannot be repaired
+                    // until the next snapshot
+                    self.books.remove(&update.product_id);
+                    return Err(e);
+                }
+                Ok(Some(MarketEvent::Book(book.snapshot(usize::MAX))))
+            }
+            FeedMessage::Ticker(ticker) => {
+                if !self.subscribed(&ticker.product_id, Channel::Ticker) {
+                    return Ok(None);
+                }
+                self.silence.seen(&ticker.product_id);
+                Ok(Some(MarketEvent::Ticker(ticker.into_ticker()?)))
+            }
+            FeedMessage::Match(trade) => {
+                if !self.subscribed(&trade.product_id, Channel::Trade) {
+                    return Ok(None);
+                }
+                self.silence.seen(&trade.product_id);
+                Ok(Some(MarketEvent::Trade(trade.into_market_trade()?)))
+            }
+            FeedMessage::Error { message, reason } => {
+                warn!(
+                    "Coinbase feed error: {} ({})",
+                    message,
+                    reason.as_deref().unwrap_or("no reason given")
+                );
+                Ok(None)
+            }
+            FeedMessage::Subscriptions { .. }
+            | FeedMessage::LastMatch(_)
+            | FeedMessage::Heartbeat {}
+            | FeedMessage::Other => Ok(None),
+        }
+    }
+
+    /// Whether a product's book is maintained, i.e. its snapshot arrived
+    /// and no update has failed since.
+    #[cfg(test)]
+    fn has_book(&self, product_id: &str) -> bool {
+        self.books.contains_key(product_id)
+    }
+}
+
+pub(super) fn parse_side(side: &str) -> Result<Side> {
+    match side {
+        "buy" => Ok(Side::Buy),
+        "sell" => Ok(Side::Sell),
+        _ => anyhow::bail!("unknown side {}", side),
+    }
+}
+
+fn decimal(s: &str) -> Result<Decimal> {
+    Decimal::from_str(s).with_context(|| format!("invalid decimal {}", s))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connectors::market::subscription_channel;
+
+    fn message(value: Value) -> FeedMessage {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn test_maintains_books_from_snapshots_and_updates() {
+        let (_handle, commands) = subscription_channel();
+        let mut state = FeedState::new(&[Subscription::depth("BTC-USD")], commands, false);
+        assert_eq!(
+            state.reconnected().unwrap(),
+            json!({"type": "subscribe", "channels": [
+                {"name": "level2_batch", "product_ids": ["BTC-USD"]},
+            ]})
+        );
+
+        // Updates before the snapshot have no book to apply to
+        let update = |changes: Value| {
+            message(json!({
+                "type": "l2update", "product_id": "BTC-USD",
+                "time": "2024-05-01T12:00:01.000000Z", "changes": changes,
+            }))
+        };
+        assert!(state
+            .route(update(json!([["buy", "100", "1"]])))
+            .unwrap()
+            .is_none());
+
+        let snapshot = message(json!({
+            "type": "snapshot", "product_id": "BTC-USD",
+            "bids": [["100.00", "1.5"], ["99.50", "2"]], "asks": [["100.50", "3"]],
+        }));
+        assert!(state.route(snapshot).unwrap().is_some());
+        let Some(MarketEvent::Book(book)) = state
+            .route(update(json!([
+                ["buy", "100.00", "0"],
+                ["sell", "100.25", "0.4"]
+            ])))
+            .unwrap()
+        else {
+            panic!("expected a book");
+        };
+        assert_eq!(book.bids[0].price, "99.5".parse::<Decimal>().unwrap());
+        assert_eq!(book.asks[0].price, "100.25".parse::<Decimal>().unwrap());
+        assert_eq!(book.asks.len(), 2);
+
+        // A malformed update drops the book until the next snapshot
+        assert!(state.route(update(json!([["buy", "oops", "1"]]))).is_err());
+        assert!(!state.has_book("BTC-USD"));
+    }
+
+    #[test]
+    fn test_matches_name_the_buyer_and_seller() {
+        let FeedMessage::Match(trade) = message(json!({
+            "type": "match", "trade_id": 10, "sequence": 50,
+            "maker_order_id": "ac928c66-ca53-498f-9c13-a110027a60e8",
+            "taker_order_id": "132fb6ae-456b-4654-b4e0-d681ac05cea1",
+            "time": "2014-11-07T08:19:27.028459Z", "product_id": "BTC-USD",
+            "size": "5.23512", "price": "400.23", "side": "sell",
+        })) else {
+            panic!("expected a match");
+        };
+        let print = trade.clone().into_market_trade().unwrap();
+        // The maker sold, so the taker bought
+        assert_eq!(print.aggressor(), Side::Buy);
+        let trade = trade.into_trade().unwrap();
+        assert_eq!(
+            trade.sell_order_id.to_string(),
+            "ac928c66-ca53-498f-9c13-a110027a60e8"
+        );
+        assert_eq!(
+            trade.buy_order_id.to_string(),
+            "132fb6ae-456b-4654-b4e0-d681ac05cea1"
+        );
+        assert_eq!(trade.price, "400.23".parse::<Decimal>().unwrap());
+    }
+}
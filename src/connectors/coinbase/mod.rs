@@ -0,0 +1,241 @@
+//! Coinbase Exchange: the WebSocket feed for books, tickers and matches,
+//! and signed REST order entry.
+
+pub mod feed;
+pub mod trading;
+
+use super::exchange::{
+    check_subscriptions, Balance, Capabilities, ExchangeConnector, MarketDataCallback, OrderRef,
+    VenueOrder,
+};
+use super::market::{next_command, wait_until, MarketEvent, Subscription, SubscriptionCommands};
+use super::reconnect::{
+    next_text, supervise, ReconnectPolicy, SessionSender, StreamEvent, WsStream,
+};
+use crate::utils::types::Order;
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use chrono::Utc;
+use feed::{FeedMessage, FeedState};
+use futures::SinkExt;
+use serde_json::Value;
+use tracing::error;
+use trading::{Credentials, TradingClient};
+
+/// Endpoints for [`CoinbaseConnector`].
+#[derive(Debug, Clone)]
+pub struct CoinbaseConfig {
+    /// WebSocket feed; every channel shares one connection.
+    pub ws_url: String,
+    /// REST API base, e.g. the sandbox or a local stand-in.
+    pub rest_url: String,
+    /// Reconnection of a feed that drops.
+    pub reconnect: ReconnectPolicy,
+}
+
+impl CoinbaseConfig {
+    pub fn new() -> Self {
+        Self {
+            ws_url: "wss://ws-feed.exchange.coinbase.com".to_string(),
+            rest_url: "https://api.exchange.coinbase.com".to_string(),
+            reconnect: ReconnectPolicy::new(),
+        }
+    }
+}
+
+impl Default for CoinbaseConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub struct CoinbaseConnector {
+    config: CoinbaseConfig,
+    /// Order entry and account queries, and authenticated feed
+    /// subscriptions, given credentials.
+    trading: Option<TradingClient>,
+}
+
+impl CoinbaseConnector {
+    pub fn new() -> Self {
+        Self::with_config(CoinbaseConfig::new())
+    }
+
+    pub fn with_config(config: CoinbaseConfig) -> Self {
+        Self {
+            config,
+            trading: None,
+        }
+    }
+
+    /// A connector that can also trade on the account of `credentials`.
+    pub fn with_credentials(config: CoinbaseConfig, credentials: Credentials) -> Self {
+        Self {
+            trading: Some(TradingClient::new(&config, credentials)),
+            ..Self::with_config(config)
+        }
+    }
+
+    fn trading(&self) -> Result<&TradingClient> {
+        self.trading
+            .as_ref()
+            .context("coinbase connector has no API credentials")
+    }
+
+    /// Streams every subscription over one feed connection, resubscribing
+    /// and rebuilding books from fresh snapshots after every reconnect.
+    /// Books come from `level2` when the connector has credentials and from
+    /// the public `level2_batch` otherwise.
+    pub async fn stream_feed<F>(
+        &self,
+        subscriptions: &[Subscription],
+        commands: SubscriptionCommands,
+        mut callback: F,
+    ) -> Result<()>
+    where
+        F: FnMut(StreamEvent<MarketEvent>) + Send + 'static,
+    {
+        let state = tokio::sync::Mutex::new(FeedState::new(
+            subscriptions,
+            commands,
+            self.trading.is_some(),
+        ));
+        supervise(
+            &self.config.ws_url,
+            &self.config.reconnect,
+            &mut callback,
+            |ws_stream, tx| self.feed_session(ws_stream, &state, tx),
+        )
+        .await
+    }
+
+    async fn feed_session(
+        &self,
+        mut ws_stream: WsStream,
+        state: &tokio::sync::Mutex<FeedState>,
+        tx: SessionSender<MarketEvent>,
+    ) -> Result<()> {
+        let mut state = state.lock().await;
+        let stale_after = self.config.reconnect.stale_after;
+        if let Some(request) = state.reconnected() {
+            self.send_request(&mut ws_stream, request).await?;
+        }
+
+        loop {
+            let quiet = state.silence.deadline(stale_after);
+            tokio::select! {
+                text = next_text(&mut ws_stream) => {
+                    let Some(text) = text? else {
+                        return Ok(());
+                    };
+                    match serde_json::from_str::<FeedMessage>(&text)
+                        .map_err(anyhow::Error::from)
+                        .and_then(|message| state.route(message))
+                    {
+                        Ok(Some(event)) => {
+                            let _ = tx.send(event);
+                        }
+                        Ok(None) => {}
+                        Err(e) => error!("Failed to handle Coinbase feed message: {}", e),
+                    }
+                }
+                command = next_command(&mut state.commands) => match command {
+                    Some(command) => {
+                        if let Some(request) = state.apply(command) {
+                            self.send_request(&mut ws_stream, request).await?;
+                        }
+                    }
+                    // Every handle is gone; keep streaming the current set
+                    None => state.commands = None,
+                },
+                _ = wait_until(quiet) => {
+                    for (symbol, silent_for) in state.silence.silent(stale_after) {
+                        tx.stale(&symbol, silent_for);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Sends a subscribe or unsubscribe request, signed like a REST request
+    /// to `/users/self/verify` when there are credentials.
+    async fn send_request(&self, ws_stream: &mut WsStream, mut request: Value) -> Result<()> {
+        if let Some(trading) = &self.trading {
+            let credentials = trading.credentials();
+            let timestamp = Utc::now().timestamp().to_string();
+            request["signature"] = credentials
+                .sign(&timestamp, "GET", "/users/self/verify", "")
+                .into();
+            request["key"] = credentials.api_key.clone().into();
+            request["passphrase"] = credentials.passphrase.clone().into();
+            request["timestamp"] = timestamp.into();
+        }
+        ws_stream
+            .send(tungstenite::Message::Text(request.to_string()))
+            .await
+            .context("Failed to send Coinbase subscription request")
+    }
+}
+
+impl Default for CoinbaseConnector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl ExchangeConnector for CoinbaseConnector {
+    fn name(&self) -> &'static str {
+        "coinbase"
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            order_book: true,
+            ticker: true,
+            trades: true,
+            agg_trades: false,
+            klines: false,
+            live_subscriptions: true,
+            order_entry: self.trading.is_some(),
+            account: self.trading.is_some(),
+        }
+    }
+
+    async fn stream_market_data(
+        &self,
+        subscriptions: &[Subscription],
+        commands: SubscriptionCommands,
+        callback: MarketDataCallback,
+    ) -> Result<()> {
+        check_subscriptions(self, subscriptions)?;
+        self.stream_feed(subscriptions, commands, callback).await
+    }
+
+    async fn place_order(&self, order: &Order) -> Result<VenueOrder> {
+        let placed = self.trading()?.place_order(order).await?;
+        Ok(placed.into_venue_order())
+    }
+
+    async fn cancel_order(&self, _symbol: &str, order: &OrderRef) -> Result<VenueOrder> {
+        let cancelled = self.trading()?.cancel_order(order).await?;
+        Ok(cancelled.into_venue_order())
+    }
+
+    async fn query_order(&self, _symbol: &str, order: &OrderRef) -> Result<VenueOrder> {
+        let queried = self.trading()?.query_order(order).await?;
+        Ok(queried.into_venue_order())
+    }
+
+    async fn open_orders(&self, symbol: Option<&str>) -> Result<Vec<VenueOrder>> {
+        let orders = self.trading()?.open_orders(symbol).await?;
+        Ok(orders
+            .into_iter()
+            .map(trading::ExchangeOrder::into_venue_order)
+            .collect())
+    }
+
+    async fn balances(&self) -> Result<Vec<Balance>> {
+        Ok(self.trading()?.balances().await?)
+    }
+}
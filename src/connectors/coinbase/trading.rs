@@ -0,0 +1,470 @@
+//! Signed REST client for Coinbase Exchange orders and accounts.
+//!
+//! Every request carries the API key, the passphrase chosen when the key
+//! was created, a timestamp in seconds and a base64 HMAC-SHA256 of
+//! `timestamp + method + path + body` under the base64-decoded secret.
+
+use super::feed::parse_side;
+use super::CoinbaseConfig;
+use crate::connectors::exchange::{Balance, OrderRef, VenueOrder};
+use crate::utils::types::{Order, OrderStatus, OrderType, Side};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use reqwest::{Method, Response, StatusCode};
+use rust_decimal::Decimal;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Deserializer};
+use serde_json::{json, Value};
+use sha2::Sha256;
+use std::str::FromStr;
+use std::time::Duration;
+use thiserror::Error;
+use uuid::Uuid;
+
+/// API key, secret and passphrase for signed endpoints.
+#[derive(Clone)]
+pub struct Credentials {
+    pub api_key: String,
+    secret: Vec<u8>,
+    pub passphrase: String,
+}
+
+impl Credentials {
+    /// `secret` is the base64 string Coinbase shows when the key is created.
+    pub fn new(api_key: &str, secret: &str, passphrase: &str) -> Result<Self, CoinbaseError> {
+        let secret = BASE64
+            .decode(secret)
+            .map_err(|e| CoinbaseError::InvalidSecret(e.to_string()))?;
+        Ok(Self {
+            api_key: api_key.to_string(),
+            secret,
+            passphrase: passphrase.to_string(),
+        })
+    }
+
+    /// Base64 HMAC-SHA256 of the request under the secret.
+    pub fn sign(&self, timestamp: &str, method: &str, path: &str, body: &str) -> String {
+        let mut mac =
+            Hmac::<Sha256>::new_from_slice(&self.secret).expect("HMAC accepts keys of any length");
+        mac.update(timestamp.as_bytes());
+        mac.update(method.as_bytes());
+        mac.update(path.as_bytes());
+        mac.update(body.as_bytes());
+        BASE64.encode(mac.finalize().into_bytes())
+    }
+}
+
+impl std::fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("Credentials")
+            .field("api_key", &self.api_key)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Failures of a REST request. Coinbase reports errors as an HTTP status
+/// with a `{"message": ..}` body.
+#[derive(Debug, Error)]
+pub enum CoinbaseError {
+    #[error("invalid api secret: {0}")]
+    InvalidSecret(String),
+    /// HTTP 400, e.g. insufficient funds or an invalid size or price.
+    #[error("bad request: {0}")]
+    BadRequest(String),
+    /// HTTP 401 and 403: bad key, signature, passphrase or permissions.
+    #[error("unauthorized: {0}")]
+    Unauthorized(String),
+    /// HTTP 404, including orders cancelled before any fill.
+    #[error("not found: {0}")]
+    NotFound(String),
+    /// HTTP 429.
+    #[error("rate limited, retry after {retry_after:?}")]
+    RateLimited { retry_after: Option<Duration> },
+    #[error("http {status}: {body}")]
+    Http { status: u16, body: String },
+    #[error("request failed: {0}")]
+    Transport(#[from] reqwest::Error),
+    #[error("unexpected response: {0}")]
+    Decode(String),
+    /// Refused locally, before anything is sent.
+    #[error("invalid order: {0}")]
+    InvalidOrder(String),
+}
+
+/// An order as Coinbase reports it.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ExchangeOrder {
+    pub id: String,
+    #[serde(default)]
+    pub client_oid: Option<String>,
+    pub product_id: String,
+    #[serde(deserialize_with = "de_side")]
+    pub side: Side,
+    /// `limit` or `market`; stops carry a `stop` direction as well.
+    #[serde(rename = "type")]
+    pub order_type: String,
+    #[serde(default)]
+    pub stop: Option<String>,
+    /// Absent for market orders.
+    #[serde(default, deserialize_with = "de_opt_decimal")]
+    pub price: Option<Decimal>,
+    /// Absent for market orders sized in quote currency.
+    #[serde(default, deserialize_with = "de_opt_decimal")]
+    pub size: Option<Decimal>,
+    #[serde(deserialize_with = "de_decimal")]
+    pub filled_size: Decimal,
+    /// `pending`, `open`, `active` or `done`.
+    pub status: String,
+    /// Why a `done` order is done: `filled`, `canceled` or `rejected`.
+    #[serde(default)]
+    pub done_reason: Option<String>,
+}
+
+impl ExchangeOrder {
+    pub fn order_status(&self) -> OrderStatus {
+        match (self.status.as_str(), self.done_reason.as_deref()) {
+            ("pending" | "received", _) => OrderStatus::Pending,
+            ("done", Some("filled")) => OrderStatus::Filled,
+            ("done", Some("rejected")) | ("rejected", _) => OrderStatus::Rejected,
+            ("done", _) => OrderStatus::Cancelled,
+            _ if !self.filled_size.is_zero() => OrderStatus::PartiallyFilled,
+            _ => OrderStatus::Open,
+        }
+    }
+
+    pub fn into_venue_order(self) -> VenueOrder {
+        let order_type = match (self.order_type.as_str(), self.stop.is_some()) {
+            ("limit", false) => Some(OrderType::Limit),
+            ("market", false) => Some(OrderType::Market),
+            ("limit", true) => Some(OrderType::StopLimit),
+            ("market", true) => Some(OrderType::StopMarket),
+            _ => None,
+        };
+        VenueOrder {
+            status: self.order_status(),
+            order_type,
+            symbol: self.product_id,
+            order_id: self.id,
+            client_id: self.client_oid,
+            side: self.side,
+            price: self.price.unwrap_or_default(),
+            quantity: self.size.unwrap_or_default(),
+            filled_quantity: self.filled_size,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct Account {
+    currency: String,
+    #[serde(deserialize_with = "de_decimal")]
+    available: Decimal,
+    #[serde(deserialize_with = "de_decimal")]
+    hold: Decimal,
+}
+
+#[derive(Deserialize)]
+struct ErrorBody {
+    message: String,
+}
+
+/// Signed order entry and account queries over REST.
+pub struct TradingClient {
+    http: reqwest::Client,
+    config: CoinbaseConfig,
+    credentials: Credentials,
+}
+
+impl TradingClient {
+    pub fn new(config: &CoinbaseConfig, credentials: Credentials) -> Self {
+        Self {
+            // Coinbase rejects requests without a user agent
+            http: reqwest::Client::builder()
+                .user_agent(concat!("quantumflow/", env!("CARGO_PKG_VERSION")))
+                .build()
+                .expect("default TLS backend is available"),
+            config: config.clone(),
+            credentials,
+        }
+    }
+
+    /// Places `order` as good-till-cancelled where it rests. Coinbase needs
+    /// a UUID `client_oid`, so `client_id` is sent only when it is one and
+    /// the order's own id otherwise. Pegged and trailing orders are refused,
+    /// as are expiry times, since Coinbase's good-till-time orders expire
+    /// only after a minute, an hour or a day.
+    pub async fn place_order(&self, order: &Order) -> Result<ExchangeOrder, CoinbaseError> {
+        let unsupported = if order.peg.is_some() {
+            Some("pegged orders")
+        } else if order.trailing.is_some() {
+            Some("trailing stops")
+        } else if order.expires_at.is_some() {
+            Some("orders with an expiry time")
+        } else {
+            None
+        };
+        if let Some(unsupported) = unsupported {
+            return Err(CoinbaseError::InvalidOrder(format!(
+                "coinbase does not support {}",
+                unsupported
+            )));
+        }
+        let client_oid = order
+            .client_id
+            .as_deref()
+            .and_then(|id| Uuid::try_parse(id).ok())
+            .unwrap_or(order.id);
+        let mut body = json!({
+            "client_oid": client_oid.to_string(),
+            "product_id": order.symbol.to_uppercase(),
+            "side": side_name(order.side),
+            "size": order.quantity.normalize().to_string(),
+        });
+        if matches!(order.order_type, OrderType::Limit | OrderType::StopLimit) {
+            body["type"] = json!("limit");
+            body["price"] = json!(order.price.normalize().to_string());
+            body["time_in_force"] = json!("GTC");
+        } else {
+            body["type"] = json!("market");
+        }
+        if matches!(
+            order.order_type,
+            OrderType::StopLimit | OrderType::StopMarket
+        ) {
+            let stop_price = order.stop_price.ok_or_else(|| {
+                CoinbaseError::InvalidOrder("stop order without a stop price".to_string())
+            })?;
+            // Sell stops trigger as the price falls, buy stops as it rises
+            body["stop"] = json!(match order.side {
+                Side::Sell => "loss",
+                Side::Buy => "entry",
+            });
+            body["stop_price"] = json!(stop_price.normalize().to_string());
+        }
+        self.signed(Method::POST, "/orders", Some(&body)).await
+    }
+
+    /// Cancels an order and reports it as it stood when cancelled. Coinbase
+    /// answers a cancel with the order id alone, so the order is read again
+    /// afterwards for any fills made meanwhile. It forgets orders cancelled
+    /// before any fill, so it is also read first.
+    pub async fn cancel_order(&self, order: &OrderRef) -> Result<ExchangeOrder, CoinbaseError> {
+        let before = self.query_order(order).await?;
+        let _: Value = self
+            .signed(Method::DELETE, &order_path(order), None)
+            .await?;
+        let mut current = match self.query_order(order).await {
+            Ok(current) => current,
+            Err(CoinbaseError::NotFound(_)) => before,
+            Err(e) => return Err(e),
+        };
+        // The cancel may not have settled by the time it is read
+        if current.status != "done" {
+            current.status = "done".to_string();
+            current.done_reason = Some("canceled".to_string());
+        }
+        Ok(current)
+    }
+
+    pub async fn query_order(&self, order: &OrderRef) -> Result<ExchangeOrder, CoinbaseError> {
+        self.signed(Method::GET, &order_path(order), None).await
+    }
+
+    /// Orders still working for `product_id`, or for every product when
+    /// `None`, following the pages Coinbase splits them into.
+    pub async fn open_orders(
+        &self,
+        product_id: Option<&str>,
+    ) -> Result<Vec<ExchangeOrder>, CoinbaseError> {
+        // The serializer is not `Send`, so it must not live across a request
+        let query = {
+            let mut query = url::form_urlencoded::Serializer::new(String::new());
+            query
+                .extend_pairs([
+                    ("status", "open"),
+                    ("status", "pending"),
+                    ("status", "active"),
+                ])
+                .append_pair("limit", "1000");
+            if let Some(product_id) = product_id {
+                query.append_pair("product_id", &product_id.to_uppercase());
+            }
+            query.finish()
+        };
+        let mut orders = Vec::new();
+        let mut after: Option<String> = None;
+        loop {
+            let path = match &after {
+                Some(cursor) => format!("/orders?{}&after={}", query, cursor),
+                None => format!("/orders?{}", query),
+            };
+            let response = self.send(Method::GET, &path, None).await?;
+            let next = response
+                .headers()
+                .get("CB-AFTER")
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string);
+            let page: Vec<ExchangeOrder> = parse_response(response).await?;
+            let done = page.is_empty() || next.is_none() || next == after;
+            orders.extend(page);
+            if done {
+                return Ok(orders);
+            }
+            after = next;
+        }
+    }
+
+    /// Currencies with a non-zero available or held amount.
+    pub async fn balances(&self) -> Result<Vec<Balance>, CoinbaseError> {
+        let accounts: Vec<Account> = self.signed(Method::GET, "/accounts", None).await?;
+        Ok(accounts
+            .into_iter()
+            .filter(|account| !account.available.is_zero() || !account.hold.is_zero())
+            .map(|account| Balance {
+                asset: account.currency,
+                free: account.available,
+                locked: account.hold,
+            })
+            .collect())
+    }
+
+    pub(super) fn credentials(&self) -> &Credentials {
+        &self.credentials
+    }
+
+    async fn signed<T: DeserializeOwned>(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<&Value>,
+    ) -> Result<T, CoinbaseError> {
+        let response = self.send(method, path, body).await?;
+        parse_response(response).await
+    }
+
+    async fn send(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<&Value>,
+    ) -> Result<Response, CoinbaseError> {
+        let timestamp = Utc::now().timestamp().to_string();
+        let body = body.map(Value::to_string).unwrap_or_default();
+        let signature = self
+            .credentials
+            .sign(&timestamp, method.as_str(), path, &body);
+        let mut request = self
+            .http
+            .request(method, format!("{}{}", self.config.rest_url, path))
+            .header("CB-ACCESS-KEY", &self.credentials.api_key)
+            .header("CB-ACCESS-SIGN", signature)
+            .header("CB-ACCESS-TIMESTAMP", timestamp)
+            .header("CB-ACCESS-PASSPHRASE", &self.credentials.passphrase);
+        if !body.is_empty() {
+            request = request
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .body(body);
+        }
+        Ok(request.send().await?)
+    }
+}
+
+async fn parse_response<T: DeserializeOwned>(response: Response) -> Result<T, CoinbaseError> {
+    let status = response.status();
+    if status == StatusCode::TOO_MANY_REQUESTS {
+        let retry_after = response
+            .headers()
+            .get(reqwest::header::RETRY_AFTER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse().ok())
+            .map(Duration::from_secs);
+        return Err(CoinbaseError::RateLimited { retry_after });
+    }
+    let body = response.text().await?;
+    if status.is_success() {
+        return serde_json::from_str(&body).map_err(|e| CoinbaseError::Decode(e.to_string()));
+    }
+    let message = serde_json::from_str::<ErrorBody>(&body)
+        .map(|error| error.message)
+        .unwrap_or_else(|_| body.clone());
+    match status {
+        StatusCode::BAD_REQUEST => Err(CoinbaseError::BadRequest(message)),
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => {
+            Err(CoinbaseError::Unauthorized(message))
+        }
+        StatusCode::NOT_FOUND => Err(CoinbaseError::NotFound(message)),
+        _ => Err(CoinbaseError::Http {
+            status: status.as_u16(),
+            body,
+        }),
+    }
+}
+
+fn order_path(order: &OrderRef) -> String {
+    match order {
+        OrderRef::Id(id) => format!("/orders/{}", id),
+        OrderRef::ClientId(id) => format!("/orders/client:{}", id),
+    }
+}
+
+fn side_name(side: Side) -> &'static str {
+    match side {
+        Side::Buy => "buy",
+        Side::Sell => "sell",
+    }
+}
+
+/// Coinbase sends prices and sizes as strings.
+fn de_decimal<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Decimal, D::Error> {
+    let s = String::deserialize(deserializer)?;
+    Decimal::from_str(&s).map_err(serde::de::Error::custom)
+}
+
+fn de_opt_decimal<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Decimal>, D::Error> {
+    Option::<String>::deserialize(deserializer)?
+        .map(|s| Decimal::from_str(&s).map_err(serde::de::Error::custom))
+        .transpose()
+}
+
+fn de_side<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Side, D::Error> {
+    let s = String::deserialize(deserializer)?;
+    parse_side(&s).map_err(serde::de::Error::custom)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_signs_timestamp_method_path_and_body() {
+        let credentials = Credentials::new("key", &BASE64.encode(b"secret"), "pass").unwrap();
+        let body = r#"{"size":"1"}"#;
+        let signature = credentials.sign("1700000000", "POST", "/orders", body);
+
+        let mut mac = Hmac::<Sha256>::new_from_slice(b"secret").unwrap();
+        mac.update(br#"1700000000POST/orders{"size":"1"}"#);
+        assert_eq!(signature, BASE64.encode(mac.finalize().into_bytes()));
+        assert!(Credentials::new("key", "not base64!", "pass").is_err());
+        assert!(!format!("{:?}", credentials).contains("pass"));
+    }
+
+    #[test]
+    fn test_maps_order_states() {
+        let order = |status: &str, done_reason: Option<&str>, filled: &str| {
+            serde_json::from_value::<ExchangeOrder>(json!({
+                "id": "d0c5340b-6d6c-49d9-b567-48c4bfca13d2", "product_id": "BTC-USD",
+                "side": "buy", "type": "limit", "price": "0.10000000", "size": "0.01000000",
+                "filled_size": filled, "status": status, "done_reason": done_reason,
+            }))
+            .unwrap()
+            .order_status()
+        };
+        assert_eq!(order("pending", None, "0"), OrderStatus::Pending);
+        assert_eq!(order("open", None, "0"), OrderStatus::Open);
+        assert_eq!(order("open", None, "0.005"), OrderStatus::PartiallyFilled);
+        assert_eq!(order("done", Some("filled"), "0.01"), OrderStatus::Filled);
+        assert_eq!(order("done", Some("canceled"), "0"), OrderStatus::Cancelled);
+    }
+}
@@ -0,0 +1,154 @@
+//! Venue-neutral connector interface, so strategies and the CLI can run
+//! against any exchange the crate connects to.
+
+use super::market::{Channel, MarketEvent, Subscription, SubscriptionCommands};
+use super::reconnect::StreamEvent;
+use crate::utils::types::{Order, OrderStatus, OrderType, Side};
+use anyhow::Result;
+use async_trait::async_trait;
+use rust_decimal::Decimal;
+
+/// Receives every event of a market data stream.
+pub type MarketDataCallback = Box<dyn FnMut(StreamEvent<MarketEvent>) + Send>;
+
+/// What a connector supports. Calls outside its capabilities fail.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Capabilities {
+    pub order_book: bool,
+    pub ticker: bool,
+    pub trades: bool,
+    /// Trades merged per taker order and price.
+    pub agg_trades: bool,
+    pub klines: bool,
+    /// Subscriptions can change while the stream runs.
+    pub live_subscriptions: bool,
+    /// Placing, cancelling and querying orders.
+    pub order_entry: bool,
+    /// Balances of the account.
+    pub account: bool,
+}
+
+impl Capabilities {
+    pub fn supports(&self, channel: Channel) -> bool {
+        match channel {
+            Channel::Depth => self.order_book,
+            Channel::Ticker => self.ticker,
+            Channel::Trade => self.trades,
+            Channel::AggTrade => self.agg_trades,
+            Channel::Kline(_) => self.klines,
+        }
+    }
+}
+
+/// Identifies an order on a venue.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OrderRef {
+    /// The id the venue assigned.
+    Id(String),
+    /// The id we assigned when placing it.
+    ClientId(String),
+}
+
+/// An order as the venue reports it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VenueOrder {
+    pub symbol: String,
+    /// The id the venue assigned.
+    pub order_id: String,
+    pub client_id: Option<String>,
+    pub side: Side,
+    /// `None` for order types the engine does not model.
+    pub order_type: Option<OrderType>,
+    pub price: Decimal,
+    pub quantity: Decimal,
+    pub filled_quantity: Decimal,
+    pub status: OrderStatus,
+}
+
+/// Holdings of one asset.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Balance {
+    pub asset: String,
+    pub free: Decimal,
+    /// Held by open orders.
+    pub locked: Decimal,
+}
+
+impl Balance {
+    pub fn total(&self) -> Decimal {
+        self.free + self.locked
+    }
+}
+
+/// A venue's market data, order entry and account.
+#[async_trait]
+pub trait ExchangeConnector: Send + Sync {
+    /// Lower-case venue name, e.g. `binance`.
+    fn name(&self) -> &'static str;
+
+    fn capabilities(&self) -> Capabilities;
+
+    /// Streams every subscription, reconnecting under the connector's
+    /// policy, until the policy gives up. Subscriptions change through the
+    /// handle paired with `commands` where the venue supports it. Fails at
+    /// once, per [`check_subscriptions`], on a channel the connector does
+    /// not stream; one subscribed later through the handle is dropped with
+    /// a warning.
+    async fn stream_market_data(
+        &self,
+        subscriptions: &[Subscription],
+        commands: SubscriptionCommands,
+        callback: MarketDataCallback,
+    ) -> Result<()>;
+
+    async fn place_order(&self, order: &Order) -> Result<VenueOrder>;
+
+    async fn cancel_order(&self, symbol: &str, order: &OrderRef) -> Result<VenueOrder>;
+
+    async fn query_order(&self, symbol: &str, order: &OrderRef) -> Result<VenueOrder>;
+
+    /// Open orders for `symbol`, or for every symbol when `None`.
+    async fn open_orders(&self, symbol: Option<&str>) -> Result<Vec<VenueOrder>>;
+
+    /// Assets with a non-zero balance.
+    async fn balances(&self) -> Result<Vec<Balance>>;
+}
+
+/// Fails unless the connector streams every subscription's channel.
+pub fn check_subscriptions(
+    connector: &dyn ExchangeConnector,
+    subscriptions: &[Subscription],
+) -> Result<()> {
+    let capabilities = connector.capabilities();
+    match subscriptions
+        .iter()
+        .find(|subscription| !capabilities.supports(subscription.channel))
+    {
+        Some(subscription) => anyhow::bail!(
+            "{} does not stream {:?} for {}",
+            connector.name(),
+            subscription.channel,
+            subscription.symbol
+        ),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connectors::market::KlineInterval;
+
+    #[test]
+    fn test_capabilities_cover_channels() {
+        let capabilities = Capabilities {
+            order_book: true,
+            trades: true,
+            ..Capabilities::default()
+        };
+        assert!(capabilities.supports(Channel::Depth));
+        assert!(capabilities.supports(Channel::Trade));
+        assert!(!capabilities.supports(Channel::AggTrade));
+        assert!(!capabilities.supports(Channel::Kline(KlineInterval::Minute1)));
+    }
+}
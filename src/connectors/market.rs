@@ -0,0 +1,326 @@
+//! Market data as every venue delivers it: what can be subscribed to and
+//! the events subscriptions produce.
+
+use super::reconnect::{StreamEvent, Timestamped};
+use crate::backtest::engine::OHLCV;
+use crate::utils::types::{MarketTrade, OrderBookSnapshot, Ticker};
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Channel {
+    /// Incremental depth, maintained into a local book.
+    Depth,
+    /// Best bid and offer with the last price and 24h volume.
+    Ticker,
+    /// Every fill on the public tape.
+    Trade,
+    /// Fills merged per taker order and price.
+    AggTrade,
+    /// Candlesticks, in progress and closed.
+    Kline(KlineInterval),
+}
+
+/// One channel for one symbol.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Subscription {
+    /// Upper-case symbol in the venue's notation, e.g. `BTCUSDT` or
+    /// `BTC-USD`.
+    pub symbol: String,
+    pub channel: Channel,
+}
+
+impl Subscription {
+    pub fn new(symbol: &str, channel: Channel) -> Self {
+        Self {
+            symbol: symbol.to_uppercase(),
+            channel,
+        }
+    }
+
+    pub fn depth(symbol: &str) -> Self {
+        Self::new(symbol, Channel::Depth)
+    }
+
+    pub fn ticker(symbol: &str) -> Self {
+        Self::new(symbol, Channel::Ticker)
+    }
+
+    pub fn trade(symbol: &str) -> Self {
+        Self::new(symbol, Channel::Trade)
+    }
+
+    pub fn agg_trade(symbol: &str) -> Self {
+        Self::new(symbol, Channel::AggTrade)
+    }
+
+    pub fn kline(symbol: &str, interval: KlineInterval) -> Self {
+        Self::new(symbol, Channel::Kline(interval))
+    }
+}
+
+/// Everything a market data stream delivers, whatever its channel.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MarketEvent {
+    /// Full local book after a depth change.
+    Book(OrderBookSnapshot),
+    Ticker(Ticker),
+    /// A print from either trade channel.
+    Trade(MarketTrade),
+    Kline(Kline),
+}
+
+impl MarketEvent {
+    pub fn symbol(&self) -> &str {
+        match self {
+            MarketEvent::Book(book) => &book.symbol,
+            MarketEvent::Ticker(ticker) => &ticker.symbol,
+            MarketEvent::Trade(trade) => &trade.symbol,
+            MarketEvent::Kline(kline) => &kline.symbol,
+        }
+    }
+}
+
+impl StreamEvent<MarketEvent> {
+    /// The symbol the event concerns, or `None` when it concerns every
+    /// symbol on the connection, e.g. a disconnect.
+    pub fn symbol(&self) -> Option<&str> {
+        match self {
+            StreamEvent::Data { data, .. } => Some(data.symbol()),
+            StreamEvent::Stale { symbol, .. } => symbol.as_deref(),
+            _ => None,
+        }
+    }
+}
+
+impl Timestamped for MarketEvent {
+    fn timestamp(&self) -> DateTime<Utc> {
+        match self {
+            MarketEvent::Book(book) => book.timestamp,
+            MarketEvent::Ticker(ticker) => ticker.timestamp,
+            MarketEvent::Trade(trade) => trade.timestamp,
+            MarketEvent::Kline(kline) => kline.timestamp(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub(crate) enum Command {
+    Subscribe(Vec<Subscription>),
+    Unsubscribe(Vec<Subscription>),
+}
+
+/// Changes the subscriptions of a running stream. Cloneable; the stream
+/// keeps running on its current set once every handle is dropped.
+#[derive(Debug, Clone)]
+pub struct SubscriptionHandle {
+    commands: mpsc::UnboundedSender<Command>,
+}
+
+impl SubscriptionHandle {
+    pub fn subscribe(&self, subscriptions: &[Subscription]) -> Result<()> {
+        self.send(Command::Subscribe(subscriptions.to_vec()))
+    }
+
+    pub fn unsubscribe(&self, subscriptions: &[Subscription]) -> Result<()> {
+        self.send(Command::Unsubscribe(subscriptions.to_vec()))
+    }
+
+    fn send(&self, command: Command) -> Result<()> {
+        self.commands
+            .send(command)
+            .map_err(|_| anyhow::anyhow!("market data stream has stopped"))
+    }
+}
+
+/// Receiving end of a [`SubscriptionHandle`], consumed by the stream it
+/// controls.
+#[derive(Debug)]
+pub struct SubscriptionCommands {
+    pub(crate) commands: mpsc::UnboundedReceiver<Command>,
+}
+
+/// A handle for changing subscriptions and the commands it sends.
+pub fn subscription_channel() -> (SubscriptionHandle, SubscriptionCommands) {
+    let (tx, rx) = mpsc::unbounded_channel();
+    (
+        SubscriptionHandle { commands: tx },
+        SubscriptionCommands { commands: rx },
+    )
+}
+
+/// Next subscription command, pending forever once there are no handles.
+pub(crate) async fn next_command(commands: &mut Option<SubscriptionCommands>) -> Option<Command> {
+    match commands {
+        Some(commands) => commands.commands.recv().await,
+        None => std::future::pending().await,
+    }
+}
+
+/// When each symbol on a shared connection last delivered anything, so one
+/// that goes quiet is noticed while the others keep the connection busy.
+#[derive(Debug, Default)]
+pub(crate) struct SymbolSilence {
+    /// Last data per symbol, and whether its current silence was reported.
+    last: HashMap<String, (Instant, bool)>,
+}
+
+impl SymbolSilence {
+    /// Watches the symbols of `subscriptions` and no others, timing new
+    /// ones from now.
+    pub(crate) fn track(&mut self, subscriptions: &BTreeSet<Subscription>) {
+        let now = Instant::now();
+        self.last
+            .retain(|symbol, _| subscriptions.iter().any(|s| &s.symbol == symbol));
+        for subscription in subscriptions {
+            self.last
+                .entry(subscription.symbol.clone())
+                .or_insert((now, false));
+        }
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.last.clear();
+    }
+
+    pub(crate) fn seen(&mut self, symbol: &str) {
+        if let Some(last) = self.last.get_mut(symbol) {
+            *last = (Instant::now(), false);
+        }
+    }
+
+    /// When the next symbol not yet reported goes stale.
+    pub(crate) fn deadline(&self, stale_after: Duration) -> Option<Instant> {
+        self.last
+            .values()
+            .filter(|(_, reported)| !reported)
+            .map(|(at, _)| *at + stale_after)
+            .min()
+    }
+
+    /// Symbols silent for longer than `stale_after`, with how long, each
+    /// reported once per silence.
+    pub(crate) fn silent(&mut self, stale_after: Duration) -> Vec<(String, Duration)> {
+        let mut silent = Vec::new();
+        for (symbol, (at, reported)) in &mut self.last {
+            if !*reported && at.elapsed() >= stale_after {
+                *reported = true;
+                silent.push((symbol.clone(), at.elapsed()));
+            }
+        }
+        silent
+    }
+}
+
+/// Sleeps until `deadline`, or forever without one.
+pub(crate) async fn wait_until(deadline: Option<Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline).await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Bar lengths, named as Binance names them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum KlineInterval {
+    Minute1,
+    Minute3,
+    Minute5,
+    Minute15,
+    Minute30,
+    Hour1,
+    Hour2,
+    Hour4,
+    Hour6,
+    Hour8,
+    Hour12,
+    Day1,
+    Day3,
+    Week1,
+    Month1,
+}
+
+impl KlineInterval {
+    pub(crate) const ALL: [KlineInterval; 15] = [
+        KlineInterval::Minute1,
+        KlineInterval::Minute3,
+        KlineInterval::Minute5,
+        KlineInterval::Minute15,
+        KlineInterval::Minute30,
+        KlineInterval::Hour1,
+        KlineInterval::Hour2,
+        KlineInterval::Hour4,
+        KlineInterval::Hour6,
+        KlineInterval::Hour8,
+        KlineInterval::Hour12,
+        KlineInterval::Day1,
+        KlineInterval::Day3,
+        KlineInterval::Week1,
+        KlineInterval::Month1,
+    ];
+
+    /// Name of the interval, e.g. `1m` or `1M`.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            KlineInterval::Minute1 => "1m",
+            KlineInterval::Minute3 => "3m",
+            KlineInterval::Minute5 => "5m",
+            KlineInterval::Minute15 => "15m",
+            KlineInterval::Minute30 => "30m",
+            KlineInterval::Hour1 => "1h",
+            KlineInterval::Hour2 => "2h",
+            KlineInterval::Hour4 => "4h",
+            KlineInterval::Hour6 => "6h",
+            KlineInterval::Hour8 => "8h",
+            KlineInterval::Hour12 => "12h",
+            KlineInterval::Day1 => "1d",
+            KlineInterval::Day3 => "3d",
+            KlineInterval::Week1 => "1w",
+            KlineInterval::Month1 => "1M",
+        }
+    }
+}
+
+impl fmt::Display for KlineInterval {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for KlineInterval {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|interval| interval.as_str() == s)
+            .with_context(|| format!("unknown kline interval {}", s))
+    }
+}
+
+/// One update of a bar, in progress or final.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Kline {
+    pub symbol: String,
+    pub interval: KlineInterval,
+    /// The bar so far, stamped with its open time.
+    pub bar: OHLCV,
+    pub close_time: DateTime<Utc>,
+    /// Set on the last update of the bar; earlier ones may still change.
+    pub closed: bool,
+    /// Exchange event time.
+    pub event_time: DateTime<Utc>,
+}
+
+impl Timestamped for Kline {
+    /// Event time, not the bar's open time, so an open bar is not stale.
+    fn timestamp(&self) -> DateTime<Utc> {
+        self.event_time
+    }
+}
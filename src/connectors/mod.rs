@@ -1 +1,5 @@
 pub mod binance;
+pub mod coinbase;
+pub mod exchange;
+pub mod market;
+pub mod reconnect;
@@ -0,0 +1,445 @@
+//! Supervised WebSocket streams that reconnect with jittered exponential
+//! backoff.
+//!
+//! A stream is driven by a session function that owns one connection and
+//! returns when it ends. The supervisor reconnects and starts a fresh
+//! session, so per-connection state such as a synchronised book is rebuilt
+//! from scratch, and reports every transition to the caller as a
+//! [`StreamEvent`].
+//!
+//! The supervisor also watches for silence: data is flagged stale when the
+//! exchange stamped it too long ago, and a connection that delivers nothing
+//! for a while is reported as stale, so consumers can stop quoting on a
+//! market view that is no longer current. Sessions carrying many symbols
+//! report a single symbol that goes quiet through their [`SessionSender`].
+
+use crate::utils::types::{MarketTrade, OrderBookSnapshot, Ticker};
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use futures::{SinkExt, StreamExt};
+use rand::Rng;
+use std::future::Future;
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::{connect_async, MaybeTlsStream, WebSocketStream};
+use tracing::{info, warn};
+
+pub type WsStream = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+/// Market data stamped by the exchange.
+pub trait Timestamped {
+    fn timestamp(&self) -> DateTime<Utc>;
+}
+
+impl Timestamped for OrderBookSnapshot {
+    fn timestamp(&self) -> DateTime<Utc> {
+        self.timestamp
+    }
+}
+
+impl Timestamped for Ticker {
+    fn timestamp(&self) -> DateTime<Utc> {
+        self.timestamp
+    }
+}
+
+impl Timestamped for MarketTrade {
+    fn timestamp(&self) -> DateTime<Utc> {
+        self.timestamp
+    }
+}
+
+/// What a supervised stream delivers to its caller.
+#[derive(Debug, Clone, PartialEq)]
+pub enum StreamEvent<T> {
+    /// `stale` is set when the exchange timestamp is older than the policy's
+    /// `stale_after`, e.g. a backlog delivered late.
+    Data { data: T, stale: bool },
+    /// Nothing has arrived for `silent_for`; reported once per silence. Set
+    /// `symbol` is the only one gone quiet on a connection still delivering
+    /// others; `None` means the whole connection.
+    Stale {
+        symbol: Option<String>,
+        silent_for: Duration,
+    },
+    /// A connection was established; data that follows comes from it.
+    Connected,
+    /// An established connection ended.
+    Disconnected { reason: String },
+    /// Waiting `delay` before reconnection attempt `attempt`, counted from
+    /// the last connection that delivered data.
+    Reconnecting { attempt: u32, delay: Duration },
+}
+
+impl<T> StreamEvent<T> {
+    /// Whether the caller's view of the market is stale after this event, or
+    /// `None` if the event does not change it.
+    pub fn staleness(&self) -> Option<bool> {
+        match self {
+            StreamEvent::Data { stale, .. } => Some(*stale),
+            StreamEvent::Stale { .. } | StreamEvent::Disconnected { .. } => Some(true),
+            StreamEvent::Connected | StreamEvent::Reconnecting { .. } => None,
+        }
+    }
+}
+
+/// When and how often a dropped stream is reconnected.
+#[derive(Debug, Clone)]
+pub struct ReconnectPolicy {
+    /// Delay before the first attempt; doubled on every failure after it.
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    /// Fraction of each delay that is randomised, between 0 and 1, so that
+    /// many streams dropped together do not reconnect together.
+    pub jitter: f64,
+    /// Consecutive failed attempts before giving up; `None` retries forever.
+    pub max_retries: Option<u32>,
+    /// Connections are renewed after this long. Binance closes every
+    /// connection at 24 hours, so the default stays under that.
+    pub max_connection_age: Duration,
+    /// How old data may be, by exchange timestamp, and how long a connection
+    /// may stay silent, before the market view counts as stale.
+    pub stale_after: Duration,
+}
+
+impl ReconnectPolicy {
+    pub fn new() -> Self {
+        Self {
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(30),
+            jitter: 0.2,
+            max_retries: None,
+            max_connection_age: Duration::from_secs(23 * 60 * 60),
+            stale_after: Duration::from_secs(5),
+        }
+    }
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Delay sequence for consecutive failed attempts under a policy.
+#[derive(Debug, Clone)]
+pub struct Backoff {
+    policy: ReconnectPolicy,
+    failures: u32,
+}
+
+impl Backoff {
+    pub fn new(policy: ReconnectPolicy) -> Self {
+        Self {
+            policy,
+            failures: 0,
+        }
+    }
+
+    pub fn failures(&self) -> u32 {
+        self.failures
+    }
+
+    /// Delay before the next attempt, or `None` once retries are exhausted.
+    pub fn next_delay(&mut self) -> Option<Duration> {
+        if self
+            .policy
+            .max_retries
+            .is_some_and(|max| self.failures >= max)
+        {
+            return None;
+        }
+        let exponent = self.failures.min(31);
+        self.failures += 1;
+        let delay = self
+            .policy
+            .initial_backoff
+            .saturating_mul(1 << exponent)
+            .min(self.policy.max_backoff);
+        let jitter = self.policy.jitter.clamp(0.0, 1.0);
+        Some(delay.mul_f64(1.0 - jitter * rand::thread_rng().gen::<f64>()))
+    }
+
+    pub fn reset(&mut self) {
+        self.failures = 0;
+    }
+}
+
+/// What a session passes to its supervisor.
+#[derive(Debug)]
+enum SessionEvent<T> {
+    Data(T),
+    Stale {
+        symbol: String,
+        silent_for: Duration,
+    },
+}
+
+/// Where a session delivers its data.
+#[derive(Debug)]
+pub struct SessionSender<T> {
+    tx: mpsc::UnboundedSender<SessionEvent<T>>,
+}
+
+impl<T> SessionSender<T> {
+    /// Delivers data; `false` once the supervisor has stopped listening.
+    pub fn send(&self, data: T) -> bool {
+        self.tx.send(SessionEvent::Data(data)).is_ok()
+    }
+
+    /// Reports that `symbol` has delivered nothing for `silent_for` while
+    /// the connection carries on with others.
+    pub fn stale(&self, symbol: &str, silent_for: Duration) -> bool {
+        self.tx
+            .send(SessionEvent::Stale {
+                symbol: symbol.to_string(),
+                silent_for,
+            })
+            .is_ok()
+    }
+}
+
+/// Runs `session` over successive connections to `url` until the policy
+/// gives up. The session sends its data through the channel it is given and
+/// returns when its connection ends; an error is reported as the reason.
+/// Backoff restarts once a connection has delivered data, and connections
+/// reaching the maximum age are renewed without waiting.
+pub async fn supervise<T, F, S, Fut>(
+    url: &str,
+    policy: &ReconnectPolicy,
+    callback: &mut F,
+    session: S,
+) -> Result<()>
+where
+    T: Timestamped,
+    F: FnMut(StreamEvent<T>),
+    S: FnMut(WsStream, SessionSender<T>) -> Fut,
+    Fut: Future<Output = Result<()>>,
+{
+    let endpoint = || std::future::ready(Ok(url.to_string()));
+    supervise_endpoint(endpoint, policy, callback, session).await
+}
+
+/// Like [`supervise`], but resolves the URL before every connection, e.g.
+/// to obtain a fresh token. A failure to resolve counts as a failed attempt.
+pub async fn supervise_endpoint<T, F, E, EFut, S, Fut>(
+    mut endpoint: E,
+    policy: &ReconnectPolicy,
+    callback: &mut F,
+    mut session: S,
+) -> Result<()>
+where
+    T: Timestamped,
+    F: FnMut(StreamEvent<T>),
+    E: FnMut() -> EFut,
+    EFut: Future<Output = Result<String>>,
+    S: FnMut(WsStream, SessionSender<T>) -> Fut,
+    Fut: Future<Output = Result<()>>,
+{
+    let mut backoff = Backoff::new(policy.clone());
+    loop {
+        let (url, connected) = match endpoint().await {
+            Ok(url) => {
+                let connected = connect_async(&url).await;
+                (
+                    url,
+                    connected.map_err(|e| format!("failed to connect: {}", e)),
+                )
+            }
+            Err(e) => (
+                "unresolved endpoint".to_string(),
+                Err(format!("failed to resolve endpoint: {:#}", e)),
+            ),
+        };
+        let reason = match connected {
+            Ok((ws_stream, _)) => {
+                info!("Connected to {}", url);
+                callback(StreamEvent::Connected);
+                let (tx, mut rx) = mpsc::unbounded_channel();
+                let running = session(ws_stream, SessionSender { tx });
+                tokio::pin!(running);
+                let age_limit = tokio::time::sleep(policy.max_connection_age);
+                tokio::pin!(age_limit);
+
+                let mut received = false;
+                let mut last_data = Instant::now();
+                let silence = tokio::time::sleep(policy.stale_after);
+                tokio::pin!(silence);
+                let mut watching = true;
+                let ended = loop {
+                    tokio::select! {
+                        biased;
+                        Some(event) = rx.recv() => {
+                            received = true;
+                            last_data = Instant::now();
+                            silence.as_mut().reset(last_data + policy.stale_after);
+                            watching = true;
+                            callback(session_event(event, &url, policy.stale_after));
+                        }
+                        ended = &mut running => break Some(ended),
+                        _ = &mut age_limit => break None,
+                        _ = &mut silence, if watching => {
+                            watching = false;
+                            let silent_for = last_data.elapsed();
+                            warn!("No data from {} for {:?}", url, silent_for);
+                            callback(StreamEvent::Stale {
+                                symbol: None,
+                                silent_for,
+                            });
+                        }
+                    }
+                };
+                while let Ok(event) = rx.try_recv() {
+                    received = true;
+                    callback(session_event(event, &url, policy.stale_after));
+                }
+                if received {
+                    backoff.reset();
+                }
+
+                let reason = match ended {
+                    Some(Ok(())) => "connection closed".to_string(),
+                    Some(Err(e)) => e.to_string(),
+                    None => {
+                        info!("Renewing connection to {} at its age limit", url);
+                        callback(StreamEvent::Disconnected {
+                            reason: "connection age limit reached".to_string(),
+                        });
+                        continue;
+                    }
+                };
+                callback(StreamEvent::Disconnected {
+                    reason: reason.clone(),
+                });
+                reason
+            }
+            Err(reason) => reason,
+        };
+
+        let Some(delay) = backoff.next_delay() else {
+            anyhow::bail!(
+                "Giving up on {} after {} attempts: {}",
+                url,
+                backoff.failures(),
+                reason
+            );
+        };
+        warn!(
+            "Stream {} interrupted ({}), reconnecting in {:?}",
+            url, reason, delay
+        );
+        callback(StreamEvent::Reconnecting {
+            attempt: backoff.failures(),
+            delay,
+        });
+        tokio::time::sleep(delay).await;
+    }
+}
+
+fn session_event<T: Timestamped>(
+    event: SessionEvent<T>,
+    url: &str,
+    stale_after: Duration,
+) -> StreamEvent<T> {
+    match event {
+        SessionEvent::Data(data) => data_event(data, stale_after),
+        SessionEvent::Stale { symbol, silent_for } => {
+            warn!("No {} data from {} for {:?}", symbol, url, silent_for);
+            StreamEvent::Stale {
+                symbol: Some(symbol),
+                silent_for,
+            }
+        }
+    }
+}
+
+fn data_event<T: Timestamped>(data: T, stale_after: Duration) -> StreamEvent<T> {
+    let age = Utc::now() - data.timestamp();
+    let stale = age.to_std().is_ok_and(|age| age > stale_after);
+    StreamEvent::Data { data, stale }
+}
+
+/// Next text frame, or `None` once the server closes the connection. Pings
+/// are answered with a pong carrying the same payload.
+pub async fn next_text(ws_stream: &mut WsStream) -> Result<Option<String>> {
+    while let Some(msg) = ws_stream.next().await {
+        match msg.context("WebSocket error")? {
+            Message::Text(text) => return Ok(Some(text)),
+            Message::Ping(payload) => {
+                ws_stream
+                    .send(Message::Pong(payload))
+                    .await
+                    .context("Failed to answer ping")?;
+            }
+            Message::Close(_) => {
+                info!("WebSocket connection closed");
+                return Ok(None);
+            }
+            _ => {}
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff_doubles_up_to_cap_and_gives_up() {
+        let mut backoff = Backoff::new(ReconnectPolicy {
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_millis(350),
+            jitter: 0.0,
+            max_retries: Some(4),
+            ..ReconnectPolicy::new()
+        });
+        let delays: Vec<_> = std::iter::from_fn(|| backoff.next_delay()).collect();
+        assert_eq!(
+            delays,
+            [100, 200, 350, 350].map(Duration::from_millis).to_vec()
+        );
+        assert_eq!(backoff.failures(), 4);
+
+        backoff.reset();
+        assert_eq!(backoff.next_delay(), Some(Duration::from_millis(100)));
+    }
+
+    #[test]
+    fn test_flags_data_by_exchange_timestamp() {
+        let ticker = |age: chrono::Duration| Ticker {
+            symbol: "BTCUSDT".to_string(),
+            bid: Default::default(),
+            ask: Default::default(),
+            last: Default::default(),
+            volume_24h: Default::default(),
+            timestamp: Utc::now() - age,
+        };
+        let stale_after = Duration::from_secs(5);
+
+        let fresh = data_event(ticker(chrono::Duration::seconds(1)), stale_after);
+        assert_eq!(fresh.staleness(), Some(false));
+        let late = data_event(ticker(chrono::Duration::seconds(30)), stale_after);
+        assert_eq!(late.staleness(), Some(true));
+        // Exchange clocks running slightly ahead do not count as stale
+        let ahead = data_event(ticker(chrono::Duration::seconds(-1)), stale_after);
+        assert_eq!(ahead.staleness(), Some(false));
+    }
+
+    #[test]
+    fn test_jitter_only_shortens_delays() {
+        let mut backoff = Backoff::new(ReconnectPolicy {
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(1),
+            jitter: 0.5,
+            ..ReconnectPolicy::new()
+        });
+        for _ in 0..100 {
+            let delay = backoff.next_delay().unwrap();
+            assert!(delay >= Duration::from_millis(500) && delay <= Duration::from_secs(1));
+        }
+    }
+}
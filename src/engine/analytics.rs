@@ -0,0 +1,294 @@
+//! Order book analytics shared by local books and mirrored snapshots.
+//!
+//! Everything here is derived from best-first price levels, so the same
+//! calculations work on an engine [`OrderBook`] and on an
+//! [`OrderBookSnapshot`] received from an exchange connector.
+
+use crate::engine::orderbook::OrderBook;
+use crate::utils::types::{OrderBookLevel, OrderBookSnapshot, Side};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+const BPS: Decimal = Decimal::from_parts(10_000, 0, 0, false, 0);
+
+/// Amount to execute when estimating a fill.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FillTarget {
+    /// A base-asset quantity.
+    Quantity(Decimal),
+    /// A quote-asset amount (price x quantity).
+    Notional(Decimal),
+}
+
+/// Result of walking the book for a hypothetical aggressive order.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FillEstimate {
+    pub filled_quantity: Decimal,
+    pub notional: Decimal,
+    pub average_price: Decimal,
+    pub worst_price: Decimal,
+    /// `false` if the visible book ran out before the target was reached.
+    pub complete: bool,
+    /// Cost versus the best opposite price, in basis points (positive is worse).
+    pub touch_slippage_bps: Decimal,
+    /// Cost versus the mid price, in basis points, when both sides are present.
+    pub mid_slippage_bps: Option<Decimal>,
+}
+
+/// Book math over best-first price levels.
+pub trait BookAnalytics {
+    /// Returns up to `levels` levels on `side`, best price first.
+    fn levels(&self, side: Side, levels: usize) -> Vec<OrderBookLevel>;
+
+    fn best_level(&self, side: Side) -> Option<OrderBookLevel> {
+        self.levels(side, 1).into_iter().next()
+    }
+
+    fn mid_price(&self) -> Option<Decimal> {
+        let bid = self.best_level(Side::Buy)?;
+        let ask = self.best_level(Side::Sell)?;
+        Some((bid.price + ask.price) / Decimal::TWO)
+    }
+
+    /// Top-of-book price weighted towards the side with less resting quantity.
+    fn microprice(&self) -> Option<Decimal> {
+        self.weighted_mid(1)
+    }
+
+    /// Generalises [`BookAnalytics::microprice`] to the top `levels` levels:
+    /// the volume-weighted bid and ask prices, each weighted by the opposite
+    /// side's quantity.
+    fn weighted_mid(&self, levels: usize) -> Option<Decimal> {
+        let (bid_qty, bid_vwap) = side_vwap(&self.levels(Side::Buy, levels))?;
+        let (ask_qty, ask_vwap) = side_vwap(&self.levels(Side::Sell, levels))?;
+        Some((bid_vwap * ask_qty + ask_vwap * bid_qty) / (bid_qty + ask_qty))
+    }
+
+    /// `(bid - ask) / (bid + ask)` quantity over the top `levels` levels, in `[-1, 1]`.
+    fn imbalance(&self, levels: usize) -> Option<Decimal> {
+        let bid: Decimal = self
+            .levels(Side::Buy, levels)
+            .iter()
+            .map(|l| l.quantity)
+            .sum();
+        let ask: Decimal = self
+            .levels(Side::Sell, levels)
+            .iter()
+            .map(|l| l.quantity)
+            .sum();
+        let total = bid + ask;
+        if total.is_zero() {
+            return None;
+        }
+        Some((bid - ask) / total)
+    }
+
+    /// Resting quantity on `side` priced within `bps` basis points of the mid.
+    fn quantity_within_bps(&self, side: Side, bps: Decimal) -> Decimal {
+        let Some(mid) = self.mid_price() else {
+            return Decimal::ZERO;
+        };
+        let offset = mid * bps / BPS;
+
+        self.levels(side, usize::MAX)
+            .iter()
+            .take_while(|l| match side {
+                Side::Buy => l.price >= mid - offset,
+                Side::Sell => l.price <= mid + offset,
+            })
+            .map(|l| l.quantity)
+            .sum()
+    }
+
+    /// Walks the book for an aggressive order on `side` sized by `target`.
+    ///
+    /// Returns `None` if there is no opposite liquidity at all.
+    fn estimate_fill(&self, side: Side, target: FillTarget) -> Option<FillEstimate> {
+        let opposite = match side {
+            Side::Buy => Side::Sell,
+            Side::Sell => Side::Buy,
+        };
+        let levels = self.levels(opposite, usize::MAX);
+        let touch = levels.first()?.price;
+
+        let mut filled_quantity = Decimal::ZERO;
+        let mut notional = Decimal::ZERO;
+        let mut worst_price = touch;
+        let mut complete = false;
+
+        for level in &levels {
+            let wanted = match target {
+                FillTarget::Quantity(quantity) => quantity - filled_quantity,
+                FillTarget::Notional(amount) if level.price.is_zero() => {
+                    if amount > notional {
+                        level.quantity
+                    } else {
+                        Decimal::ZERO
+                    }
+                }
+                FillTarget::Notional(amount) => (amount - notional) / level.price,
+            };
+            if wanted <= Decimal::ZERO {
+                complete = true;
+                break;
+            }
+
+            let take = wanted.min(level.quantity);
+            filled_quantity += take;
+            notional += take * level.price;
+            worst_price = level.price;
+
+            if take == wanted {
+                complete = true;
+                break;
+            }
+        }
+
+        if filled_quantity.is_zero() {
+            return None;
+        }
+
+        let average_price = notional / filled_quantity;
+        let cost_bps = |reference: Decimal| -> Decimal {
+            let diff = match side {
+                Side::Buy => average_price - reference,
+                Side::Sell => reference - average_price,
+            };
+            diff / reference * BPS
+        };
+
+        Some(FillEstimate {
+            filled_quantity,
+            notional,
+            average_price,
+            worst_price,
+            complete,
+            touch_slippage_bps: if touch.is_zero() {
+                Decimal::ZERO
+            } else {
+                cost_bps(touch)
+            },
+            mid_slippage_bps: self.mid_price().filter(|m| !m.is_zero()).map(cost_bps),
+        })
+    }
+}
+
+/// Total quantity and volume-weighted price of a run of levels.
+fn side_vwap(levels: &[OrderBookLevel]) -> Option<(Decimal, Decimal)> {
+    let quantity: Decimal = levels.iter().map(|l| l.quantity).sum();
+    if quantity.is_zero() {
+        return None;
+    }
+    let notional: Decimal = levels.iter().map(|l| l.price * l.quantity).sum();
+    Some((quantity, notional / quantity))
+}
+
+impl BookAnalytics for OrderBook {
+    fn levels(&self, side: Side, levels: usize) -> Vec<OrderBookLevel> {
+        self.get_depth(side, levels)
+    }
+}
+
+impl BookAnalytics for OrderBookSnapshot {
+    fn levels(&self, side: Side, levels: usize) -> Vec<OrderBookLevel> {
+        let book = match side {
+            Side::Buy => &self.bids,
+            Side::Sell => &self.asks,
+        };
+        book.iter().take(levels).cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    fn level(price: i64, quantity: i64) -> OrderBookLevel {
+        OrderBookLevel {
+            price: Decimal::from(price),
+            quantity: Decimal::from(quantity),
+            order_count: 1,
+        }
+    }
+
+    fn snapshot() -> OrderBookSnapshot {
+        OrderBookSnapshot {
+            symbol: "BTCUSD".to_string(),
+            bids: vec![level(99, 3), level(98, 5)],
+            asks: vec![level(101, 1), level(102, 4)],
+            timestamp: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_mid_microprice_and_imbalance() {
+        let book = snapshot();
+
+        assert_eq!(book.mid_price(), Some(Decimal::from(100)));
+        // (99 * 1 + 101 * 3) / 4
+        assert_eq!(book.microprice(), Some(Decimal::new(1005, 1)));
+        assert_eq!(book.imbalance(1), Some(Decimal::new(5, 1)));
+        assert_eq!(
+            book.imbalance(2),
+            Some(Decimal::from(3) / Decimal::from(13))
+        );
+    }
+
+    #[test]
+    fn test_estimate_fill_by_quantity_and_notional() {
+        let book = snapshot();
+
+        let fill = book
+            .estimate_fill(Side::Buy, FillTarget::Quantity(Decimal::from(3)))
+            .unwrap();
+        assert!(fill.complete);
+        assert_eq!(fill.notional, Decimal::from(305));
+        assert_eq!(fill.worst_price, Decimal::from(102));
+        assert_eq!(
+            fill.touch_slippage_bps,
+            (fill.average_price - Decimal::from(101)) / Decimal::from(101) * BPS
+        );
+        assert_eq!(
+            fill.mid_slippage_bps,
+            Some((fill.average_price - Decimal::from(100)) / Decimal::from(100) * BPS)
+        );
+
+        let fill = book
+            .estimate_fill(Side::Sell, FillTarget::Notional(Decimal::from(1000)))
+            .unwrap();
+        assert!(!fill.complete);
+        assert_eq!(fill.filled_quantity, Decimal::from(8));
+
+        assert_eq!(
+            book.quantity_within_bps(Side::Sell, Decimal::from(150)),
+            Decimal::from(1)
+        );
+        assert_eq!(
+            book.quantity_within_bps(Side::Buy, Decimal::from(200)),
+            Decimal::from(8)
+        );
+    }
+
+    #[test]
+    fn test_local_book_matches_its_snapshot() {
+        let mut book = OrderBook::new("BTCUSD".to_string());
+        for (side, price, qty) in [(Side::Buy, 99, 3), (Side::Buy, 98, 5), (Side::Sell, 101, 1)] {
+            book.add_order(crate::utils::types::Order::new(
+                "BTCUSD".to_string(),
+                side,
+                crate::utils::types::OrderType::Limit,
+                Decimal::from(price),
+                Decimal::from(qty),
+            ));
+        }
+
+        let snapshot = book.get_snapshot();
+        assert_eq!(book.microprice(), snapshot.microprice());
+        assert_eq!(book.imbalance(5), snapshot.imbalance(5));
+        assert_eq!(
+            book.estimate_fill(Side::Sell, FillTarget::Quantity(Decimal::from(4))),
+            snapshot.estimate_fill(Side::Sell, FillTarget::Quantity(Decimal::from(4)))
+        );
+    }
+}
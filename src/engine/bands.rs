@@ -0,0 +1,163 @@
+//! Price band protection for the order book.
+//!
+//! A static band is anchored on a per-instrument reference price (typically
+//! the previous close or the last auction price); a dynamic band is anchored
+//! on the last trade price. Matching stops at the first level outside either
+//! band, and the configured [`BandBreachAction`] decides what happens to the
+//! remainder of the aggressing order.
+
+use chrono::{DateTime, Duration, Utc};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+/// What the engine does with an order whose remainder would trade outside the bands.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BandBreachAction {
+    /// Reject the unfilled remainder of the aggressing order.
+    Reject,
+    /// Rest the remainder and move the symbol into a volatility auction.
+    VolatilityAuction,
+}
+
+/// Per-instrument price band configuration.
+///
+/// Widths are fractions of the anchor price, e.g. `0.05` for a 5% band.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PriceBandConfig {
+    pub static_width: Option<Decimal>,
+    pub dynamic_width: Option<Decimal>,
+    pub breach_action: BandBreachAction,
+    pub auction_duration: Duration,
+}
+
+impl Default for PriceBandConfig {
+    fn default() -> Self {
+        Self {
+            static_width: Some(Decimal::new(10, 2)),
+            dynamic_width: Some(Decimal::new(2, 2)),
+            breach_action: BandBreachAction::VolatilityAuction,
+            auction_duration: Duration::seconds(5),
+        }
+    }
+}
+
+/// Trading phase of a single order book.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TradingPhase {
+    /// Incoming orders match continuously against the book.
+    Continuous,
+    /// Orders rest without matching until the auction uncrosses at `ends_at`.
+    VolatilityAuction { ends_at: DateTime<Utc> },
+}
+
+/// Live band state for one instrument: configuration plus its anchor prices.
+#[derive(Debug, Clone)]
+pub struct PriceBands {
+    config: PriceBandConfig,
+    reference_price: Option<Decimal>,
+    last_trade_price: Option<Decimal>,
+}
+
+impl PriceBands {
+    pub fn new(config: PriceBandConfig, reference_price: Option<Decimal>) -> Self {
+        Self {
+            config,
+            reference_price,
+            last_trade_price: None,
+        }
+    }
+
+    pub fn config(&self) -> &PriceBandConfig {
+        &self.config
+    }
+
+    pub fn reference_price(&self) -> Option<Decimal> {
+        self.reference_price
+    }
+
+    pub fn last_trade_price(&self) -> Option<Decimal> {
+        self.last_trade_price
+    }
+
+    pub fn set_reference_price(&mut self, price: Decimal) {
+        self.reference_price = Some(price);
+    }
+
+    pub fn record_trade(&mut self, price: Decimal) {
+        self.last_trade_price = Some(price);
+    }
+
+    /// Returns the tightest `(low, high)` limits implied by both bands.
+    ///
+    /// A bound is `None` when no band with an anchor price constrains that side.
+    pub fn limits(&self) -> (Option<Decimal>, Option<Decimal>) {
+        let mut low: Option<Decimal> = None;
+        let mut high: Option<Decimal> = None;
+
+        let anchors = [
+            (self.reference_price, self.config.static_width),
+            (self.last_trade_price, self.config.dynamic_width),
+        ];
+
+        for (anchor, width) in anchors {
+            if let (Some(anchor), Some(width)) = (anchor, width) {
+                let band_low = anchor * (Decimal::ONE - width);
+                let band_high = anchor * (Decimal::ONE + width);
+                low = Some(low.map_or(band_low, |l| l.max(band_low)));
+                high = Some(high.map_or(band_high, |h| h.min(band_high)));
+            }
+        }
+
+        (low, high)
+    }
+
+    /// Returns `true` if `price` may trade under the current limits.
+    pub fn contains(&self, price: Decimal) -> bool {
+        let (low, high) = self.limits();
+        low.is_none_or(|l| price >= l) && high.is_none_or(|h| price <= h)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(static_width: Option<Decimal>, dynamic_width: Option<Decimal>) -> PriceBandConfig {
+        PriceBandConfig {
+            static_width,
+            dynamic_width,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_static_band_limits() {
+        let bands = PriceBands::new(
+            config(Some(Decimal::new(10, 2)), None),
+            Some(Decimal::from(100)),
+        );
+
+        assert_eq!(
+            bands.limits(),
+            (Some(Decimal::from(90)), Some(Decimal::from(110)))
+        );
+        assert!(bands.contains(Decimal::from(110)));
+        assert!(!bands.contains(Decimal::from(111)));
+    }
+
+    #[test]
+    fn test_dynamic_band_tightens_static_band() {
+        let mut bands = PriceBands::new(
+            config(Some(Decimal::new(10, 2)), Some(Decimal::new(2, 2))),
+            Some(Decimal::from(100)),
+        );
+        assert!(bands.contains(Decimal::from(105)));
+
+        bands.record_trade(Decimal::from(100));
+        assert_eq!(
+            bands.limits(),
+            (Some(Decimal::from(98)), Some(Decimal::from(102)))
+        );
+        assert!(!bands.contains(Decimal::from(105)));
+    }
+}
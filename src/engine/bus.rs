@@ -0,0 +1,346 @@
+//! Bounded fan-out of engine events to multiple subscribers.
+//!
+//! Every subscriber owns a fixed-capacity queue and chooses what happens when
+//! it falls behind: the publisher waits, the oldest event is dropped, or the
+//! subscription is cut and the consumer has to resync from a snapshot.
+
+use parking_lot::{Mutex, RwLock};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::pin::pin;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use thiserror::Error;
+use tokio::sync::Notify;
+use tracing::warn;
+
+/// What the bus does when a subscriber's queue is full.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BackpressurePolicy {
+    /// The publisher waits until the subscriber has room.
+    Block,
+    /// The oldest queued event is discarded to make room.
+    DropOldest,
+    /// The queue is cleared and the subscriber must resync before receiving again.
+    Disconnect,
+}
+
+/// Error returned by [`Subscriber::recv`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+pub enum RecvError {
+    /// The subscriber overflowed under [`BackpressurePolicy::Disconnect`] and
+    /// `dropped` events were lost. Call [`Subscriber::resync`] to continue.
+    #[error("subscriber lagged, {dropped} events dropped")]
+    Lagged { dropped: u64 },
+    /// The bus has been dropped and no more events will arrive.
+    #[error("event bus closed")]
+    Closed,
+}
+
+/// Point-in-time counters for a single subscriber.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SubscriberMetrics {
+    pub id: u64,
+    pub policy: BackpressurePolicy,
+    pub capacity: usize,
+    pub depth: usize,
+    pub delivered: u64,
+    pub dropped: u64,
+    pub disconnects: u64,
+}
+
+struct Queue<T> {
+    id: u64,
+    policy: BackpressurePolicy,
+    capacity: usize,
+    items: Mutex<VecDeque<T>>,
+    item_ready: Notify,
+    space_ready: Notify,
+    lagged: AtomicU64,
+    detached: AtomicBool,
+    closed: AtomicBool,
+    delivered: AtomicU64,
+    dropped: AtomicU64,
+    disconnects: AtomicU64,
+}
+
+impl<T> Queue<T> {
+    fn metrics(&self) -> SubscriberMetrics {
+        SubscriberMetrics {
+            id: self.id,
+            policy: self.policy,
+            capacity: self.capacity,
+            depth: self.items.lock().len(),
+            delivered: self.delivered.load(Ordering::Relaxed),
+            dropped: self.dropped.load(Ordering::Relaxed),
+            disconnects: self.disconnects.load(Ordering::Relaxed),
+        }
+    }
+
+    async fn push(&self, item: T) {
+        match self.policy {
+            BackpressurePolicy::Block => {
+                let mut item = Some(item);
+                loop {
+                    let mut space = pin!(self.space_ready.notified());
+                    space.as_mut().enable();
+                    {
+                        let mut items = self.items.lock();
+                        if items.len() < self.capacity || self.detached.load(Ordering::Acquire) {
+                            if !self.detached.load(Ordering::Acquire) {
+                                items.extend(item.take());
+                            }
+                            break;
+                        }
+                    }
+                    space.await;
+                }
+            }
+            BackpressurePolicy::DropOldest => {
+                let mut items = self.items.lock();
+                if items.len() >= self.capacity && items.pop_front().is_some() {
+                    self.dropped.fetch_add(1, Ordering::Relaxed);
+                }
+                items.push_back(item);
+            }
+            BackpressurePolicy::Disconnect => {
+                if self.lagged.load(Ordering::Acquire) > 0 {
+                    self.lagged.fetch_add(1, Ordering::AcqRel);
+                    self.dropped.fetch_add(1, Ordering::Relaxed);
+                    return;
+                }
+                let mut items = self.items.lock();
+                if items.len() >= self.capacity {
+                    let lost = items.len() as u64 + 1;
+                    items.clear();
+                    self.lagged.store(lost, Ordering::Release);
+                    self.dropped.fetch_add(lost, Ordering::Relaxed);
+                    self.disconnects.fetch_add(1, Ordering::Relaxed);
+                    warn!("Subscriber {} lagged, disconnecting until resync", self.id);
+                } else {
+                    items.push_back(item);
+                }
+            }
+        }
+        self.item_ready.notify_one();
+    }
+}
+
+/// Multi-subscriber publisher with per-subscriber bounded queues.
+pub struct EventBus<T> {
+    subscribers: RwLock<Vec<Arc<Queue<T>>>>,
+    next_id: AtomicU64,
+}
+
+impl<T: Clone> EventBus<T> {
+    pub fn new() -> Self {
+        Self {
+            subscribers: RwLock::new(Vec::new()),
+            next_id: AtomicU64::new(1),
+        }
+    }
+
+    /// Registers a new subscriber with a queue of `capacity` events.
+    pub fn subscribe(&self, capacity: usize, policy: BackpressurePolicy) -> Subscriber<T> {
+        let queue = Arc::new(Queue {
+            id: self.next_id.fetch_add(1, Ordering::Relaxed),
+            policy,
+            capacity: capacity.max(1),
+            items: Mutex::new(VecDeque::with_capacity(capacity.max(1))),
+            item_ready: Notify::new(),
+            space_ready: Notify::new(),
+            lagged: AtomicU64::new(0),
+            detached: AtomicBool::new(false),
+            closed: AtomicBool::new(false),
+            delivered: AtomicU64::new(0),
+            dropped: AtomicU64::new(0),
+            disconnects: AtomicU64::new(0),
+        });
+        self.subscribers.write().push(queue.clone());
+        Subscriber { queue }
+    }
+
+    /// Delivers `event` to every live subscriber according to its policy.
+    ///
+    /// Only waits when a [`BackpressurePolicy::Block`] subscriber is full.
+    pub async fn publish(&self, event: T) {
+        let subscribers: Vec<Arc<Queue<T>>> = {
+            let mut subscribers = self.subscribers.write();
+            subscribers.retain(|q| !q.detached.load(Ordering::Acquire));
+            subscribers.clone()
+        };
+
+        for queue in subscribers {
+            queue.push(event.clone()).await;
+        }
+    }
+
+    pub fn subscriber_count(&self) -> usize {
+        self.subscribers
+            .read()
+            .iter()
+            .filter(|q| !q.detached.load(Ordering::Acquire))
+            .count()
+    }
+
+    pub fn metrics(&self) -> Vec<SubscriberMetrics> {
+        self.subscribers
+            .read()
+            .iter()
+            .map(|q| q.metrics())
+            .collect()
+    }
+}
+
+impl<T: Clone> Default for EventBus<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Drop for EventBus<T> {
+    fn drop(&mut self) {
+        for queue in self.subscribers.read().iter() {
+            queue.closed.store(true, Ordering::Release);
+            queue.item_ready.notify_one();
+        }
+    }
+}
+
+/// Receiving half of an [`EventBus`] subscription.
+pub struct Subscriber<T> {
+    queue: Arc<Queue<T>>,
+}
+
+impl<T> Subscriber<T> {
+    /// Waits for the next event.
+    pub async fn recv(&mut self) -> Result<T, RecvError> {
+        let queue = self.queue.clone();
+        loop {
+            let mut ready = pin!(queue.item_ready.notified());
+            ready.as_mut().enable();
+
+            if let Some(item) = self.try_recv()? {
+                return Ok(item);
+            }
+            if queue.closed.load(Ordering::Acquire) {
+                return Err(RecvError::Closed);
+            }
+            ready.await;
+        }
+    }
+
+    /// Returns the next queued event without waiting.
+    pub fn try_recv(&mut self) -> Result<Option<T>, RecvError> {
+        let dropped = self.queue.lagged.load(Ordering::Acquire);
+        if dropped > 0 {
+            return Err(RecvError::Lagged { dropped });
+        }
+
+        let item = self.queue.items.lock().pop_front();
+        if item.is_some() {
+            self.queue.delivered.fetch_add(1, Ordering::Relaxed);
+            self.queue.space_ready.notify_one();
+        }
+        Ok(item)
+    }
+
+    /// Re-attaches a subscriber cut off under [`BackpressurePolicy::Disconnect`].
+    ///
+    /// Events published before this call are lost; the consumer should rebuild
+    /// its state from a snapshot taken after resyncing.
+    pub fn resync(&mut self) {
+        self.queue.lagged.store(0, Ordering::Release);
+    }
+
+    pub fn id(&self) -> u64 {
+        self.queue.id
+    }
+
+    /// Number of events currently queued for this subscriber.
+    pub fn depth(&self) -> usize {
+        self.queue.items.lock().len()
+    }
+
+    pub fn metrics(&self) -> SubscriberMetrics {
+        self.queue.metrics()
+    }
+}
+
+impl<T> Drop for Subscriber<T> {
+    fn drop(&mut self) {
+        self.queue.detached.store(true, Ordering::Release);
+        self.queue.space_ready.notify_one();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_bus_fans_out_to_all_subscribers() {
+        let bus = EventBus::new();
+        let mut a = bus.subscribe(4, BackpressurePolicy::Block);
+        let mut b = bus.subscribe(4, BackpressurePolicy::DropOldest);
+
+        bus.publish(1u32).await;
+        bus.publish(2u32).await;
+
+        assert_eq!(a.recv().await, Ok(1));
+        assert_eq!(a.recv().await, Ok(2));
+        assert_eq!(b.recv().await, Ok(1));
+        assert_eq!(b.recv().await, Ok(2));
+    }
+
+    #[tokio::test]
+    async fn test_bus_drop_oldest_counts_drops() {
+        let bus = EventBus::new();
+        let mut sub = bus.subscribe(2, BackpressurePolicy::DropOldest);
+
+        for i in 0..5u32 {
+            bus.publish(i).await;
+        }
+
+        assert_eq!(sub.depth(), 2);
+        assert_eq!(sub.metrics().dropped, 3);
+        assert_eq!(sub.recv().await, Ok(3));
+        assert_eq!(sub.recv().await, Ok(4));
+    }
+
+    #[tokio::test]
+    async fn test_bus_disconnect_requires_resync() {
+        let bus = EventBus::new();
+        let mut sub = bus.subscribe(2, BackpressurePolicy::Disconnect);
+
+        for i in 0..4u32 {
+            bus.publish(i).await;
+        }
+        assert_eq!(sub.recv().await, Err(RecvError::Lagged { dropped: 4 }));
+
+        sub.resync();
+        bus.publish(10).await;
+        assert_eq!(sub.recv().await, Ok(10));
+        assert_eq!(sub.metrics().disconnects, 1);
+    }
+
+    #[tokio::test]
+    async fn test_bus_block_waits_for_consumer() {
+        let bus = Arc::new(EventBus::new());
+        let mut sub = bus.subscribe(1, BackpressurePolicy::Block);
+
+        bus.publish(1u32).await;
+        let publisher = {
+            let bus = bus.clone();
+            tokio::spawn(async move { bus.publish(2u32).await })
+        };
+
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(!publisher.is_finished());
+
+        assert_eq!(sub.recv().await, Ok(1));
+        publisher.await.unwrap();
+        assert_eq!(sub.recv().await, Ok(2));
+    }
+}
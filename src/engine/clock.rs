@@ -0,0 +1,55 @@
+//! Time source for the matching engine.
+//!
+//! Everything time-based in the engine (order expiry, volatility auction end
+//! times) reads the engine clock rather than the system time, so a replay or
+//! simulation driven by a [`SimulatedClock`] behaves exactly like live trading.
+
+use chrono::{DateTime, Duration, Utc};
+use parking_lot::RwLock;
+
+pub trait Clock: Send + Sync {
+    fn now(&self) -> DateTime<Utc>;
+}
+
+/// Wall-clock time.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+/// Manually driven time for backtests and replays. It never runs backwards.
+#[derive(Debug)]
+pub struct SimulatedClock {
+    now: RwLock<DateTime<Utc>>,
+}
+
+impl SimulatedClock {
+    pub fn new(start: DateTime<Utc>) -> Self {
+        Self {
+            now: RwLock::new(start),
+        }
+    }
+
+    /// Moves the clock to `time`; earlier times are ignored.
+    pub fn set(&self, time: DateTime<Utc>) {
+        let mut now = self.now.write();
+        if time > *now {
+            *now = time;
+        }
+    }
+
+    pub fn advance(&self, by: Duration) {
+        let mut now = self.now.write();
+        *now += by.max(Duration::zero());
+    }
+}
+
+impl Clock for SimulatedClock {
+    fn now(&self) -> DateTime<Utc> {
+        *self.now.read()
+    }
+}
@@ -0,0 +1,584 @@
+//! One-cancels-other and bracket orders layered on top of the matching engine.
+//!
+//! The manager submits ordinary orders to [`MatchingEngine`] and reacts to its
+//! execution reports: a trade on one OCO leg cancels the other, and fills on a
+//! bracket's entry order release take-profit/stop-loss children sized in
+//! proportion to how much of the entry has filled.
+
+use crate::engine::bus::{RecvError, Subscriber};
+use crate::engine::matching::MatchingEngine;
+use crate::utils::types::{ExecType, ExecutionReport, Order, OrderStatus};
+use parking_lot::Mutex;
+use rust_decimal::Decimal;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::mpsc;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+/// A live OCO pair. Groups are dropped as soon as either leg trades.
+#[derive(Debug, Clone)]
+struct OcoGroup {
+    symbol: String,
+    legs: [Uuid; 2],
+}
+
+impl OcoGroup {
+    fn sibling(&self, leg: Uuid) -> Uuid {
+        if self.legs[0] == leg {
+            self.legs[1]
+        } else {
+            self.legs[0]
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Bracket {
+    entry_quantity: Decimal,
+    take_profit: Order,
+    stop_loss: Order,
+    /// Cumulative entry fill the children have been sized for.
+    entry_filled: Decimal,
+    /// Child quantity currently working or already traded, per leg.
+    released: [Decimal; 2],
+    /// The most recently submitted child pair.
+    active: Option<Uuid>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    oco_groups: HashMap<Uuid, OcoGroup>,
+    leg_to_group: HashMap<Uuid, Uuid>,
+    brackets: HashMap<Uuid, Bracket>,
+}
+
+impl State {
+    /// Removes a group and its leg index, returning it if it was still live.
+    fn close_group(&mut self, group_id: Uuid) -> Option<OcoGroup> {
+        let group = self.oco_groups.remove(&group_id)?;
+        for leg in group.legs {
+            self.leg_to_group.remove(&leg);
+        }
+        Some(group)
+    }
+}
+
+/// Contingent order layer driven by [`MatchingEngine`] execution reports.
+///
+/// Feed it reports through [`ContingentOrderManager::run`] or
+/// [`ContingentOrderManager::handle_execution`]. The manager submits and
+/// cancels orders while handling reports, and those publish further reports
+/// to its own subscription; `run` keeps draining the subscription meanwhile,
+/// so it is safe with
+/// [`BackpressurePolicy::Block`](crate::engine::bus::BackpressurePolicy::Block).
+/// Callers driving `handle_execution` themselves must do the same.
+pub struct ContingentOrderManager {
+    engine: Arc<MatchingEngine>,
+    state: Mutex<State>,
+}
+
+impl ContingentOrderManager {
+    pub fn new(engine: Arc<MatchingEngine>) -> Self {
+        Self {
+            engine,
+            state: Mutex::new(State::default()),
+        }
+    }
+
+    /// Submits two orders as a one-cancels-other pair and returns the group id.
+    ///
+    /// If the first leg trades on arrival the second is never submitted. If
+    /// the second leg is rejected the first is cancelled and the error returned.
+    pub async fn submit_oco(&self, first: Order, second: Order) -> anyhow::Result<Uuid> {
+        if first.symbol != second.symbol {
+            return Err(anyhow::anyhow!("OCO legs must share a symbol"));
+        }
+
+        let group_id = Uuid::new_v4();
+        let (first_id, symbol) = (first.id, first.symbol.clone());
+        self.register_group(group_id, &first, &second);
+
+        let first_result = match self.engine.submit_order(first).await {
+            Ok(order) => order,
+            Err(e) => {
+                self.state.lock().close_group(group_id);
+                return Err(e);
+            }
+        };
+        if first_result.filled_quantity > Decimal::ZERO
+            || !self.state.lock().oco_groups.contains_key(&group_id)
+        {
+            self.state.lock().close_group(group_id);
+            info!("OCO {} first leg traded, second leg not sent", group_id);
+            return Ok(group_id);
+        }
+
+        match self.engine.submit_order(second).await {
+            Ok(order) if order.filled_quantity > Decimal::ZERO => {
+                self.on_leg_traded(order.id).await;
+            }
+            Ok(_) => {}
+            Err(e) => {
+                warn!("OCO {} second leg failed: {}", group_id, e);
+                self.state.lock().close_group(group_id);
+                self.cancel_unprotected(first_id, &symbol).await;
+                return Err(e);
+            }
+        }
+
+        Ok(group_id)
+    }
+
+    /// Submits `entry` with take-profit and stop-loss children.
+    ///
+    /// The children are held back until the entry fills and are then released
+    /// as an OCO pair, sized by the entry's cumulative fill ratio and rounded
+    /// down to whole lots.
+    pub async fn submit_bracket(
+        &self,
+        entry: Order,
+        take_profit: Order,
+        stop_loss: Order,
+    ) -> anyhow::Result<Order> {
+        for child in [&take_profit, &stop_loss] {
+            if child.symbol != entry.symbol || child.side == entry.side {
+                return Err(anyhow::anyhow!(
+                    "Bracket children must be on the opposite side of the same symbol"
+                ));
+            }
+        }
+
+        let entry_id = entry.id;
+        self.state.lock().brackets.insert(
+            entry_id,
+            Bracket {
+                entry_quantity: entry.quantity,
+                take_profit,
+                stop_loss,
+                entry_filled: Decimal::ZERO,
+                released: [Decimal::ZERO; 2],
+                active: None,
+            },
+        );
+
+        let result = match self.engine.submit_order(entry).await {
+            Ok(order) => order,
+            Err(e) => {
+                self.state.lock().brackets.remove(&entry_id);
+                return Err(e);
+            }
+        };
+
+        if result.filled_quantity > Decimal::ZERO {
+            self.on_entry_filled(entry_id, result.filled_quantity).await;
+        }
+        if !matches!(
+            result.status,
+            OrderStatus::Open | OrderStatus::PartiallyFilled
+        ) {
+            self.state.lock().brackets.remove(&entry_id);
+        }
+
+        Ok(result)
+    }
+
+    /// Applies one execution report to the OCO groups and brackets it touches.
+    pub async fn handle_execution(&self, report: &ExecutionReport) {
+        if report.exec_type == ExecType::Trade {
+            self.on_leg_traded(report.order_id).await;
+
+            let is_entry = self.state.lock().brackets.contains_key(&report.order_id);
+            if is_entry {
+                self.on_entry_filled(report.order_id, report.filled_quantity)
+                    .await;
+            }
+        }
+
+        if matches!(
+            report.status,
+            OrderStatus::Filled
+                | OrderStatus::Cancelled
+                | OrderStatus::Rejected
+                | OrderStatus::Expired
+        ) {
+            self.state.lock().brackets.remove(&report.order_id);
+        }
+    }
+
+    /// Consumes execution reports until the engine's bus closes.
+    ///
+    /// Reports are moved off the subscription into a local queue as they
+    /// arrive and handled from there. A follow-up submit or cancel that waits
+    /// on a full subscription therefore never waits on itself.
+    pub async fn run(&self, mut executions: Subscriber<ExecutionReport>) {
+        let (report_tx, mut report_rx) = mpsc::unbounded_channel();
+        let receive = async move {
+            loop {
+                match executions.recv().await {
+                    Ok(report) => {
+                        if report_tx.send(report).is_err() {
+                            break;
+                        }
+                    }
+                    Err(RecvError::Lagged { dropped }) => {
+                        warn!(
+                            "Contingent order manager missed {} execution reports",
+                            dropped
+                        );
+                        executions.resync();
+                    }
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        };
+        let handle = async {
+            while let Some(report) = report_rx.recv().await {
+                self.handle_execution(&report).await;
+            }
+        };
+        tokio::join!(receive, handle);
+    }
+
+    /// Number of OCO pairs still waiting for either leg to trade.
+    pub fn active_oco_groups(&self) -> usize {
+        self.state.lock().oco_groups.len()
+    }
+
+    /// Number of bracket entries still working.
+    pub fn active_brackets(&self) -> usize {
+        self.state.lock().brackets.len()
+    }
+
+    fn register_group(&self, group_id: Uuid, first: &Order, second: &Order) {
+        let mut state = self.state.lock();
+        state.oco_groups.insert(
+            group_id,
+            OcoGroup {
+                symbol: first.symbol.clone(),
+                legs: [first.id, second.id],
+            },
+        );
+        state.leg_to_group.insert(first.id, group_id);
+        state.leg_to_group.insert(second.id, group_id);
+    }
+
+    /// Closes the group of `leg` and cancels its sibling.
+    async fn on_leg_traded(&self, leg: Uuid) {
+        let group = {
+            let mut state = self.state.lock();
+            let Some(group_id) = state.leg_to_group.get(&leg).copied() else {
+                return;
+            };
+            state.close_group(group_id)
+        };
+        let Some(group) = group else {
+            return;
+        };
+
+        let sibling = group.sibling(leg);
+        // The sibling may not have been sent yet or may have filled already
+        if self
+            .engine
+            .cancel_order(sibling, &group.symbol)
+            .await
+            .is_ok()
+        {
+            info!("OCO leg {} traded, cancelled {}", leg, sibling);
+        }
+    }
+
+    /// Releases bracket children for the entry's cumulative fill `entry_filled`.
+    async fn on_entry_filled(&self, entry_id: Uuid, entry_filled: Decimal) {
+        // Take the untouched pair out of the book first so it can be resized
+        let untouched = {
+            let mut state = self.state.lock();
+            let Some(bracket) = state.brackets.get_mut(&entry_id) else {
+                return;
+            };
+            if entry_filled <= bracket.entry_filled {
+                return;
+            }
+            bracket.entry_filled = entry_filled;
+            let active = bracket.active.take();
+            active.and_then(|group_id| state.close_group(group_id))
+        };
+
+        if let Some(group) = untouched {
+            for (i, leg) in group.legs.into_iter().enumerate() {
+                if let Ok(cancelled) = self.engine.cancel_order(leg, &group.symbol).await {
+                    let mut state = self.state.lock();
+                    if let Some(bracket) = state.brackets.get_mut(&entry_id) {
+                        bracket.released[i] -= cancelled.remaining_quantity();
+                    }
+                }
+            }
+        }
+
+        let (take_profit, stop_loss) = {
+            let mut state = self.state.lock();
+            let Some(bracket) = state.brackets.get_mut(&entry_id) else {
+                return;
+            };
+            let spec = self
+                .engine
+                .instrument_spec(&bracket.take_profit.symbol)
+                .unwrap_or_default();
+            let ratio = bracket.entry_filled / bracket.entry_quantity;
+
+            let mut children = [bracket.take_profit.clone(), bracket.stop_loss.clone()];
+            for (i, child) in children.iter_mut().enumerate() {
+                let target =
+                    spec.lots_to_quantity(spec.quantity_to_lots_floor(child.quantity * ratio));
+                let quantity = (target - bracket.released[i]).max(Decimal::ZERO);
+                bracket.released[i] += quantity;
+                *child = Order {
+                    id: Uuid::new_v4(),
+                    quantity,
+                    filled_quantity: Decimal::ZERO,
+                    status: OrderStatus::Pending,
+                    timestamp: self.engine.now(),
+                    ..child.clone()
+                };
+            }
+            let [take_profit, stop_loss] = children;
+            (take_profit, stop_loss)
+        };
+
+        if take_profit.quantity.is_zero() || stop_loss.quantity.is_zero() {
+            // Too little filled for a whole lot on both legs; send the pair later
+            let mut state = self.state.lock();
+            if let Some(bracket) = state.brackets.get_mut(&entry_id) {
+                bracket.released[0] -= take_profit.quantity;
+                bracket.released[1] -= stop_loss.quantity;
+            }
+            return;
+        }
+
+        let group_id = Uuid::new_v4();
+        self.state
+            .lock()
+            .brackets
+            .entry(entry_id)
+            .and_modify(|b| b.active = Some(group_id));
+        info!(
+            "Bracket {} releasing take-profit {} and stop-loss {}",
+            entry_id, take_profit.quantity, stop_loss.quantity
+        );
+        if let Err(e) = self.submit_pair(group_id, take_profit, stop_loss).await {
+            warn!("Bracket {} children failed: {}", entry_id, e);
+        }
+    }
+
+    async fn submit_pair(&self, group_id: Uuid, first: Order, second: Order) -> anyhow::Result<()> {
+        let (first_id, symbol) = (first.id, first.symbol.clone());
+        self.register_group(group_id, &first, &second);
+
+        let first_result = self.engine.submit_order(first).await?;
+        if first_result.filled_quantity > Decimal::ZERO
+            || !self.state.lock().oco_groups.contains_key(&group_id)
+        {
+            self.state.lock().close_group(group_id);
+            return Ok(());
+        }
+
+        let second_result = match self.engine.submit_order(second).await {
+            Ok(order) => order,
+            Err(e) => {
+                self.state.lock().close_group(group_id);
+                self.cancel_unprotected(first_id, &symbol).await;
+                return Err(e);
+            }
+        };
+        if second_result.filled_quantity > Decimal::ZERO {
+            self.on_leg_traded(second_result.id).await;
+        }
+        Ok(())
+    }
+
+    /// Cancels the first leg of a pair whose second leg never made it into the
+    /// book, so it does not rest without its protection.
+    async fn cancel_unprotected(&self, leg: Uuid, symbol: &str) {
+        // It may have traded against something in the meantime
+        if let Err(e) = self.engine.cancel_order(leg, symbol).await {
+            warn!("Could not cancel unprotected leg {}: {}", leg, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::bus::BackpressurePolicy;
+    use crate::utils::types::{OrderType, Side};
+
+    fn order(side: Side, order_type: OrderType, price: i64, quantity: i64) -> Order {
+        Order::new(
+            "BTCUSD".to_string(),
+            side,
+            order_type,
+            Decimal::from(price),
+            Decimal::from(quantity),
+        )
+    }
+
+    fn stop(side: Side, stop_price: i64, quantity: i64) -> Order {
+        Order {
+            stop_price: Some(Decimal::from(stop_price)),
+            ..order(side, OrderType::StopMarket, 0, quantity)
+        }
+    }
+
+    async fn drain(manager: &ContingentOrderManager, rx: &mut Subscriber<ExecutionReport>) {
+        while let Ok(Some(report)) = rx.try_recv() {
+            manager.handle_execution(&report).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_oco_fill_cancels_sibling() {
+        let engine = Arc::new(MatchingEngine::new());
+        let mut rx = engine.subscribe_executions(1024, BackpressurePolicy::Block);
+        let manager = ContingentOrderManager::new(engine.clone());
+
+        let take_profit = order(Side::Sell, OrderType::Limit, 110, 1);
+        let stop_loss = stop(Side::Sell, 90, 1);
+        let stop_loss_id = stop_loss.id;
+        manager.submit_oco(take_profit, stop_loss).await.unwrap();
+        assert_eq!(manager.active_oco_groups(), 1);
+
+        engine
+            .submit_order(order(Side::Buy, OrderType::Limit, 110, 1))
+            .await
+            .unwrap();
+        drain(&manager, &mut rx).await;
+
+        assert_eq!(manager.active_oco_groups(), 0);
+        assert!(engine.cancel_order(stop_loss_id, "BTCUSD").await.is_err());
+        assert!(engine
+            .get_or_create_orderbook("BTCUSD")
+            .stop_orders()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_run_does_not_block_on_its_own_reports() {
+        let engine = Arc::new(MatchingEngine::new());
+        let rx = engine.subscribe_executions(1, BackpressurePolicy::Block);
+        let manager = Arc::new(ContingentOrderManager::new(engine.clone()));
+        tokio::spawn({
+            let manager = manager.clone();
+            async move { manager.run(rx).await }
+        });
+
+        // Releasing the children publishes two reports into the one-slot queue
+        // from inside the manager's own handler
+        let released = async {
+            let entry = order(Side::Buy, OrderType::Limit, 100, 1);
+            let take_profit = order(Side::Sell, OrderType::Limit, 120, 1);
+            let stop_loss = stop(Side::Sell, 80, 1);
+            manager
+                .submit_bracket(entry, take_profit, stop_loss)
+                .await
+                .unwrap();
+            engine
+                .submit_order(order(Side::Sell, OrderType::Limit, 100, 1))
+                .await
+                .unwrap();
+            // Its report only gets out once the manager has drained its own
+            engine
+                .submit_order(order(Side::Buy, OrderType::Limit, 50, 1))
+                .await
+                .unwrap();
+        };
+        tokio::time::timeout(std::time::Duration::from_secs(5), released)
+            .await
+            .expect("contingent manager deadlocked");
+
+        assert_eq!(manager.active_oco_groups(), 1);
+        assert_eq!(
+            engine.get_or_create_orderbook("BTCUSD").stop_orders().len(),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn test_oco_rejected_second_leg_cancels_first() {
+        let engine = Arc::new(MatchingEngine::new());
+        let manager = ContingentOrderManager::new(engine.clone());
+
+        let take_profit = order(Side::Sell, OrderType::Limit, 110, 1);
+        let take_profit_id = take_profit.id;
+        // A stop without a stop price is rejected by the engine
+        let stop_loss = order(Side::Sell, OrderType::StopMarket, 0, 1);
+        assert!(manager.submit_oco(take_profit, stop_loss).await.is_err());
+
+        assert_eq!(manager.active_oco_groups(), 0);
+        assert!(engine.cancel_order(take_profit_id, "BTCUSD").await.is_err());
+        assert!(engine
+            .get_orderbook_snapshot("BTCUSD")
+            .unwrap()
+            .asks
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_bracket_children_follow_partial_fills() {
+        let engine = Arc::new(MatchingEngine::new());
+        let mut rx = engine.subscribe_executions(1024, BackpressurePolicy::Block);
+        let manager = ContingentOrderManager::new(engine.clone());
+
+        let entry = order(Side::Buy, OrderType::Limit, 100, 4);
+        let take_profit = order(Side::Sell, OrderType::Limit, 120, 4);
+        let stop_loss = stop(Side::Sell, 80, 4);
+        let result = manager
+            .submit_bracket(entry, take_profit, stop_loss)
+            .await
+            .unwrap();
+        assert_eq!(result.status, OrderStatus::Open);
+        drain(&manager, &mut rx).await;
+        assert!(engine
+            .get_orderbook_snapshot("BTCUSD")
+            .unwrap()
+            .asks
+            .is_empty());
+
+        engine
+            .submit_order(order(Side::Sell, OrderType::Limit, 100, 1))
+            .await
+            .unwrap();
+        drain(&manager, &mut rx).await;
+
+        let book = engine.get_or_create_orderbook("BTCUSD");
+        assert_eq!(book.get_depth(Side::Sell, 1)[0].quantity, Decimal::from(1));
+        assert_eq!(
+            book.stop_orders().iter().next().unwrap().quantity,
+            Decimal::from(1)
+        );
+
+        // The untouched pair is replaced by one covering the whole fill
+        engine
+            .submit_order(order(Side::Sell, OrderType::Limit, 100, 2))
+            .await
+            .unwrap();
+        drain(&manager, &mut rx).await;
+
+        let book = engine.get_or_create_orderbook("BTCUSD");
+        assert_eq!(book.get_depth(Side::Sell, 1)[0].quantity, Decimal::from(3));
+        assert_eq!(book.get_depth(Side::Sell, 1)[0].order_count, 1);
+        assert_eq!(book.stop_orders().len(), 1);
+        assert_eq!(manager.active_oco_groups(), 1);
+
+        // Take-profit trades, so the stop-loss goes
+        engine
+            .submit_order(order(Side::Buy, OrderType::Limit, 120, 3))
+            .await
+            .unwrap();
+        drain(&manager, &mut rx).await;
+        assert!(engine
+            .get_or_create_orderbook("BTCUSD")
+            .stop_orders()
+            .is_empty());
+        assert_eq!(manager.active_oco_groups(), 0);
+    }
+}
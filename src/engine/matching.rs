@@ -1,24 +1,114 @@
-use crate::engine::orderbook::OrderBook;
-use crate::utils::types::{Order, OrderStatus, Trade};
+use crate::engine::bands::{BandBreachAction, PriceBandConfig};
+use crate::engine::bus::{BackpressurePolicy, EventBus, Subscriber, SubscriberMetrics};
+use crate::engine::clock::{Clock, SystemClock};
+use crate::engine::orderbook::{Fill, OrderBook};
+use crate::engine::stops;
+use crate::engine::ticks::InstrumentSpec;
+use crate::engine::timer::TimerWheel;
+use crate::utils::types::{
+    ExecType, ExecutionReport, Order, OrderStatus, OrderType, Side, Trade, TrailingOffset,
+};
+use chrono::{DateTime, Utc};
 use dashmap::DashMap;
+use parking_lot::Mutex;
+use rust_decimal::Decimal;
 use std::sync::Arc;
-use tokio::sync::mpsc;
-use tracing::{error, info};
+use tracing::{info, warn};
 use uuid::Uuid;
 
+/// Resolution of the expiry timer wheel.
+const EXPIRY_TICK_MS: i64 = 100;
+/// Slots in the expiry timer wheel; one revolution covers 51.2 seconds.
+const EXPIRY_SLOTS: usize = 512;
+
 pub struct MatchingEngine {
     orderbooks: Arc<DashMap<String, OrderBook>>,
-    trade_sender: mpsc::UnboundedSender<Trade>,
+    trades: EventBus<Trade>,
+    executions: EventBus<ExecutionReport>,
+    clock: Arc<dyn Clock>,
+    expiries: Mutex<TimerWheel<(String, Uuid)>>,
+}
+
+/// Trades and execution reports produced by one engine operation, published together.
+#[derive(Default)]
+struct Outcome {
+    trades: Vec<Trade>,
+    reports: Vec<ExecutionReport>,
+}
+
+impl Outcome {
+    fn record_fills(&mut self, fills: Vec<Fill>) {
+        for fill in fills {
+            self.reports
+                .push(ExecutionReport::fill(&fill.buy, &fill.trade));
+            self.reports
+                .push(ExecutionReport::fill(&fill.sell, &fill.trade));
+            self.trades.push(fill.trade);
+        }
+    }
 }
 
 impl MatchingEngine {
-    pub fn new(trade_sender: mpsc::UnboundedSender<Trade>) -> Self {
+    pub fn new() -> Self {
+        Self::with_clock(Arc::new(SystemClock))
+    }
+
+    /// Creates an engine whose expiries and auctions run on `clock`, such as a
+    /// [`SimulatedClock`](crate::engine::clock::SimulatedClock) for replays.
+    pub fn with_clock(clock: Arc<dyn Clock>) -> Self {
+        let expiries = TimerWheel::new(
+            chrono::Duration::milliseconds(EXPIRY_TICK_MS),
+            EXPIRY_SLOTS,
+            clock.now(),
+        );
         Self {
             orderbooks: Arc::new(DashMap::new()),
-            trade_sender,
+            trades: EventBus::new(),
+            executions: EventBus::new(),
+            clock,
+            expiries: Mutex::new(expiries),
         }
     }
 
+    /// Current engine time.
+    pub fn now(&self) -> DateTime<Utc> {
+        self.clock.now()
+    }
+
+    /// Subscribes to executed trades through a bounded queue of `capacity` trades.
+    pub fn subscribe_trades(
+        &self,
+        capacity: usize,
+        policy: BackpressurePolicy,
+    ) -> Subscriber<Trade> {
+        self.trades.subscribe(capacity, policy)
+    }
+
+    /// Queue depth and drop counters for every trade subscriber.
+    pub fn trade_subscriber_metrics(&self) -> Vec<SubscriberMetrics> {
+        self.trades.metrics()
+    }
+
+    /// Subscribes to per-order execution reports for both sides of every trade,
+    /// plus acceptances, cancels, rejects and stop triggers.
+    pub fn subscribe_executions(
+        &self,
+        capacity: usize,
+        policy: BackpressurePolicy,
+    ) -> Subscriber<ExecutionReport> {
+        self.executions.subscribe(capacity, policy)
+    }
+
+    /// Queue depth and drop counters for every execution report subscriber.
+    pub fn execution_subscriber_metrics(&self) -> Vec<SubscriberMetrics> {
+        self.executions.metrics()
+    }
+
+    pub fn instrument_spec(&self, symbol: &str) -> Option<InstrumentSpec> {
+        self.orderbooks.get(symbol).map(|book| *book.spec())
+    }
+
+    /// Returns a copy of the book for `symbol`, creating an empty one if needed.
     pub fn get_or_create_orderbook(&self, symbol: &str) -> OrderBook {
         self.orderbooks
             .entry(symbol.to_string())
@@ -26,18 +116,210 @@ impl MatchingEngine {
             .clone()
     }
 
+    /// Sets the tick and lot sizes used for `symbol`.
+    ///
+    /// Fails if the symbol already has resting or stop orders, since re-scaling
+    /// them could move them off the grid.
+    pub fn set_instrument_spec(&self, symbol: &str, spec: InstrumentSpec) -> anyhow::Result<()> {
+        let mut book = self
+            .orderbooks
+            .entry(symbol.to_string())
+            .or_insert_with(|| OrderBook::with_spec(symbol.to_string(), spec));
+
+        if book.spec() == &spec {
+            return Ok(());
+        }
+        if !book.is_empty() {
+            return Err(anyhow::anyhow!(
+                "Cannot change instrument spec for {} with resting orders",
+                symbol
+            ));
+        }
+
+        let mut rescaled = OrderBook::with_spec(symbol.to_string(), spec);
+        rescaled.set_snapshot_depth(book.snapshot_depth());
+        if let Some(bands) = book.price_bands() {
+            rescaled.set_price_bands(bands.config().clone(), bands.reference_price());
+        }
+        *book = rescaled;
+        Ok(())
+    }
+
+    /// Sets how many levels per side [`MatchingEngine::get_orderbook_snapshot`] returns for `symbol`.
+    pub fn set_snapshot_depth(&self, symbol: &str, levels: usize) {
+        self.orderbooks
+            .entry(symbol.to_string())
+            .or_insert_with(|| OrderBook::new(symbol.to_string()))
+            .set_snapshot_depth(levels);
+    }
+
+    /// Installs static/dynamic price bands for `symbol`, anchored on `reference_price`.
+    pub fn set_price_bands(
+        &self,
+        symbol: &str,
+        config: PriceBandConfig,
+        reference_price: Option<Decimal>,
+    ) {
+        self.orderbooks
+            .entry(symbol.to_string())
+            .or_insert_with(|| OrderBook::new(symbol.to_string()))
+            .set_price_bands(config, reference_price);
+    }
+
+    /// Uncrosses every symbol whose volatility auction has reached its end time.
+    pub async fn run_due_auctions(&self) -> Vec<Trade> {
+        let now = self.clock.now();
+        let mut outcome = Outcome::default();
+
+        for mut entry in self.orderbooks.iter_mut() {
+            if entry.is_auction_due(now) {
+                info!("Volatility auction ended for {}", entry.key());
+                outcome.record_fills(entry.uncross_with_fills(now));
+                Self::settle(&mut entry, now, &mut outcome);
+            }
+        }
+
+        let trades = outcome.trades.clone();
+        self.publish(outcome).await;
+        trades
+    }
+
     pub async fn submit_order(&self, mut order: Order) -> anyhow::Result<Order> {
         info!(
             "Submitting order: {} {} {} @ {} qty {}",
             order.id, order.symbol, order.side, order.price, order.quantity
         );
 
+        if let Some(trailing) = order.trailing {
+            let offset = match trailing.offset {
+                TrailingOffset::Amount(amount) => amount,
+                TrailingOffset::Percent(percent) => percent,
+            };
+            if !order.is_stop() || offset <= Decimal::ZERO {
+                return Err(anyhow::anyhow!(
+                    "Order {} has an invalid trailing stop",
+                    order.id
+                ));
+            }
+        }
+        if order.peg.is_some() && order.order_type != OrderType::Limit {
+            return Err(anyhow::anyhow!(
+                "Pegged order {} must be a limit order",
+                order.id
+            ));
+        }
+        // A trailing stop-market may take its first stop price from the market
+        let trailing_market = order.trailing.is_some() && order.order_type == OrderType::StopMarket;
+        if order.is_stop() && order.stop_price.is_none() && !trailing_market {
+            return Err(anyhow::anyhow!("Stop order {} has no stop price", order.id));
+        }
+
+        // Nothing may trade against an order past its expiry
+        self.expire_orders().await;
+        let now = self.clock.now();
+        if order.expires_at.is_some_and(|expires_at| expires_at <= now) {
+            return Err(anyhow::anyhow!("Order {} has already expired", order.id));
+        }
+
         order.status = OrderStatus::Open;
 
         let symbol = order.symbol.clone();
-        let mut book = self.get_or_create_orderbook(&symbol);
+        let mut outcome = Outcome::default();
+        // The book is matched in place under its map entry, so concurrent
+        // submits, cancels and expiries on the symbol are serialised
+        let final_order = {
+            let mut book = self
+                .orderbooks
+                .entry(symbol.clone())
+                .or_insert_with(|| OrderBook::new(symbol.clone()));
+            book.validate_order(&order)?;
+
+            if book.is_auction_due(now) {
+                info!("Volatility auction ended for {}", symbol);
+                outcome.record_fills(book.uncross_with_fills(now));
+            }
 
-        let (matched_order, trades) = book.match_order(order);
+            let market = book.market_prices();
+            stops::trail(&mut order, &market, book.spec());
+
+            let final_order = if order.is_stop() && !stops::is_triggered(&order, &market) {
+                // Park the stop until the market reaches its trigger price
+                order.status = OrderStatus::Pending;
+                outcome
+                    .reports
+                    .push(ExecutionReport::new(&order, ExecType::New, now));
+                book.add_stop_order(order.clone());
+                order
+            } else {
+                outcome
+                    .reports
+                    .push(ExecutionReport::new(&order, ExecType::New, now));
+                if order.is_stop() {
+                    outcome
+                        .reports
+                        .push(ExecutionReport::new(&order, ExecType::Triggered, now));
+                    order = stops::activate(order);
+                }
+                Self::execute(&mut book, order, now, &mut outcome)
+            };
+
+            Self::settle(&mut book, now, &mut outcome);
+
+            // Scheduled while the book is still held, so an expiry tick cannot
+            // look for the order before it rests
+            if let Some(expires_at) = final_order.expires_at {
+                if matches!(
+                    final_order.status,
+                    OrderStatus::Pending | OrderStatus::Open | OrderStatus::PartiallyFilled
+                ) {
+                    self.expiries
+                        .lock()
+                        .insert(expires_at, (symbol.clone(), final_order.id));
+                }
+            }
+
+            debug_assert_eq!(book.check_invariants(), Ok(()));
+            final_order
+        };
+
+        self.publish(outcome).await;
+
+        Ok(final_order)
+    }
+
+    /// Matches an active order against `book` and rests whatever is left.
+    fn execute(
+        book: &mut OrderBook,
+        order: Order,
+        now: DateTime<Utc>,
+        outcome: &mut Outcome,
+    ) -> Order {
+        let symbol = order.symbol.clone();
+        let mut progress = order.clone();
+        let result = book.execute(order, now);
+
+        for (trade, counterparty) in result.trades.iter().zip(&result.counterparties) {
+            progress.filled_quantity += trade.quantity;
+            progress.status = if progress.is_fully_filled() {
+                OrderStatus::Filled
+            } else {
+                OrderStatus::PartiallyFilled
+            };
+            outcome
+                .reports
+                .push(ExecutionReport::fill(&progress, trade));
+            outcome
+                .reports
+                .push(ExecutionReport::fill(counterparty, trade));
+        }
+        outcome.trades.extend(result.trades);
+
+        let matched_order = result.order;
+        let breach_action = if book.is_band_breached(&matched_order) {
+            book.price_bands().map(|b| b.config().breach_action)
+        } else {
+            None
+        };
 
         // Update order status
         let final_order = if matched_order.is_fully_filled() {
@@ -45,7 +327,27 @@ impl MatchingEngine {
                 status: OrderStatus::Filled,
                 ..matched_order
             }
-        } else if matched_order.filled_quantity > rust_decimal::Decimal::ZERO {
+        } else if breach_action == Some(BandBreachAction::Reject) {
+            warn!(
+                "Order {} hit the price band for {}, remainder rejected",
+                matched_order.id, symbol
+            );
+            let status = if matched_order.filled_quantity > Decimal::ZERO {
+                OrderStatus::Cancelled
+            } else {
+                OrderStatus::Rejected
+            };
+            Order {
+                status,
+                ..matched_order
+            }
+        } else if matched_order.order_type == OrderType::Market {
+            // Market orders never rest; whatever could not fill is cancelled
+            Order {
+                status: OrderStatus::Cancelled,
+                ..matched_order
+            }
+        } else if matched_order.filled_quantity > Decimal::ZERO {
             Order {
                 status: OrderStatus::PartiallyFilled,
                 ..matched_order
@@ -54,46 +356,166 @@ impl MatchingEngine {
             matched_order
         };
 
-        // If not fully filled, add to book
-        if !final_order.is_fully_filled() {
-            book.add_order(final_order.clone());
+        if breach_action == Some(BandBreachAction::VolatilityAuction) {
+            let duration = book
+                .price_bands()
+                .map(|b| b.config().auction_duration)
+                .unwrap_or_default();
+            warn!(
+                "Order {} hit the price band for {}, entering volatility auction",
+                final_order.id, symbol
+            );
+            book.start_auction(now + duration);
         }
 
-        // Update orderbook
-        self.orderbooks.insert(symbol, book);
+        match final_order.status {
+            OrderStatus::Cancelled => {
+                outcome
+                    .reports
+                    .push(ExecutionReport::new(&final_order, ExecType::Cancelled, now))
+            }
+            OrderStatus::Rejected => {
+                outcome
+                    .reports
+                    .push(ExecutionReport::new(&final_order, ExecType::Rejected, now))
+            }
+            // If not fully filled, add to book
+            OrderStatus::Open | OrderStatus::PartiallyFilled => book.add_order(final_order.clone()),
+            _ => {}
+        }
+
+        final_order
+    }
+
+    /// Crosses repriced pegs and activates triggered stops until the book is quiet.
+    fn settle(book: &mut OrderBook, now: DateTime<Utc>, outcome: &mut Outcome) {
+        loop {
+            let fills = book.match_pegs(now);
+            let crossed = !fills.is_empty();
+            outcome.record_fills(fills);
+
+            let triggered = book.take_triggered_stops();
+            if triggered.is_empty() && !crossed {
+                break;
+            }
+            for stop in triggered {
+                info!("Stop order {} triggered", stop.id);
+                let order = Order {
+                    status: OrderStatus::Open,
+                    ..stops::activate(stop)
+                };
+                outcome
+                    .reports
+                    .push(ExecutionReport::new(&order, ExecType::Triggered, now));
+                Self::execute(book, order, now, outcome);
+            }
+        }
+    }
+
+    async fn publish(&self, outcome: Outcome) {
+        self.publish_trades(&outcome.trades).await;
+        for report in outcome.reports {
+            self.executions.publish(report).await;
+        }
+    }
 
-        // Send trades
+    async fn publish_trades(&self, trades: &[Trade]) {
         for trade in trades {
             info!(
                 "Trade executed: {} {} @ {} qty {}",
                 trade.symbol, trade.id, trade.price, trade.quantity
             );
-            if let Err(e) = self.trade_sender.send(trade) {
-                error!("Failed to send trade: {}", e);
-            }
+            self.trades.publish(trade.clone()).await;
         }
+    }
 
-        Ok(final_order)
+    /// Cancels a resting or pending stop order, returning it as it stood when cancelled.
+    pub async fn cancel_order(&self, order_id: Uuid, symbol: &str) -> anyhow::Result<Order> {
+        let now = self.clock.now();
+        let mut outcome = Outcome::default();
+        let removed = {
+            let mut book = self
+                .orderbooks
+                .get_mut(symbol)
+                .ok_or_else(|| anyhow::anyhow!("Orderbook not found for symbol: {}", symbol))?;
+
+            if let Some(order) = Self::remove_anywhere(&mut book, order_id) {
+                info!("Cancelled {} order: {}", order.side, order.id);
+                let order = Order {
+                    status: OrderStatus::Cancelled,
+                    ..order
+                };
+                outcome
+                    .reports
+                    .push(ExecutionReport::new(&order, ExecType::Cancelled, now));
+                // Pulling a best price reprices pegs and can trigger stops trailing the book
+                Self::settle(&mut book, now, &mut outcome);
+                Some(order)
+            } else {
+                None
+            }
+        };
+
+        let Some(order) = removed else {
+            return Err(anyhow::anyhow!("Order not found: {}", order_id));
+        };
+        self.publish(outcome).await;
+        Ok(order)
     }
 
-    pub async fn cancel_order(&self, order_id: Uuid, symbol: &str) -> anyhow::Result<()> {
-        let mut book = self
-            .orderbooks
-            .get_mut(symbol)
-            .ok_or_else(|| anyhow::anyhow!("Orderbook not found for symbol: {}", symbol))?;
+    /// Removes every order whose expiry time the engine clock has reached,
+    /// publishing an `Expired` execution report for each.
+    ///
+    /// Called at the start of every submission; live deployments should also
+    /// drive it periodically, e.g. with [`MatchingEngine::run_expiry`], and
+    /// replays after advancing their clock.
+    pub async fn expire_orders(&self) -> Vec<Order> {
+        let now = self.clock.now();
+        let due = self.expiries.lock().advance(now);
+        if due.is_empty() {
+            return Vec::new();
+        }
 
-        // Try both sides
-        if let Some(order) = book.remove_order(order_id, crate::utils::types::Side::Buy) {
-            info!("Cancelled buy order: {}", order.id);
-            return Ok(());
+        let mut outcome = Outcome::default();
+        let mut expired = Vec::new();
+        for (symbol, order_id) in due {
+            let Some(mut book) = self.orderbooks.get_mut(&symbol) else {
+                continue;
+            };
+            // Orders that filled or were cancelled in the meantime are gone already
+            let Some(order) = Self::remove_anywhere(&mut book, order_id) else {
+                continue;
+            };
+            info!("Order {} expired", order.id);
+            let order = Order {
+                status: OrderStatus::Expired,
+                ..order
+            };
+            outcome
+                .reports
+                .push(ExecutionReport::new(&order, ExecType::Expired, now));
+            Self::settle(&mut book, now, &mut outcome);
+            expired.push(order);
         }
 
-        if let Some(order) = book.remove_order(order_id, crate::utils::types::Side::Sell) {
-            info!("Cancelled sell order: {}", order.id);
-            return Ok(());
+        self.publish(outcome).await;
+        expired
+    }
+
+    /// Expires orders every `interval` of wall-clock time, forever.
+    pub async fn run_expiry(&self, interval: std::time::Duration) {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            self.expire_orders().await;
         }
+    }
 
-        Err(anyhow::anyhow!("Order not found: {}", order_id))
+    /// Removes an order from either side of the book or from the stops.
+    fn remove_anywhere(book: &mut OrderBook, order_id: Uuid) -> Option<Order> {
+        book.remove_order(order_id, Side::Buy)
+            .or_else(|| book.remove_order(order_id, Side::Sell))
+            .or_else(|| book.remove_stop_order(order_id))
     }
 
     pub fn get_orderbook_snapshot(
@@ -103,21 +525,36 @@ impl MatchingEngine {
         self.orderbooks.get(symbol).map(|book| book.get_snapshot())
     }
 
+    /// Every level of `symbol`'s book, where
+    /// [`MatchingEngine::get_orderbook_snapshot`] stops at the snapshot depth.
+    pub fn get_full_orderbook_snapshot(
+        &self,
+        symbol: &str,
+    ) -> Option<crate::utils::types::OrderBookSnapshot> {
+        self.orderbooks.get(symbol).map(|book| book.get_full_snapshot())
+    }
+
     pub fn get_all_symbols(&self) -> Vec<String> {
         self.orderbooks.iter().map(|entry| entry.key().clone()).collect()
     }
 }
 
+impl Default for MatchingEngine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::engine::bands::TradingPhase;
     use crate::utils::types::{OrderType, Side};
-    use rust_decimal::Decimal;
 
     #[tokio::test]
     async fn test_matching_engine_submit_and_match() {
-        let (tx, mut rx) = mpsc::unbounded_channel();
-        let engine = MatchingEngine::new(tx);
+        let engine = MatchingEngine::new();
+        let mut rx = engine.subscribe_trades(16, BackpressurePolicy::Block);
 
         let buy_order = Order::new(
             "BTCUSD".to_string(),
@@ -143,13 +580,12 @@ mod tests {
 
         // Should receive one trade
         let trade = rx.recv().await;
-        assert!(trade.is_some());
+        assert!(trade.is_ok());
     }
 
     #[tokio::test]
     async fn test_matching_engine_cancel() {
-        let (tx, _rx) = mpsc::unbounded_channel();
-        let engine = MatchingEngine::new(tx);
+        let engine = MatchingEngine::new();
 
         let order = Order::new(
             "BTCUSD".to_string(),
@@ -166,4 +602,379 @@ mod tests {
         let cancel_result = engine.cancel_order(order_id, "BTCUSD").await;
         assert!(cancel_result.is_ok());
     }
+
+    #[tokio::test]
+    async fn test_matching_engine_band_breach_rejects_remainder() {
+        let engine = MatchingEngine::new();
+        engine.set_price_bands(
+            "BTCUSD",
+            PriceBandConfig {
+                static_width: Some(Decimal::new(1, 2)),
+                dynamic_width: None,
+                breach_action: BandBreachAction::Reject,
+                ..Default::default()
+            },
+            Some(Decimal::from(50000)),
+        );
+
+        for price in [50100, 51000] {
+            let sell = Order::new(
+                "BTCUSD".to_string(),
+                Side::Sell,
+                OrderType::Limit,
+                Decimal::from(price),
+                Decimal::from(1),
+            );
+            engine.submit_order(sell).await.unwrap();
+        }
+
+        let buy = Order::new(
+            "BTCUSD".to_string(),
+            Side::Buy,
+            OrderType::Limit,
+            Decimal::from(52000),
+            Decimal::from(2),
+        );
+        let result = engine.submit_order(buy).await.unwrap();
+
+        assert_eq!(result.status, OrderStatus::Cancelled);
+        assert_eq!(result.filled_quantity, Decimal::from(1));
+
+        let snapshot = engine.get_orderbook_snapshot("BTCUSD").unwrap();
+        assert!(snapshot.bids.is_empty());
+        assert_eq!(snapshot.asks[0].price, Decimal::from(51000));
+    }
+
+    #[tokio::test]
+    async fn test_matching_engine_band_breach_starts_auction() {
+        let engine = MatchingEngine::new();
+        engine.set_price_bands(
+            "BTCUSD",
+            PriceBandConfig {
+                static_width: Some(Decimal::new(1, 2)),
+                dynamic_width: None,
+                breach_action: BandBreachAction::VolatilityAuction,
+                auction_duration: chrono::Duration::zero(),
+            },
+            Some(Decimal::from(50000)),
+        );
+
+        let sell = Order::new(
+            "BTCUSD".to_string(),
+            Side::Sell,
+            OrderType::Limit,
+            Decimal::from(51000),
+            Decimal::from(1),
+        );
+        engine.submit_order(sell).await.unwrap();
+
+        let buy = Order::new(
+            "BTCUSD".to_string(),
+            Side::Buy,
+            OrderType::Limit,
+            Decimal::from(52000),
+            Decimal::from(1),
+        );
+        let result = engine.submit_order(buy).await.unwrap();
+        assert_eq!(result.status, OrderStatus::Open);
+        assert!(matches!(
+            engine.get_or_create_orderbook("BTCUSD").trading_phase(),
+            TradingPhase::VolatilityAuction { .. }
+        ));
+
+        let trades = engine.run_due_auctions().await;
+        assert_eq!(trades.len(), 1);
+        assert_eq!(trades[0].price, Decimal::from(51000));
+    }
+
+    #[tokio::test]
+    async fn test_matching_engine_rejects_off_tick_price() {
+        let engine = MatchingEngine::new();
+        engine
+            .set_instrument_spec(
+                "BTCUSD",
+                InstrumentSpec::new(Decimal::new(5, 1), Decimal::new(1, 3)).unwrap(),
+            )
+            .unwrap();
+
+        let order = Order::new(
+            "BTCUSD".to_string(),
+            Side::Buy,
+            OrderType::Limit,
+            Decimal::new(5000025, 2),
+            Decimal::from(1),
+        );
+        assert!(engine.submit_order(order).await.is_err());
+
+        let order = Order::new(
+            "BTCUSD".to_string(),
+            Side::Buy,
+            OrderType::Limit,
+            Decimal::new(500005, 1),
+            Decimal::new(1234, 3),
+        );
+        let result = engine.submit_order(order).await.unwrap();
+        assert_eq!(result.status, OrderStatus::Open);
+
+        let snapshot = engine.get_orderbook_snapshot("BTCUSD").unwrap();
+        assert_eq!(snapshot.bids[0].price, Decimal::new(500005, 1));
+        assert_eq!(snapshot.bids[0].quantity, Decimal::new(1234, 3));
+    }
+
+    #[tokio::test]
+    async fn test_matching_engine_reports_both_sides_and_triggers_stops() {
+        let engine = MatchingEngine::new();
+        let mut rx = engine.subscribe_executions(64, BackpressurePolicy::Block);
+
+        let stop = Order {
+            stop_price: Some(Decimal::from(100)),
+            ..Order::new(
+                "BTCUSD".to_string(),
+                Side::Sell,
+                OrderType::StopMarket,
+                Decimal::ZERO,
+                Decimal::from(1),
+            )
+        };
+        let result = engine.submit_order(stop.clone()).await.unwrap();
+        assert_eq!(result.status, OrderStatus::Pending);
+
+        for (side, price) in [(Side::Buy, 100), (Side::Buy, 98), (Side::Sell, 99)] {
+            engine
+                .submit_order(Order::new(
+                    "BTCUSD".to_string(),
+                    side,
+                    OrderType::Limit,
+                    Decimal::from(price),
+                    Decimal::from(1),
+                ))
+                .await
+                .unwrap();
+        }
+
+        let mut reports = Vec::new();
+        while let Ok(Some(report)) = rx.try_recv() {
+            reports.push(report);
+        }
+        let fills: Vec<_> = reports
+            .iter()
+            .filter(|r| r.exec_type == ExecType::Trade)
+            .collect();
+        // Sell at 99 hits the 100 bid, then the stop sells into the 98 bid
+        assert_eq!(fills.len(), 4);
+        assert_eq!(fills[0].trade_id, fills[1].trade_id);
+        assert!(reports
+            .iter()
+            .any(|r| r.order_id == stop.id && r.exec_type == ExecType::Triggered));
+        let stop_fill = fills.iter().find(|r| r.order_id == stop.id).unwrap();
+        assert_eq!(stop_fill.last_price, Some(Decimal::from(98)));
+        assert_eq!(stop_fill.status, OrderStatus::Filled);
+
+        let snapshot = engine.get_orderbook_snapshot("BTCUSD").unwrap();
+        assert!(snapshot.bids.is_empty() && snapshot.asks.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_matching_engine_cancels_pending_stop() {
+        let engine = MatchingEngine::new();
+        let mut rx = engine.subscribe_executions(8, BackpressurePolicy::Block);
+
+        let stop = Order {
+            stop_price: Some(Decimal::from(110)),
+            ..Order::new(
+                "BTCUSD".to_string(),
+                Side::Buy,
+                OrderType::StopLimit,
+                Decimal::from(111),
+                Decimal::from(1),
+            )
+        };
+        engine.submit_order(stop.clone()).await.unwrap();
+        let cancelled = engine.cancel_order(stop.id, "BTCUSD").await.unwrap();
+        assert_eq!(cancelled.status, OrderStatus::Cancelled);
+
+        assert_eq!(rx.recv().await.unwrap().exec_type, ExecType::New);
+        assert_eq!(rx.recv().await.unwrap().exec_type, ExecType::Cancelled);
+    }
+
+    #[tokio::test]
+    async fn test_matching_engine_trailing_stop_follows_best_bid() {
+        use crate::utils::types::{TrailReference, TrailingStop};
+
+        let engine = MatchingEngine::new();
+        let limit = |side, price| {
+            Order::new(
+                "BTCUSD".to_string(),
+                side,
+                OrderType::Limit,
+                Decimal::from(price),
+                Decimal::from(1),
+            )
+        };
+
+        engine.submit_order(limit(Side::Buy, 100)).await.unwrap();
+        let trailing = Order {
+            trailing: Some(TrailingStop {
+                offset: TrailingOffset::Amount(Decimal::from(5)),
+                reference: TrailReference::BestPrice,
+            }),
+            ..Order::new(
+                "BTCUSD".to_string(),
+                Side::Sell,
+                OrderType::StopMarket,
+                Decimal::ZERO,
+                Decimal::from(1),
+            )
+        };
+        let result = engine.submit_order(trailing.clone()).await.unwrap();
+        assert_eq!(result.status, OrderStatus::Pending);
+        assert_eq!(result.stop_price, Some(Decimal::from(95)));
+
+        // A higher bid drags the stop up to 105
+        let high_bid = limit(Side::Buy, 110);
+        engine.submit_order(high_bid.clone()).await.unwrap();
+        engine.submit_order(limit(Side::Buy, 104)).await.unwrap();
+        let book = engine.get_or_create_orderbook("BTCUSD");
+        assert_eq!(
+            book.stop_orders().get(trailing.id).unwrap().stop_price,
+            Some(Decimal::from(105))
+        );
+
+        // Pulling the 110 bid leaves 104 as best, which fires the stop into it
+        engine.cancel_order(high_bid.id, "BTCUSD").await.unwrap();
+        let snapshot = engine.get_orderbook_snapshot("BTCUSD").unwrap();
+        assert!(engine
+            .get_or_create_orderbook("BTCUSD")
+            .stop_orders()
+            .is_empty());
+        assert_eq!(snapshot.bids[0].price, Decimal::from(100));
+    }
+
+    #[tokio::test]
+    async fn test_matching_engine_midpoint_pegs_cross_when_book_forms() {
+        use crate::utils::types::{PegInstruction, PegReference};
+
+        let engine = MatchingEngine::new();
+        let peg = |side| Order {
+            peg: Some(PegInstruction {
+                reference: PegReference::Midpoint,
+                offset: Decimal::ZERO,
+                limit: None,
+            }),
+            ..Order::new(
+                "BTCUSD".to_string(),
+                side,
+                OrderType::Limit,
+                Decimal::ZERO,
+                Decimal::from(1),
+            )
+        };
+
+        // Without a two-sided book neither peg has a price
+        for side in [Side::Buy, Side::Sell] {
+            let result = engine.submit_order(peg(side)).await.unwrap();
+            assert_eq!(result.status, OrderStatus::Open);
+        }
+
+        let mut rx = engine.subscribe_trades(8, BackpressurePolicy::Block);
+        for (side, price) in [(Side::Buy, 100), (Side::Sell, 105)] {
+            engine
+                .submit_order(Order::new(
+                    "BTCUSD".to_string(),
+                    side,
+                    OrderType::Limit,
+                    Decimal::from(price),
+                    Decimal::from(1),
+                ))
+                .await
+                .unwrap();
+        }
+
+        let trade = rx.recv().await.unwrap();
+        assert_eq!(trade.price, Decimal::new(1025, 1));
+        let book = engine.get_or_create_orderbook("BTCUSD");
+        assert_eq!(book.pegged_order_count(Side::Buy), 0);
+        assert_eq!(book.pegged_order_count(Side::Sell), 0);
+    }
+
+    #[tokio::test]
+    async fn test_matching_engine_expires_orders_on_engine_clock() {
+        use crate::engine::clock::SimulatedClock;
+
+        let start = DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+        let clock = Arc::new(SimulatedClock::new(start));
+        let engine = MatchingEngine::with_clock(clock.clone());
+        let mut rx = engine.subscribe_executions(16, BackpressurePolicy::Block);
+
+        let order = Order {
+            expires_at: Some(start + chrono::Duration::seconds(90)),
+            ..Order::new(
+                "BTCUSD".to_string(),
+                Side::Buy,
+                OrderType::Limit,
+                Decimal::from(50000),
+                Decimal::from(1),
+            )
+        };
+        engine.submit_order(order.clone()).await.unwrap();
+        let accepted = rx.recv().await.unwrap();
+        assert_eq!(accepted.exec_type, ExecType::New);
+        assert_eq!(accepted.timestamp, start);
+
+        clock.advance(chrono::Duration::seconds(89));
+        assert!(engine.expire_orders().await.is_empty());
+
+        // An arriving sell never sees the expired bid
+        clock.advance(chrono::Duration::seconds(1));
+        let sell = Order::new(
+            "BTCUSD".to_string(),
+            Side::Sell,
+            OrderType::Limit,
+            Decimal::from(50000),
+            Decimal::from(1),
+        );
+        let result = engine.submit_order(sell).await.unwrap();
+        assert_eq!(result.status, OrderStatus::Open);
+
+        let report = rx.recv().await.unwrap();
+        assert_eq!(report.exec_type, ExecType::Expired);
+        assert_eq!(report.order_id, order.id);
+        assert_eq!(report.status, OrderStatus::Expired);
+        assert_eq!(report.timestamp, start + chrono::Duration::seconds(90));
+
+        let late = Order {
+            expires_at: Some(start),
+            ..order
+        };
+        assert!(engine.submit_order(late).await.is_err());
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_matching_engine_concurrent_submits_keep_every_order() {
+        let engine = Arc::new(MatchingEngine::new());
+        let tasks: Vec<_> = (0..8)
+            .map(|task| {
+                let engine = Arc::clone(&engine);
+                tokio::spawn(async move {
+                    for i in 0..50 {
+                        let order = Order::new(
+                            "BTCUSD".to_string(),
+                            Side::Buy,
+                            OrderType::Limit,
+                            Decimal::from(40000 + task * 100 + i),
+                            Decimal::from(1),
+                        );
+                        engine.submit_order(order).await.unwrap();
+                    }
+                })
+            })
+            .collect();
+        for task in tasks {
+            task.await.unwrap();
+        }
+
+        let book = engine.get_or_create_orderbook("BTCUSD");
+        assert_eq!(book.get_depth(Side::Buy, usize::MAX).len(), 400);
+        assert_eq!(book.check_invariants(), Ok(()));
+    }
 }
@@ -1,2 +1,11 @@
+pub mod analytics;
+pub mod bands;
+pub mod bus;
+pub mod clock;
+pub mod contingent;
 pub mod matching;
 pub mod orderbook;
+pub mod pegs;
+pub mod stops;
+pub mod ticks;
+pub mod timer;
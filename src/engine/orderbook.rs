@@ -1,53 +1,421 @@
-use crate::utils::types::{Order, OrderBookLevel, OrderBookSnapshot, Side, Trade};
-use chrono::Utc;
+use crate::engine::bands::{PriceBandConfig, PriceBands, TradingPhase};
+use crate::engine::pegs::{self, HalfTicks};
+use crate::engine::stops::{MarketPrices, StopBook};
+use crate::engine::ticks::{InstrumentSpec, Lots, ScaleError, Ticks};
+use crate::utils::types::{
+    Order, OrderBookLevel, OrderBookSnapshot, OrderStatus, OrderType, PegInstruction, Side, Trade,
+};
+use chrono::{DateTime, Utc};
 use rust_decimal::Decimal;
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use thiserror::Error;
 use uuid::Uuid;
 
+/// A broken internal invariant reported by [`OrderBook::check_invariants`].
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum InvariantViolation {
+    #[error("book is crossed: best bid {bid} >= best ask {ask}")]
+    Crossed { bid: Decimal, ask: Decimal },
+    #[error("empty {side} level at {price}")]
+    EmptyLevel { side: Side, price: Decimal },
+    #[error("{side} level at {price} records {recorded} lots but its orders hold {actual}")]
+    LevelTotal {
+        side: Side,
+        price: Decimal,
+        recorded: Lots,
+        actual: Lots,
+    },
+    #[error("{side} level at {price} is out of arrival order")]
+    Fifo { side: Side, price: Decimal },
+    #[error("order {0} rests on the wrong side or at the wrong price")]
+    Misplaced(Uuid),
+    #[error("order {0} rests with no open quantity")]
+    NoLeaves(Uuid),
+    #[error("order {0} fills and open quantity do not add up to its size")]
+    Quantity(Uuid),
+    #[error("order {0} is in the book more than once")]
+    Duplicate(Uuid),
+    #[error("order {0} in the stop book is not a stop order")]
+    NotAStop(Uuid),
+}
+
+/// An order resting in the book with its open and filled quantity kept in lots.
+///
+/// `order.filled_quantity` is only brought up to date when the order leaves
+/// the book, so fills never touch `Decimal` arithmetic. `seq` is the order's
+/// arrival sequence in this book.
+#[derive(Debug, Clone)]
+struct RestingOrder {
+    order: Order,
+    seq: u64,
+    leaves: Lots,
+    filled: Lots,
+}
+
+impl RestingOrder {
+    fn new(order: Order, seq: u64, leaves: Lots) -> Self {
+        Self {
+            order,
+            seq,
+            leaves,
+            filled: 0,
+        }
+    }
+
+    fn into_order(mut self, spec: &InstrumentSpec) -> Order {
+        self.order.filled_quantity += spec.lots_to_quantity(self.filled);
+        self.order
+    }
+
+    /// Copy of the order as it stands now, with fill quantity and status brought up to date.
+    fn snapshot(&self, spec: &InstrumentSpec) -> Order {
+        let mut order = self.order.clone();
+        order.filled_quantity += spec.lots_to_quantity(self.filled);
+        order.status = if self.leaves == 0 {
+            OrderStatus::Filled
+        } else {
+            OrderStatus::PartiallyFilled
+        };
+        order
+    }
+}
+
+/// Outcome of matching one incoming order.
+#[derive(Debug, Clone)]
+pub struct MatchResult {
+    pub order: Order,
+    pub trades: Vec<Trade>,
+    /// Resting counterparty of each trade as it stood after the fill, aligned with `trades`.
+    pub counterparties: Vec<Order>,
+}
+
+/// An execution between two resting orders, such as an auction uncross or a
+/// peg repricing into the opposite side, with both orders as they stood after
+/// the fill.
+#[derive(Debug, Clone)]
+pub struct Fill {
+    pub trade: Trade,
+    pub buy: Order,
+    pub sell: Order,
+}
+
+/// All orders resting at one price, with their open quantity kept as a running total.
+#[derive(Debug, Clone, Default)]
+struct PriceLevel {
+    orders: Vec<RestingOrder>,
+    total_lots: Lots,
+}
+
+impl PriceLevel {
+    fn push(&mut self, resting: RestingOrder) {
+        self.total_lots += resting.leaves;
+        self.orders.push(resting);
+    }
+
+    fn remove(&mut self, pos: usize) -> RestingOrder {
+        let resting = self.orders.remove(pos);
+        self.total_lots -= resting.leaves;
+        resting
+    }
+
+    fn is_empty(&self) -> bool {
+        self.orders.is_empty()
+    }
+
+    fn order_count(&self) -> usize {
+        self.orders.len()
+    }
+}
+
+/// A non-displayed order priced from the lit best bid and offer.
+///
+/// Pegs keep their arrival sequence when they reprice, since the move is not
+/// the owner's action, so equally priced pegs trade in arrival order. Lit
+/// orders always trade ahead of pegs at the same price.
+#[derive(Debug, Clone)]
+struct PeggedOrder {
+    peg: PegInstruction,
+    resting: RestingOrder,
+}
+
+/// The best order on one side of a peg cross.
+#[derive(Debug, Clone, Copy)]
+enum Contra {
+    Lit(Ticks),
+    Peg(usize),
+}
+
+/// Index and price of the best active peg: best price, then earliest arrival.
+fn best_peg(
+    pegs: &[PeggedOrder],
+    side: Side,
+    best_bid: Option<Ticks>,
+    best_ask: Option<Ticks>,
+    spec: &InstrumentSpec,
+    within: impl Fn(HalfTicks) -> bool,
+) -> Option<(usize, HalfTicks)> {
+    let mut best: Option<(usize, HalfTicks)> = None;
+    for (i, pegged) in pegs.iter().enumerate() {
+        if pegged.resting.leaves == 0 {
+            continue;
+        }
+        let Some(price) = pegs::peg_price(side, &pegged.peg, best_bid, best_ask, spec) else {
+            continue;
+        };
+        if !within(price) {
+            continue;
+        }
+        let better = best.is_none_or(|(_, b)| match side {
+            Side::Buy => price > b,
+            Side::Sell => price < b,
+        });
+        if better {
+            best = Some((i, price));
+        }
+    }
+    best
+}
+
+/// Trades up to `leaves` lots of the incoming `order` against `resting`.
+fn fill_resting(
+    symbol: &str,
+    order: &Order,
+    resting: &mut RestingOrder,
+    leaves: Lots,
+    price: Decimal,
+    spec: &InstrumentSpec,
+    now: DateTime<Utc>,
+) -> (Lots, Trade) {
+    let lots = leaves.min(resting.leaves);
+    let (buy_order_id, sell_order_id) = match order.side {
+        Side::Buy => (order.id, resting.order.id),
+        Side::Sell => (resting.order.id, order.id),
+    };
+    resting.leaves -= lots;
+    resting.filled += lots;
+    let trade = Trade::new(
+        symbol.to_string(),
+        price,
+        spec.lots_to_quantity(lots),
+        buy_order_id,
+        sell_order_id,
+        now,
+    );
+    (lots, trade)
+}
+
 #[derive(Debug, Clone)]
 pub struct OrderBook {
     symbol: String,
-    bids: BTreeMap<Decimal, Vec<Order>>, // Price -> Orders (descending)
-    asks: BTreeMap<Decimal, Vec<Order>>, // Price -> Orders (ascending)
+    spec: InstrumentSpec,
+    bids: BTreeMap<Ticks, PriceLevel>, // Price -> Orders (descending)
+    asks: BTreeMap<Ticks, PriceLevel>, // Price -> Orders (ascending)
+    bands: Option<PriceBands>,
+    phase: TradingPhase,
+    snapshot_depth: usize,
+    stops: StopBook,
+    peg_bids: Vec<PeggedOrder>,
+    peg_asks: Vec<PeggedOrder>,
+    next_seq: u64,
+    last_trade: Option<Decimal>,
 }
 
+/// Number of levels per side included in [`OrderBook::get_snapshot`] by default.
+pub const DEFAULT_SNAPSHOT_DEPTH: usize = 20;
+
 impl OrderBook {
     pub fn new(symbol: String) -> Self {
+        Self::with_spec(symbol, InstrumentSpec::default())
+    }
+
+    pub fn with_spec(symbol: String, spec: InstrumentSpec) -> Self {
         Self {
             symbol,
+            spec,
             bids: BTreeMap::new(),
             asks: BTreeMap::new(),
+            bands: None,
+            phase: TradingPhase::Continuous,
+            snapshot_depth: DEFAULT_SNAPSHOT_DEPTH,
+            stops: StopBook::new(),
+            peg_bids: Vec::new(),
+            peg_asks: Vec::new(),
+            next_seq: 0,
+            last_trade: None,
+        }
+    }
+
+    pub fn snapshot_depth(&self) -> usize {
+        self.snapshot_depth
+    }
+
+    /// Sets how many levels per side [`OrderBook::get_snapshot`] returns.
+    pub fn set_snapshot_depth(&mut self, levels: usize) {
+        self.snapshot_depth = levels;
+    }
+
+    pub fn spec(&self) -> &InstrumentSpec {
+        &self.spec
+    }
+
+    /// Checks that an order's price and quantity lie exactly on the instrument grid.
+    pub fn validate_order(&self, order: &Order) -> Result<(), ScaleError> {
+        self.spec.price_to_ticks(order.price)?;
+        self.spec.quantity_to_lots(order.quantity)?;
+        self.spec.quantity_to_lots(order.filled_quantity)?;
+        if let Some(stop_price) = order.stop_price {
+            self.spec.price_to_ticks(stop_price)?;
+        }
+        if let Some(peg) = order.peg {
+            self.spec.price_to_ticks(peg.offset)?;
+            if let Some(limit) = peg.limit {
+                self.spec.price_to_ticks(limit)?;
+            }
         }
+        Ok(())
     }
 
+    pub fn last_trade_price(&self) -> Option<Decimal> {
+        self.last_trade
+    }
+
+    /// Returns `true` if there are no resting, pegged or stop orders.
+    pub fn is_empty(&self) -> bool {
+        self.bids.is_empty()
+            && self.asks.is_empty()
+            && self.peg_bids.is_empty()
+            && self.peg_asks.is_empty()
+            && self.stops.is_empty()
+    }
+
+    /// Current price of a resting pegged order, or `None` if it is unknown or
+    /// its reference price is missing.
+    pub fn peg_price(&self, order_id: Uuid) -> Option<Decimal> {
+        let (best_bid, best_ask) = self.lit_best();
+        [(Side::Buy, &self.peg_bids), (Side::Sell, &self.peg_asks)]
+            .into_iter()
+            .find_map(|(side, pegs)| {
+                let pegged = pegs.iter().find(|p| p.resting.order.id == order_id)?;
+                pegs::peg_price(side, &pegged.peg, best_bid, best_ask, &self.spec)
+            })
+            .map(|price| pegs::half_ticks_to_price(&self.spec, price))
+    }
+
+    /// Number of pegged orders resting on `side`.
+    pub fn pegged_order_count(&self, side: Side) -> usize {
+        match side {
+            Side::Buy => self.peg_bids.len(),
+            Side::Sell => self.peg_asks.len(),
+        }
+    }
+
+    fn lit_best(&self) -> (Option<Ticks>, Option<Ticks>) {
+        (
+            self.bids.keys().next_back().copied(),
+            self.asks.keys().next().copied(),
+        )
+    }
+
+    /// Last trade and best prices, as seen by the stop book.
+    pub fn market_prices(&self) -> MarketPrices {
+        MarketPrices {
+            last_trade: self.last_trade_price(),
+            best_bid: self.get_best_bid(),
+            best_ask: self.get_best_ask(),
+        }
+    }
+
+    /// Parks a stop order until the market reaches its stop price.
+    pub fn add_stop_order(&mut self, order: Order) {
+        self.stops.add(order);
+    }
+
+    pub fn remove_stop_order(&mut self, order_id: Uuid) -> Option<Order> {
+        self.stops.remove(order_id)
+    }
+
+    pub fn stop_orders(&self) -> &StopBook {
+        &self.stops
+    }
+
+    /// Ratchets trailing stops to the current market, then removes and returns
+    /// the stops it triggers, oldest first.
+    pub fn take_triggered_stops(&mut self) -> Vec<Order> {
+        let market = self.market_prices();
+        self.stops.update_trailing(&market, &self.spec);
+        self.stops.take_triggered(&market)
+    }
+
+    pub fn set_price_bands(&mut self, config: PriceBandConfig, reference_price: Option<Decimal>) {
+        self.bands = Some(PriceBands::new(config, reference_price));
+    }
+
+    pub fn price_bands(&self) -> Option<&PriceBands> {
+        self.bands.as_ref()
+    }
+
+    pub fn trading_phase(&self) -> TradingPhase {
+        self.phase
+    }
+
+    /// Rests `order` in the book.
+    ///
+    /// Prices off the tick grid are rounded passively (bids down, asks up) and
+    /// quantities down to whole lots; use [`OrderBook::validate_order`] first to
+    /// reject such orders instead.
     pub fn add_order(&mut self, order: Order) {
-        let price = order.price;
+        let leaves = self.spec.quantity_to_lots_floor(order.remaining_quantity());
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        if let Some(peg) = order.peg {
+            let pegged = PeggedOrder {
+                peg,
+                resting: RestingOrder::new(order, seq, leaves),
+            };
+            match pegged.resting.order.side {
+                Side::Buy => self.peg_bids.push(pegged),
+                Side::Sell => self.peg_asks.push(pegged),
+            }
+            return;
+        }
         match order.side {
             Side::Buy => {
-                self.bids.entry(price).or_insert_with(Vec::new).push(order);
+                let ticks = self.spec.price_to_ticks_floor(order.price);
+                self.bids
+                    .entry(ticks)
+                    .or_default()
+                    .push(RestingOrder::new(order, seq, leaves));
             }
             Side::Sell => {
-                self.asks.entry(price).or_insert_with(Vec::new).push(order);
+                let ticks = self.spec.price_to_ticks_ceil(order.price);
+                self.asks
+                    .entry(ticks)
+                    .or_default()
+                    .push(RestingOrder::new(order, seq, leaves));
             }
         }
     }
 
     pub fn remove_order(&mut self, order_id: Uuid, side: Side) -> Option<Order> {
-        let book = match side {
-            Side::Buy => &mut self.bids,
-            Side::Sell => &mut self.asks,
+        let (book, pegs) = match side {
+            Side::Buy => (&mut self.bids, &mut self.peg_bids),
+            Side::Sell => (&mut self.asks, &mut self.peg_asks),
         };
 
+        if let Some(pos) = pegs.iter().position(|p| p.resting.order.id == order_id) {
+            return Some(pegs.remove(pos).resting.into_order(&self.spec));
+        }
+
         let mut price_to_remove = None;
         let mut found_order = None;
 
-        for (price, orders) in book.iter_mut() {
-            if let Some(pos) = orders.iter().position(|o| o.id == order_id) {
-                let order = orders.remove(pos);
-                if orders.is_empty() {
+        for (price, level) in book.iter_mut() {
+            if let Some(pos) = level.orders.iter().position(|o| o.order.id == order_id) {
+                let resting = level.remove(pos);
+                if level.is_empty() {
                     price_to_remove = Some(*price);
                 }
-                found_order = Some(order);
+                found_order = Some(resting.into_order(&self.spec));
                 break;
             }
         }
@@ -59,83 +427,599 @@ impl OrderBook {
         found_order
     }
 
-    pub fn match_order(&mut self, mut order: Order) -> (Order, Vec<Trade>) {
+    /// Matches `order` against the book, stamping its trades with `now`.
+    pub fn match_order(&mut self, order: Order, now: DateTime<Utc>) -> (Order, Vec<Trade>) {
+        self.match_inner(order, None, now)
+    }
+
+    /// Matches `order` like [`OrderBook::match_order`], also reporting the
+    /// state of every resting order it traded against.
+    pub fn execute(&mut self, order: Order, now: DateTime<Utc>) -> MatchResult {
+        let mut counterparties = Vec::new();
+        let (order, trades) = self.match_inner(order, Some(&mut counterparties), now);
+        MatchResult {
+            order,
+            trades,
+            counterparties,
+        }
+    }
+
+    fn match_inner(
+        &mut self,
+        mut order: Order,
+        mut counterparties: Option<&mut Vec<Order>>,
+        now: DateTime<Utc>,
+    ) -> (Order, Vec<Trade>) {
         let mut trades = Vec::new();
 
-        let opposite_book = match order.side {
-            Side::Buy => &mut self.asks,
-            Side::Sell => &mut self.bids,
+        if self.phase != TradingPhase::Continuous {
+            return (order, trades);
+        }
+
+        // Limits are fixed before matching so a sweep cannot drag its own dynamic band.
+        let (band_low, band_high) = match self.bands.as_ref() {
+            Some(bands) => {
+                let (low, high) = bands.limits();
+                (
+                    low.map(|l| self.spec.price_to_ticks_ceil(l).saturating_mul(2)),
+                    high.map(|h| self.spec.price_to_ticks_floor(h).saturating_mul(2)),
+                )
+            }
+            None => (None, None),
+        };
+
+        // Pegs are priced off the book as it stood when the order arrived.
+        let spec = self.spec;
+        let (best_bid, best_ask) = self.lit_best();
+
+        // Market orders take any price; only the bands limit how far they walk.
+        let side = order.side;
+        let limit = match (order.peg, order.order_type, side) {
+            (Some(peg), _, _) => pegs::peg_price(side, &peg, best_bid, best_ask, &spec),
+            (None, OrderType::Market, Side::Buy) => Some(HalfTicks::MAX),
+            (None, OrderType::Market, Side::Sell) => Some(HalfTicks::MIN),
+            (None, _, Side::Buy) => Some(spec.price_to_ticks_floor(order.price).saturating_mul(2)),
+            (None, _, Side::Sell) => Some(spec.price_to_ticks_ceil(order.price).saturating_mul(2)),
         };
+        let Some(limit) = limit else {
+            // An inactive peg has no price to trade at
+            return (order, trades);
+        };
+        let within = |price: HalfTicks| {
+            let marketable = match side {
+                Side::Buy => price <= limit,
+                Side::Sell => price >= limit,
+            };
+            marketable
+                && band_low.is_none_or(|l| price >= l)
+                && band_high.is_none_or(|h| price <= h)
+        };
+
+        let mut leaves = spec.quantity_to_lots_floor(order.remaining_quantity());
+        let mut filled_lots: Lots = 0;
 
-        let prices_to_match: Vec<Decimal> = match order.side {
-            Side::Buy => opposite_book
-                .iter()
-                .filter(|(price, _)| **price <= order.price)
-                .map(|(price, _)| *price)
-                .collect(),
-            Side::Sell => opposite_book
-                .iter()
-                .rev()
-                .filter(|(price, _)| **price >= order.price)
-                .map(|(price, _)| *price)
-                .collect(),
+        let (opposite_book, opposite_pegs, opposite_side) = match side {
+            Side::Buy => (&mut self.asks, &mut self.peg_asks, Side::Sell),
+            Side::Sell => (&mut self.bids, &mut self.peg_bids, Side::Buy),
         };
 
-        for price in prices_to_match {
-            if order.is_fully_filled() {
-                break;
+        while leaves > 0 {
+            let lit = match side {
+                Side::Buy => opposite_book.keys().next().copied(),
+                Side::Sell => opposite_book.keys().next_back().copied(),
             }
+            .filter(|p| within(p.saturating_mul(2)));
+            let peg = best_peg(
+                opposite_pegs,
+                opposite_side,
+                best_bid,
+                best_ask,
+                &spec,
+                within,
+            );
 
-            if let Some(orders_at_price) = opposite_book.get_mut(&price) {
-                let mut i = 0;
-                while i < orders_at_price.len() && !order.is_fully_filled() {
-                    let opposite_order = &mut orders_at_price[i];
-                    let trade_quantity = order
-                        .remaining_quantity()
-                        .min(opposite_order.remaining_quantity());
-
-                    // Create trade
-                    let (buy_order_id, sell_order_id) = match order.side {
-                        Side::Buy => (order.id, opposite_order.id),
-                        Side::Sell => (opposite_order.id, order.id),
-                    };
+            // Lit orders keep priority over pegs at the same price
+            let take_lit = match (lit, peg) {
+                (Some(price), Some((_, peg_price))) => match side {
+                    Side::Buy => price.saturating_mul(2) <= peg_price,
+                    Side::Sell => price.saturating_mul(2) >= peg_price,
+                },
+                (Some(_), None) => true,
+                (None, Some(_)) => false,
+                (None, None) => break,
+            };
 
-                    let trade = Trade::new(
-                        self.symbol.clone(),
-                        price,
-                        trade_quantity,
-                        buy_order_id,
-                        sell_order_id,
-                    );
+            if take_lit {
+                let price = lit.expect("lit level chosen");
+                let level = opposite_book.get_mut(&price).expect("best level exists");
+                let trade_price = spec.ticks_to_price(price);
 
+                let mut filled = 0;
+                for resting in level.orders.iter_mut() {
+                    if leaves == 0 {
+                        break;
+                    }
+                    let (lots, trade) = fill_resting(
+                        &self.symbol,
+                        &order,
+                        resting,
+                        leaves,
+                        trade_price,
+                        &spec,
+                        now,
+                    );
                     trades.push(trade);
 
                     // Update filled quantities
-                    order.filled_quantity += trade_quantity;
-                    opposite_order.filled_quantity += trade_quantity;
+                    leaves -= lots;
+                    filled_lots += lots;
+                    level.total_lots -= lots;
 
-                    if opposite_order.is_fully_filled() {
-                        orders_at_price.remove(i);
-                    } else {
-                        i += 1;
+                    if let Some(counterparties) = counterparties.as_deref_mut() {
+                        counterparties.push(resting.snapshot(&spec));
+                    }
+
+                    if resting.leaves == 0 {
+                        filled += 1;
                     }
                 }
 
-                if orders_at_price.is_empty() {
+                level.orders.drain(..filled);
+                if level.is_empty() {
                     opposite_book.remove(&price);
                 }
+                self.last_trade = Some(trade_price);
+            } else {
+                let (_, price) = peg.expect("peg chosen");
+                let trade_price = pegs::half_ticks_to_price(&spec, price);
+
+                for pegged in opposite_pegs.iter_mut() {
+                    if leaves == 0 {
+                        break;
+                    }
+                    let current =
+                        pegs::peg_price(opposite_side, &pegged.peg, best_bid, best_ask, &spec);
+                    if pegged.resting.leaves == 0 || current != Some(price) {
+                        continue;
+                    }
+                    let (lots, trade) = fill_resting(
+                        &self.symbol,
+                        &order,
+                        &mut pegged.resting,
+                        leaves,
+                        trade_price,
+                        &spec,
+                        now,
+                    );
+                    trades.push(trade);
+                    leaves -= lots;
+                    filled_lots += lots;
+
+                    if let Some(counterparties) = counterparties.as_deref_mut() {
+                        counterparties.push(pegged.resting.snapshot(&spec));
+                    }
+                }
+
+                opposite_pegs.retain(|p| p.resting.leaves > 0);
+                self.last_trade = Some(trade_price);
             }
         }
 
+        if filled_lots > 0 {
+            order.filled_quantity += spec.lots_to_quantity(filled_lots);
+        }
+
+        if let (Some(bands), Some(last)) = (self.bands.as_mut(), trades.last()) {
+            bands.record_trade(last.price);
+        }
+
         (order, trades)
     }
 
+    /// Executes pegged orders whose current price crosses the opposite side.
+    ///
+    /// Pegs reprice whenever the lit best bid or offer moves, which can leave
+    /// a market peg through the opposite best or a buy and sell midpoint peg at
+    /// the same price. A peg crossing a lit order trades at the lit price; two
+    /// crossing pegs trade at the price of the one that arrived first. Call
+    /// this after every change to the book.
+    pub fn match_pegs(&mut self, now: DateTime<Utc>) -> Vec<Fill> {
+        let mut fills = Vec::new();
+        if self.phase != TradingPhase::Continuous
+            || (self.peg_bids.is_empty() && self.peg_asks.is_empty())
+        {
+            return fills;
+        }
+
+        let spec = self.spec;
+        loop {
+            let (best_bid, best_ask) = self.lit_best();
+            let bid_peg = best_peg(&self.peg_bids, Side::Buy, best_bid, best_ask, &spec, |_| {
+                true
+            });
+            let ask_peg = best_peg(
+                &self.peg_asks,
+                Side::Sell,
+                best_bid,
+                best_ask,
+                &spec,
+                |_| true,
+            );
+
+            let buy = match (best_bid, bid_peg) {
+                (Some(lit), Some((i, peg))) if peg > lit.saturating_mul(2) => (Contra::Peg(i), peg),
+                (Some(lit), _) => (Contra::Lit(lit), lit.saturating_mul(2)),
+                (None, Some((i, peg))) => (Contra::Peg(i), peg),
+                (None, None) => break,
+            };
+            let sell = match (best_ask, ask_peg) {
+                (Some(lit), Some((i, peg))) if peg < lit.saturating_mul(2) => (Contra::Peg(i), peg),
+                (Some(lit), _) => (Contra::Lit(lit), lit.saturating_mul(2)),
+                (None, Some((i, peg))) => (Contra::Peg(i), peg),
+                (None, None) => break,
+            };
+            if buy.1 < sell.1 {
+                break;
+            }
+
+            let price = match (buy.0, sell.0) {
+                (Contra::Lit(_), Contra::Lit(_)) => break,
+                (Contra::Lit(_), _) => buy.1,
+                (_, Contra::Lit(_)) => sell.1,
+                (Contra::Peg(b), Contra::Peg(s)) => {
+                    if self.peg_bids[b].resting.seq < self.peg_asks[s].resting.seq {
+                        buy.1
+                    } else {
+                        sell.1
+                    }
+                }
+            };
+            let trade_price = pegs::half_ticks_to_price(&spec, price);
+            if self
+                .bands
+                .as_ref()
+                .is_some_and(|bands| !bands.contains(trade_price))
+            {
+                break;
+            }
+
+            let lots = self
+                .contra_leaves(Side::Buy, buy.0)
+                .min(self.contra_leaves(Side::Sell, sell.0));
+            let buy_order = self.consume(Side::Buy, buy.0, lots);
+            let sell_order = self.consume(Side::Sell, sell.0, lots);
+
+            let trade = Trade::new(
+                self.symbol.clone(),
+                trade_price,
+                spec.lots_to_quantity(lots),
+                buy_order.id,
+                sell_order.id,
+                now,
+            );
+            self.last_trade = Some(trade_price);
+            if let Some(bands) = self.bands.as_mut() {
+                bands.record_trade(trade_price);
+            }
+            fills.push(Fill {
+                trade,
+                buy: buy_order,
+                sell: sell_order,
+            });
+        }
+
+        fills
+    }
+
+    fn contra_leaves(&self, side: Side, contra: Contra) -> Lots {
+        match (contra, side) {
+            (Contra::Lit(price), Side::Buy) => self.bids[&price].orders[0].leaves,
+            (Contra::Lit(price), Side::Sell) => self.asks[&price].orders[0].leaves,
+            (Contra::Peg(i), Side::Buy) => self.peg_bids[i].resting.leaves,
+            (Contra::Peg(i), Side::Sell) => self.peg_asks[i].resting.leaves,
+        }
+    }
+
+    /// Fills `lots` of the given order and returns it as it stands afterwards,
+    /// dropping it from the book once it is done.
+    fn consume(&mut self, side: Side, contra: Contra, lots: Lots) -> Order {
+        match contra {
+            Contra::Lit(price) => {
+                let book = match side {
+                    Side::Buy => &mut self.bids,
+                    Side::Sell => &mut self.asks,
+                };
+                let level = book.get_mut(&price).expect("crossing level exists");
+                let resting = &mut level.orders[0];
+                resting.leaves -= lots;
+                resting.filled += lots;
+                level.total_lots -= lots;
+                let order = resting.snapshot(&self.spec);
+                if resting.leaves == 0 {
+                    level.orders.remove(0);
+                    if level.is_empty() {
+                        book.remove(&price);
+                    }
+                }
+                order
+            }
+            Contra::Peg(i) => {
+                let pegs = match side {
+                    Side::Buy => &mut self.peg_bids,
+                    Side::Sell => &mut self.peg_asks,
+                };
+                let resting = &mut pegs[i].resting;
+                resting.leaves -= lots;
+                resting.filled += lots;
+                let order = resting.snapshot(&self.spec);
+                if resting.leaves == 0 {
+                    pegs.remove(i);
+                }
+                order
+            }
+        }
+    }
+
+    /// Returns `true` if the unfilled remainder of `order` is still marketable
+    /// against the opposite side but was stopped by the price bands.
+    ///
+    /// Pegged orders never breach; they simply rest until the bands allow them to trade.
+    pub fn is_band_breached(&self, order: &Order) -> bool {
+        let Some(bands) = self.bands.as_ref() else {
+            return false;
+        };
+        if order.is_fully_filled() || order.peg.is_some() {
+            return false;
+        }
+
+        let market = order.order_type == OrderType::Market;
+        let best_opposite = match order.side {
+            Side::Buy => self
+                .get_best_ask()
+                .filter(|ask| market || *ask <= order.price),
+            Side::Sell => self
+                .get_best_bid()
+                .filter(|bid| market || *bid >= order.price),
+        };
+
+        best_opposite.is_some_and(|price| !bands.contains(price))
+    }
+
+    /// Verifies the book's internal consistency.
+    ///
+    /// Checks that the book is not crossed outside an auction, that every
+    /// level's running total matches its orders and levels are never empty,
+    /// that each level is in arrival order, that every order rests on its own
+    /// side and price with fills and open quantity adding up to its size, and
+    /// that no order appears twice. Costs a full walk of the book, so it is
+    /// meant for tests and debug builds.
+    pub fn check_invariants(&self) -> Result<(), InvariantViolation> {
+        if self.phase == TradingPhase::Continuous {
+            if let (Some(bid), Some(ask)) = (self.get_best_bid(), self.get_best_ask()) {
+                if bid >= ask {
+                    return Err(InvariantViolation::Crossed { bid, ask });
+                }
+            }
+        }
+
+        let mut seen = HashSet::new();
+        let mut check_order = |resting: &RestingOrder, side: Side| {
+            let id = resting.order.id;
+            if !seen.insert(id) {
+                return Err(InvariantViolation::Duplicate(id));
+            }
+            if resting.order.side != side {
+                return Err(InvariantViolation::Misplaced(id));
+            }
+            if resting.leaves <= 0 {
+                return Err(InvariantViolation::NoLeaves(id));
+            }
+            let size = self
+                .spec
+                .quantity_to_lots_floor(resting.order.remaining_quantity());
+            if resting.filled + resting.leaves != size {
+                return Err(InvariantViolation::Quantity(id));
+            }
+            Ok(())
+        };
+
+        for (side, book) in [(Side::Buy, &self.bids), (Side::Sell, &self.asks)] {
+            for (&ticks, level) in book {
+                let price = self.spec.ticks_to_price(ticks);
+                if level.is_empty() {
+                    return Err(InvariantViolation::EmptyLevel { side, price });
+                }
+                let actual: Lots = level.orders.iter().map(|o| o.leaves).sum();
+                if actual != level.total_lots {
+                    return Err(InvariantViolation::LevelTotal {
+                        side,
+                        price,
+                        recorded: level.total_lots,
+                        actual,
+                    });
+                }
+                if level.orders.windows(2).any(|w| w[0].seq >= w[1].seq) {
+                    return Err(InvariantViolation::Fifo { side, price });
+                }
+                for resting in &level.orders {
+                    check_order(resting, side)?;
+                    let placed = match side {
+                        Side::Buy => self.spec.price_to_ticks_floor(resting.order.price),
+                        Side::Sell => self.spec.price_to_ticks_ceil(resting.order.price),
+                    };
+                    if placed != ticks {
+                        return Err(InvariantViolation::Misplaced(resting.order.id));
+                    }
+                }
+            }
+        }
+
+        for (side, pegs) in [(Side::Buy, &self.peg_bids), (Side::Sell, &self.peg_asks)] {
+            for pegged in pegs {
+                check_order(&pegged.resting, side)?;
+            }
+            if pegs
+                .windows(2)
+                .any(|w| w[0].resting.seq >= w[1].resting.seq)
+            {
+                return Err(InvariantViolation::Fifo {
+                    side,
+                    price: Decimal::ZERO,
+                });
+            }
+        }
+
+        for order in self.stops.iter() {
+            if !order.is_stop() {
+                return Err(InvariantViolation::NotAStop(order.id));
+            }
+            if !seen.insert(order.id) {
+                return Err(InvariantViolation::Duplicate(order.id));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Suspends continuous matching until `ends_at`.
+    pub fn start_auction(&mut self, ends_at: DateTime<Utc>) {
+        self.phase = TradingPhase::VolatilityAuction { ends_at };
+    }
+
+    /// Returns `true` if a volatility auction is running and has reached its end time.
+    pub fn is_auction_due(&self, now: DateTime<Utc>) -> bool {
+        matches!(self.phase, TradingPhase::VolatilityAuction { ends_at } if now >= ends_at)
+    }
+
+    /// Ends a volatility auction by executing all crossing interest at a single price.
+    ///
+    /// The uncrossing price maximises executable volume, then minimises the
+    /// surplus left on either side, then stays closest to the reference price.
+    /// It becomes the new band reference and last trade price.
+    pub fn uncross(&mut self, now: DateTime<Utc>) -> Vec<Trade> {
+        self.uncross_with_fills(now)
+            .into_iter()
+            .map(|fill| fill.trade)
+            .collect()
+    }
+
+    /// Uncrosses like [`OrderBook::uncross`], also reporting both orders of each execution.
+    ///
+    /// Pegged orders do not take part in the auction.
+    pub fn uncross_with_fills(&mut self, now: DateTime<Utc>) -> Vec<Fill> {
+        self.phase = TradingPhase::Continuous;
+
+        let mut fills = Vec::new();
+        let Some(price) = self.uncrossing_price() else {
+            return fills;
+        };
+        let trade_price = self.spec.ticks_to_price(price);
+
+        while let (Some((&bid, _)), Some((&ask, _))) =
+            (self.bids.last_key_value(), self.asks.first_key_value())
+        {
+            if bid < price || ask > price {
+                break;
+            }
+
+            let bid_level = self.bids.get_mut(&bid).expect("best bid level exists");
+            let ask_level = self.asks.get_mut(&ask).expect("best ask level exists");
+            let buy = &mut bid_level.orders[0];
+            let sell = &mut ask_level.orders[0];
+
+            let lots = buy.leaves.min(sell.leaves);
+            let quantity = self.spec.lots_to_quantity(lots);
+            let trade = Trade::new(
+                self.symbol.clone(),
+                trade_price,
+                quantity,
+                buy.order.id,
+                sell.order.id,
+                now,
+            );
+            buy.leaves -= lots;
+            sell.leaves -= lots;
+            buy.filled += lots;
+            sell.filled += lots;
+            bid_level.total_lots -= lots;
+            ask_level.total_lots -= lots;
+            fills.push(Fill {
+                trade,
+                buy: buy.snapshot(&self.spec),
+                sell: sell.snapshot(&self.spec),
+            });
+
+            if buy.leaves == 0 {
+                bid_level.orders.remove(0);
+                if bid_level.is_empty() {
+                    self.bids.remove(&bid);
+                }
+            }
+            if sell.leaves == 0 {
+                ask_level.orders.remove(0);
+                if ask_level.is_empty() {
+                    self.asks.remove(&ask);
+                }
+            }
+        }
+
+        if !fills.is_empty() {
+            self.last_trade = Some(trade_price);
+        }
+        if let Some(bands) = self.bands.as_mut() {
+            bands.set_reference_price(trade_price);
+            if !fills.is_empty() {
+                bands.record_trade(trade_price);
+            }
+        }
+
+        fills
+    }
+
+    fn uncrossing_price(&self) -> Option<Ticks> {
+        let reference = self
+            .bands
+            .as_ref()
+            .and_then(|b| b.last_trade_price().or(b.reference_price()))
+            .map(|r| self.spec.price_to_ticks_floor(r));
+
+        let mut best: Option<(Ticks, Lots, Lots, Ticks)> = None; // price, volume, surplus, distance
+        let candidates: BTreeSet<Ticks> =
+            self.bids.keys().chain(self.asks.keys()).copied().collect();
+        for price in candidates {
+            let demand: Lots = self.bids.range(price..).map(|(_, l)| l.total_lots).sum();
+            let supply: Lots = self.asks.range(..=price).map(|(_, l)| l.total_lots).sum();
+            let volume = demand.min(supply);
+            if volume == 0 {
+                continue;
+            }
+            let surplus = (demand - supply).abs();
+            let distance = reference.map_or(0, |r| (price - r).abs());
+
+            let better = match best {
+                None => true,
+                Some((_, v, s, d)) => (volume, -surplus, -distance) > (v, -s, -d),
+            };
+            if better {
+                best = Some((price, volume, surplus, distance));
+            }
+        }
+
+        best.map(|(price, _, _, _)| price)
+    }
+
     pub fn get_best_bid(&self) -> Option<Decimal> {
-        self.bids.keys().next_back().copied()
+        self.bids
+            .keys()
+            .next_back()
+            .map(|ticks| self.spec.ticks_to_price(*ticks))
     }
 
     pub fn get_best_ask(&self) -> Option<Decimal> {
-        self.asks.keys().next().copied()
+        self.asks
+            .keys()
+            .next()
+            .map(|ticks| self.spec.ticks_to_price(*ticks))
     }
 
     pub fn get_spread(&self) -> Option<Decimal> {
@@ -146,37 +1030,19 @@ impl OrderBook {
     }
 
     pub fn get_snapshot(&self) -> OrderBookSnapshot {
-        let bids: Vec<OrderBookLevel> = self
-            .bids
-            .iter()
-            .rev()
-            .take(20)
-            .map(|(price, orders)| {
-                let quantity = orders.iter().map(|o| o.remaining_quantity()).sum();
-                OrderBookLevel {
-                    price: *price,
-                    quantity,
-                }
-            })
-            .collect();
-
-        let asks: Vec<OrderBookLevel> = self
-            .asks
-            .iter()
-            .take(20)
-            .map(|(price, orders)| {
-                let quantity = orders.iter().map(|o| o.remaining_quantity()).sum();
-                OrderBookLevel {
-                    price: *price,
-                    quantity,
-                }
-            })
-            .collect();
+        self.snapshot_to(self.snapshot_depth)
+    }
+
+    /// Every level on both sides, however deep the book.
+    pub fn get_full_snapshot(&self) -> OrderBookSnapshot {
+        self.snapshot_to(usize::MAX)
+    }
 
+    fn snapshot_to(&self, levels: usize) -> OrderBookSnapshot {
         OrderBookSnapshot {
             symbol: self.symbol.clone(),
-            bids,
-            asks,
+            bids: self.get_depth(Side::Buy, levels),
+            asks: self.get_depth(Side::Sell, levels),
             timestamp: Utc::now(),
         }
     }
@@ -187,18 +1053,16 @@ impl OrderBook {
             Side::Sell => &self.asks,
         };
 
-        let iter: Box<dyn Iterator<Item = (&Decimal, &Vec<Order>)>> = match side {
+        let iter: Box<dyn Iterator<Item = (&Ticks, &PriceLevel)>> = match side {
             Side::Buy => Box::new(book.iter().rev()),
             Side::Sell => Box::new(book.iter()),
         };
 
         iter.take(levels)
-            .map(|(price, orders)| {
-                let quantity = orders.iter().map(|o| o.remaining_quantity()).sum();
-                OrderBookLevel {
-                    price: *price,
-                    quantity,
-                }
+            .map(|(price, level)| OrderBookLevel {
+                price: self.spec.ticks_to_price(*price),
+                quantity: self.spec.lots_to_quantity(level.total_lots),
+                order_count: level.order_count(),
             })
             .collect()
     }
@@ -208,6 +1072,7 @@ impl OrderBook {
 mod tests {
     use super::*;
     use crate::utils::types::OrderType;
+    use crate::utils::types::PegReference;
 
     #[test]
     fn test_orderbook_add_and_match() {
@@ -230,7 +1095,7 @@ mod tests {
         );
 
         book.add_order(buy_order.clone());
-        let (matched_order, trades) = book.match_order(sell_order);
+        let (matched_order, trades) = book.match_order(sell_order, Utc::now());
 
         assert_eq!(trades.len(), 1);
         assert_eq!(trades[0].quantity, Decimal::from(1));
@@ -259,4 +1124,276 @@ mod tests {
 
         assert_eq!(book.get_spread(), Some(Decimal::from(200)));
     }
+
+    #[test]
+    fn test_orderbook_match_stops_at_band() {
+        let mut book = OrderBook::new("BTCUSD".to_string());
+        book.set_price_bands(
+            PriceBandConfig {
+                static_width: Some(Decimal::new(5, 2)),
+                dynamic_width: None,
+                ..Default::default()
+            },
+            Some(Decimal::from(100)),
+        );
+
+        for price in [101, 104, 110] {
+            book.add_order(Order::new(
+                "BTCUSD".to_string(),
+                Side::Sell,
+                OrderType::Limit,
+                Decimal::from(price),
+                Decimal::from(1),
+            ));
+        }
+
+        let buy = Order::new(
+            "BTCUSD".to_string(),
+            Side::Buy,
+            OrderType::Limit,
+            Decimal::from(120),
+            Decimal::from(3),
+        );
+        let (buy, trades) = book.match_order(buy, Utc::now());
+
+        assert_eq!(trades.len(), 2);
+        assert_eq!(buy.filled_quantity, Decimal::from(2));
+        assert!(book.is_band_breached(&buy));
+        assert_eq!(book.get_best_ask(), Some(Decimal::from(110)));
+    }
+
+    #[test]
+    fn test_orderbook_auction_uncross() {
+        let mut book = OrderBook::new("BTCUSD".to_string());
+        book.set_price_bands(
+            PriceBandConfig {
+                static_width: None,
+                dynamic_width: None,
+                ..Default::default()
+            },
+            Some(Decimal::from(104)),
+        );
+        book.start_auction(Utc::now());
+
+        for (side, price, qty) in [
+            (Side::Buy, 105, 2),
+            (Side::Buy, 103, 1),
+            (Side::Sell, 102, 1),
+            (Side::Sell, 104, 3),
+        ] {
+            let order = Order::new(
+                "BTCUSD".to_string(),
+                side,
+                OrderType::Limit,
+                Decimal::from(price),
+                Decimal::from(qty),
+            );
+            let (order, trades) = book.match_order(order, Utc::now());
+            assert!(trades.is_empty());
+            book.add_order(order);
+        }
+
+        assert!(book.is_auction_due(Utc::now()));
+        let trades = book.uncross(Utc::now());
+
+        assert_eq!(book.trading_phase(), TradingPhase::Continuous);
+        assert_eq!(
+            trades.iter().map(|t| t.quantity).sum::<Decimal>(),
+            Decimal::from(2)
+        );
+        assert!(trades.iter().all(|t| t.price == Decimal::from(104)));
+        assert_eq!(book.get_best_bid(), Some(Decimal::from(103)));
+        assert_eq!(book.get_best_ask(), Some(Decimal::from(104)));
+    }
+
+    #[test]
+    fn test_orderbook_level_aggregates_track_fills_and_cancels() {
+        let mut book = OrderBook::new("BTCUSD".to_string());
+
+        let mut ids = Vec::new();
+        for qty in [1, 2, 3] {
+            let order = Order::new(
+                "BTCUSD".to_string(),
+                Side::Sell,
+                OrderType::Limit,
+                Decimal::from(50000),
+                Decimal::from(qty),
+            );
+            ids.push(order.id);
+            book.add_order(order);
+        }
+
+        let level = &book.get_depth(Side::Sell, 1)[0];
+        assert_eq!(level.quantity, Decimal::from(6));
+        assert_eq!(level.order_count, 3);
+
+        let buy = Order::new(
+            "BTCUSD".to_string(),
+            Side::Buy,
+            OrderType::Limit,
+            Decimal::from(50000),
+            Decimal::new(15, 1),
+        );
+        book.match_order(buy, Utc::now());
+
+        let level = &book.get_depth(Side::Sell, 1)[0];
+        assert_eq!(level.quantity, Decimal::new(45, 1));
+        assert_eq!(level.order_count, 2);
+
+        let cancelled = book.remove_order(ids[1], Side::Sell).unwrap();
+        assert_eq!(cancelled.filled_quantity, Decimal::new(5, 1));
+
+        let level = &book.get_depth(Side::Sell, 1)[0];
+        assert_eq!(level.quantity, Decimal::from(3));
+        assert_eq!(level.order_count, 1);
+    }
+
+    fn pegged(side: Side, reference: PegReference, quantity: i64) -> Order {
+        Order {
+            peg: Some(PegInstruction {
+                reference,
+                offset: Decimal::ZERO,
+                limit: None,
+            }),
+            ..Order::new(
+                "BTCUSD".to_string(),
+                side,
+                OrderType::Limit,
+                Decimal::ZERO,
+                Decimal::from(quantity),
+            )
+        }
+    }
+
+    #[test]
+    fn test_orderbook_midpoint_pegs_trade_at_half_ticks() {
+        let mut book = OrderBook::with_spec(
+            "BTCUSD".to_string(),
+            InstrumentSpec::new(Decimal::ONE, Decimal::ONE).unwrap(),
+        );
+        for (side, price) in [(Side::Buy, 100), (Side::Sell, 103)] {
+            book.add_order(Order::new(
+                "BTCUSD".to_string(),
+                side,
+                OrderType::Limit,
+                Decimal::from(price),
+                Decimal::from(1),
+            ));
+        }
+
+        let resting = pegged(Side::Sell, PegReference::Midpoint, 2);
+        book.add_order(resting.clone());
+        assert_eq!(book.peg_price(resting.id), Some(Decimal::new(1015, 1)));
+        assert!(book.get_depth(Side::Sell, 5).len() == 1);
+
+        // The incoming midpoint buy meets the resting one at 101.5
+        let result = book.execute(pegged(Side::Buy, PegReference::Midpoint, 1), Utc::now());
+        assert_eq!(result.trades.len(), 1);
+        assert_eq!(result.trades[0].price, Decimal::new(1015, 1));
+        assert_eq!(result.counterparties[0].id, resting.id);
+
+        // A lit buy at 103 takes the cheaper peg before the lit ask
+        let (_, trades) = book.match_order(
+            Order::new(
+                "BTCUSD".to_string(),
+                Side::Buy,
+                OrderType::Limit,
+                Decimal::from(103),
+                Decimal::from(2),
+            ),
+            Utc::now(),
+        );
+        assert_eq!(trades[0].price, Decimal::new(1015, 1));
+        assert_eq!(trades[1].price, Decimal::from(103));
+        assert_eq!(book.pegged_order_count(Side::Sell), 0);
+    }
+
+    #[test]
+    fn test_orderbook_pegs_reprice_and_cross() {
+        let mut book = OrderBook::with_spec(
+            "BTCUSD".to_string(),
+            InstrumentSpec::new(Decimal::ONE, Decimal::ONE).unwrap(),
+        );
+        let bid = pegged(Side::Buy, PegReference::Primary, 1);
+        book.add_order(bid.clone());
+        assert_eq!(book.peg_price(bid.id), None);
+
+        for (side, price) in [(Side::Buy, 100), (Side::Sell, 104)] {
+            book.add_order(Order::new(
+                "BTCUSD".to_string(),
+                side,
+                OrderType::Limit,
+                Decimal::from(price),
+                Decimal::from(1),
+            ));
+        }
+        assert_eq!(book.peg_price(bid.id), Some(Decimal::from(100)));
+
+        let mid = pegged(Side::Sell, PegReference::Midpoint, 1);
+        book.add_order(mid.clone());
+        assert!(book.match_pegs(Utc::now()).is_empty());
+
+        // Two below the offer reaches the resting midpoint, which sets the price
+        let market = Order {
+            peg: Some(PegInstruction {
+                reference: PegReference::Market,
+                offset: Decimal::from(-2),
+                limit: None,
+            }),
+            ..pegged(Side::Buy, PegReference::Market, 1)
+        };
+        book.add_order(market.clone());
+        let fills = book.match_pegs(Utc::now());
+        assert_eq!(fills.len(), 1);
+        assert_eq!(fills[0].buy.id, market.id);
+        assert_eq!(fills[0].sell.id, mid.id);
+        assert_eq!(fills[0].trade.price, Decimal::from(102));
+        assert_eq!(book.pegged_order_count(Side::Buy), 1);
+    }
+
+    #[test]
+    fn test_orderbook_check_invariants() {
+        let mut book = OrderBook::new("BTCUSD".to_string());
+        for (side, price) in [(Side::Buy, 99), (Side::Buy, 99), (Side::Sell, 101)] {
+            book.add_order(Order::new(
+                "BTCUSD".to_string(),
+                side,
+                OrderType::Limit,
+                Decimal::from(price),
+                Decimal::from(2),
+            ));
+        }
+        book.match_order(
+            Order::new(
+                "BTCUSD".to_string(),
+                Side::Sell,
+                OrderType::Limit,
+                Decimal::from(99),
+                Decimal::from(3),
+            ),
+            Utc::now(),
+        );
+        assert_eq!(book.check_invariants(), Ok(()));
+
+        // Resting an order through the ask leaves the book crossed
+        book.add_order(Order::new(
+            "BTCUSD".to_string(),
+            Side::Buy,
+            OrderType::Limit,
+            Decimal::from(102),
+            Decimal::from(1),
+        ));
+        assert!(matches!(
+            book.check_invariants(),
+            Err(InvariantViolation::Crossed { .. })
+        ));
+
+        let level = book.bids.get_mut(&(99 * 100_000_000)).unwrap();
+        level.total_lots += 1;
+        book.bids.remove(&(102 * 100_000_000));
+        assert!(matches!(
+            book.check_invariants(),
+            Err(InvariantViolation::LevelTotal { .. })
+        ));
+    }
 }
@@ -0,0 +1,110 @@
+//! Pricing for orders pegged to the local best bid and offer.
+//!
+//! Pegged orders are not displayed and are never keyed by price. The book
+//! re-derives their price from the lit best bid and offer whenever it needs
+//! one, so they follow the market without being moved between levels.
+//! Prices are kept in half ticks so that midpoint pegs can sit between two
+//! ticks.
+
+use crate::engine::ticks::{InstrumentSpec, Ticks};
+use crate::utils::types::{PegInstruction, PegReference, Side};
+use rust_decimal::Decimal;
+
+/// Price in half ticks: twice the tick count.
+pub type HalfTicks = i64;
+
+/// Current price of a peg on `side`, or `None` while its reference is missing.
+///
+/// Offsets and limits off the tick grid are rounded passively.
+pub fn peg_price(
+    side: Side,
+    peg: &PegInstruction,
+    best_bid: Option<Ticks>,
+    best_ask: Option<Ticks>,
+    spec: &InstrumentSpec,
+) -> Option<HalfTicks> {
+    let reference = match (peg.reference, side) {
+        (PegReference::Primary, Side::Buy) | (PegReference::Market, Side::Sell) => {
+            best_bid?.saturating_mul(2)
+        }
+        (PegReference::Primary, Side::Sell) | (PegReference::Market, Side::Buy) => {
+            best_ask?.saturating_mul(2)
+        }
+        (PegReference::Midpoint, _) => best_bid?.saturating_add(best_ask?),
+    };
+
+    let price = match side {
+        Side::Buy => {
+            let offset = spec.price_to_ticks_floor(peg.offset).saturating_mul(2);
+            let price = reference.saturating_add(offset);
+            match peg.limit {
+                Some(limit) => price.min(spec.price_to_ticks_floor(limit).saturating_mul(2)),
+                None => price,
+            }
+        }
+        Side::Sell => {
+            let offset = spec.price_to_ticks_ceil(peg.offset).saturating_mul(2);
+            let price = reference.saturating_add(offset);
+            match peg.limit {
+                Some(limit) => price.max(spec.price_to_ticks_ceil(limit).saturating_mul(2)),
+                None => price,
+            }
+        }
+    };
+    Some(price)
+}
+
+pub fn half_ticks_to_price(spec: &InstrumentSpec, half_ticks: HalfTicks) -> Decimal {
+    if half_ticks % 2 == 0 {
+        spec.ticks_to_price(half_ticks / 2)
+    } else {
+        (spec.ticks_to_price(half_ticks) / Decimal::TWO).normalize()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn peg(reference: PegReference, offset: i64, limit: Option<i64>) -> PegInstruction {
+        PegInstruction {
+            reference,
+            offset: Decimal::from(offset),
+            limit: limit.map(Decimal::from),
+        }
+    }
+
+    #[test]
+    fn test_peg_prices_follow_the_bbo() {
+        let spec = InstrumentSpec::new(Decimal::ONE, Decimal::ONE).unwrap();
+        let (bid, ask) = (Some(100), Some(103));
+
+        let primary = peg(PegReference::Primary, 0, None);
+        assert_eq!(peg_price(Side::Buy, &primary, bid, ask, &spec), Some(200));
+        assert_eq!(peg_price(Side::Sell, &primary, bid, ask, &spec), Some(206));
+
+        let market = peg(PegReference::Market, -1, None);
+        assert_eq!(peg_price(Side::Buy, &market, bid, ask, &spec), Some(204));
+
+        let midpoint = peg(PegReference::Midpoint, 0, None);
+        let mid = peg_price(Side::Sell, &midpoint, bid, ask, &spec).unwrap();
+        assert_eq!(half_ticks_to_price(&spec, mid), Decimal::new(1015, 1));
+        assert_eq!(peg_price(Side::Sell, &midpoint, bid, None, &spec), None);
+    }
+
+    #[test]
+    fn test_peg_limit_caps_price() {
+        let spec = InstrumentSpec::new(Decimal::ONE, Decimal::ONE).unwrap();
+
+        let capped = peg(PegReference::Primary, 1, Some(100));
+        assert_eq!(
+            peg_price(Side::Buy, &capped, Some(100), Some(105), &spec),
+            Some(200)
+        );
+        let floored = peg(PegReference::Market, 0, Some(102));
+        assert_eq!(
+            peg_price(Side::Sell, &floored, Some(100), Some(105), &spec),
+            Some(204)
+        );
+    }
+}
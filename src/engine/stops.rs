@@ -0,0 +1,224 @@
+//! Stop orders waiting for their trigger price.
+//!
+//! Stops do not rest in the visible book. They are parked here per symbol
+//! and released, in arrival order, once the last trade price reaches their
+//! stop price: buy stops at or above it, sell stops at or below it.
+//!
+//! Trailing stops live in the same book. Before each trigger check their stop
+//! price is ratcheted towards the market by [`trail`], and they trigger on the
+//! price they follow rather than always on the last trade.
+
+use crate::engine::ticks::InstrumentSpec;
+use crate::utils::types::{Order, OrderType, Side, TrailReference, TrailingOffset};
+use rust_decimal::Decimal;
+use uuid::Uuid;
+
+/// Market prices stops are trailed and triggered against.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MarketPrices {
+    pub last_trade: Option<Decimal>,
+    pub best_bid: Option<Decimal>,
+    pub best_ask: Option<Decimal>,
+}
+
+impl MarketPrices {
+    /// A single observed price standing in for the last trade and both sides,
+    /// as when replaying bars in a backtest.
+    pub fn at(price: Decimal) -> Self {
+        Self {
+            last_trade: Some(price),
+            best_bid: Some(price),
+            best_ask: Some(price),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct StopBook {
+    orders: Vec<Order>,
+}
+
+impl StopBook {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, order: Order) {
+        self.orders.push(order);
+    }
+
+    pub fn remove(&mut self, order_id: Uuid) -> Option<Order> {
+        let pos = self.orders.iter().position(|o| o.id == order_id)?;
+        Some(self.orders.remove(pos))
+    }
+
+    pub fn get(&self, order_id: Uuid) -> Option<&Order> {
+        self.orders.iter().find(|o| o.id == order_id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.orders.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.orders.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Order> {
+        self.orders.iter()
+    }
+
+    /// Ratchets every trailing stop towards `market`, keeping stops on the tick grid of `spec`.
+    pub fn update_trailing(&mut self, market: &MarketPrices, spec: &InstrumentSpec) {
+        for order in self.orders.iter_mut() {
+            trail(order, market, spec);
+        }
+    }
+
+    /// Removes and returns every stop triggered at `market`, oldest first.
+    pub fn take_triggered(&mut self, market: &MarketPrices) -> Vec<Order> {
+        let (triggered, waiting) = std::mem::take(&mut self.orders)
+            .into_iter()
+            .partition(|o| is_triggered(o, market));
+        self.orders = waiting;
+        triggered
+    }
+}
+
+/// Price `order` trails and triggers on: the last trade, or the same-side
+/// best price for trailing stops that follow the book.
+pub fn reference_price(order: &Order, market: &MarketPrices) -> Option<Decimal> {
+    match order.trailing.map(|t| t.reference) {
+        Some(TrailReference::BestPrice) => match order.side {
+            Side::Buy => market.best_ask,
+            Side::Sell => market.best_bid,
+        },
+        _ => market.last_trade,
+    }
+}
+
+/// Returns `true` if `market` reaches the stop price of `order`.
+pub fn is_triggered(order: &Order, market: &MarketPrices) -> bool {
+    let (Some(stop_price), Some(reference)) = (order.stop_price, reference_price(order, market))
+    else {
+        return false;
+    };
+    match order.side {
+        Side::Buy => reference >= stop_price,
+        Side::Sell => reference <= stop_price,
+    }
+}
+
+/// Moves the stop price of a trailing stop towards `market`, never away from it.
+///
+/// Sell stops are rounded down and buy stops up onto the tick grid. A trailing
+/// stop without a stop price takes its first one from the market.
+pub fn trail(order: &mut Order, market: &MarketPrices, spec: &InstrumentSpec) {
+    let Some(trailing) = order.trailing else {
+        return;
+    };
+    let Some(reference) = reference_price(order, market) else {
+        return;
+    };
+
+    let offset = match trailing.offset {
+        TrailingOffset::Amount(amount) => amount,
+        TrailingOffset::Percent(percent) => reference * percent / Decimal::ONE_HUNDRED,
+    };
+    let candidate = match order.side {
+        Side::Sell => spec.ticks_to_price(spec.price_to_ticks_floor(reference - offset)),
+        Side::Buy => spec.ticks_to_price(spec.price_to_ticks_ceil(reference + offset)),
+    };
+
+    let improves = match (order.side, order.stop_price) {
+        (_, None) => true,
+        (Side::Sell, Some(stop_price)) => candidate > stop_price,
+        (Side::Buy, Some(stop_price)) => candidate < stop_price,
+    };
+    if !improves {
+        return;
+    }
+    if let (OrderType::StopLimit, Some(stop_price)) = (order.order_type, order.stop_price) {
+        order.price += candidate - stop_price;
+    }
+    order.stop_price = Some(candidate);
+}
+
+/// Converts a triggered stop into the order it becomes on the book.
+pub fn activate(mut order: Order) -> Order {
+    order.order_type = match order.order_type {
+        OrderType::StopMarket => OrderType::Market,
+        OrderType::StopLimit => OrderType::Limit,
+        other => other,
+    };
+    order
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::types::TrailingStop;
+
+    fn stop(side: Side, stop_price: i64) -> Order {
+        Order {
+            stop_price: Some(Decimal::from(stop_price)),
+            ..Order::new(
+                "BTCUSD".to_string(),
+                side,
+                OrderType::StopMarket,
+                Decimal::ZERO,
+                Decimal::from(1),
+            )
+        }
+    }
+
+    #[test]
+    fn test_stop_book_triggers_by_side() {
+        let mut stops = StopBook::new();
+        stops.add(stop(Side::Buy, 105));
+        stops.add(stop(Side::Sell, 95));
+        stops.add(stop(Side::Buy, 110));
+
+        let at = |price: i64| MarketPrices::at(Decimal::from(price));
+        assert!(stops.take_triggered(&at(100)).is_empty());
+
+        let triggered = stops.take_triggered(&at(106));
+        assert_eq!(triggered.len(), 1);
+        assert_eq!(triggered[0].stop_price, Some(Decimal::from(105)));
+        assert_eq!(activate(triggered[0].clone()).order_type, OrderType::Market);
+
+        let triggered = stops.take_triggered(&at(95));
+        assert_eq!(triggered.len(), 1);
+        assert_eq!(triggered[0].side, Side::Sell);
+        assert_eq!(stops.len(), 1);
+    }
+
+    #[test]
+    fn test_trailing_stop_ratchets_and_keeps_limit_offset() {
+        let spec = InstrumentSpec::new(Decimal::ONE, Decimal::ONE).unwrap();
+        let mut order = Order {
+            order_type: OrderType::StopLimit,
+            price: Decimal::from(94),
+            trailing: Some(TrailingStop {
+                offset: TrailingOffset::Percent(Decimal::from(5)),
+                reference: TrailReference::BestPrice,
+            }),
+            ..stop(Side::Sell, 95)
+        };
+        let bid = |price: i64| MarketPrices {
+            best_bid: Some(Decimal::from(price)),
+            ..Default::default()
+        };
+
+        // 5% below 110 is 104.5, floored onto the grid
+        trail(&mut order, &bid(110), &spec);
+        assert_eq!(order.stop_price, Some(Decimal::from(104)));
+        assert_eq!(order.price, Decimal::from(103));
+
+        // The stop never moves back down
+        trail(&mut order, &bid(105), &spec);
+        assert_eq!(order.stop_price, Some(Decimal::from(104)));
+        assert!(!is_triggered(&order, &bid(105)));
+        assert!(is_triggered(&order, &bid(104)));
+    }
+}
@@ -0,0 +1,222 @@
+//! Integer tick/lot scaling for order book prices and quantities.
+//!
+//! The order book works on `i64` multiples of the instrument tick and lot
+//! sizes so that level lookups and fills avoid `Decimal` arithmetic. Values
+//! only cross back into `Decimal` at the API boundary, and conversion in
+//! either direction is exact for any price or quantity on the grid.
+
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+/// Price in integer ticks.
+pub type Ticks = i64;
+
+/// Quantity in integer lots.
+pub type Lots = i64;
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum ScaleError {
+    #[error("price {price} is not a multiple of tick size {tick_size}")]
+    OffTick { price: Decimal, tick_size: Decimal },
+    #[error("quantity {quantity} is not a multiple of lot size {lot_size}")]
+    OffLot {
+        quantity: Decimal,
+        lot_size: Decimal,
+    },
+    #[error("value {0} is out of range for the instrument scale")]
+    Overflow(Decimal),
+    #[error("tick and lot sizes must be positive")]
+    InvalidSpec,
+}
+
+/// Tick and lot sizes of a tradable instrument.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InstrumentSpec {
+    pub tick_size: Decimal,
+    pub lot_size: Decimal,
+}
+
+impl InstrumentSpec {
+    pub fn new(tick_size: Decimal, lot_size: Decimal) -> Result<Self, ScaleError> {
+        if tick_size <= Decimal::ZERO || lot_size <= Decimal::ZERO {
+            return Err(ScaleError::InvalidSpec);
+        }
+        Ok(Self {
+            tick_size,
+            lot_size,
+        })
+    }
+
+    /// Converts a price to ticks, failing if it is not on the tick grid.
+    pub fn price_to_ticks(&self, price: Decimal) -> Result<Ticks, ScaleError> {
+        match Units::of(price, self.tick_size) {
+            Some(units) if units.is_exact() => units.floor().ok_or(ScaleError::Overflow(price)),
+            Some(_) => Err(ScaleError::OffTick {
+                price,
+                tick_size: self.tick_size,
+            }),
+            None => Err(ScaleError::Overflow(price)),
+        }
+    }
+
+    /// Converts a quantity to lots, failing if it is not a whole number of lots.
+    pub fn quantity_to_lots(&self, quantity: Decimal) -> Result<Lots, ScaleError> {
+        match Units::of(quantity, self.lot_size) {
+            Some(units) if units.is_exact() => units.floor().ok_or(ScaleError::Overflow(quantity)),
+            Some(_) => Err(ScaleError::OffLot {
+                quantity,
+                lot_size: self.lot_size,
+            }),
+            None => Err(ScaleError::Overflow(quantity)),
+        }
+    }
+
+    /// Rounds a price down onto the tick grid, saturating on overflow.
+    pub fn price_to_ticks_floor(&self, price: Decimal) -> Ticks {
+        Units::of(price, self.tick_size)
+            .and_then(|u| u.floor())
+            .unwrap_or_else(|| saturate(price))
+    }
+
+    /// Rounds a price up onto the tick grid, saturating on overflow.
+    pub fn price_to_ticks_ceil(&self, price: Decimal) -> Ticks {
+        Units::of(price, self.tick_size)
+            .and_then(|u| u.ceil())
+            .unwrap_or_else(|| saturate(price))
+    }
+
+    /// Rounds a quantity down to whole lots, saturating on overflow.
+    pub fn quantity_to_lots_floor(&self, quantity: Decimal) -> Lots {
+        Units::of(quantity, self.lot_size)
+            .and_then(|u| u.floor())
+            .unwrap_or_else(|| saturate(quantity))
+            .max(0)
+    }
+
+    pub fn ticks_to_price(&self, ticks: Ticks) -> Decimal {
+        scale_up(ticks, self.tick_size)
+    }
+
+    pub fn lots_to_quantity(&self, lots: Lots) -> Decimal {
+        scale_up(lots, self.lot_size)
+    }
+}
+
+/// `value / unit` computed on integer mantissas, kept as quotient and remainder.
+struct Units {
+    quotient: i128,
+    remainder: i128,
+}
+
+impl Units {
+    fn of(value: Decimal, unit: Decimal) -> Option<Self> {
+        let scale = value.scale().max(unit.scale());
+        let value_m = value
+            .mantissa()
+            .checked_mul(10i128.checked_pow(scale - value.scale())?)?;
+        let unit_m = unit
+            .mantissa()
+            .checked_mul(10i128.checked_pow(scale - unit.scale())?)?;
+        Some(Self {
+            quotient: value_m.div_euclid(unit_m),
+            remainder: value_m.rem_euclid(unit_m),
+        })
+    }
+
+    fn is_exact(&self) -> bool {
+        self.remainder == 0
+    }
+
+    fn floor(&self) -> Option<i64> {
+        i64::try_from(self.quotient).ok()
+    }
+
+    fn ceil(&self) -> Option<i64> {
+        let quotient = if self.remainder == 0 {
+            self.quotient
+        } else {
+            self.quotient + 1
+        };
+        i64::try_from(quotient).ok()
+    }
+}
+
+fn saturate(value: Decimal) -> i64 {
+    if value.is_sign_negative() {
+        i64::MIN
+    } else {
+        i64::MAX
+    }
+}
+
+/// Multiplies `units` by `unit` without going through `Decimal` multiplication,
+/// dropping trailing zeros so values print the way they were entered.
+fn scale_up(units: i64, unit: Decimal) -> Decimal {
+    let scaled = i64::try_from(unit.mantissa())
+        .ok()
+        .and_then(|m| units.checked_mul(m));
+    let Some(mut mantissa) = scaled else {
+        return (Decimal::from(units) * unit).normalize();
+    };
+
+    let mut scale = unit.scale();
+    while scale > 0 && mantissa % 10 == 0 {
+        mantissa /= 10;
+        scale -= 1;
+    }
+    Decimal::new(mantissa, scale)
+}
+
+impl Default for InstrumentSpec {
+    /// Eight decimal places for both price and quantity, matching most crypto venues.
+    fn default() -> Self {
+        Self {
+            tick_size: Decimal::new(1, 8),
+            lot_size: Decimal::new(1, 8),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_tick_round_trip_is_lossless() {
+        let spec = InstrumentSpec::new(Decimal::new(5, 1), Decimal::new(1, 3)).unwrap();
+
+        let price = Decimal::from_str("50000.5").unwrap();
+        let ticks = spec.price_to_ticks(price).unwrap();
+        assert_eq!(ticks, 100001);
+        assert_eq!(spec.ticks_to_price(ticks), price);
+
+        let quantity = Decimal::from_str("1.234").unwrap();
+        let lots = spec.quantity_to_lots(quantity).unwrap();
+        assert_eq!(lots, 1234);
+        assert_eq!(spec.lots_to_quantity(lots), quantity);
+    }
+
+    #[test]
+    fn test_off_grid_values_are_rejected() {
+        let spec = InstrumentSpec::new(Decimal::new(5, 1), Decimal::new(1, 3)).unwrap();
+
+        assert!(matches!(
+            spec.price_to_ticks(Decimal::from_str("50000.25").unwrap()),
+            Err(ScaleError::OffTick { .. })
+        ));
+        assert!(matches!(
+            spec.quantity_to_lots(Decimal::from_str("0.0001").unwrap()),
+            Err(ScaleError::OffLot { .. })
+        ));
+        assert_eq!(
+            spec.price_to_ticks_floor(Decimal::from_str("10.7").unwrap()),
+            21
+        );
+        assert_eq!(
+            spec.price_to_ticks_ceil(Decimal::from_str("10.2").unwrap()),
+            21
+        );
+    }
+}
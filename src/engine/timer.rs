@@ -0,0 +1,139 @@
+//! Hashed timer wheel for order expiry.
+//!
+//! Timers are bucketed by deadline into a fixed ring of slots, each covering
+//! one tick of time, so inserting is O(1) and advancing only visits the slots
+//! that elapsed. Deadlines further out than one revolution share a slot with
+//! nearer ones and simply stay put until their own round comes up. Expiry is
+//! decided on the exact deadline, so the tick size only affects how much
+//! work an advance does, never when a timer fires.
+
+use chrono::{DateTime, Duration, Utc};
+
+struct Timer<T> {
+    deadline: DateTime<Utc>,
+    tick: i64,
+    seq: u64,
+    item: T,
+}
+
+pub struct TimerWheel<T> {
+    tick_ms: i64,
+    slots: Vec<Vec<Timer<T>>>,
+    /// Tick the last advance stopped at; it is revisited by the next one.
+    current: i64,
+    len: usize,
+    next_seq: u64,
+}
+
+impl<T> TimerWheel<T> {
+    /// Creates a wheel of `slots` slots, each `tick` long, starting at `start`.
+    pub fn new(tick: Duration, slots: usize, start: DateTime<Utc>) -> Self {
+        let tick_ms = tick.num_milliseconds().max(1);
+        Self {
+            tick_ms,
+            slots: (0..slots.max(1)).map(|_| Vec::new()).collect(),
+            current: start.timestamp_millis().div_euclid(tick_ms),
+            len: 0,
+            next_seq: 0,
+        }
+    }
+
+    fn tick_of(&self, time: DateTime<Utc>) -> i64 {
+        time.timestamp_millis().div_euclid(self.tick_ms)
+    }
+
+    fn slot_of(&self, tick: i64) -> usize {
+        tick.rem_euclid(self.slots.len() as i64) as usize
+    }
+
+    /// Schedules `item` to fire once time reaches `deadline`.
+    pub fn insert(&mut self, deadline: DateTime<Utc>, item: T) {
+        // Deadlines already passed fire on the next advance
+        let tick = self.tick_of(deadline).max(self.current);
+        let slot = self.slot_of(tick);
+        self.slots[slot].push(Timer {
+            deadline,
+            tick,
+            seq: self.next_seq,
+            item,
+        });
+        self.next_seq += 1;
+        self.len += 1;
+    }
+
+    /// Removes and returns every item whose deadline is at or before `now`,
+    /// earliest deadline first and in insertion order on ties.
+    pub fn advance(&mut self, now: DateTime<Utc>) -> Vec<T> {
+        let target = self.tick_of(now);
+        if target < self.current {
+            return Vec::new();
+        }
+
+        let steps = (target - self.current + 1).min(self.slots.len() as i64);
+        let mut fired = Vec::new();
+        for tick in self.current..self.current + steps {
+            let slot = self.slot_of(tick);
+            let (due, waiting): (Vec<_>, Vec<_>) = std::mem::take(&mut self.slots[slot])
+                .into_iter()
+                .partition(|t| t.tick <= target && t.deadline <= now);
+            self.slots[slot] = waiting;
+            fired.extend(due);
+        }
+        self.current = target;
+        self.len -= fired.len();
+
+        fired.sort_by_key(|t| (t.deadline, t.seq));
+        fired.into_iter().map(|t| t.item).collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_timer_wheel_fires_on_exact_deadline() {
+        let start = DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+        let mut wheel = TimerWheel::new(Duration::milliseconds(100), 8, start);
+
+        wheel.insert(start + Duration::milliseconds(250), "b");
+        wheel.insert(start + Duration::milliseconds(120), "a");
+        // Several revolutions out, sharing a slot with nearer timers
+        wheel.insert(start + Duration::seconds(10), "c");
+
+        assert!(wheel
+            .advance(start + Duration::milliseconds(110))
+            .is_empty());
+        assert_eq!(
+            wheel.advance(start + Duration::milliseconds(249)),
+            vec!["a"]
+        );
+        assert_eq!(
+            wheel.advance(start + Duration::milliseconds(250)),
+            vec!["b"]
+        );
+        assert_eq!(wheel.len(), 1);
+
+        assert!(wheel.advance(start + Duration::seconds(9)).is_empty());
+        assert_eq!(wheel.advance(start + Duration::seconds(20)), vec!["c"]);
+        assert!(wheel.is_empty());
+    }
+
+    #[test]
+    fn test_timer_wheel_past_deadline_fires_next_advance() {
+        let start = DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+        let mut wheel = TimerWheel::new(Duration::milliseconds(100), 8, start);
+        wheel.advance(start + Duration::seconds(5));
+
+        wheel.insert(start, 1);
+        assert_eq!(wheel.advance(start + Duration::seconds(5)), vec![1]);
+    }
+}
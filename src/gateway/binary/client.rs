@@ -0,0 +1,99 @@
+//! Client for the binary order-entry protocol.
+
+use super::codec::{ClientMessage, EnterOrder, ReplaceOrder, ServerMessage};
+use crate::utils::types::{OrderType, Side};
+use rust_decimal::Decimal;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpStream, ToSocketAddrs};
+
+/// One order-entry connection. Tokens for new orders are handed out in
+/// increasing order starting at 1.
+pub struct BinaryClient {
+    stream: TcpStream,
+    read_buf: Vec<u8>,
+    write_buf: Vec<u8>,
+    last_token: u64,
+}
+
+impl BinaryClient {
+    pub async fn connect(addr: impl ToSocketAddrs) -> anyhow::Result<Self> {
+        let stream = TcpStream::connect(addr).await?;
+        stream.set_nodelay(true)?;
+        Ok(Self {
+            stream,
+            read_buf: Vec::with_capacity(4096),
+            write_buf: Vec::with_capacity(64),
+            last_token: 0,
+        })
+    }
+
+    /// Enters a new order and returns its token.
+    pub async fn enter_order(
+        &mut self,
+        symbol: &str,
+        side: Side,
+        order_type: OrderType,
+        quantity: Decimal,
+        price: Decimal,
+    ) -> anyhow::Result<u64> {
+        let token = self.next_token();
+        self.send(&ClientMessage::EnterOrder(EnterOrder {
+            token,
+            side,
+            order_type,
+            symbol: symbol.to_string(),
+            quantity,
+            price,
+        }))
+        .await?;
+        Ok(token)
+    }
+
+    pub async fn cancel_order(&mut self, token: u64) -> anyhow::Result<()> {
+        self.send(&ClientMessage::CancelOrder { token }).await
+    }
+
+    /// Replaces the order `token` and returns the replacement's token.
+    pub async fn replace_order(
+        &mut self,
+        token: u64,
+        quantity: Decimal,
+        price: Decimal,
+    ) -> anyhow::Result<u64> {
+        let new_token = self.next_token();
+        self.send(&ClientMessage::ReplaceOrder(ReplaceOrder {
+            token,
+            new_token,
+            quantity,
+            price,
+        }))
+        .await?;
+        Ok(new_token)
+    }
+
+    pub async fn send(&mut self, message: &ClientMessage) -> anyhow::Result<()> {
+        self.write_buf.clear();
+        message.encode(&mut self.write_buf)?;
+        self.stream.write_all(&self.write_buf).await?;
+        Ok(())
+    }
+
+    /// Waits for the next message from the server, or `None` once it closes
+    /// the connection.
+    pub async fn recv(&mut self) -> anyhow::Result<Option<ServerMessage>> {
+        loop {
+            if let Some((message, used)) = ServerMessage::decode(&self.read_buf)? {
+                self.read_buf.drain(..used);
+                return Ok(Some(message));
+            }
+            if self.stream.read_buf(&mut self.read_buf).await? == 0 {
+                return Ok(None);
+            }
+        }
+    }
+
+    fn next_token(&mut self) -> u64 {
+        self.last_token += 1;
+        self.last_token
+    }
+}
@@ -0,0 +1,573 @@
+//! Wire format of the binary order-entry protocol.
+//!
+//! Every message is a one-byte type followed by a fixed layout, so its length
+//! is known from the type alone. Integers are big-endian. Prices and
+//! quantities are unsigned fixed point with [`SCALE`] implied decimals,
+//! symbols are ASCII padded with spaces to [`SYMBOL_LEN`] bytes, timestamps
+//! are nanoseconds since the Unix epoch and order ids are raw UUID bytes.
+//!
+//! | Message           | Type | Layout                                                         |
+//! |-------------------|------|----------------------------------------------------------------|
+//! | Enter order       | `O`  | token u64, side, order type, symbol, quantity u64, price u64   |
+//! | Cancel order      | `X`  | token u64                                                      |
+//! | Replace order     | `U`  | token u64, new token u64, quantity u64, price u64              |
+//! | Accepted          | `A`  | timestamp u64, token u64, side, symbol, quantity, price, order id |
+//! | Executed          | `E`  | timestamp u64, token u64, quantity u64, price u64, match id    |
+//! | Cancelled         | `C`  | timestamp u64, token u64, quantity u64, reason                 |
+//! | Rejected          | `J`  | timestamp u64, token u64, reason                               |
+
+use crate::utils::types::{OrderType, Side};
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use thiserror::Error;
+use uuid::Uuid;
+
+/// Implied decimal places of prices and quantities.
+pub const SCALE: u32 = 8;
+/// Width of the symbol field.
+pub const SYMBOL_LEN: usize = 8;
+
+pub mod msg_type {
+    pub const ENTER_ORDER: u8 = b'O';
+    pub const CANCEL_ORDER: u8 = b'X';
+    pub const REPLACE_ORDER: u8 = b'U';
+    pub const ACCEPTED: u8 = b'A';
+    pub const EXECUTED: u8 = b'E';
+    pub const CANCELLED: u8 = b'C';
+    pub const REJECTED: u8 = b'J';
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum CodecError {
+    #[error("unknown message type {0:#04x}")]
+    UnknownType(u8),
+    #[error("invalid {field}: {value}")]
+    InvalidField { field: &'static str, value: String },
+}
+
+pub(crate) fn invalid(field: &'static str, value: impl ToString) -> CodecError {
+    CodecError::InvalidField {
+        field,
+        value: value.to_string(),
+    }
+}
+
+/// Why an order, or the rest of it, left the book.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CancelReason {
+    /// Cancelled on request.
+    User,
+    /// Cancelled by a replace; the replacement is accepted under its new token.
+    Replaced,
+    /// The unfilled rest of a market order, or an order the engine cancelled.
+    Immediate,
+    /// Reached its expiry time.
+    Expired,
+}
+
+impl CancelReason {
+    fn code(self) -> u8 {
+        match self {
+            CancelReason::User => b'U',
+            CancelReason::Replaced => b'R',
+            CancelReason::Immediate => b'I',
+            CancelReason::Expired => b'E',
+        }
+    }
+
+    fn from_code(code: u8) -> Result<Self, CodecError> {
+        match code {
+            b'U' => Ok(CancelReason::User),
+            b'R' => Ok(CancelReason::Replaced),
+            b'I' => Ok(CancelReason::Immediate),
+            b'E' => Ok(CancelReason::Expired),
+            other => Err(invalid("cancel reason", other as char)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RejectReason {
+    /// No open order has the token.
+    UnknownToken,
+    /// The token is not above every token used before on the connection.
+    DuplicateToken,
+    /// The order to cancel or replace has already left the book.
+    TooLate,
+    /// The matching engine refused the order.
+    Refused,
+}
+
+impl RejectReason {
+    fn code(self) -> u8 {
+        match self {
+            RejectReason::UnknownToken => b'T',
+            RejectReason::DuplicateToken => b'D',
+            RejectReason::TooLate => b'L',
+            RejectReason::Refused => b'R',
+        }
+    }
+
+    fn from_code(code: u8) -> Result<Self, CodecError> {
+        match code {
+            b'T' => Ok(RejectReason::UnknownToken),
+            b'D' => Ok(RejectReason::DuplicateToken),
+            b'L' => Ok(RejectReason::TooLate),
+            b'R' => Ok(RejectReason::Refused),
+            other => Err(invalid("reject reason", other as char)),
+        }
+    }
+}
+
+/// A new order. Tokens are chosen by the client and must increase on each
+/// connection; all later messages about the order carry it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EnterOrder {
+    pub token: u64,
+    pub side: Side,
+    /// `Limit` or `Market`; the price of a market order is ignored.
+    pub order_type: OrderType,
+    pub symbol: String,
+    pub quantity: Decimal,
+    pub price: Decimal,
+}
+
+/// Cancels an open order and re-enters it as `new_token` with a new quantity
+/// and price. The replacement loses time priority.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReplaceOrder {
+    pub token: u64,
+    pub new_token: u64,
+    pub quantity: Decimal,
+    pub price: Decimal,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ClientMessage {
+    EnterOrder(EnterOrder),
+    CancelOrder { token: u64 },
+    ReplaceOrder(ReplaceOrder),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Accepted {
+    pub timestamp: DateTime<Utc>,
+    pub token: u64,
+    pub side: Side,
+    pub symbol: String,
+    pub quantity: Decimal,
+    pub price: Decimal,
+    pub order_id: Uuid,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Executed {
+    pub timestamp: DateTime<Utc>,
+    pub token: u64,
+    pub quantity: Decimal,
+    pub price: Decimal,
+    pub match_id: Uuid,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cancelled {
+    pub timestamp: DateTime<Utc>,
+    pub token: u64,
+    /// Quantity removed from the book.
+    pub quantity: Decimal,
+    pub reason: CancelReason,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rejected {
+    pub timestamp: DateTime<Utc>,
+    pub token: u64,
+    pub reason: RejectReason,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ServerMessage {
+    Accepted(Accepted),
+    Executed(Executed),
+    Cancelled(Cancelled),
+    Rejected(Rejected),
+}
+
+/// Length of a message of type `kind`, including the type byte.
+pub fn message_len(kind: u8) -> Result<usize, CodecError> {
+    match kind {
+        msg_type::ENTER_ORDER => Ok(1 + 8 + 1 + 1 + SYMBOL_LEN + 8 + 8),
+        msg_type::CANCEL_ORDER => Ok(1 + 8),
+        msg_type::REPLACE_ORDER => Ok(1 + 8 + 8 + 8 + 8),
+        msg_type::ACCEPTED => Ok(1 + 8 + 8 + 1 + SYMBOL_LEN + 8 + 8 + 16),
+        msg_type::EXECUTED => Ok(1 + 8 + 8 + 8 + 8 + 16),
+        msg_type::CANCELLED => Ok(1 + 8 + 8 + 8 + 1),
+        msg_type::REJECTED => Ok(1 + 8 + 8 + 1),
+        other => Err(CodecError::UnknownType(other)),
+    }
+}
+
+impl ClientMessage {
+    /// Appends the message to `buf`, leaving it unchanged on error.
+    pub fn encode(&self, buf: &mut Vec<u8>) -> Result<(), CodecError> {
+        let start = buf.len();
+        let result = self.write(buf);
+        if result.is_err() {
+            buf.truncate(start);
+        }
+        result
+    }
+
+    fn write(&self, buf: &mut Vec<u8>) -> Result<(), CodecError> {
+        match self {
+            ClientMessage::EnterOrder(order) => {
+                buf.push(msg_type::ENTER_ORDER);
+                buf.extend_from_slice(&order.token.to_be_bytes());
+                buf.push(side_code(order.side));
+                buf.push(match order.order_type {
+                    OrderType::Limit => b'L',
+                    OrderType::Market => b'M',
+                    other => return Err(invalid("order type", format!("{:?}", other))),
+                });
+                put_symbol(buf, &order.symbol)?;
+                put_decimal(buf, "quantity", order.quantity)?;
+                put_decimal(buf, "price", order.price)?;
+            }
+            ClientMessage::CancelOrder { token } => {
+                buf.push(msg_type::CANCEL_ORDER);
+                buf.extend_from_slice(&token.to_be_bytes());
+            }
+            ClientMessage::ReplaceOrder(replace) => {
+                buf.push(msg_type::REPLACE_ORDER);
+                buf.extend_from_slice(&replace.token.to_be_bytes());
+                buf.extend_from_slice(&replace.new_token.to_be_bytes());
+                put_decimal(buf, "quantity", replace.quantity)?;
+                put_decimal(buf, "price", replace.price)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Decodes the first message in `buf`, returning it with the number of
+    /// bytes it used, or `None` until the whole message has arrived.
+    pub fn decode(buf: &[u8]) -> Result<Option<(Self, usize)>, CodecError> {
+        let Some((kind, mut reader)) = frame(buf)? else {
+            return Ok(None);
+        };
+        let message = match kind {
+            msg_type::ENTER_ORDER => ClientMessage::EnterOrder(EnterOrder {
+                token: reader.u64(),
+                side: reader.side()?,
+                order_type: match reader.u8() {
+                    b'L' => OrderType::Limit,
+                    b'M' => OrderType::Market,
+                    other => return Err(invalid("order type", other as char)),
+                },
+                symbol: reader.symbol()?,
+                quantity: reader.decimal(),
+                price: reader.decimal(),
+            }),
+            msg_type::CANCEL_ORDER => ClientMessage::CancelOrder {
+                token: reader.u64(),
+            },
+            msg_type::REPLACE_ORDER => ClientMessage::ReplaceOrder(ReplaceOrder {
+                token: reader.u64(),
+                new_token: reader.u64(),
+                quantity: reader.decimal(),
+                price: reader.decimal(),
+            }),
+            other => return Err(CodecError::UnknownType(other)),
+        };
+        Ok(Some((message, reader.pos)))
+    }
+}
+
+impl ServerMessage {
+    /// Appends the message to `buf`, leaving it unchanged on error.
+    pub fn encode(&self, buf: &mut Vec<u8>) -> Result<(), CodecError> {
+        let start = buf.len();
+        let result = self.write(buf);
+        if result.is_err() {
+            buf.truncate(start);
+        }
+        result
+    }
+
+    fn write(&self, buf: &mut Vec<u8>) -> Result<(), CodecError> {
+        match self {
+            ServerMessage::Accepted(accepted) => {
+                buf.push(msg_type::ACCEPTED);
+                put_timestamp(buf, accepted.timestamp);
+                buf.extend_from_slice(&accepted.token.to_be_bytes());
+                buf.push(side_code(accepted.side));
+                put_symbol(buf, &accepted.symbol)?;
+                put_decimal(buf, "quantity", accepted.quantity)?;
+                put_decimal(buf, "price", accepted.price)?;
+                buf.extend_from_slice(accepted.order_id.as_bytes());
+            }
+            ServerMessage::Executed(executed) => {
+                buf.push(msg_type::EXECUTED);
+                put_timestamp(buf, executed.timestamp);
+                buf.extend_from_slice(&executed.token.to_be_bytes());
+                put_decimal(buf, "quantity", executed.quantity)?;
+                put_decimal(buf, "price", executed.price)?;
+                buf.extend_from_slice(executed.match_id.as_bytes());
+            }
+            ServerMessage::Cancelled(cancelled) => {
+                buf.push(msg_type::CANCELLED);
+                put_timestamp(buf, cancelled.timestamp);
+                buf.extend_from_slice(&cancelled.token.to_be_bytes());
+                put_decimal(buf, "quantity", cancelled.quantity)?;
+                buf.push(cancelled.reason.code());
+            }
+            ServerMessage::Rejected(rejected) => {
+                buf.push(msg_type::REJECTED);
+                put_timestamp(buf, rejected.timestamp);
+                buf.extend_from_slice(&rejected.token.to_be_bytes());
+                buf.push(rejected.reason.code());
+            }
+        }
+        Ok(())
+    }
+
+    /// Decodes the first message in `buf`, returning it with the number of
+    /// bytes it used, or `None` until the whole message has arrived.
+    pub fn decode(buf: &[u8]) -> Result<Option<(Self, usize)>, CodecError> {
+        let Some((kind, mut reader)) = frame(buf)? else {
+            return Ok(None);
+        };
+        let message = match kind {
+            msg_type::ACCEPTED => ServerMessage::Accepted(Accepted {
+                timestamp: reader.timestamp(),
+                token: reader.u64(),
+                side: reader.side()?,
+                symbol: reader.symbol()?,
+                quantity: reader.decimal(),
+                price: reader.decimal(),
+                order_id: reader.uuid(),
+            }),
+            msg_type::EXECUTED => ServerMessage::Executed(Executed {
+                timestamp: reader.timestamp(),
+                token: reader.u64(),
+                quantity: reader.decimal(),
+                price: reader.decimal(),
+                match_id: reader.uuid(),
+            }),
+            msg_type::CANCELLED => ServerMessage::Cancelled(Cancelled {
+                timestamp: reader.timestamp(),
+                token: reader.u64(),
+                quantity: reader.decimal(),
+                reason: CancelReason::from_code(reader.u8())?,
+            }),
+            msg_type::REJECTED => ServerMessage::Rejected(Rejected {
+                timestamp: reader.timestamp(),
+                token: reader.u64(),
+                reason: RejectReason::from_code(reader.u8())?,
+            }),
+            other => return Err(CodecError::UnknownType(other)),
+        };
+        Ok(Some((message, reader.pos)))
+    }
+
+    /// The client token the message is about.
+    pub fn token(&self) -> u64 {
+        match self {
+            ServerMessage::Accepted(m) => m.token,
+            ServerMessage::Executed(m) => m.token,
+            ServerMessage::Cancelled(m) => m.token,
+            ServerMessage::Rejected(m) => m.token,
+        }
+    }
+}
+
+/// Returns the type and a reader past it once a whole message is buffered.
+fn frame(buf: &[u8]) -> Result<Option<(u8, Reader<'_>)>, CodecError> {
+    let Some(&kind) = buf.first() else {
+        return Ok(None);
+    };
+    if buf.len() < message_len(kind)? {
+        return Ok(None);
+    }
+    Ok(Some((kind, Reader::new(buf, 1))))
+}
+
+/// Reads fields of a message whose full length has been checked.
+pub(crate) struct Reader<'a> {
+    buf: &'a [u8],
+    pub(crate) pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub(crate) fn new(buf: &'a [u8], pos: usize) -> Self {
+        Self { buf, pos }
+    }
+
+    pub(crate) fn bytes<const N: usize>(&mut self) -> [u8; N] {
+        let bytes = self.buf[self.pos..self.pos + N].try_into().unwrap();
+        self.pos += N;
+        bytes
+    }
+
+    pub(crate) fn u8(&mut self) -> u8 {
+        self.bytes::<1>()[0]
+    }
+
+    pub(crate) fn u16(&mut self) -> u16 {
+        u16::from_be_bytes(self.bytes())
+    }
+
+    pub(crate) fn u64(&mut self) -> u64 {
+        u64::from_be_bytes(self.bytes())
+    }
+
+    pub(crate) fn decimal(&mut self) -> Decimal {
+        Decimal::from_i128_with_scale(self.u64() as i128, SCALE).normalize()
+    }
+
+    pub(crate) fn timestamp(&mut self) -> DateTime<Utc> {
+        DateTime::from_timestamp_nanos(self.u64() as i64)
+    }
+
+    pub(crate) fn uuid(&mut self) -> Uuid {
+        Uuid::from_bytes(self.bytes())
+    }
+
+    pub(crate) fn side(&mut self) -> Result<Side, CodecError> {
+        match self.u8() {
+            b'B' => Ok(Side::Buy),
+            b'S' => Ok(Side::Sell),
+            other => Err(invalid("side", other as char)),
+        }
+    }
+
+    pub(crate) fn symbol(&mut self) -> Result<String, CodecError> {
+        let bytes: [u8; SYMBOL_LEN] = self.bytes();
+        let symbol = std::str::from_utf8(&bytes)
+            .ok()
+            .filter(|s| s.is_ascii())
+            .ok_or_else(|| invalid("symbol", String::from_utf8_lossy(&bytes)))?;
+        Ok(symbol.trim_end_matches(' ').to_string())
+    }
+}
+
+pub(crate) fn side_code(side: Side) -> u8 {
+    match side {
+        Side::Buy => b'B',
+        Side::Sell => b'S',
+    }
+}
+
+pub(crate) fn put_symbol(buf: &mut Vec<u8>, symbol: &str) -> Result<(), CodecError> {
+    if !symbol.is_ascii() || symbol.is_empty() || symbol.len() > SYMBOL_LEN {
+        return Err(invalid("symbol", symbol));
+    }
+    buf.extend_from_slice(symbol.as_bytes());
+    buf.resize(buf.len() + SYMBOL_LEN - symbol.len(), b' ');
+    Ok(())
+}
+
+pub(crate) fn put_decimal(
+    buf: &mut Vec<u8>,
+    field: &'static str,
+    value: Decimal,
+) -> Result<(), CodecError> {
+    let scaled = value * Decimal::from(10u64.pow(SCALE));
+    if scaled.fract() != Decimal::ZERO {
+        return Err(invalid(field, value));
+    }
+    let scaled = u64::try_from(scaled).map_err(|_| invalid(field, value))?;
+    buf.extend_from_slice(&scaled.to_be_bytes());
+    Ok(())
+}
+
+pub(crate) fn put_timestamp(buf: &mut Vec<u8>, timestamp: DateTime<Utc>) {
+    let nanos = timestamp.timestamp_nanos_opt().unwrap_or_default().max(0) as u64;
+    buf.extend_from_slice(&nanos.to_be_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dec(value: &str) -> Decimal {
+        value.parse().unwrap()
+    }
+
+    #[test]
+    fn test_round_trip_and_partial_frames() {
+        let messages = [
+            ClientMessage::EnterOrder(EnterOrder {
+                token: 7,
+                side: Side::Sell,
+                order_type: OrderType::Limit,
+                symbol: "BTCUSD".to_string(),
+                quantity: dec("0.125"),
+                price: dec("50000.5"),
+            }),
+            ClientMessage::CancelOrder { token: 7 },
+            ClientMessage::ReplaceOrder(ReplaceOrder {
+                token: 7,
+                new_token: 8,
+                quantity: dec("2"),
+                price: dec("49999"),
+            }),
+        ];
+        let mut buf = Vec::new();
+        for message in &messages {
+            message.encode(&mut buf).unwrap();
+        }
+        assert_eq!(buf.len(), 35 + 9 + 33);
+
+        // Nothing decodes until a message is complete
+        assert_eq!(ClientMessage::decode(&buf[..34]).unwrap(), None);
+        let mut decoded = Vec::new();
+        let mut rest = &buf[..];
+        while let Some((message, used)) = ClientMessage::decode(rest).unwrap() {
+            decoded.push(message);
+            rest = &rest[used..];
+        }
+        assert_eq!(decoded, messages);
+
+        let executed = ServerMessage::Executed(Executed {
+            timestamp: DateTime::from_timestamp_nanos(1_700_000_000_123_456_789),
+            token: 8,
+            quantity: dec("1.5"),
+            price: dec("49999"),
+            match_id: Uuid::new_v4(),
+        });
+        let mut buf = Vec::new();
+        executed.encode(&mut buf).unwrap();
+        assert_eq!(
+            ServerMessage::decode(&buf).unwrap(),
+            Some((executed, message_len(msg_type::EXECUTED).unwrap()))
+        );
+    }
+
+    #[test]
+    fn test_invalid_values() {
+        let order = EnterOrder {
+            token: 1,
+            side: Side::Buy,
+            order_type: OrderType::Limit,
+            symbol: "BTCUSD".to_string(),
+            quantity: dec("1"),
+            price: dec("0.000000001"),
+        };
+        let mut buf = Vec::new();
+        assert!(ClientMessage::EnterOrder(order.clone())
+            .encode(&mut buf)
+            .is_err());
+        assert!(buf.is_empty());
+        let long = EnterOrder {
+            symbol: "BTCUSDPERP".to_string(),
+            price: dec("1"),
+            ..order
+        };
+        assert!(ClientMessage::EnterOrder(long).encode(&mut buf).is_err());
+
+        assert_eq!(
+            ClientMessage::decode(b"Z"),
+            Err(CodecError::UnknownType(b'Z'))
+        );
+    }
+}
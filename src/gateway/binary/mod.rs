@@ -0,0 +1,119 @@
+//! Binary order-entry protocol for latency-sensitive clients.
+//!
+//! A compact, fixed-layout alternative to FIX in the style of OUCH: clients
+//! enter, cancel and replace orders identified by their own increasing
+//! tokens and receive accepted, executed, cancelled and rejected messages
+//! for them. There is no logon or sequencing; a connection is a session,
+//! and its open orders are cancelled when it closes. See [`codec`] for the
+//! wire format and [`client`] for a Rust client.
+
+pub mod client;
+pub mod codec;
+mod session;
+
+use crate::engine::bus::{BackpressurePolicy, RecvError};
+use crate::engine::matching::MatchingEngine;
+use codec::ClientMessage;
+use dashmap::DashSet;
+use session::Session;
+use std::sync::Arc;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tracing::{error, info, warn};
+
+/// Execution reports buffered per connection between the engine and the socket.
+const REPORT_CAPACITY: usize = 4096;
+
+pub struct BinaryServer {
+    engine: Arc<MatchingEngine>,
+}
+
+impl BinaryServer {
+    pub fn new(engine: Arc<MatchingEngine>) -> Self {
+        Self { engine }
+    }
+
+    /// Binds `addr` and serves connections until the listener fails.
+    pub async fn run(self: Arc<Self>, addr: &str) -> anyhow::Result<()> {
+        let listener = TcpListener::bind(addr).await?;
+        info!("Binary order entry listening on {}", addr);
+        self.serve(listener).await
+    }
+
+    /// Serves connections on an already bound listener.
+    pub async fn serve(self: Arc<Self>, listener: TcpListener) -> anyhow::Result<()> {
+        loop {
+            let (stream, peer) = listener.accept().await?;
+            let server = self.clone();
+            tokio::spawn(async move {
+                if let Err(e) = server.handle(stream).await {
+                    warn!("Binary connection from {} closed: {}", peer, e);
+                }
+            });
+        }
+    }
+
+    async fn handle(&self, mut stream: TcpStream) -> anyhow::Result<()> {
+        stream.set_nodelay(true)?;
+
+        // Same hand-off as the FIX acceptor: the engine never waits on a socket
+        let owned = Arc::new(DashSet::new());
+        let mut executions = self
+            .engine
+            .subscribe_executions(REPORT_CAPACITY, BackpressurePolicy::Block);
+        let (report_tx, mut report_rx) = mpsc::unbounded_channel();
+        let forward_owned = owned.clone();
+        let forwarder = tokio::spawn(async move {
+            loop {
+                match executions.recv().await {
+                    Ok(report) if forward_owned.contains(&report.order_id) => {
+                        if report_tx.send(report).is_err() {
+                            break;
+                        }
+                    }
+                    Ok(_) => {}
+                    Err(RecvError::Lagged { dropped }) => {
+                        error!("Binary session lost {} execution reports", dropped);
+                        executions.resync();
+                    }
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        });
+
+        let mut session = Session::new(self.engine.clone(), owned);
+        let mut buf = Vec::new();
+        let result = async {
+            loop {
+                tokio::select! {
+                    read = stream.read_buf(&mut buf) => {
+                        if read? == 0 {
+                            break;
+                        }
+                        // A malformed message leaves no way to find the next one
+                        let mut used = 0;
+                        while let Some((message, len)) = ClientMessage::decode(&buf[used..])? {
+                            used += len;
+                            session.on_message(message).await;
+                        }
+                        buf.drain(..used);
+                    }
+                    Some(report) = report_rx.recv() => {
+                        session.on_report(report);
+                        while let Ok(report) = report_rx.try_recv() {
+                            session.on_report(report);
+                        }
+                    }
+                }
+                stream.write_all(&session.take_outgoing()).await?;
+            }
+            Ok(())
+        }
+        .await;
+
+        session.cancel_all().await;
+        forwarder.abort();
+        result
+    }
+}
@@ -0,0 +1,255 @@
+//! Order state of one binary order-entry connection.
+
+use super::codec::{
+    Accepted, CancelReason, Cancelled, ClientMessage, EnterOrder, Executed, RejectReason, Rejected,
+    ReplaceOrder, ServerMessage,
+};
+use crate::engine::matching::MatchingEngine;
+use crate::utils::types::{ExecType, ExecutionReport, Order, OrderStatus, OrderType, Side};
+use chrono::Utc;
+use dashmap::DashSet;
+use rust_decimal::Decimal;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+struct OrderEntry {
+    token: u64,
+    symbol: String,
+    side: Side,
+    /// Set while a cancel or replace we asked for is on its way.
+    cancel_reason: Option<CancelReason>,
+}
+
+pub(crate) struct Session {
+    engine: Arc<MatchingEngine>,
+    /// Engine ids of our orders, shared with the report forwarder.
+    owned: Arc<DashSet<Uuid>>,
+    orders: HashMap<Uuid, OrderEntry>,
+    tokens: HashMap<u64, Uuid>,
+    last_token: Option<u64>,
+    outgoing: Vec<u8>,
+}
+
+impl Session {
+    pub(crate) fn new(engine: Arc<MatchingEngine>, owned: Arc<DashSet<Uuid>>) -> Self {
+        Self {
+            engine,
+            owned,
+            orders: HashMap::new(),
+            tokens: HashMap::new(),
+            last_token: None,
+            outgoing: Vec::new(),
+        }
+    }
+
+    /// Encoded messages waiting to be written to the socket.
+    pub(crate) fn take_outgoing(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.outgoing)
+    }
+
+    pub(crate) async fn on_message(&mut self, message: ClientMessage) {
+        match message {
+            ClientMessage::EnterOrder(order) => self.enter_order(order).await,
+            ClientMessage::CancelOrder { token } => self.cancel_order(token).await,
+            ClientMessage::ReplaceOrder(replace) => self.replace_order(replace).await,
+        }
+    }
+
+    /// Translates an engine execution report for one of our orders.
+    pub(crate) fn on_report(&mut self, report: ExecutionReport) {
+        let Some(entry) = self.orders.get(&report.order_id) else {
+            return;
+        };
+        let token = entry.token;
+        let timestamp = report.timestamp;
+        let open = report.quantity - report.filled_quantity;
+        let message = match report.exec_type {
+            ExecType::New => Some(ServerMessage::Accepted(Accepted {
+                timestamp,
+                token,
+                side: report.side,
+                symbol: report.symbol.clone(),
+                quantity: open,
+                price: report.price,
+                order_id: report.order_id,
+            })),
+            ExecType::Trade => match (report.last_price, report.last_quantity) {
+                (Some(price), Some(quantity)) => Some(ServerMessage::Executed(Executed {
+                    timestamp,
+                    token,
+                    quantity,
+                    price,
+                    match_id: report.trade_id.unwrap_or_default(),
+                })),
+                _ => None,
+            },
+            ExecType::Cancelled => Some(ServerMessage::Cancelled(Cancelled {
+                timestamp,
+                token,
+                quantity: open,
+                reason: entry.cancel_reason.unwrap_or(CancelReason::Immediate),
+            })),
+            ExecType::Expired => Some(ServerMessage::Cancelled(Cancelled {
+                timestamp,
+                token,
+                quantity: open,
+                reason: CancelReason::Expired,
+            })),
+            ExecType::Rejected => Some(ServerMessage::Rejected(Rejected {
+                timestamp,
+                token,
+                reason: RejectReason::Refused,
+            })),
+            ExecType::Triggered => None,
+        };
+        if let Some(message) = message {
+            self.send(message);
+        }
+
+        if matches!(
+            report.status,
+            OrderStatus::Filled
+                | OrderStatus::Cancelled
+                | OrderStatus::Rejected
+                | OrderStatus::Expired
+        ) {
+            self.forget(report.order_id);
+        }
+    }
+
+    /// Cancels every open order, as when the connection closes.
+    pub(crate) async fn cancel_all(&mut self) {
+        let open: Vec<(Uuid, String)> = self
+            .orders
+            .iter()
+            .map(|(id, entry)| (*id, entry.symbol.clone()))
+            .collect();
+        for (id, symbol) in open {
+            if self.engine.cancel_order(id, &symbol).await.is_ok() {
+                info!("Cancelled order {} of disconnected binary session", id);
+            }
+            self.forget(id);
+        }
+    }
+
+    async fn enter_order(&mut self, order: EnterOrder) {
+        if !self.use_token(order.token) {
+            return;
+        }
+        let price = match order.order_type {
+            OrderType::Market => Decimal::ZERO,
+            _ => order.price,
+        };
+        let engine_order = Order::new(
+            order.symbol,
+            order.side,
+            order.order_type,
+            price,
+            order.quantity,
+        );
+        self.submit(order.token, engine_order).await;
+    }
+
+    async fn cancel_order(&mut self, token: u64) {
+        let Some(&id) = self.tokens.get(&token) else {
+            self.reject(token, RejectReason::UnknownToken);
+            return;
+        };
+        if !self.cancel(id, CancelReason::User).await {
+            self.reject(token, RejectReason::TooLate);
+        }
+    }
+
+    async fn replace_order(&mut self, replace: ReplaceOrder) {
+        if !self.use_token(replace.new_token) {
+            return;
+        }
+        let Some(&old_id) = self.tokens.get(&replace.token) else {
+            self.reject(replace.new_token, RejectReason::UnknownToken);
+            return;
+        };
+        let old = &self.orders[&old_id];
+        let replacement = Order::new(
+            old.symbol.clone(),
+            old.side,
+            OrderType::Limit,
+            replace.price,
+            replace.quantity,
+        );
+        // Fails once another session's trade has taken the order, so a
+        // replacement is only entered for an order that was still resting
+        if !self.cancel(old_id, CancelReason::Replaced).await {
+            self.reject(replace.new_token, RejectReason::TooLate);
+            return;
+        }
+        self.submit(replace.new_token, replacement).await;
+    }
+
+    /// Claims `token` for a new order, rejecting it unless it is above every
+    /// token seen so far.
+    fn use_token(&mut self, token: u64) -> bool {
+        if self.last_token.is_some_and(|last| token <= last) {
+            self.reject(token, RejectReason::DuplicateToken);
+            return false;
+        }
+        self.last_token = Some(token);
+        true
+    }
+
+    async fn submit(&mut self, token: u64, order: Order) {
+        let id = order.id;
+        self.orders.insert(
+            id,
+            OrderEntry {
+                token,
+                symbol: order.symbol.clone(),
+                side: order.side,
+                cancel_reason: None,
+            },
+        );
+        self.tokens.insert(token, id);
+        self.owned.insert(id);
+        if let Err(e) = self.engine.submit_order(order).await {
+            warn!("Binary order {} refused: {}", token, e);
+            self.forget(id);
+            self.reject(token, RejectReason::Refused);
+        }
+    }
+
+    /// Asks the engine to cancel `id`; its report carries `reason`.
+    async fn cancel(&mut self, id: Uuid, reason: CancelReason) -> bool {
+        let entry = self.orders.get_mut(&id).unwrap();
+        entry.cancel_reason = Some(reason);
+        let symbol = entry.symbol.clone();
+        if self.engine.cancel_order(id, &symbol).await.is_err() {
+            if let Some(entry) = self.orders.get_mut(&id) {
+                entry.cancel_reason = None;
+            }
+            return false;
+        }
+        true
+    }
+
+    fn forget(&mut self, id: Uuid) {
+        if let Some(entry) = self.orders.remove(&id) {
+            self.tokens.remove(&entry.token);
+        }
+        self.owned.remove(&id);
+    }
+
+    fn reject(&mut self, token: u64, reason: RejectReason) {
+        self.send(ServerMessage::Rejected(Rejected {
+            timestamp: Utc::now(),
+            token,
+            reason,
+        }));
+    }
+
+    fn send(&mut self, message: ServerMessage) {
+        if let Err(e) = message.encode(&mut self.outgoing) {
+            warn!("Dropping unencodable binary message {:?}: {}", message, e);
+        }
+    }
+}
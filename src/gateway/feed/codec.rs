@@ -0,0 +1,474 @@
+//! Wire format of the market data feed and its recovery service.
+//!
+//! Feed packets follow MoldUDP64: a session id, the sequence number of the
+//! first message and a message count, followed by that many messages, each
+//! prefixed with its length. A packet without messages is a heartbeat whose
+//! sequence number is the next one to be published. Fields use the binary
+//! order-entry encoding: big-endian integers, fixed point prices and
+//! quantities with [`SCALE`] decimals and space-padded symbols.
+//!
+//! | Message      | Type | Layout                                                      |
+//! |--------------|------|-------------------------------------------------------------|
+//! | Level update | `L`  | timestamp u64, symbol, side, price u64, quantity u64        |
+//! | Trade        | `P`  | timestamp u64, symbol, price u64, quantity u64, match id    |
+//!
+//! A level update carries the new total quantity at a price; zero removes
+//! the level. Recovery requests and responses travel over TCP as frames
+//! prefixed with a u16 length.
+
+use crate::gateway::binary::codec::{
+    invalid, put_decimal, put_symbol, put_timestamp, side_code, CodecError, Reader, SYMBOL_LEN,
+};
+use crate::utils::types::Side;
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use uuid::Uuid;
+
+pub use crate::gateway::binary::codec::SCALE;
+
+/// Width of the session id.
+pub const SESSION_LEN: usize = 10;
+/// Session id, sequence number and message count.
+pub const HEADER_LEN: usize = SESSION_LEN + 8 + 2;
+/// Largest packet the publisher sends, to stay within a typical MTU.
+pub const MAX_PACKET_LEN: usize = 1400;
+
+pub mod msg_type {
+    pub const LEVEL_UPDATE: u8 = b'L';
+    pub const TRADE: u8 = b'P';
+    pub const RETRANSMIT: u8 = b'R';
+    pub const SNAPSHOT: u8 = b'S';
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LevelUpdate {
+    pub timestamp: DateTime<Utc>,
+    pub symbol: String,
+    pub side: Side,
+    pub price: Decimal,
+    pub quantity: Decimal,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TradeMessage {
+    pub timestamp: DateTime<Utc>,
+    pub symbol: String,
+    pub price: Decimal,
+    pub quantity: Decimal,
+    pub match_id: Uuid,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FeedMessage {
+    LevelUpdate(LevelUpdate),
+    Trade(TradeMessage),
+}
+
+impl FeedMessage {
+    pub fn symbol(&self) -> &str {
+        match self {
+            FeedMessage::LevelUpdate(update) => &update.symbol,
+            FeedMessage::Trade(trade) => &trade.symbol,
+        }
+    }
+
+    /// Encoded length, including the type byte.
+    pub fn encoded_len(&self) -> usize {
+        match self {
+            FeedMessage::LevelUpdate(_) => message_len(msg_type::LEVEL_UPDATE),
+            FeedMessage::Trade(_) => message_len(msg_type::TRADE),
+        }
+        .unwrap()
+    }
+
+    fn write(&self, buf: &mut Vec<u8>) -> Result<(), CodecError> {
+        match self {
+            FeedMessage::LevelUpdate(update) => {
+                buf.push(msg_type::LEVEL_UPDATE);
+                put_timestamp(buf, update.timestamp);
+                put_symbol(buf, &update.symbol)?;
+                buf.push(side_code(update.side));
+                put_decimal(buf, "price", update.price)?;
+                put_decimal(buf, "quantity", update.quantity)?;
+            }
+            FeedMessage::Trade(trade) => {
+                buf.push(msg_type::TRADE);
+                put_timestamp(buf, trade.timestamp);
+                put_symbol(buf, &trade.symbol)?;
+                put_decimal(buf, "price", trade.price)?;
+                put_decimal(buf, "quantity", trade.quantity)?;
+                buf.extend_from_slice(trade.match_id.as_bytes());
+            }
+        }
+        Ok(())
+    }
+
+    fn read(body: &[u8]) -> Result<Self, CodecError> {
+        let Some(&kind) = body.first() else {
+            return Err(invalid("message", "empty"));
+        };
+        if body.len() != message_len(kind)? {
+            return Err(invalid("message length", body.len()));
+        }
+        let mut reader = Reader::new(body, 1);
+        Ok(match kind {
+            msg_type::LEVEL_UPDATE => FeedMessage::LevelUpdate(LevelUpdate {
+                timestamp: reader.timestamp(),
+                symbol: reader.symbol()?,
+                side: reader.side()?,
+                price: reader.decimal(),
+                quantity: reader.decimal(),
+            }),
+            _ => FeedMessage::Trade(TradeMessage {
+                timestamp: reader.timestamp(),
+                symbol: reader.symbol()?,
+                price: reader.decimal(),
+                quantity: reader.decimal(),
+                match_id: reader.uuid(),
+            }),
+        })
+    }
+}
+
+/// Length of a feed message of type `kind`, including the type byte.
+pub fn message_len(kind: u8) -> Result<usize, CodecError> {
+    match kind {
+        msg_type::LEVEL_UPDATE => Ok(1 + 8 + SYMBOL_LEN + 1 + 8 + 8),
+        msg_type::TRADE => Ok(1 + 8 + SYMBOL_LEN + 8 + 8 + 16),
+        other => Err(CodecError::UnknownType(other)),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Packet {
+    pub session: String,
+    /// Sequence number of the first message, or of the next message to be
+    /// published for a heartbeat.
+    pub sequence: u64,
+    pub messages: Vec<FeedMessage>,
+}
+
+impl Packet {
+    /// Sequence number following the last message of the packet.
+    pub fn next_sequence(&self) -> u64 {
+        self.sequence + self.messages.len() as u64
+    }
+
+    pub fn encoded_len(&self) -> usize {
+        HEADER_LEN
+            + self
+                .messages
+                .iter()
+                .map(|m| 2 + m.encoded_len())
+                .sum::<usize>()
+    }
+
+    /// Appends the packet to `buf`, leaving it unchanged on error.
+    pub fn encode(&self, buf: &mut Vec<u8>) -> Result<(), CodecError> {
+        let start = buf.len();
+        let result = self.write(buf);
+        if result.is_err() {
+            buf.truncate(start);
+        }
+        result
+    }
+
+    fn write(&self, buf: &mut Vec<u8>) -> Result<(), CodecError> {
+        if !self.session.is_ascii() || self.session.len() > SESSION_LEN {
+            return Err(invalid("session", &self.session));
+        }
+        buf.extend_from_slice(self.session.as_bytes());
+        buf.resize(buf.len() + SESSION_LEN - self.session.len(), b' ');
+        buf.extend_from_slice(&self.sequence.to_be_bytes());
+        let count = u16::try_from(self.messages.len())
+            .map_err(|_| invalid("message count", self.messages.len()))?;
+        buf.extend_from_slice(&count.to_be_bytes());
+        for message in &self.messages {
+            buf.extend_from_slice(&(message.encoded_len() as u16).to_be_bytes());
+            message.write(buf)?;
+        }
+        Ok(())
+    }
+
+    /// Decodes a whole packet, as received in one datagram.
+    pub fn decode(buf: &[u8]) -> Result<Self, CodecError> {
+        if buf.len() < HEADER_LEN {
+            return Err(invalid("packet length", buf.len()));
+        }
+        let session = std::str::from_utf8(&buf[..SESSION_LEN])
+            .map_err(|_| invalid("session", String::from_utf8_lossy(&buf[..SESSION_LEN])))?
+            .trim_end_matches(' ')
+            .to_string();
+        let mut reader = Reader::new(buf, SESSION_LEN);
+        let sequence = reader.u64();
+        let count = reader.u16();
+        let mut pos = reader.pos;
+        let mut messages = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            let Some(len) = buf.get(pos..pos + 2) else {
+                return Err(invalid("packet length", buf.len()));
+            };
+            let len = u16::from_be_bytes([len[0], len[1]]) as usize;
+            let Some(body) = buf.get(pos + 2..pos + 2 + len) else {
+                return Err(invalid("packet length", buf.len()));
+            };
+            messages.push(FeedMessage::read(body)?);
+            pos += 2 + len;
+        }
+        Ok(Self {
+            session,
+            sequence,
+            messages,
+        })
+    }
+}
+
+/// Total quantity at one price.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Level {
+    pub price: Decimal,
+    pub quantity: Decimal,
+}
+
+/// A book as published up to, not including, `sequence`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BookSnapshot {
+    pub sequence: u64,
+    pub symbol: String,
+    pub bids: Vec<Level>,
+    pub asks: Vec<Level>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RecoveryRequest {
+    /// Resend up to `count` messages starting at `sequence`.
+    Retransmit {
+        sequence: u64,
+        count: u16,
+    },
+    Snapshot {
+        symbol: String,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RecoveryResponse {
+    /// The retained messages from the requested range. The packet starts
+    /// later than requested when the earlier messages are no longer kept.
+    Retransmit(Packet),
+    Snapshot(BookSnapshot),
+}
+
+impl RecoveryRequest {
+    pub fn encode(&self, buf: &mut Vec<u8>) -> Result<(), CodecError> {
+        frame(buf, |buf| {
+            match self {
+                RecoveryRequest::Retransmit { sequence, count } => {
+                    buf.push(msg_type::RETRANSMIT);
+                    buf.extend_from_slice(&sequence.to_be_bytes());
+                    buf.extend_from_slice(&count.to_be_bytes());
+                }
+                RecoveryRequest::Snapshot { symbol } => {
+                    buf.push(msg_type::SNAPSHOT);
+                    put_symbol(buf, symbol)?;
+                }
+            }
+            Ok(())
+        })
+    }
+
+    /// Decodes the first frame in `buf`, returning it with the number of
+    /// bytes it used, or `None` until the whole frame has arrived.
+    pub fn decode(buf: &[u8]) -> Result<Option<(Self, usize)>, CodecError> {
+        let Some((body, used)) = unframe(buf) else {
+            return Ok(None);
+        };
+        let request = match (body.first(), body.len()) {
+            (Some(&msg_type::RETRANSMIT), 11) => {
+                let mut reader = Reader::new(body, 1);
+                RecoveryRequest::Retransmit {
+                    sequence: reader.u64(),
+                    count: reader.u16(),
+                }
+            }
+            (Some(&msg_type::SNAPSHOT), len) if len == 1 + SYMBOL_LEN => {
+                RecoveryRequest::Snapshot {
+                    symbol: Reader::new(body, 1).symbol()?,
+                }
+            }
+            (Some(&kind), _) => return Err(CodecError::UnknownType(kind)),
+            (None, _) => return Err(invalid("frame", "empty")),
+        };
+        Ok(Some((request, used)))
+    }
+}
+
+impl RecoveryResponse {
+    pub fn encode(&self, buf: &mut Vec<u8>) -> Result<(), CodecError> {
+        frame(buf, |buf| {
+            match self {
+                RecoveryResponse::Retransmit(packet) => {
+                    buf.push(msg_type::RETRANSMIT);
+                    packet.write(buf)?;
+                }
+                RecoveryResponse::Snapshot(snapshot) => {
+                    buf.push(msg_type::SNAPSHOT);
+                    buf.extend_from_slice(&snapshot.sequence.to_be_bytes());
+                    put_symbol(buf, &snapshot.symbol)?;
+                    for levels in [&snapshot.bids, &snapshot.asks] {
+                        let count = u16::try_from(levels.len())
+                            .map_err(|_| invalid("level count", levels.len()))?;
+                        buf.extend_from_slice(&count.to_be_bytes());
+                    }
+                    for level in snapshot.bids.iter().chain(&snapshot.asks) {
+                        put_decimal(buf, "price", level.price)?;
+                        put_decimal(buf, "quantity", level.quantity)?;
+                    }
+                }
+            }
+            Ok(())
+        })
+    }
+
+    /// Decodes the first frame in `buf`, returning it with the number of
+    /// bytes it used, or `None` until the whole frame has arrived.
+    pub fn decode(buf: &[u8]) -> Result<Option<(Self, usize)>, CodecError> {
+        let Some((body, used)) = unframe(buf) else {
+            return Ok(None);
+        };
+        let response = match body.first() {
+            Some(&msg_type::RETRANSMIT) => {
+                RecoveryResponse::Retransmit(Packet::decode(&body[1..])?)
+            }
+            Some(&msg_type::SNAPSHOT) => {
+                let header = 1 + 8 + SYMBOL_LEN + 2 + 2;
+                if body.len() < header {
+                    return Err(invalid("snapshot length", body.len()));
+                }
+                let mut reader = Reader::new(body, 1);
+                let sequence = reader.u64();
+                let symbol = reader.symbol()?;
+                let bid_count = reader.u16() as usize;
+                let ask_count = reader.u16() as usize;
+                if body.len() != header + (bid_count + ask_count) * 16 {
+                    return Err(invalid("snapshot length", body.len()));
+                }
+                let mut levels: Vec<Level> = (0..bid_count + ask_count)
+                    .map(|_| Level {
+                        price: reader.decimal(),
+                        quantity: reader.decimal(),
+                    })
+                    .collect();
+                let asks = levels.split_off(bid_count);
+                RecoveryResponse::Snapshot(BookSnapshot {
+                    sequence,
+                    symbol,
+                    bids: levels,
+                    asks,
+                })
+            }
+            Some(&kind) => return Err(CodecError::UnknownType(kind)),
+            None => return Err(invalid("frame", "empty")),
+        };
+        Ok(Some((response, used)))
+    }
+}
+
+/// Writes a u16 length followed by what `write` appends, leaving `buf`
+/// unchanged on error.
+fn frame(
+    buf: &mut Vec<u8>,
+    write: impl FnOnce(&mut Vec<u8>) -> Result<(), CodecError>,
+) -> Result<(), CodecError> {
+    let start = buf.len();
+    buf.extend_from_slice(&[0, 0]);
+    let result = write(buf).and_then(|()| {
+        let len = buf.len() - start - 2;
+        let len = u16::try_from(len).map_err(|_| invalid("frame length", len))?;
+        buf[start..start + 2].copy_from_slice(&len.to_be_bytes());
+        Ok(())
+    });
+    if result.is_err() {
+        buf.truncate(start);
+    }
+    result
+}
+
+fn unframe(buf: &[u8]) -> Option<(&[u8], usize)> {
+    let len = u16::from_be_bytes([*buf.first()?, *buf.get(1)?]) as usize;
+    let body = buf.get(2..2 + len)?;
+    Some((body, 2 + len))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn level_update(side: Side, price: i64, quantity: i64) -> FeedMessage {
+        FeedMessage::LevelUpdate(LevelUpdate {
+            timestamp: DateTime::from_timestamp_nanos(1_700_000_000_000_000_000),
+            symbol: "BTCUSD".to_string(),
+            side,
+            price: Decimal::from(price),
+            quantity: Decimal::from(quantity),
+        })
+    }
+
+    #[test]
+    fn test_packet_round_trip() {
+        let packet = Packet {
+            session: "QFLOW".to_string(),
+            sequence: 42,
+            messages: vec![
+                level_update(Side::Buy, 100, 3),
+                FeedMessage::Trade(TradeMessage {
+                    timestamp: DateTime::from_timestamp_nanos(1_700_000_000_000_000_001),
+                    symbol: "BTCUSD".to_string(),
+                    price: Decimal::new(1005, 1),
+                    quantity: Decimal::new(25, 2),
+                    match_id: Uuid::new_v4(),
+                }),
+                level_update(Side::Sell, 101, 0),
+            ],
+        };
+        let mut buf = Vec::new();
+        packet.encode(&mut buf).unwrap();
+        assert_eq!(buf.len(), packet.encoded_len());
+        assert_eq!(Packet::decode(&buf).unwrap(), packet);
+        assert_eq!(packet.next_sequence(), 45);
+
+        // A datagram cut short is rejected rather than read past its end
+        assert!(Packet::decode(&buf[..buf.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn test_recovery_frames() {
+        let mut buf = Vec::new();
+        let request = RecoveryRequest::Retransmit {
+            sequence: 7,
+            count: 3,
+        };
+        request.encode(&mut buf).unwrap();
+        assert_eq!(RecoveryRequest::decode(&buf[..5]).unwrap(), None);
+        assert_eq!(
+            RecoveryRequest::decode(&buf).unwrap(),
+            Some((request, buf.len()))
+        );
+
+        let snapshot = RecoveryResponse::Snapshot(BookSnapshot {
+            sequence: 9,
+            symbol: "ETHUSD".to_string(),
+            bids: vec![Level {
+                price: Decimal::from(2000),
+                quantity: Decimal::from(4),
+            }],
+            asks: Vec::new(),
+        });
+        let mut buf = Vec::new();
+        snapshot.encode(&mut buf).unwrap();
+        assert_eq!(
+            RecoveryResponse::decode(&buf).unwrap(),
+            Some((snapshot, buf.len()))
+        );
+    }
+}
@@ -0,0 +1,390 @@
+//! Sequenced binary market data over UDP, in the style of ITCH.
+//!
+//! The publisher sends every change to the engine's books as level updates,
+//! at full depth rather than the engine's snapshot depth, along with every
+//! trade, in numbered packets to a multicast group or a unicast address.
+//! Subscribers that miss packets ask its TCP recovery service to retransmit
+//! recent messages, or for a snapshot of a book as of a sequence number. See
+//! [`codec`] for the wire format and [`subscriber`] for a reference
+//! subscriber.
+
+pub mod codec;
+pub mod subscriber;
+
+use crate::engine::bus::{BackpressurePolicy, RecvError};
+use crate::engine::matching::MatchingEngine;
+use crate::gateway::levels::diff_levels;
+use crate::utils::types::{OrderBookLevel, Side};
+use codec::{
+    BookSnapshot, FeedMessage, Level, LevelUpdate, Packet, RecoveryRequest, RecoveryResponse,
+    TradeMessage, HEADER_LEN, MAX_PACKET_LEN,
+};
+use parking_lot::Mutex;
+use std::collections::{HashMap, VecDeque};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream, UdpSocket};
+use tracing::{error, info, warn};
+
+/// Events buffered between the engine and the publisher.
+const EVENT_CAPACITY: usize = 4096;
+
+#[derive(Debug, Clone)]
+pub struct FeedConfig {
+    /// Session id stamped on every packet, up to ten ASCII characters.
+    pub session: String,
+    /// Multicast group or unicast address the packets are sent to.
+    pub destination: SocketAddr,
+    /// Most recent messages kept for retransmission.
+    pub history: usize,
+    /// Heartbeat interval while there is nothing to publish.
+    pub heartbeat: Duration,
+}
+
+impl FeedConfig {
+    pub fn new(destination: SocketAddr) -> Self {
+        Self {
+            session: "QFLOW".to_string(),
+            destination,
+            history: 100_000,
+            heartbeat: Duration::from_secs(1),
+        }
+    }
+}
+
+/// Levels of one book as published so far.
+#[derive(Default)]
+struct PublishedBook {
+    bids: Vec<OrderBookLevel>,
+    asks: Vec<OrderBookLevel>,
+}
+
+struct FeedState {
+    next_sequence: u64,
+    books: HashMap<String, PublishedBook>,
+    history: VecDeque<FeedMessage>,
+}
+
+pub struct FeedPublisher {
+    engine: Arc<MatchingEngine>,
+    config: FeedConfig,
+    state: Mutex<FeedState>,
+}
+
+impl FeedPublisher {
+    pub fn new(engine: Arc<MatchingEngine>, config: FeedConfig) -> Self {
+        Self {
+            engine,
+            config,
+            state: Mutex::new(FeedState {
+                next_sequence: 1,
+                books: HashMap::new(),
+                history: VecDeque::new(),
+            }),
+        }
+    }
+
+    /// Binds the recovery service to `recovery_addr` and publishes until the
+    /// engine shuts down.
+    pub async fn run(self: Arc<Self>, recovery_addr: &str) -> anyhow::Result<()> {
+        let socket = UdpSocket::bind("0.0.0.0:0").await?;
+        let listener = TcpListener::bind(recovery_addr).await?;
+        info!(
+            "Publishing market data to {}, recovery on {}",
+            self.config.destination, recovery_addr
+        );
+        self.serve(socket, listener).await
+    }
+
+    /// Publishes from `socket` and serves recovery on `listener`.
+    pub async fn serve(
+        self: Arc<Self>,
+        socket: UdpSocket,
+        listener: TcpListener,
+    ) -> anyhow::Result<()> {
+        if self.config.destination.ip().is_multicast() {
+            // Keep the feed on the local network
+            socket.set_multicast_ttl_v4(1)?;
+        }
+
+        let recovery = self.clone();
+        tokio::spawn(async move {
+            loop {
+                let (stream, peer) = match listener.accept().await {
+                    Ok(accepted) => accepted,
+                    Err(e) => {
+                        error!("Market data recovery service stopped: {}", e);
+                        return;
+                    }
+                };
+                let recovery = recovery.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = recovery.recover(stream).await {
+                        warn!("Recovery connection from {} closed: {}", peer, e);
+                    }
+                });
+            }
+        });
+
+        // Book levels heal from the next snapshot after a lag; trades cannot
+        let mut trades = self
+            .engine
+            .subscribe_trades(EVENT_CAPACITY, BackpressurePolicy::Block);
+        let mut reports = self
+            .engine
+            .subscribe_executions(EVENT_CAPACITY, BackpressurePolicy::DropOldest);
+        let mut heartbeat = tokio::time::interval(self.config.heartbeat);
+        let mut idle = true;
+
+        // Books that already exist are published in full first
+        let (first, messages) = self.level_updates(&self.engine.get_all_symbols());
+        self.publish(&socket, first, messages).await;
+
+        loop {
+            let (first, messages) = tokio::select! {
+                trade = trades.recv() => match trade {
+                    Ok(trade) => {
+                        let message = FeedMessage::Trade(TradeMessage {
+                            timestamp: trade.timestamp,
+                            symbol: trade.symbol,
+                            price: trade.price,
+                            quantity: trade.quantity,
+                            match_id: trade.id,
+                        });
+                        self.record(&mut self.state.lock(), vec![message])
+                    }
+                    Err(RecvError::Lagged { dropped }) => {
+                        error!("Market data feed lost {} trades", dropped);
+                        trades.resync();
+                        continue;
+                    }
+                    Err(RecvError::Closed) => break,
+                },
+                report = reports.recv() => match report {
+                    Ok(report) => self.level_updates(&[report.symbol]),
+                    Err(RecvError::Lagged { .. }) => {
+                        reports.resync();
+                        self.level_updates(&self.engine.get_all_symbols())
+                    }
+                    Err(RecvError::Closed) => break,
+                },
+                _ = heartbeat.tick() => {
+                    if idle {
+                        let next_sequence = self.state.lock().next_sequence;
+                        self.send(&socket, next_sequence, Vec::new()).await;
+                    }
+                    idle = true;
+                    continue;
+                }
+            };
+            if !messages.is_empty() {
+                self.publish(&socket, first, messages).await;
+                idle = false;
+            }
+        }
+        Ok(())
+    }
+
+    /// Level updates that bring the published books of `symbols` up to date,
+    /// numbered from the returned sequence number.
+    fn level_updates(&self, symbols: &[String]) -> (u64, Vec<FeedMessage>) {
+        let mut state = self.state.lock();
+        let mut messages = Vec::new();
+        for symbol in symbols {
+            let Some(snapshot) = self.engine.get_full_orderbook_snapshot(symbol) else {
+                continue;
+            };
+            let published = state.books.entry(symbol.clone()).or_default();
+            for (side, old, new) in [
+                (Side::Buy, &mut published.bids, snapshot.bids),
+                (Side::Sell, &mut published.asks, snapshot.asks),
+            ] {
+                // Order counts are not published
+                let new: Vec<OrderBookLevel> = new
+                    .into_iter()
+                    .map(|level| OrderBookLevel {
+                        order_count: 0,
+                        ..level
+                    })
+                    .collect();
+                messages.extend(diff_levels(old, &new).into_iter().map(|level| {
+                    FeedMessage::LevelUpdate(LevelUpdate {
+                        timestamp: snapshot.timestamp,
+                        symbol: symbol.clone(),
+                        side,
+                        price: level.price,
+                        quantity: level.quantity,
+                    })
+                }));
+                *old = new;
+            }
+        }
+        self.record(&mut state, messages)
+    }
+
+    /// Numbers `messages` and keeps them for retransmission, returning the
+    /// sequence number of the first.
+    fn record(&self, state: &mut FeedState, messages: Vec<FeedMessage>) -> (u64, Vec<FeedMessage>) {
+        let first = state.next_sequence;
+        state.next_sequence += messages.len() as u64;
+        state.history.extend(messages.iter().cloned());
+        let excess = state.history.len().saturating_sub(self.config.history);
+        state.history.drain(..excess);
+        (first, messages)
+    }
+
+    /// Sends `messages`, numbered from `first`, in as few packets as fit.
+    async fn publish(&self, socket: &UdpSocket, first: u64, messages: Vec<FeedMessage>) {
+        let mut sequence = first;
+        let mut batch = Vec::new();
+        let mut len = HEADER_LEN;
+        for message in messages {
+            if !batch.is_empty() && len + 2 + message.encoded_len() > MAX_PACKET_LEN {
+                let count = batch.len() as u64;
+                self.send(socket, sequence, std::mem::take(&mut batch))
+                    .await;
+                sequence += count;
+                len = HEADER_LEN;
+            }
+            len += 2 + message.encoded_len();
+            batch.push(message);
+        }
+        if !batch.is_empty() {
+            self.send(socket, sequence, batch).await;
+        }
+    }
+
+    async fn send(&self, socket: &UdpSocket, sequence: u64, messages: Vec<FeedMessage>) {
+        let packet = Packet {
+            session: self.config.session.clone(),
+            sequence,
+            messages,
+        };
+        let mut buf = Vec::with_capacity(packet.encoded_len());
+        if let Err(e) = packet.encode(&mut buf) {
+            error!("Unencodable market data packet {}: {}", sequence, e);
+            return;
+        }
+        // Subscribers recover lost packets, so a failed send is not fatal
+        if let Err(e) = socket.send_to(&buf, self.config.destination).await {
+            warn!("Failed to send market data packet {}: {}", sequence, e);
+        }
+    }
+
+    async fn recover(&self, mut stream: TcpStream) -> anyhow::Result<()> {
+        let mut buf = Vec::new();
+        loop {
+            while let Some((request, used)) = RecoveryRequest::decode(&buf)? {
+                buf.drain(..used);
+                let mut out = Vec::new();
+                self.respond(request).encode(&mut out)?;
+                stream.write_all(&out).await?;
+            }
+            if stream.read_buf(&mut buf).await? == 0 {
+                return Ok(());
+            }
+        }
+    }
+
+    fn respond(&self, request: RecoveryRequest) -> RecoveryResponse {
+        let state = self.state.lock();
+        match request {
+            RecoveryRequest::Retransmit { sequence, count } => {
+                let oldest = state.next_sequence - state.history.len() as u64;
+                let start = sequence.clamp(oldest, state.next_sequence);
+                let end = sequence
+                    .saturating_add(count as u64)
+                    .clamp(start, state.next_sequence);
+
+                // Stay within the u16 length of a recovery frame
+                let mut len = 1 + HEADER_LEN;
+                let messages: Vec<FeedMessage> = state
+                    .history
+                    .range((start - oldest) as usize..(end - oldest) as usize)
+                    .take_while(|m| {
+                        len += 2 + m.encoded_len();
+                        len <= u16::MAX as usize
+                    })
+                    .cloned()
+                    .collect();
+                RecoveryResponse::Retransmit(Packet {
+                    session: self.config.session.clone(),
+                    sequence: start,
+                    messages,
+                })
+            }
+            RecoveryRequest::Snapshot { symbol } => {
+                let levels = |levels: &[OrderBookLevel]| {
+                    levels
+                        .iter()
+                        .map(|level| Level {
+                            price: level.price,
+                            quantity: level.quantity,
+                        })
+                        .collect()
+                };
+                let book = state.books.get(&symbol);
+                RecoveryResponse::Snapshot(BookSnapshot {
+                    sequence: state.next_sequence,
+                    bids: book.map(|b| levels(&b.bids)).unwrap_or_default(),
+                    asks: book.map(|b| levels(&b.asks)).unwrap_or_default(),
+                    symbol,
+                })
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::types::{Order, OrderType};
+    use rust_decimal::Decimal;
+
+    #[tokio::test]
+    async fn test_retransmit_from_history() {
+        let engine = Arc::new(MatchingEngine::new());
+        let destination = "127.0.0.1:9".parse().unwrap();
+        let publisher = FeedPublisher::new(
+            engine.clone(),
+            FeedConfig {
+                history: 2,
+                ..FeedConfig::new(destination)
+            },
+        );
+        for price in [100, 101, 102] {
+            let order = Order::new(
+                "BTCUSD".to_string(),
+                Side::Buy,
+                OrderType::Limit,
+                Decimal::from(price),
+                Decimal::from(1),
+            );
+            engine.submit_order(order).await.unwrap();
+            let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+            let (first, messages) = publisher.level_updates(&["BTCUSD".to_string()]);
+            publisher.publish(&socket, first, messages).await;
+        }
+
+        // Only the last two of the three updates are kept
+        let RecoveryResponse::Retransmit(packet) = publisher.respond(RecoveryRequest::Retransmit {
+            sequence: 1,
+            count: 10,
+        }) else {
+            panic!("expected a retransmission");
+        };
+        assert_eq!(packet.sequence, 2);
+        assert_eq!(packet.messages.len(), 2);
+
+        let RecoveryResponse::Snapshot(snapshot) = publisher.respond(RecoveryRequest::Snapshot {
+            symbol: "BTCUSD".to_string(),
+        }) else {
+            panic!("expected a snapshot");
+        };
+        assert_eq!(snapshot.sequence, 4);
+        assert_eq!(snapshot.bids.len(), 3);
+        assert_eq!(snapshot.bids[0].price, Decimal::from(102));
+    }
+}
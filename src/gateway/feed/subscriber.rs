@@ -0,0 +1,233 @@
+//! Reference subscriber that rebuilds one book from the feed.
+
+use super::codec::{BookSnapshot, FeedMessage, Packet, RecoveryRequest, RecoveryResponse};
+use crate::engine::matching::MatchingEngine;
+use crate::engine::orderbook::OrderBook;
+use crate::utils::types::{Order, OrderType, Side};
+use rust_decimal::Decimal;
+use std::collections::HashMap;
+use std::net::{Ipv4Addr, SocketAddr};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpStream, UdpSocket};
+use tracing::{info, warn};
+use uuid::Uuid;
+
+/// Follows the feed for one symbol, keeping an [`OrderBook`] with a single
+/// order per published level. It starts from a recovery snapshot, fills
+/// gaps by retransmission and falls back to a new snapshot when the
+/// publisher no longer has the missing messages.
+pub struct FeedSubscriber {
+    socket: UdpSocket,
+    recovery_addr: SocketAddr,
+    recovery: Option<TcpStream>,
+    recovery_buf: Vec<u8>,
+    symbol: String,
+    book: OrderBook,
+    /// Id of the order standing in for each level.
+    levels: HashMap<(Side, Decimal), Uuid>,
+    next_sequence: Option<u64>,
+    datagram: Vec<u8>,
+}
+
+impl FeedSubscriber {
+    /// Receives packets on `local` and recovers from the service at `recovery`.
+    pub async fn bind(
+        local: SocketAddr,
+        recovery: SocketAddr,
+        symbol: &str,
+    ) -> anyhow::Result<Self> {
+        Ok(Self {
+            socket: UdpSocket::bind(local).await?,
+            recovery_addr: recovery,
+            recovery: None,
+            recovery_buf: Vec::new(),
+            symbol: symbol.to_string(),
+            book: OrderBook::new(symbol.to_string()),
+            levels: HashMap::new(),
+            next_sequence: None,
+            datagram: vec![0; u16::MAX as usize],
+        })
+    }
+
+    pub fn join_multicast(&self, group: Ipv4Addr) -> anyhow::Result<()> {
+        self.socket
+            .join_multicast_v4(group, Ipv4Addr::UNSPECIFIED)?;
+        Ok(())
+    }
+
+    pub fn local_addr(&self) -> anyhow::Result<SocketAddr> {
+        Ok(self.socket.local_addr()?)
+    }
+
+    pub fn book(&self) -> &OrderBook {
+        &self.book
+    }
+
+    /// Sequence number of the next message to apply, once synchronised.
+    pub fn next_sequence(&self) -> Option<u64> {
+        self.next_sequence
+    }
+
+    /// Waits for the next packet and applies it, recovering anything missed
+    /// first. Returns the messages for our symbol that were applied.
+    pub async fn recv(&mut self) -> anyhow::Result<Vec<FeedMessage>> {
+        let len = self.socket.recv(&mut self.datagram).await?;
+        let packet = Packet::decode(&self.datagram[..len])?;
+
+        let mut applied = Vec::new();
+        let next_sequence = match self.next_sequence {
+            Some(next_sequence) => next_sequence,
+            None => self.resync().await?,
+        };
+        if packet.sequence > next_sequence {
+            self.recover(packet.sequence, &mut applied).await?;
+        }
+        self.apply(&packet, &mut applied);
+        Ok(applied)
+    }
+
+    /// Compares the rebuilt book with the engine's, level by level at full
+    /// depth.
+    pub fn verify(&self, engine: &MatchingEngine) -> Result<(), String> {
+        let snapshot = engine.get_full_orderbook_snapshot(&self.symbol);
+        for side in [Side::Buy, Side::Sell] {
+            let expected: Vec<(Decimal, Decimal)> = snapshot
+                .iter()
+                .flat_map(|s| match side {
+                    Side::Buy => &s.bids,
+                    Side::Sell => &s.asks,
+                })
+                .map(|level| (level.price, level.quantity))
+                .collect();
+            let actual: Vec<(Decimal, Decimal)> = self
+                .book
+                .get_depth(side, usize::MAX)
+                .into_iter()
+                .map(|level| (level.price, level.quantity))
+                .collect();
+            if actual != expected {
+                return Err(format!(
+                    "{} {} levels differ: feed {:?}, engine {:?}",
+                    self.symbol, side, actual, expected
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Replaces the book with a recovery snapshot.
+    async fn resync(&mut self) -> anyhow::Result<u64> {
+        let request = RecoveryRequest::Snapshot {
+            symbol: self.symbol.clone(),
+        };
+        let RecoveryResponse::Snapshot(snapshot) = self.request(&request).await? else {
+            anyhow::bail!("recovery service answered a snapshot request with a retransmission");
+        };
+        info!(
+            "Synchronised {} at sequence {}",
+            self.symbol, snapshot.sequence
+        );
+        Ok(self.load(snapshot))
+    }
+
+    fn load(&mut self, snapshot: BookSnapshot) -> u64 {
+        self.book = OrderBook::new(self.symbol.clone());
+        self.levels.clear();
+        for level in snapshot.bids {
+            self.set_level(Side::Buy, level.price, level.quantity);
+        }
+        for level in snapshot.asks {
+            self.set_level(Side::Sell, level.price, level.quantity);
+        }
+        self.next_sequence = Some(snapshot.sequence);
+        snapshot.sequence
+    }
+
+    /// Fills the gap up to `end` by retransmission, or by a new snapshot
+    /// when the messages are no longer available.
+    async fn recover(&mut self, end: u64, applied: &mut Vec<FeedMessage>) -> anyhow::Result<()> {
+        while let Some(next_sequence) = self.next_sequence.filter(|&next| next < end) {
+            let request = RecoveryRequest::Retransmit {
+                sequence: next_sequence,
+                count: (end - next_sequence).min(u16::MAX as u64) as u16,
+            };
+            match self.request(&request).await? {
+                RecoveryResponse::Retransmit(packet)
+                    if packet.sequence == next_sequence && !packet.messages.is_empty() =>
+                {
+                    self.apply(&packet, applied);
+                }
+                _ => {
+                    warn!(
+                        "Messages from {} are no longer available, resynchronising {}",
+                        next_sequence, self.symbol
+                    );
+                    self.resync().await?;
+                    return Ok(());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn request(&mut self, request: &RecoveryRequest) -> anyhow::Result<RecoveryResponse> {
+        let mut out = Vec::new();
+        request.encode(&mut out)?;
+        let stream = match &mut self.recovery {
+            Some(stream) => stream,
+            None => self
+                .recovery
+                .insert(TcpStream::connect(self.recovery_addr).await?),
+        };
+        let result = async {
+            stream.write_all(&out).await?;
+            loop {
+                if let Some((response, used)) = RecoveryResponse::decode(&self.recovery_buf)? {
+                    self.recovery_buf.drain(..used);
+                    return Ok(response);
+                }
+                if stream.read_buf(&mut self.recovery_buf).await? == 0 {
+                    anyhow::bail!("recovery service closed the connection");
+                }
+            }
+        }
+        .await;
+        if result.is_err() {
+            // Reconnect on the next request
+            self.recovery = None;
+            self.recovery_buf.clear();
+        }
+        result
+    }
+
+    fn apply(&mut self, packet: &Packet, applied: &mut Vec<FeedMessage>) {
+        let Some(mut next_sequence) = self.next_sequence else {
+            return;
+        };
+        for (sequence, message) in (packet.sequence..).zip(&packet.messages) {
+            if sequence < next_sequence {
+                continue;
+            }
+            next_sequence = sequence + 1;
+            if message.symbol() != self.symbol {
+                continue;
+            }
+            if let FeedMessage::LevelUpdate(update) = message {
+                self.set_level(update.side, update.price, update.quantity);
+            }
+            applied.push(message.clone());
+        }
+        self.next_sequence = Some(next_sequence);
+    }
+
+    fn set_level(&mut self, side: Side, price: Decimal, quantity: Decimal) {
+        if let Some(id) = self.levels.remove(&(side, price)) {
+            self.book.remove_order(id, side);
+        }
+        if quantity > Decimal::ZERO {
+            let order = Order::new(self.symbol.clone(), side, OrderType::Limit, price, quantity);
+            self.levels.insert((side, price), order.id);
+            self.book.add_order(order);
+        }
+    }
+}
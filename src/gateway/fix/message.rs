@@ -0,0 +1,356 @@
+//! FIX 4.4 tag=value encoding.
+//!
+//! A [`FixMessage`] holds its fields in order, without `BeginString`,
+//! `BodyLength` and `CheckSum`; those are computed by [`FixMessage::encode`]
+//! and verified by [`FixMessage::decode`]. Repeating groups are not used by
+//! the gateway and are kept as plain fields.
+
+use chrono::{DateTime, NaiveDateTime, Utc};
+use std::str::FromStr;
+use thiserror::Error;
+
+pub const BEGIN_STRING: &str = "FIX.4.4";
+const SOH: u8 = 0x01;
+const TIMESTAMP_FORMAT: &str = "%Y%m%d-%H:%M:%S%.3f";
+
+/// Tag numbers used by the gateway.
+pub mod tags {
+    pub const AVG_PX: u32 = 6;
+    pub const BEGIN_SEQ_NO: u32 = 7;
+    pub const BEGIN_STRING: u32 = 8;
+    pub const BODY_LENGTH: u32 = 9;
+    pub const CHECK_SUM: u32 = 10;
+    pub const CL_ORD_ID: u32 = 11;
+    pub const CUM_QTY: u32 = 14;
+    pub const END_SEQ_NO: u32 = 16;
+    pub const EXEC_ID: u32 = 17;
+    pub const LAST_PX: u32 = 31;
+    pub const LAST_QTY: u32 = 32;
+    pub const MSG_SEQ_NUM: u32 = 34;
+    pub const MSG_TYPE: u32 = 35;
+    pub const NEW_SEQ_NO: u32 = 36;
+    pub const ORDER_ID: u32 = 37;
+    pub const ORDER_QTY: u32 = 38;
+    pub const ORD_STATUS: u32 = 39;
+    pub const ORD_TYPE: u32 = 40;
+    pub const ORIG_CL_ORD_ID: u32 = 41;
+    pub const POSS_DUP_FLAG: u32 = 43;
+    pub const PRICE: u32 = 44;
+    pub const REF_SEQ_NUM: u32 = 45;
+    pub const SENDER_COMP_ID: u32 = 49;
+    pub const SENDING_TIME: u32 = 52;
+    pub const SIDE: u32 = 54;
+    pub const SYMBOL: u32 = 55;
+    pub const TARGET_COMP_ID: u32 = 56;
+    pub const TEXT: u32 = 58;
+    pub const TIME_IN_FORCE: u32 = 59;
+    pub const TRANSACT_TIME: u32 = 60;
+    pub const ENCRYPT_METHOD: u32 = 98;
+    pub const STOP_PX: u32 = 99;
+    pub const CXL_REJ_REASON: u32 = 102;
+    pub const ORD_REJ_REASON: u32 = 103;
+    pub const HEART_BT_INT: u32 = 108;
+    pub const TEST_REQ_ID: u32 = 112;
+    pub const ORIG_SENDING_TIME: u32 = 122;
+    pub const GAP_FILL_FLAG: u32 = 123;
+    pub const EXPIRE_TIME: u32 = 126;
+    pub const RESET_SEQ_NUM_FLAG: u32 = 141;
+    pub const EXEC_TYPE: u32 = 150;
+    pub const LEAVES_QTY: u32 = 151;
+    pub const REF_TAG_ID: u32 = 371;
+    pub const REF_MSG_TYPE: u32 = 372;
+    pub const SESSION_REJECT_REASON: u32 = 373;
+    pub const BUSINESS_REJECT_REASON: u32 = 380;
+    pub const CXL_REJ_RESPONSE_TO: u32 = 434;
+}
+
+/// `MsgType` values used by the gateway.
+pub mod msg_type {
+    pub const HEARTBEAT: &str = "0";
+    pub const TEST_REQUEST: &str = "1";
+    pub const RESEND_REQUEST: &str = "2";
+    pub const REJECT: &str = "3";
+    pub const SEQUENCE_RESET: &str = "4";
+    pub const LOGOUT: &str = "5";
+    pub const EXECUTION_REPORT: &str = "8";
+    pub const ORDER_CANCEL_REJECT: &str = "9";
+    pub const LOGON: &str = "A";
+    pub const NEW_ORDER_SINGLE: &str = "D";
+    pub const ORDER_CANCEL_REQUEST: &str = "F";
+    pub const ORDER_CANCEL_REPLACE_REQUEST: &str = "G";
+    pub const BUSINESS_MESSAGE_REJECT: &str = "j";
+
+    /// Session-level message types, which are gap-filled rather than resent.
+    pub fn is_admin(msg_type: &str) -> bool {
+        matches!(msg_type, "0" | "1" | "2" | "3" | "4" | "5" | "A")
+    }
+}
+
+/// Standard header fields, written straight after `MsgType` in this order.
+const HEADER: [u32; 6] = [
+    tags::SENDER_COMP_ID,
+    tags::TARGET_COMP_ID,
+    tags::MSG_SEQ_NUM,
+    tags::POSS_DUP_FLAG,
+    tags::SENDING_TIME,
+    tags::ORIG_SENDING_TIME,
+];
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum FixError {
+    #[error("garbled message: {0}")]
+    Garbled(String),
+    #[error("checksum mismatch: expected {expected:03}, got {actual:03}")]
+    CheckSum { expected: u8, actual: u8 },
+    #[error("unsupported BeginString {0}")]
+    BeginString(String),
+    #[error("required tag {0} missing")]
+    MissingField(u32),
+    #[error("tag {tag} has an invalid value {value:?}")]
+    InvalidField { tag: u32, value: String },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FixMessage {
+    fields: Vec<(u32, String)>,
+}
+
+impl FixMessage {
+    pub fn new(msg_type: &str) -> Self {
+        Self {
+            fields: vec![(tags::MSG_TYPE, msg_type.to_string())],
+        }
+    }
+
+    pub fn msg_type(&self) -> &str {
+        self.get(tags::MSG_TYPE).unwrap_or_default()
+    }
+
+    /// Appends a field, builder style.
+    pub fn with(mut self, tag: u32, value: impl ToString) -> Self {
+        self.fields.push((tag, value.to_string()));
+        self
+    }
+
+    /// Replaces the first occurrence of `tag`, or appends it.
+    pub fn set(&mut self, tag: u32, value: impl ToString) {
+        match self.fields.iter_mut().find(|(t, _)| *t == tag) {
+            Some(field) => field.1 = value.to_string(),
+            None => self.fields.push((tag, value.to_string())),
+        }
+    }
+
+    pub fn remove(&mut self, tag: u32) {
+        self.fields.retain(|(t, _)| *t != tag);
+    }
+
+    pub fn get(&self, tag: u32) -> Option<&str> {
+        self.fields
+            .iter()
+            .find(|(t, _)| *t == tag)
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn require(&self, tag: u32) -> Result<&str, FixError> {
+        self.get(tag).ok_or(FixError::MissingField(tag))
+    }
+
+    /// Parses an optional field.
+    pub fn parse<T: FromStr>(&self, tag: u32) -> Result<Option<T>, FixError> {
+        self.get(tag)
+            .map(|value| {
+                value.parse().map_err(|_| FixError::InvalidField {
+                    tag,
+                    value: value.to_string(),
+                })
+            })
+            .transpose()
+    }
+
+    /// Parses a required field.
+    pub fn parse_required<T: FromStr>(&self, tag: u32) -> Result<T, FixError> {
+        self.parse(tag)?.ok_or(FixError::MissingField(tag))
+    }
+
+    pub fn flag(&self, tag: u32) -> bool {
+        self.get(tag) == Some("Y")
+    }
+
+    pub fn timestamp(&self, tag: u32) -> Result<Option<DateTime<Utc>>, FixError> {
+        self.get(tag)
+            .map(|value| parse_timestamp(tag, value))
+            .transpose()
+    }
+
+    /// Serializes the message with `BeginString`, `BodyLength` and `CheckSum`.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut body = Vec::new();
+        let mut push = |tag: u32, value: &str| {
+            body.extend_from_slice(tag.to_string().as_bytes());
+            body.push(b'=');
+            body.extend_from_slice(value.as_bytes());
+            body.push(SOH);
+        };
+        push(tags::MSG_TYPE, self.msg_type());
+        for tag in HEADER {
+            if let Some(value) = self.get(tag) {
+                push(tag, value);
+            }
+        }
+        for (tag, value) in &self.fields {
+            if *tag != tags::MSG_TYPE && !HEADER.contains(tag) {
+                push(*tag, value);
+            }
+        }
+
+        let mut out = format!("8={}\x019={}\x01", BEGIN_STRING, body.len()).into_bytes();
+        out.extend_from_slice(&body);
+        let sum = checksum(&out);
+        out.extend_from_slice(format!("10={:03}\x01", sum).as_bytes());
+        out
+    }
+
+    /// Decodes the first message in `buf`.
+    ///
+    /// Returns the message and the number of bytes it used, or `None` if
+    /// `buf` does not hold a complete message yet.
+    pub fn decode(buf: &[u8]) -> Result<Option<(Self, usize)>, FixError> {
+        let Some(begin_end) = find_soh(buf, 0) else {
+            return Ok(None);
+        };
+        let begin = field(&buf[..begin_end])?;
+        if begin.0 != tags::BEGIN_STRING {
+            return Err(FixError::Garbled(
+                "message does not start with BeginString".into(),
+            ));
+        }
+        if begin.1 != BEGIN_STRING {
+            return Err(FixError::BeginString(begin.1.to_string()));
+        }
+
+        let Some(length_end) = find_soh(buf, begin_end + 1) else {
+            return Ok(None);
+        };
+        let (tag, length) = field(&buf[begin_end + 1..length_end])?;
+        if tag != tags::BODY_LENGTH {
+            return Err(FixError::Garbled(
+                "BodyLength must be the second field".into(),
+            ));
+        }
+        let length: usize = length
+            .parse()
+            .map_err(|_| FixError::Garbled(format!("invalid BodyLength {:?}", length)))?;
+
+        let body_start = length_end + 1;
+        let trailer_start = body_start + length;
+        // "10=nnn" plus its delimiter
+        let total = trailer_start + 7;
+        if buf.len() < total {
+            return Ok(None);
+        }
+        let (tag, sum) = field(&buf[trailer_start..total - 1])?;
+        if tag != tags::CHECK_SUM || buf[total - 1] != SOH {
+            return Err(FixError::Garbled("CheckSum must follow the body".into()));
+        }
+        let actual: u8 = sum
+            .parse()
+            .map_err(|_| FixError::Garbled(format!("invalid CheckSum {:?}", sum)))?;
+        let expected = checksum(&buf[..trailer_start]);
+        if actual != expected {
+            return Err(FixError::CheckSum { expected, actual });
+        }
+
+        let mut fields = Vec::new();
+        let mut start = body_start;
+        while start < trailer_start {
+            let end = find_soh(buf, start)
+                .filter(|end| *end < trailer_start)
+                .ok_or_else(|| FixError::Garbled("unterminated field".into()))?;
+            let (tag, value) = field(&buf[start..end])?;
+            fields.push((tag, value.to_string()));
+            start = end + 1;
+        }
+        if fields.first().map(|(tag, _)| *tag) != Some(tags::MSG_TYPE) {
+            return Err(FixError::Garbled("MsgType must be the third field".into()));
+        }
+        Ok(Some((Self { fields }, total)))
+    }
+}
+
+pub fn format_timestamp(time: DateTime<Utc>) -> String {
+    time.format(TIMESTAMP_FORMAT).to_string()
+}
+
+fn parse_timestamp(tag: u32, value: &str) -> Result<DateTime<Utc>, FixError> {
+    ["%Y%m%d-%H:%M:%S%.f", "%Y%m%d-%H:%M:%S"]
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(value, format).ok())
+        .map(|time| time.and_utc())
+        .ok_or_else(|| FixError::InvalidField {
+            tag,
+            value: value.to_string(),
+        })
+}
+
+fn checksum(bytes: &[u8]) -> u8 {
+    bytes.iter().fold(0u8, |sum, b| sum.wrapping_add(*b))
+}
+
+fn find_soh(buf: &[u8], from: usize) -> Option<usize> {
+    buf.get(from..)?
+        .iter()
+        .position(|b| *b == SOH)
+        .map(|i| from + i)
+}
+
+fn field(bytes: &[u8]) -> Result<(u32, &str), FixError> {
+    let text = std::str::from_utf8(bytes)
+        .map_err(|_| FixError::Garbled("field is not valid UTF-8".into()))?;
+    let (tag, value) = text
+        .split_once('=')
+        .ok_or_else(|| FixError::Garbled(format!("field {:?} has no '='", text)))?;
+    let tag = tag
+        .parse()
+        .map_err(|_| FixError::Garbled(format!("invalid tag {:?}", tag)))?;
+    Ok((tag, value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_orders_header_and_round_trips() {
+        let mut message = FixMessage::new(msg_type::NEW_ORDER_SINGLE)
+            .with(tags::CL_ORD_ID, "c1")
+            .with(tags::SYMBOL, "BTCUSD");
+        message.set(tags::MSG_SEQ_NUM, 7);
+        message.set(tags::SENDER_COMP_ID, "OMS");
+
+        let bytes = message.encode();
+        let text = String::from_utf8(bytes.clone())
+            .unwrap()
+            .replace('\x01', "|");
+        assert!(text.starts_with("8=FIX.4.4|9="));
+        assert!(text.contains("|35=D|49=OMS|34=7|11=c1|55=BTCUSD|10="));
+
+        let (decoded, used) = FixMessage::decode(&bytes).unwrap().unwrap();
+        assert_eq!(used, bytes.len());
+        assert_eq!(decoded.get(tags::CL_ORD_ID), Some("c1"));
+        assert_eq!(decoded.parse_required::<u64>(tags::MSG_SEQ_NUM), Ok(7));
+    }
+
+    #[test]
+    fn test_decode_waits_for_complete_message_and_checks_sum() {
+        let bytes = FixMessage::new(msg_type::HEARTBEAT).encode();
+        assert_eq!(FixMessage::decode(&bytes[..bytes.len() - 1]), Ok(None));
+
+        let mut corrupted = bytes.clone();
+        let last_body_byte = corrupted.len() - 8;
+        corrupted[last_body_byte] = b'1';
+        assert!(matches!(
+            FixMessage::decode(&corrupted),
+            Err(FixError::CheckSum { .. })
+        ));
+    }
+}
@@ -0,0 +1,232 @@
+//! FIX 4.4 order-entry acceptor.
+//!
+//! Counterparties log on over TCP and trade on a shared [`MatchingEngine`]
+//! with NewOrderSingle, OrderCancelRequest and OrderCancelReplaceRequest.
+//! Engine execution reports for their orders come back as ExecutionReports.
+//! Sequence numbers and sent messages are kept per counterparty, in memory
+//! or in a directory, so sessions resume and resend across reconnects.
+//! Order state is not: a session's open orders are cancelled when its
+//! connection closes.
+
+pub mod message;
+mod session;
+pub mod store;
+
+use crate::engine::bus::{BackpressurePolicy, RecvError};
+use crate::engine::matching::MatchingEngine;
+use dashmap::{DashMap, DashSet};
+use message::{msg_type, tags, FixMessage};
+use parking_lot::Mutex;
+use session::{Flow, Session};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use store::{FileStore, MemoryStore, MessageStore};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tracing::{error, info, warn};
+
+/// How long a new connection has to send its Logon.
+const LOGON_TIMEOUT: Duration = Duration::from_secs(10);
+/// Execution reports buffered per session between the engine and the socket.
+const REPORT_CAPACITY: usize = 4096;
+
+#[derive(Debug, Clone)]
+pub struct FixConfig {
+    /// Our `SenderCompID`; counterparties must send it as `TargetCompID`.
+    pub comp_id: String,
+    /// Directory for persistent sequence numbers and messages. Sessions are
+    /// kept in memory, and restart at 1 with the gateway, when `None`.
+    pub store_dir: Option<PathBuf>,
+}
+
+impl FixConfig {
+    pub fn new(comp_id: &str) -> Self {
+        Self {
+            comp_id: comp_id.to_string(),
+            store_dir: None,
+        }
+    }
+}
+
+pub struct FixAcceptor {
+    engine: Arc<MatchingEngine>,
+    config: FixConfig,
+    stores: DashMap<String, Arc<Mutex<Box<dyn MessageStore>>>>,
+    logged_on: DashSet<String>,
+}
+
+impl FixAcceptor {
+    pub fn new(engine: Arc<MatchingEngine>, config: FixConfig) -> Self {
+        Self {
+            engine,
+            config,
+            stores: DashMap::new(),
+            logged_on: DashSet::new(),
+        }
+    }
+
+    /// Binds `addr` and serves FIX sessions until the listener fails.
+    pub async fn run(self: Arc<Self>, addr: &str) -> anyhow::Result<()> {
+        let listener = TcpListener::bind(addr).await?;
+        info!("FIX acceptor {} listening on {}", self.config.comp_id, addr);
+        self.serve(listener).await
+    }
+
+    /// Serves FIX sessions on an already bound listener.
+    pub async fn serve(self: Arc<Self>, listener: TcpListener) -> anyhow::Result<()> {
+        loop {
+            let (stream, peer) = listener.accept().await?;
+            let acceptor = self.clone();
+            tokio::spawn(async move {
+                if let Err(e) = acceptor.handle(stream).await {
+                    warn!("FIX connection from {} closed: {}", peer, e);
+                }
+            });
+        }
+    }
+
+    fn store(&self, counterparty: &str) -> anyhow::Result<Arc<Mutex<Box<dyn MessageStore>>>> {
+        if let Some(store) = self.stores.get(counterparty) {
+            return Ok(store.clone());
+        }
+        let store: Box<dyn MessageStore> = match &self.config.store_dir {
+            Some(dir) => {
+                let session = format!("{}-{}", self.config.comp_id, counterparty);
+                Box::new(FileStore::open(dir, &session)?)
+            }
+            None => Box::new(MemoryStore::new()),
+        };
+        Ok(self
+            .stores
+            .entry(counterparty.to_string())
+            .or_insert_with(|| Arc::new(Mutex::new(store)))
+            .clone())
+    }
+
+    async fn handle(&self, mut stream: TcpStream) -> anyhow::Result<()> {
+        let mut buf = Vec::new();
+        let logon = tokio::time::timeout(LOGON_TIMEOUT, read_message(&mut stream, &mut buf))
+            .await
+            .map_err(|_| anyhow::anyhow!("no Logon received"))??;
+        let Some(logon) = logon else {
+            return Ok(());
+        };
+
+        if logon.msg_type() != msg_type::LOGON {
+            anyhow::bail!("first message was {}, not Logon", logon.msg_type());
+        }
+        if logon.get(tags::TARGET_COMP_ID) != Some(self.config.comp_id.as_str()) {
+            anyhow::bail!(
+                "Logon for unknown TargetCompID {:?}",
+                logon.get(tags::TARGET_COMP_ID)
+            );
+        }
+        let counterparty = logon.require(tags::SENDER_COMP_ID)?.to_string();
+        let heartbeat =
+            Duration::from_secs(logon.parse_required::<u64>(tags::HEART_BT_INT)?.max(1));
+        if !self.logged_on.insert(counterparty.clone()) {
+            anyhow::bail!("{} is already logged on", counterparty);
+        }
+
+        let result = self
+            .run_session(stream, buf, &logon, counterparty.clone(), heartbeat)
+            .await;
+        self.logged_on.remove(&counterparty);
+        result
+    }
+
+    async fn run_session(
+        &self,
+        mut stream: TcpStream,
+        mut buf: Vec<u8>,
+        logon: &FixMessage,
+        counterparty: String,
+        heartbeat: Duration,
+    ) -> anyhow::Result<()> {
+        let store = self.store(&counterparty)?;
+
+        // Forward reports for this session's orders; the engine must never
+        // wait on a slow socket, so the hop to the session is unbounded
+        let owned = Arc::new(DashSet::new());
+        let mut executions = self
+            .engine
+            .subscribe_executions(REPORT_CAPACITY, BackpressurePolicy::Block);
+        let (report_tx, mut report_rx) = mpsc::unbounded_channel();
+        let forward_owned = owned.clone();
+        let forwarder = tokio::spawn(async move {
+            loop {
+                match executions.recv().await {
+                    Ok(report) if forward_owned.contains(&report.order_id) => {
+                        if report_tx.send(report).is_err() {
+                            break;
+                        }
+                    }
+                    Ok(_) => {}
+                    Err(RecvError::Lagged { dropped }) => {
+                        error!("FIX session lost {} execution reports", dropped);
+                        executions.resync();
+                    }
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        });
+
+        let mut session = Session::new(
+            self.engine.clone(),
+            store,
+            self.config.comp_id.clone(),
+            counterparty,
+            heartbeat,
+            owned,
+        );
+        let mut flow = session.on_logon(logon);
+        stream.write_all(&session.take_outgoing()).await?;
+
+        let mut timer = tokio::time::interval(Duration::from_secs(1));
+        let result = async {
+            while flow == Flow::Continue {
+                tokio::select! {
+                    read = stream.read_buf(&mut buf) => {
+                        if read? == 0 {
+                            break;
+                        }
+                        while let Some((message, used)) = FixMessage::decode(&buf)? {
+                            buf.drain(..used);
+                            flow = session.on_message(message).await;
+                            if flow == Flow::Disconnect {
+                                break;
+                            }
+                        }
+                    }
+                    Some(report) = report_rx.recv() => session.on_report(report),
+                    _ = timer.tick() => flow = session.on_timer(),
+                }
+                stream.write_all(&session.take_outgoing()).await?;
+            }
+            Ok(())
+        }
+        .await;
+
+        session.cancel_all().await;
+        forwarder.abort();
+        result
+    }
+}
+
+/// Reads until `buf` holds one complete message, or returns `None` on EOF.
+async fn read_message(
+    stream: &mut TcpStream,
+    buf: &mut Vec<u8>,
+) -> anyhow::Result<Option<FixMessage>> {
+    loop {
+        if let Some((message, used)) = FixMessage::decode(buf)? {
+            buf.drain(..used);
+            return Ok(Some(message));
+        }
+        if stream.read_buf(buf).await? == 0 {
+            return Ok(None);
+        }
+    }
+}
@@ -0,0 +1,875 @@
+//! A logged-on FIX session: sequencing, heartbeats, resends and order entry.
+//!
+//! The session is driven by its connection task and never touches the socket
+//! itself. Every message it sends is numbered, stored and appended to an
+//! outgoing buffer that the connection flushes after each event.
+
+use crate::engine::matching::MatchingEngine;
+use crate::gateway::fix::message::{format_timestamp, msg_type, tags, FixError, FixMessage};
+use crate::gateway::fix::store::MessageStore;
+use crate::utils::types::{ExecType, ExecutionReport, Order, OrderStatus, OrderType, Side};
+use chrono::Utc;
+use dashmap::DashSet;
+use parking_lot::Mutex;
+use rust_decimal::Decimal;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::Instant;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+/// Whether the connection stays up after an event.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Flow {
+    Continue,
+    Disconnect,
+}
+
+/// What the session knows about one of its orders on the engine.
+#[derive(Debug, Clone)]
+struct OrderEntry {
+    cl_ord_id: String,
+    orig_cl_ord_id: Option<String>,
+    symbol: String,
+    side: Side,
+    status: OrderStatus,
+    /// `ClOrdID` of a cancel request waiting for the engine's cancel report.
+    cancel_cl_ord_id: Option<String>,
+    /// Quantity filled by the orders this one replaced.
+    base_filled: Decimal,
+    /// Filled price times quantity, for `AvgPx`.
+    notional: Decimal,
+    /// The engine's acceptance of this order is reported as `Replaced`.
+    replacing: bool,
+    /// The engine order that replaced this one; its cancel is not reported.
+    replaced_by: Option<Uuid>,
+}
+
+impl OrderEntry {
+    fn new(cl_ord_id: String, order: &Order) -> Self {
+        Self {
+            cl_ord_id,
+            orig_cl_ord_id: None,
+            symbol: order.symbol.clone(),
+            side: order.side,
+            status: OrderStatus::Pending,
+            cancel_cl_ord_id: None,
+            base_filled: Decimal::ZERO,
+            notional: Decimal::ZERO,
+            replacing: false,
+            replaced_by: None,
+        }
+    }
+}
+
+pub(crate) struct Session {
+    engine: Arc<MatchingEngine>,
+    store: Arc<Mutex<Box<dyn MessageStore>>>,
+    comp_id: String,
+    counterparty: String,
+    heartbeat: Duration,
+    /// Engine order ids whose execution reports are forwarded to this session.
+    owned: Arc<DashSet<Uuid>>,
+    orders: HashMap<Uuid, OrderEntry>,
+    cl_ord_ids: HashMap<String, Uuid>,
+    /// Messages received ahead of a sequence gap, processed once it is filled.
+    queued: BTreeMap<u64, FixMessage>,
+    awaiting_resend: bool,
+    last_sent: Instant,
+    last_received: Instant,
+    test_request: Option<String>,
+    test_requests_sent: u64,
+    outgoing: Vec<u8>,
+}
+
+impl Session {
+    pub(crate) fn new(
+        engine: Arc<MatchingEngine>,
+        store: Arc<Mutex<Box<dyn MessageStore>>>,
+        comp_id: String,
+        counterparty: String,
+        heartbeat: Duration,
+        owned: Arc<DashSet<Uuid>>,
+    ) -> Self {
+        let now = Instant::now();
+        Self {
+            engine,
+            store,
+            comp_id,
+            counterparty,
+            heartbeat,
+            owned,
+            orders: HashMap::new(),
+            cl_ord_ids: HashMap::new(),
+            queued: BTreeMap::new(),
+            awaiting_resend: false,
+            last_sent: now,
+            last_received: now,
+            test_request: None,
+            test_requests_sent: 0,
+            outgoing: Vec::new(),
+        }
+    }
+
+    /// Bytes sent since the last call, ready to be written to the socket.
+    pub(crate) fn take_outgoing(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.outgoing)
+    }
+
+    /// Answers the counterparty's Logon, requesting a resend if it is ahead of us.
+    pub(crate) fn on_logon(&mut self, logon: &FixMessage) -> Flow {
+        let Ok(seq) = logon.parse_required::<u64>(tags::MSG_SEQ_NUM) else {
+            self.logout("MsgSeqNum missing or invalid");
+            return Flow::Disconnect;
+        };
+        let reset = logon.flag(tags::RESET_SEQ_NUM_FLAG);
+        if reset {
+            if let Err(e) = self.store.lock().reset() {
+                warn!("Failed to reset FIX store for {}: {}", self.counterparty, e);
+            }
+        }
+
+        let expected = self.next_target_seq();
+        if seq < expected {
+            self.logout(&format!(
+                "MsgSeqNum too low, expecting {} but received {}",
+                expected, seq
+            ));
+            return Flow::Disconnect;
+        }
+
+        let mut response = FixMessage::new(msg_type::LOGON)
+            .with(tags::ENCRYPT_METHOD, 0)
+            .with(tags::HEART_BT_INT, self.heartbeat.as_secs());
+        if reset {
+            response = response.with(tags::RESET_SEQ_NUM_FLAG, "Y");
+        }
+        self.send(response);
+        info!("FIX session {} logged on", self.counterparty);
+
+        if seq == expected {
+            self.set_next_target_seq(seq + 1);
+        } else {
+            self.queued.insert(seq, logon.clone());
+            self.request_resend(expected);
+        }
+        Flow::Continue
+    }
+
+    pub(crate) async fn on_message(&mut self, message: FixMessage) -> Flow {
+        self.last_received = Instant::now();
+        self.test_request = None;
+
+        if message.get(tags::SENDER_COMP_ID) != Some(self.counterparty.as_str())
+            || message.get(tags::TARGET_COMP_ID) != Some(self.comp_id.as_str())
+        {
+            self.logout("CompID problem");
+            return Flow::Disconnect;
+        }
+        let Ok(seq) = message.parse_required::<u64>(tags::MSG_SEQ_NUM) else {
+            self.logout("MsgSeqNum missing or invalid");
+            return Flow::Disconnect;
+        };
+
+        // A reset (as opposed to a gap fill) ignores the sequence number
+        if message.msg_type() == msg_type::SEQUENCE_RESET && !message.flag(tags::GAP_FILL_FLAG) {
+            self.on_sequence_reset(&message, seq);
+            return self.drain_queue().await;
+        }
+
+        let expected = self.next_target_seq();
+        if seq < expected {
+            if message.flag(tags::POSS_DUP_FLAG) {
+                return Flow::Continue;
+            }
+            self.logout(&format!(
+                "MsgSeqNum too low, expecting {} but received {}",
+                expected, seq
+            ));
+            return Flow::Disconnect;
+        }
+        if seq > expected {
+            // The counterparty may itself be waiting on a resend from us
+            if message.msg_type() == msg_type::RESEND_REQUEST {
+                self.on_resend_request(&message, seq);
+            }
+            self.queued.insert(seq, message);
+            if !self.awaiting_resend {
+                self.request_resend(expected);
+            }
+            return Flow::Continue;
+        }
+
+        if self.process(message, seq, false).await == Flow::Disconnect {
+            return Flow::Disconnect;
+        }
+        self.drain_queue().await
+    }
+
+    /// Sends heartbeats and test requests, and drops a silent counterparty.
+    pub(crate) fn on_timer(&mut self) -> Flow {
+        let now = Instant::now();
+        let grace = self.heartbeat + self.heartbeat / 5;
+        let silent = now.duration_since(self.last_received);
+
+        if self.test_request.is_some() && silent >= grace * 2 {
+            warn!(
+                "FIX session {} did not answer a test request",
+                self.counterparty
+            );
+            self.logout("Test request not answered");
+            return Flow::Disconnect;
+        }
+        if self.test_request.is_none() && silent >= grace {
+            self.test_requests_sent += 1;
+            let id = format!("TEST{}", self.test_requests_sent);
+            self.send(FixMessage::new(msg_type::TEST_REQUEST).with(tags::TEST_REQ_ID, &id));
+            self.test_request = Some(id);
+        }
+        if now.duration_since(self.last_sent) >= self.heartbeat {
+            self.send(FixMessage::new(msg_type::HEARTBEAT));
+        }
+        Flow::Continue
+    }
+
+    /// Translates an engine execution report for one of our orders.
+    pub(crate) fn on_report(&mut self, report: ExecutionReport) {
+        let Some(entry) = self.orders.get_mut(&report.order_id) else {
+            return;
+        };
+        entry.status = report.status;
+
+        if let (Some(price), Some(quantity)) = (report.last_price, report.last_quantity) {
+            let notional = price * quantity;
+            entry.notional += notional;
+            // Fills that raced a replace also count towards the replacement
+            let mut next = entry.replaced_by;
+            while let Some(id) = next {
+                let Some(successor) = self.orders.get_mut(&id) else {
+                    break;
+                };
+                successor.notional += notional;
+                next = successor.replaced_by;
+            }
+        }
+
+        let entry = &self.orders[&report.order_id];
+        if report.exec_type == ExecType::Cancelled && entry.replaced_by.is_some() {
+            self.forget(report.order_id);
+            return;
+        }
+
+        let mut message = execution_report(entry, &report);
+        let entry = self.orders.get_mut(&report.order_id).unwrap();
+        match report.exec_type {
+            ExecType::New if entry.replacing => {
+                entry.replacing = false;
+                message.set(tags::EXEC_TYPE, "5");
+            }
+            ExecType::Cancelled => {
+                if let Some(cancel_cl_ord_id) = entry.cancel_cl_ord_id.take() {
+                    message.set(tags::CL_ORD_ID, cancel_cl_ord_id);
+                    message.set(tags::ORIG_CL_ORD_ID, &entry.cl_ord_id);
+                }
+            }
+            _ => {}
+        }
+        self.send(message);
+
+        if matches!(
+            report.status,
+            OrderStatus::Filled
+                | OrderStatus::Cancelled
+                | OrderStatus::Rejected
+                | OrderStatus::Expired
+        ) {
+            self.forget(report.order_id);
+        }
+    }
+
+    /// Cancels every open order of the session, as when its connection closes.
+    pub(crate) async fn cancel_all(&mut self) {
+        let open: Vec<(Uuid, String)> = self
+            .orders
+            .iter()
+            .filter(|(_, entry)| entry.replaced_by.is_none())
+            .map(|(id, entry)| (*id, entry.symbol.clone()))
+            .collect();
+        for (id, symbol) in open {
+            if self.engine.cancel_order(id, &symbol).await.is_ok() {
+                info!(
+                    "Cancelled order {} of disconnected FIX session {}",
+                    id, self.counterparty
+                );
+            }
+            self.forget(id);
+        }
+    }
+
+    async fn process(&mut self, message: FixMessage, seq: u64, from_queue: bool) -> Flow {
+        if message.msg_type() == msg_type::SEQUENCE_RESET {
+            match message.parse_required::<u64>(tags::NEW_SEQ_NO) {
+                Ok(new_seq) if new_seq > seq => self.set_next_target_seq(new_seq),
+                Ok(new_seq) => {
+                    self.set_next_target_seq(seq + 1);
+                    self.reject(
+                        &message,
+                        &FixError::InvalidField {
+                            tag: tags::NEW_SEQ_NO,
+                            value: new_seq.to_string(),
+                        },
+                    );
+                }
+                Err(e) => {
+                    self.set_next_target_seq(seq + 1);
+                    self.reject(&message, &e);
+                }
+            }
+            return Flow::Continue;
+        }
+        self.set_next_target_seq(seq + 1);
+
+        let result = match message.msg_type() {
+            msg_type::HEARTBEAT | msg_type::REJECT | msg_type::LOGON => Ok(()),
+            msg_type::TEST_REQUEST => {
+                let id = message.require(tags::TEST_REQ_ID).map(str::to_string);
+                id.map(|id| {
+                    self.send(FixMessage::new(msg_type::HEARTBEAT).with(tags::TEST_REQ_ID, id))
+                })
+            }
+            msg_type::RESEND_REQUEST => {
+                // Requests queued behind a gap were answered on arrival
+                if !from_queue {
+                    self.on_resend_request(&message, seq);
+                }
+                Ok(())
+            }
+            msg_type::LOGOUT => {
+                self.send(FixMessage::new(msg_type::LOGOUT));
+                info!("FIX session {} logged out", self.counterparty);
+                return Flow::Disconnect;
+            }
+            msg_type::NEW_ORDER_SINGLE => self.new_order_single(&message).await,
+            msg_type::ORDER_CANCEL_REQUEST => self.cancel_request(&message).await,
+            msg_type::ORDER_CANCEL_REPLACE_REQUEST => self.cancel_replace_request(&message).await,
+            other if msg_type::is_admin(other) => Err(FixError::InvalidField {
+                tag: tags::MSG_TYPE,
+                value: other.to_string(),
+            }),
+            other => {
+                let reject = FixMessage::new(msg_type::BUSINESS_MESSAGE_REJECT)
+                    .with(tags::REF_SEQ_NUM, seq)
+                    .with(tags::REF_MSG_TYPE, other)
+                    // Unsupported message type
+                    .with(tags::BUSINESS_REJECT_REASON, 3)
+                    .with(tags::TEXT, format!("Unsupported MsgType {}", other));
+                self.send(reject);
+                Ok(())
+            }
+        };
+        if let Err(e) = result {
+            self.reject(&message, &e);
+        }
+        Flow::Continue
+    }
+
+    async fn drain_queue(&mut self) -> Flow {
+        loop {
+            let next = self.next_target_seq();
+            self.queued.retain(|seq, _| *seq >= next);
+            let Some(message) = self.queued.remove(&next) else {
+                break;
+            };
+            if self.process(message, next, true).await == Flow::Disconnect {
+                return Flow::Disconnect;
+            }
+        }
+        if self.queued.is_empty() {
+            self.awaiting_resend = false;
+        }
+        Flow::Continue
+    }
+
+    fn on_sequence_reset(&mut self, message: &FixMessage, seq: u64) {
+        let expected = self.next_target_seq();
+        match message.parse_required::<u64>(tags::NEW_SEQ_NO) {
+            Ok(new_seq) if new_seq >= expected => self.set_next_target_seq(new_seq),
+            Ok(new_seq) => {
+                warn!(
+                    "FIX session {} tried to reset MsgSeqNum back to {} (msg {})",
+                    self.counterparty, new_seq, seq
+                );
+                self.reject(
+                    message,
+                    &FixError::InvalidField {
+                        tag: tags::NEW_SEQ_NO,
+                        value: new_seq.to_string(),
+                    },
+                );
+            }
+            Err(e) => self.reject(message, &e),
+        }
+    }
+
+    /// Resends stored application messages, gap-filling over session messages.
+    fn on_resend_request(&mut self, message: &FixMessage, seq: u64) {
+        let range = message
+            .parse_required::<u64>(tags::BEGIN_SEQ_NO)
+            .and_then(|begin| Ok((begin, message.parse_required::<u64>(tags::END_SEQ_NO)?)));
+        let (begin, end) = match range {
+            Ok(range) => range,
+            Err(e) => return self.reject(message, &e),
+        };
+        let last_sent = self.store.lock().next_sender_seq() - 1;
+        let end = if end == 0 || end > last_sent {
+            last_sent
+        } else {
+            end
+        };
+        info!(
+            "FIX session {} requested resend of {}..={} (msg {})",
+            self.counterparty, begin, end, seq
+        );
+
+        let stored: BTreeMap<u64, FixMessage> = self
+            .store
+            .lock()
+            .messages(begin, end)
+            .into_iter()
+            .filter_map(|(seq, bytes)| Some((seq, FixMessage::decode(&bytes).ok()??.0)))
+            .collect();
+
+        let mut gap_start = None;
+        for seq in begin.max(1)..=end {
+            match stored.get(&seq) {
+                Some(original) if !msg_type::is_admin(original.msg_type()) => {
+                    if let Some(start) = gap_start.take() {
+                        self.gap_fill(start, seq);
+                    }
+                    let mut resent = original.clone();
+                    if let Some(sending_time) = original.get(tags::SENDING_TIME) {
+                        resent.set(tags::ORIG_SENDING_TIME, sending_time);
+                    }
+                    resent.set(tags::POSS_DUP_FLAG, "Y");
+                    resent.set(tags::SENDING_TIME, format_timestamp(Utc::now()));
+                    self.write(&resent);
+                }
+                _ => {
+                    gap_start.get_or_insert(seq);
+                }
+            }
+        }
+        if let Some(start) = gap_start {
+            self.gap_fill(start, end + 1);
+        }
+    }
+
+    fn gap_fill(&mut self, seq: u64, new_seq: u64) {
+        let mut message = FixMessage::new(msg_type::SEQUENCE_RESET)
+            .with(tags::GAP_FILL_FLAG, "Y")
+            .with(tags::NEW_SEQ_NO, new_seq);
+        self.stamp(&mut message, seq);
+        message.set(tags::POSS_DUP_FLAG, "Y");
+        self.write(&message);
+    }
+
+    fn request_resend(&mut self, from: u64) {
+        self.awaiting_resend = true;
+        self.send(
+            FixMessage::new(msg_type::RESEND_REQUEST)
+                .with(tags::BEGIN_SEQ_NO, from)
+                .with(tags::END_SEQ_NO, 0),
+        );
+    }
+
+    async fn new_order_single(&mut self, message: &FixMessage) -> Result<(), FixError> {
+        let cl_ord_id = message.require(tags::CL_ORD_ID)?.to_string();
+        let order = self.order_from(message)?;
+        let entry = OrderEntry::new(cl_ord_id, &order);
+
+        if self.cl_ord_ids.contains_key(&entry.cl_ord_id) {
+            let mut report = self.rejection(&entry, order, "Duplicate ClOrdID");
+            // Duplicate order
+            report.set(tags::ORD_REJ_REASON, 6);
+            self.send(report);
+            return Ok(());
+        }
+        if let Err(e) = self.submit(order.clone(), entry.clone()).await {
+            let report = self.rejection(&entry, order, &e.to_string());
+            self.send(report);
+        }
+        Ok(())
+    }
+
+    async fn cancel_request(&mut self, message: &FixMessage) -> Result<(), FixError> {
+        let cl_ord_id = message.require(tags::CL_ORD_ID)?.to_string();
+        let orig_cl_ord_id = message.require(tags::ORIG_CL_ORD_ID)?.to_string();
+
+        let Some(&id) = self.cl_ord_ids.get(&orig_cl_ord_id) else {
+            self.cancel_reject(&cl_ord_id, &orig_cl_ord_id, None, "1", "1", "Unknown order");
+            return Ok(());
+        };
+        let entry = self.orders.get_mut(&id).unwrap();
+        entry.cancel_cl_ord_id = Some(cl_ord_id.clone());
+        let symbol = entry.symbol.clone();
+
+        if let Err(e) = self.engine.cancel_order(id, &symbol).await {
+            self.orders.get_mut(&id).unwrap().cancel_cl_ord_id = None;
+            // Too late to cancel
+            self.cancel_reject(
+                &cl_ord_id,
+                &orig_cl_ord_id,
+                Some(id),
+                "1",
+                "0",
+                &e.to_string(),
+            );
+        }
+        Ok(())
+    }
+
+    /// Replaces an order by cancelling it and submitting the amended order.
+    ///
+    /// The replacement is a new order on the engine and loses time priority.
+    /// `OrderQty` is the total quantity, including whatever already filled.
+    async fn cancel_replace_request(&mut self, message: &FixMessage) -> Result<(), FixError> {
+        let cl_ord_id = message.require(tags::CL_ORD_ID)?.to_string();
+        let orig_cl_ord_id = message.require(tags::ORIG_CL_ORD_ID)?.to_string();
+        let replacement = self.order_from(message)?;
+
+        let Some(&old_id) = self.cl_ord_ids.get(&orig_cl_ord_id) else {
+            self.cancel_reject(&cl_ord_id, &orig_cl_ord_id, None, "2", "1", "Unknown order");
+            return Ok(());
+        };
+        if self.cl_ord_ids.contains_key(&cl_ord_id) {
+            // Duplicate ClOrdID received
+            self.cancel_reject(
+                &cl_ord_id,
+                &orig_cl_ord_id,
+                Some(old_id),
+                "2",
+                "6",
+                "Duplicate ClOrdID",
+            );
+            return Ok(());
+        }
+        let old_entry = self.orders.get_mut(&old_id).unwrap();
+        if replacement.symbol != old_entry.symbol || replacement.side != old_entry.side {
+            self.cancel_reject(
+                &cl_ord_id,
+                &orig_cl_ord_id,
+                Some(old_id),
+                "2",
+                "99",
+                "Symbol and side cannot be changed",
+            );
+            return Ok(());
+        }
+
+        old_entry.replaced_by = Some(replacement.id);
+        let symbol = old_entry.symbol.clone();
+        let old = match self.engine.cancel_order(old_id, &symbol).await {
+            Ok(old) => old,
+            Err(e) => {
+                self.orders.get_mut(&old_id).unwrap().replaced_by = None;
+                self.cancel_reject(
+                    &cl_ord_id,
+                    &orig_cl_ord_id,
+                    Some(old_id),
+                    "2",
+                    "0",
+                    &e.to_string(),
+                );
+                return Ok(());
+            }
+        };
+
+        let old_entry = &self.orders[&old_id];
+        let entry = OrderEntry {
+            orig_cl_ord_id: Some(orig_cl_ord_id.clone()),
+            base_filled: old_entry.base_filled + old.filled_quantity,
+            notional: old_entry.notional,
+            replacing: true,
+            ..OrderEntry::new(cl_ord_id, &replacement)
+        };
+        self.cl_ord_ids.remove(&orig_cl_ord_id);
+
+        let leaves = replacement.quantity - entry.base_filled;
+        if leaves <= Decimal::ZERO {
+            let cancelled = ExecutionReport::new(&old, ExecType::Cancelled, self.engine.now());
+            let mut report = execution_report(&entry, &cancelled);
+            report.set(tags::TEXT, "OrderQty is not above the filled quantity");
+            self.send(report);
+            return Ok(());
+        }
+        let replacement = Order {
+            quantity: leaves,
+            ..replacement
+        };
+        if let Err(e) = self.submit(replacement, entry.clone()).await {
+            let cancelled = ExecutionReport::new(&old, ExecType::Cancelled, self.engine.now());
+            let mut report = execution_report(&entry, &cancelled);
+            report.set(tags::TEXT, format!("Replacement rejected: {}", e));
+            self.send(report);
+        }
+        Ok(())
+    }
+
+    /// Registers `entry` before submitting so the engine's reports find it.
+    async fn submit(&mut self, order: Order, entry: OrderEntry) -> anyhow::Result<()> {
+        let id = order.id;
+        self.cl_ord_ids.insert(entry.cl_ord_id.clone(), id);
+        self.orders.insert(id, entry);
+        self.owned.insert(id);
+        if let Err(e) = self.engine.submit_order(order).await {
+            self.forget(id);
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    fn forget(&mut self, id: Uuid) {
+        if let Some(entry) = self.orders.remove(&id) {
+            if self.cl_ord_ids.get(&entry.cl_ord_id) == Some(&id) {
+                self.cl_ord_ids.remove(&entry.cl_ord_id);
+            }
+        }
+        self.owned.remove(&id);
+    }
+
+    /// Builds an engine order from a NewOrderSingle or OrderCancelReplaceRequest.
+    ///
+    /// Day orders are accepted as good-till-cancel; the engine has no trading
+    /// session to end them.
+    fn order_from(&self, message: &FixMessage) -> Result<Order, FixError> {
+        let symbol = message.require(tags::SYMBOL)?.to_string();
+        let side = match message.require(tags::SIDE)? {
+            "1" => Side::Buy,
+            "2" => Side::Sell,
+            other => return Err(invalid(tags::SIDE, other)),
+        };
+        let quantity: Decimal = message.parse_required(tags::ORDER_QTY)?;
+        let order_type = match message.require(tags::ORD_TYPE)? {
+            "1" => OrderType::Market,
+            "2" => OrderType::Limit,
+            "3" => OrderType::StopMarket,
+            "4" => OrderType::StopLimit,
+            other => return Err(invalid(tags::ORD_TYPE, other)),
+        };
+        let price = match order_type {
+            OrderType::Limit | OrderType::StopLimit => message.parse_required(tags::PRICE)?,
+            _ => Decimal::ZERO,
+        };
+        let stop_price = match order_type {
+            OrderType::StopMarket | OrderType::StopLimit => {
+                Some(message.parse_required(tags::STOP_PX)?)
+            }
+            _ => None,
+        };
+        let expires_at = match message.get(tags::TIME_IN_FORCE) {
+            None | Some("0") | Some("1") => None,
+            Some("6") => Some(
+                message
+                    .timestamp(tags::EXPIRE_TIME)?
+                    .ok_or(FixError::MissingField(tags::EXPIRE_TIME))?,
+            ),
+            Some(other) => return Err(invalid(tags::TIME_IN_FORCE, other)),
+        };
+
+        Ok(Order {
+            client_id: Some(self.counterparty.clone()),
+            stop_price,
+            expires_at,
+            ..Order::new(symbol, side, order_type, price, quantity)
+        })
+    }
+
+    fn rejection(&self, entry: &OrderEntry, order: Order, text: &str) -> FixMessage {
+        let order = Order {
+            status: OrderStatus::Rejected,
+            ..order
+        };
+        let rejected = ExecutionReport::new(&order, ExecType::Rejected, self.engine.now());
+        let mut report = execution_report(entry, &rejected);
+        report.set(tags::TEXT, text);
+        report
+    }
+
+    fn cancel_reject(
+        &mut self,
+        cl_ord_id: &str,
+        orig_cl_ord_id: &str,
+        order_id: Option<Uuid>,
+        response_to: &str,
+        reason: &str,
+        text: &str,
+    ) {
+        let status = order_id
+            .and_then(|id| self.orders.get(&id))
+            .map_or("8", |entry| ord_status(entry.status));
+        let order_id = order_id.map_or_else(|| "NONE".to_string(), |id| id.to_string());
+        self.send(
+            FixMessage::new(msg_type::ORDER_CANCEL_REJECT)
+                .with(tags::ORDER_ID, order_id)
+                .with(tags::CL_ORD_ID, cl_ord_id)
+                .with(tags::ORIG_CL_ORD_ID, orig_cl_ord_id)
+                .with(tags::ORD_STATUS, status)
+                .with(tags::CXL_REJ_RESPONSE_TO, response_to)
+                .with(tags::CXL_REJ_REASON, reason)
+                .with(tags::TEXT, text),
+        );
+    }
+
+    /// Session-level Reject of a message that could not be processed.
+    fn reject(&mut self, message: &FixMessage, error: &FixError) {
+        let (tag, reason) = match error {
+            // Required tag missing
+            FixError::MissingField(tag) => (Some(*tag), 1),
+            // Value is incorrect for this tag
+            FixError::InvalidField { tag, .. } => (Some(*tag), 5),
+            // Other
+            _ => (None, 99),
+        };
+        let mut reject = FixMessage::new(msg_type::REJECT)
+            .with(
+                tags::REF_SEQ_NUM,
+                message.get(tags::MSG_SEQ_NUM).unwrap_or("0"),
+            )
+            .with(tags::REF_MSG_TYPE, message.msg_type())
+            .with(tags::SESSION_REJECT_REASON, reason)
+            .with(tags::TEXT, error);
+        if let Some(tag) = tag {
+            reject.set(tags::REF_TAG_ID, tag);
+        }
+        self.send(reject);
+    }
+
+    fn logout(&mut self, text: &str) {
+        warn!("Logging out FIX session {}: {}", self.counterparty, text);
+        self.send(FixMessage::new(msg_type::LOGOUT).with(tags::TEXT, text));
+    }
+
+    fn next_target_seq(&self) -> u64 {
+        self.store.lock().next_target_seq()
+    }
+
+    fn set_next_target_seq(&self, seq: u64) {
+        if let Err(e) = self.store.lock().set_next_target_seq(seq) {
+            warn!(
+                "Failed to persist FIX sequence for {}: {}",
+                self.counterparty, e
+            );
+        }
+    }
+
+    fn stamp(&self, message: &mut FixMessage, seq: u64) {
+        message.set(tags::SENDER_COMP_ID, &self.comp_id);
+        message.set(tags::TARGET_COMP_ID, &self.counterparty);
+        message.set(tags::MSG_SEQ_NUM, seq);
+        message.set(tags::SENDING_TIME, format_timestamp(Utc::now()));
+    }
+
+    /// Numbers, stores and queues a new message.
+    fn send(&mut self, mut message: FixMessage) {
+        let mut store = self.store.lock();
+        let seq = store.next_sender_seq();
+        self.stamp(&mut message, seq);
+        let bytes = message.encode();
+        if let Err(e) = store.save(seq, &bytes) {
+            warn!(
+                "Failed to persist FIX message for {}: {}",
+                self.counterparty, e
+            );
+        }
+        drop(store);
+        self.outgoing.extend_from_slice(&bytes);
+        self.last_sent = Instant::now();
+    }
+
+    /// Queues an already numbered message, as for resends.
+    fn write(&mut self, message: &FixMessage) {
+        self.outgoing.extend_from_slice(&message.encode());
+        self.last_sent = Instant::now();
+    }
+}
+
+fn invalid(tag: u32, value: &str) -> FixError {
+    FixError::InvalidField {
+        tag,
+        value: value.to_string(),
+    }
+}
+
+fn ord_status(status: OrderStatus) -> &'static str {
+    match status {
+        OrderStatus::Pending | OrderStatus::Open => "0",
+        OrderStatus::PartiallyFilled => "1",
+        OrderStatus::Filled => "2",
+        OrderStatus::Cancelled => "4",
+        OrderStatus::Rejected => "8",
+        OrderStatus::Expired => "C",
+    }
+}
+
+fn exec_type(exec_type: ExecType) -> &'static str {
+    match exec_type {
+        ExecType::New => "0",
+        ExecType::Cancelled => "4",
+        ExecType::Rejected => "8",
+        ExecType::Expired => "C",
+        ExecType::Trade => "F",
+        ExecType::Triggered => "L",
+    }
+}
+
+fn ord_type(order_type: OrderType) -> &'static str {
+    match order_type {
+        OrderType::Market => "1",
+        OrderType::Limit => "2",
+        OrderType::StopMarket => "3",
+        OrderType::StopLimit => "4",
+    }
+}
+
+/// ExecutionReport for `report`, with quantities that include fills of
+/// the orders `entry` replaced.
+fn execution_report(entry: &OrderEntry, report: &ExecutionReport) -> FixMessage {
+    let cum_qty = entry.base_filled + report.filled_quantity;
+    let avg_px = if cum_qty.is_zero() {
+        Decimal::ZERO
+    } else {
+        entry.notional / cum_qty
+    };
+
+    let mut message = FixMessage::new(msg_type::EXECUTION_REPORT)
+        .with(tags::ORDER_ID, report.order_id)
+        .with(tags::CL_ORD_ID, &entry.cl_ord_id);
+    if let Some(orig_cl_ord_id) = &entry.orig_cl_ord_id {
+        message = message.with(tags::ORIG_CL_ORD_ID, orig_cl_ord_id);
+    }
+    message = message
+        .with(tags::EXEC_ID, Uuid::new_v4())
+        .with(tags::EXEC_TYPE, exec_type(report.exec_type))
+        .with(tags::ORD_STATUS, ord_status(report.status))
+        .with(tags::SYMBOL, &report.symbol)
+        .with(tags::SIDE, if report.side == Side::Buy { "1" } else { "2" })
+        .with(tags::ORD_TYPE, ord_type(report.order_type))
+        .with(
+            tags::ORDER_QTY,
+            (entry.base_filled + report.quantity).normalize(),
+        );
+    if matches!(report.order_type, OrderType::Limit | OrderType::StopLimit) {
+        message = message.with(tags::PRICE, report.price.normalize());
+    }
+    if let (Some(price), Some(quantity)) = (report.last_price, report.last_quantity) {
+        message = message
+            .with(tags::LAST_PX, price.normalize())
+            .with(tags::LAST_QTY, quantity.normalize());
+    }
+    message
+        .with(tags::LEAVES_QTY, report.leaves_quantity().normalize())
+        .with(tags::CUM_QTY, cum_qty.normalize())
+        .with(tags::AVG_PX, avg_px.round_dp(8).normalize())
+        .with(tags::TRANSACT_TIME, format_timestamp(report.timestamp))
+}
@@ -0,0 +1,208 @@
+//! Sequence numbers and sent messages of a FIX session.
+//!
+//! A store outlives the TCP connection: when a counterparty logs on again the
+//! session continues from the stored sequence numbers and can resend anything
+//! it sent before. [`FileStore`] also survives restarts of the gateway.
+
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+pub trait MessageStore: Send {
+    /// Sequence number of the next message we send.
+    fn next_sender_seq(&self) -> u64;
+    /// Sequence number we expect on the next message received.
+    fn next_target_seq(&self) -> u64;
+    fn set_next_target_seq(&mut self, seq: u64) -> io::Result<()>;
+    /// Records a sent message and advances the sender sequence number past it.
+    fn save(&mut self, seq: u64, message: &[u8]) -> io::Result<()>;
+    /// Sent messages with sequence numbers in `begin..=end`, in order.
+    fn messages(&self, begin: u64, end: u64) -> Vec<(u64, Vec<u8>)>;
+    /// Starts both sequences again at 1 and forgets every sent message.
+    fn reset(&mut self) -> io::Result<()>;
+}
+
+#[derive(Debug, Clone)]
+pub struct MemoryStore {
+    next_sender_seq: u64,
+    next_target_seq: u64,
+    messages: BTreeMap<u64, Vec<u8>>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self {
+            next_sender_seq: 1,
+            next_target_seq: 1,
+            messages: BTreeMap::new(),
+        }
+    }
+}
+
+impl Default for MemoryStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MessageStore for MemoryStore {
+    fn next_sender_seq(&self) -> u64 {
+        self.next_sender_seq
+    }
+
+    fn next_target_seq(&self) -> u64 {
+        self.next_target_seq
+    }
+
+    fn set_next_target_seq(&mut self, seq: u64) -> io::Result<()> {
+        self.next_target_seq = seq;
+        Ok(())
+    }
+
+    fn save(&mut self, seq: u64, message: &[u8]) -> io::Result<()> {
+        self.messages.insert(seq, message.to_vec());
+        self.next_sender_seq = self.next_sender_seq.max(seq + 1);
+        Ok(())
+    }
+
+    fn messages(&self, begin: u64, end: u64) -> Vec<(u64, Vec<u8>)> {
+        if begin > end {
+            return Vec::new();
+        }
+        self.messages
+            .range(begin..=end)
+            .map(|(seq, message)| (*seq, message.clone()))
+            .collect()
+    }
+
+    fn reset(&mut self) -> io::Result<()> {
+        *self = Self::new();
+        Ok(())
+    }
+}
+
+/// A [`MemoryStore`] mirrored to two files per session in a directory:
+/// `<session>.seqnums` holds both sequence numbers and `<session>.messages`
+/// every sent message, one per line.
+pub struct FileStore {
+    memory: MemoryStore,
+    seqnums: PathBuf,
+    messages: PathBuf,
+    log: File,
+}
+
+impl FileStore {
+    /// Opens the store for `session` in `dir`, loading anything saved earlier.
+    pub fn open(dir: &Path, session: &str) -> io::Result<Self> {
+        fs::create_dir_all(dir)?;
+        let seqnums = dir.join(format!("{}.seqnums", session));
+        let messages = dir.join(format!("{}.messages", session));
+
+        let mut memory = MemoryStore::new();
+        if let Ok(text) = fs::read_to_string(&seqnums) {
+            let mut numbers = text.split_whitespace().map(str::parse::<u64>);
+            if let (Some(Ok(sender)), Some(Ok(target))) = (numbers.next(), numbers.next()) {
+                memory.next_sender_seq = sender;
+                memory.next_target_seq = target;
+            }
+        }
+        if let Ok(file) = File::open(&messages) {
+            for line in BufReader::new(file).split(b'\n') {
+                let line = line?;
+                let Some(split) = line.iter().position(|b| *b == b':') else {
+                    continue;
+                };
+                let seq = std::str::from_utf8(&line[..split])
+                    .ok()
+                    .and_then(|seq| seq.parse().ok());
+                if let Some(seq) = seq {
+                    memory.messages.insert(seq, line[split + 1..].to_vec());
+                }
+            }
+        }
+
+        let log = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&messages)?;
+        Ok(Self {
+            memory,
+            seqnums,
+            messages,
+            log,
+        })
+    }
+
+    fn write_seqnums(&self) -> io::Result<()> {
+        fs::write(
+            &self.seqnums,
+            format!(
+                "{} {}\n",
+                self.memory.next_sender_seq, self.memory.next_target_seq
+            ),
+        )
+    }
+}
+
+impl MessageStore for FileStore {
+    fn next_sender_seq(&self) -> u64 {
+        self.memory.next_sender_seq()
+    }
+
+    fn next_target_seq(&self) -> u64 {
+        self.memory.next_target_seq()
+    }
+
+    fn set_next_target_seq(&mut self, seq: u64) -> io::Result<()> {
+        self.memory.set_next_target_seq(seq)?;
+        self.write_seqnums()
+    }
+
+    fn save(&mut self, seq: u64, message: &[u8]) -> io::Result<()> {
+        self.memory.save(seq, message)?;
+        let mut line = format!("{}:", seq).into_bytes();
+        line.extend_from_slice(message);
+        line.push(b'\n');
+        self.log.write_all(&line)?;
+        self.write_seqnums()
+    }
+
+    fn messages(&self, begin: u64, end: u64) -> Vec<(u64, Vec<u8>)> {
+        self.memory.messages(begin, end)
+    }
+
+    fn reset(&mut self) -> io::Result<()> {
+        self.memory.reset()?;
+        self.log = File::create(&self.messages)?;
+        self.write_seqnums()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_file_store_survives_reopen() {
+        let dir = std::env::temp_dir().join(format!("quantumflow-fix-{}", uuid::Uuid::new_v4()));
+        {
+            let mut store = FileStore::open(&dir, "QF-OMS").unwrap();
+            store.save(1, b"8=FIX.4.4\x0135=A\x01").unwrap();
+            store.save(2, b"8=FIX.4.4\x0135=8\x01").unwrap();
+            store.set_next_target_seq(5).unwrap();
+        }
+
+        let mut store = FileStore::open(&dir, "QF-OMS").unwrap();
+        assert_eq!(store.next_sender_seq(), 3);
+        assert_eq!(store.next_target_seq(), 5);
+        let messages = store.messages(2, u64::MAX);
+        assert_eq!(messages, vec![(2, b"8=FIX.4.4\x0135=8\x01".to_vec())]);
+
+        store.reset().unwrap();
+        let store = FileStore::open(&dir, "QF-OMS").unwrap();
+        assert_eq!(store.next_sender_seq(), 1);
+        assert!(store.messages(1, u64::MAX).is_empty());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,63 @@
+//! Level-by-level changes between two views of one side of a book, shared by
+//! the WebSocket book channel and the market data feed.
+
+use crate::utils::types::OrderBookLevel;
+use rust_decimal::Decimal;
+
+/// Levels of `new` that differ from `old`, plus a zero-quantity level for
+/// every price that disappeared.
+pub(crate) fn diff_levels(old: &[OrderBookLevel], new: &[OrderBookLevel]) -> Vec<OrderBookLevel> {
+    let mut changes: Vec<OrderBookLevel> = new
+        .iter()
+        .filter(|level| {
+            !old.iter().any(|o| {
+                o.price == level.price
+                    && o.quantity == level.quantity
+                    && o.order_count == level.order_count
+            })
+        })
+        .cloned()
+        .collect();
+    changes.extend(
+        old.iter()
+            .filter(|o| !new.iter().any(|level| level.price == o.price))
+            .map(|o| OrderBookLevel {
+                price: o.price,
+                quantity: Decimal::ZERO,
+                order_count: 0,
+            }),
+    );
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn level(price: i64, quantity: i64, order_count: usize) -> OrderBookLevel {
+        OrderBookLevel {
+            price: Decimal::from(price),
+            quantity: Decimal::from(quantity),
+            order_count,
+        }
+    }
+
+    #[test]
+    fn test_diff_levels_reports_changes_and_removals() {
+        let old = vec![level(100, 5, 2), level(99, 3, 1), level(98, 1, 1)];
+        let new = vec![level(101, 2, 1), level(100, 5, 2), level(99, 4, 2)];
+
+        let changes = diff_levels(&old, &new);
+        let summary: Vec<(Decimal, Decimal)> =
+            changes.iter().map(|l| (l.price, l.quantity)).collect();
+        assert_eq!(
+            summary,
+            vec![
+                (Decimal::from(101), Decimal::from(2)),
+                (Decimal::from(99), Decimal::from(4)),
+                (Decimal::from(98), Decimal::ZERO),
+            ]
+        );
+        assert!(diff_levels(&new, &new).is_empty());
+    }
+}
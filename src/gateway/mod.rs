@@ -0,0 +1,9 @@
+//! Network gateways that give external clients access to the matching engine.
+
+pub mod binary;
+pub mod feed;
+pub mod fix;
+pub(crate) mod levels;
+pub mod request;
+pub mod rest;
+pub mod websocket;
@@ -0,0 +1,47 @@
+//! JSON order-entry requests shared by the gateways.
+
+use crate::utils::types::{Order, OrderType, PegInstruction, Side, TrailingStop};
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+/// A new order as sent by a client; the engine assigns id, status and time.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OrderRequest {
+    pub symbol: String,
+    pub side: Side,
+    pub order_type: OrderType,
+    /// Limit price; ignored for market and stop-market orders.
+    #[serde(default)]
+    pub price: Decimal,
+    pub quantity: Decimal,
+    #[serde(default)]
+    pub client_id: Option<String>,
+    #[serde(default)]
+    pub stop_price: Option<Decimal>,
+    #[serde(default)]
+    pub trailing: Option<TrailingStop>,
+    #[serde(default)]
+    pub peg: Option<PegInstruction>,
+    #[serde(default)]
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+impl OrderRequest {
+    pub fn into_order(self) -> Order {
+        Order {
+            client_id: self.client_id,
+            stop_price: self.stop_price,
+            trailing: self.trailing,
+            peg: self.peg,
+            expires_at: self.expires_at,
+            ..Order::new(
+                self.symbol,
+                self.side,
+                self.order_type,
+                self.price,
+                self.quantity,
+            )
+        }
+    }
+}
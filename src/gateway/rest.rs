@@ -0,0 +1,376 @@
+//! HTTP/JSON API for orders, books, positions and risk.
+//!
+//! | Method   | Path              |                                                  |
+//! |----------|-------------------|--------------------------------------------------|
+//! | `POST`   | `/orders`         | Submit an [`OrderRequest`]                       |
+//! | `GET`    | `/orders`         | Orders submitted through the API, `?symbol=`     |
+//! | `GET`    | `/orders/{id}`    | One order                                        |
+//! | `DELETE` | `/orders/{id}`    | Cancel an order                                  |
+//! | `GET`    | `/books/{symbol}` | Depth snapshot, `?depth=` limits the levels      |
+//! | `GET`    | `/positions`      | Positions held by the risk manager               |
+//! | `GET`    | `/risk`           | Daily PnL, exposure and circuit breaker status   |
+//!
+//! Orders pass the [`RiskManager`] before they reach the engine, and their
+//! fills update its positions before the request that caused them returns.
+//! Errors are returned as `{"error": "..."}`.
+
+use crate::engine::bus::{BackpressurePolicy, RecvError};
+use crate::engine::matching::MatchingEngine;
+use crate::gateway::request::OrderRequest;
+use crate::risk::manager::RiskManager;
+use crate::utils::types::{ExecutionReport, Order, OrderStatus};
+use dashmap::DashMap;
+use http_body_util::{BodyExt, Full};
+use hyper::body::{Bytes, Incoming};
+use hyper::header::CONTENT_TYPE;
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper::{Method, Request, Response, StatusCode};
+use hyper_util::rt::TokioIo;
+use parking_lot::Mutex;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::convert::Infallible;
+use std::sync::Arc;
+use thiserror::Error;
+use tokio::net::TcpListener;
+use tokio::sync::{mpsc, oneshot};
+use tracing::{info, warn};
+use uuid::Uuid;
+
+/// Execution reports queued for order tracking; the publisher waits rather
+/// than letting fills go missing from positions.
+const REPORT_CAPACITY: usize = 4096;
+
+#[derive(Debug, Error)]
+enum ApiError {
+    #[error("{0}")]
+    BadRequest(String),
+    #[error("{0}")]
+    NotFound(String),
+    #[error("method not allowed")]
+    MethodNotAllowed,
+    #[error("{0}")]
+    RiskRejected(String),
+    #[error("{0}")]
+    Rejected(String),
+    #[error("{0}")]
+    Internal(String),
+}
+
+impl ApiError {
+    fn status(&self) -> StatusCode {
+        match self {
+            ApiError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            ApiError::NotFound(_) => StatusCode::NOT_FOUND,
+            ApiError::MethodNotAllowed => StatusCode::METHOD_NOT_ALLOWED,
+            ApiError::RiskRejected(_) => StatusCode::FORBIDDEN,
+            ApiError::Rejected(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            ApiError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+/// Response body of `GET /risk`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RiskStatus {
+    pub daily_pnl: Decimal,
+    pub total_exposure: Decimal,
+    pub circuit_breaker: bool,
+}
+
+pub struct RestServer {
+    engine: Arc<MatchingEngine>,
+    risk: Arc<RiskManager>,
+    /// Orders submitted through the API, kept current from execution reports.
+    orders: DashMap<Uuid, Order>,
+    /// Asks the report tracker to apply everything queued so far and answer
+    /// once it has. Set while serving.
+    flushes: Mutex<Option<mpsc::UnboundedSender<oneshot::Sender<()>>>>,
+}
+
+impl RestServer {
+    pub fn new(engine: Arc<MatchingEngine>, risk: Arc<RiskManager>) -> Self {
+        Self {
+            engine,
+            risk,
+            orders: DashMap::new(),
+            flushes: Mutex::new(None),
+        }
+    }
+
+    /// Binds `addr` and serves HTTP until the listener fails.
+    pub async fn run(self: Arc<Self>, addr: &str) -> anyhow::Result<()> {
+        let listener = TcpListener::bind(addr).await?;
+        info!("REST API listening on {}", addr);
+        self.serve(listener).await
+    }
+
+    /// Serves HTTP on an already bound listener.
+    pub async fn serve(self: Arc<Self>, listener: TcpListener) -> anyhow::Result<()> {
+        let mut reports = self
+            .engine
+            .subscribe_executions(REPORT_CAPACITY, BackpressurePolicy::Block);
+        let (flush_tx, mut flushes) = mpsc::unbounded_channel::<oneshot::Sender<()>>();
+        *self.flushes.lock() = Some(flush_tx);
+        let tracker = self.clone();
+        tokio::spawn(async move {
+            loop {
+                tokio::select! {
+                    report = reports.recv() => match report {
+                        Ok(report) => tracker.track(&report),
+                        Err(RecvError::Lagged { dropped }) => {
+                            warn!("REST order tracking missed {} execution reports", dropped);
+                            reports.resync();
+                        }
+                        Err(RecvError::Closed) => break,
+                    },
+                    Some(done) = flushes.recv() => {
+                        while let Ok(Some(report)) = reports.try_recv() {
+                            tracker.track(&report);
+                        }
+                        let _ = done.send(());
+                    }
+                }
+            }
+        });
+
+        loop {
+            let (stream, peer) = listener.accept().await?;
+            let server = self.clone();
+            tokio::spawn(async move {
+                let service = service_fn(move |request| {
+                    let server = server.clone();
+                    async move { Ok::<_, Infallible>(server.respond(request).await) }
+                });
+                if let Err(e) = http1::Builder::new()
+                    .serve_connection(TokioIo::new(stream), service)
+                    .await
+                {
+                    warn!("HTTP connection from {} failed: {}", peer, e);
+                }
+            });
+        }
+    }
+
+    /// Applies a report to a tracked order and its fill to the risk positions.
+    fn track(&self, report: &ExecutionReport) {
+        let Some(mut order) = self.orders.get_mut(&report.order_id) else {
+            return;
+        };
+        update(&mut order, report.status, report.filled_quantity);
+        self.risk.on_execution(report);
+    }
+
+    /// Waits until every report published so far has been tracked, so a
+    /// response is never ahead of `/orders` and `/positions`.
+    async fn catch_up(&self) {
+        let Some(flushes) = self.flushes.lock().clone() else {
+            return;
+        };
+        let (done_tx, done_rx) = oneshot::channel();
+        if flushes.send(done_tx).is_ok() {
+            let _ = done_rx.await;
+        }
+    }
+
+    async fn respond(&self, request: Request<Incoming>) -> Response<Full<Bytes>> {
+        let (status, body) = match self.route(request).await {
+            Ok(response) => response,
+            Err(e) => (e.status(), json!({ "error": e.to_string() })),
+        };
+        Response::builder()
+            .status(status)
+            .header(CONTENT_TYPE, "application/json")
+            .body(Full::new(Bytes::from(body.to_string())))
+            .expect("static response parts are valid")
+    }
+
+    async fn route(&self, request: Request<Incoming>) -> Result<(StatusCode, Value), ApiError> {
+        let method = request.method().clone();
+        let path = request.uri().path().to_string();
+        let query: Vec<(String, String)> = request
+            .uri()
+            .query()
+            .map(|query| {
+                url::form_urlencoded::parse(query.as_bytes())
+                    .into_owned()
+                    .collect()
+            })
+            .unwrap_or_default();
+        let param = |name: &str| {
+            query
+                .iter()
+                .find(|(key, _)| key == name)
+                .map(|(_, v)| v.clone())
+        };
+        let segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+
+        match (&method, segments.as_slice()) {
+            (&Method::POST, ["orders"]) => {
+                let body = request
+                    .into_body()
+                    .collect()
+                    .await
+                    .map_err(|e| ApiError::BadRequest(e.to_string()))?
+                    .to_bytes();
+                let order: OrderRequest = serde_json::from_slice(&body)
+                    .map_err(|e| ApiError::BadRequest(format!("invalid order: {}", e)))?;
+                let order = self.submit(order.into_order()).await?;
+                Ok((StatusCode::CREATED, to_json(&order)?))
+            }
+            (&Method::GET, ["orders"]) => {
+                let symbol = param("symbol");
+                let mut orders: Vec<Order> = self
+                    .orders
+                    .iter()
+                    .filter(|o| symbol.as_ref().is_none_or(|symbol| &o.symbol == symbol))
+                    .map(|o| o.clone())
+                    .collect();
+                orders.sort_by_key(|o| o.timestamp);
+                Ok((StatusCode::OK, to_json(&orders)?))
+            }
+            (&Method::GET, ["orders", id]) => {
+                let id = parse_id(id)?;
+                let order = self
+                    .orders
+                    .get(&id)
+                    .map(|o| o.clone())
+                    .ok_or_else(|| ApiError::NotFound(format!("order {} not found", id)))?;
+                Ok((StatusCode::OK, to_json(&order)?))
+            }
+            (&Method::DELETE, ["orders", id]) => {
+                let id = parse_id(id)?;
+                let symbol = self
+                    .orders
+                    .get(&id)
+                    .map(|o| o.symbol.clone())
+                    .ok_or_else(|| ApiError::NotFound(format!("order {} not found", id)))?;
+                let order = self
+                    .engine
+                    .cancel_order(id, &symbol)
+                    .await
+                    .map_err(|e| ApiError::Rejected(e.to_string()))?;
+                if let Some(mut tracked) = self.orders.get_mut(&id) {
+                    update(&mut tracked, order.status, order.filled_quantity);
+                }
+                self.catch_up().await;
+                Ok((StatusCode::OK, to_json(&order)?))
+            }
+            (&Method::GET, ["books", symbol]) => {
+                let mut snapshot = self
+                    .engine
+                    .get_orderbook_snapshot(symbol)
+                    .ok_or_else(|| ApiError::NotFound(format!("no order book for {}", symbol)))?;
+                if let Some(depth) = param("depth") {
+                    let depth: usize = depth
+                        .parse()
+                        .map_err(|_| ApiError::BadRequest(format!("invalid depth {:?}", depth)))?;
+                    snapshot.bids.truncate(depth);
+                    snapshot.asks.truncate(depth);
+                }
+                Ok((StatusCode::OK, to_json(&snapshot)?))
+            }
+            (&Method::GET, ["positions"]) => {
+                Ok((StatusCode::OK, to_json(&self.risk.get_all_positions())?))
+            }
+            (&Method::GET, ["risk"]) => {
+                let status = RiskStatus {
+                    daily_pnl: self.risk.get_daily_pnl(),
+                    total_exposure: self.risk.get_total_exposure(),
+                    circuit_breaker: self.risk.check_circuit_breaker(),
+                };
+                Ok((StatusCode::OK, to_json(&status)?))
+            }
+            (_, ["orders"] | ["orders", _] | ["books", _] | ["positions"] | ["risk"]) => {
+                Err(ApiError::MethodNotAllowed)
+            }
+            _ => Err(ApiError::NotFound(format!("no route for {}", path))),
+        }
+    }
+
+    async fn submit(&self, order: Order) -> Result<Order, ApiError> {
+        self.risk
+            .check_order(&order)
+            .map_err(ApiError::RiskRejected)?;
+
+        // Track before submitting so reports published during matching apply
+        let id = order.id;
+        self.orders.insert(id, order.clone());
+        match self.engine.submit_order(order).await {
+            Ok(order) => {
+                if let Some(mut tracked) = self.orders.get_mut(&id) {
+                    update(&mut tracked, order.status, order.filled_quantity);
+                }
+                self.catch_up().await;
+                Ok(order)
+            }
+            Err(e) => {
+                self.orders.remove(&id);
+                Err(ApiError::Rejected(e.to_string()))
+            }
+        }
+    }
+}
+
+/// Moves a tracked order forward, ignoring updates older than what it holds.
+fn update(order: &mut Order, status: OrderStatus, filled_quantity: Decimal) {
+    let terminal = matches!(
+        order.status,
+        OrderStatus::Filled | OrderStatus::Cancelled | OrderStatus::Rejected | OrderStatus::Expired
+    );
+    if filled_quantity > order.filled_quantity
+        || (filled_quantity == order.filled_quantity && !terminal)
+    {
+        order.status = status;
+        order.filled_quantity = filled_quantity;
+    }
+}
+
+fn parse_id(id: &str) -> Result<Uuid, ApiError> {
+    id.parse()
+        .map_err(|_| ApiError::BadRequest(format!("invalid order id {:?}", id)))
+}
+
+fn to_json<T: Serialize>(value: &T) -> Result<Value, ApiError> {
+    serde_json::to_value(value).map_err(|e| ApiError::Internal(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::types::{OrderType, Side};
+
+    #[test]
+    fn test_update_ignores_stale_states() {
+        let mut order = Order::new(
+            "BTCUSD".to_string(),
+            Side::Buy,
+            OrderType::Limit,
+            Decimal::from(100),
+            Decimal::from(2),
+        );
+        update(&mut order, OrderStatus::Open, Decimal::ZERO);
+        update(&mut order, OrderStatus::Filled, Decimal::from(2));
+        assert_eq!(order.status, OrderStatus::Filled);
+
+        // The order returned from an earlier submission arrives late
+        update(&mut order, OrderStatus::Open, Decimal::ZERO);
+        assert_eq!(order.status, OrderStatus::Filled);
+        assert_eq!(order.filled_quantity, Decimal::from(2));
+    }
+
+    #[test]
+    fn test_error_statuses() {
+        assert_eq!(
+            parse_id("abc").unwrap_err().status(),
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(
+            ApiError::RiskRejected("too large".to_string()).status(),
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(ApiError::MethodNotAllowed.to_string(), "method not allowed");
+    }
+}
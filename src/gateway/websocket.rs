@@ -0,0 +1,370 @@
+//! WebSocket server for order entry and market data.
+//!
+//! Clients send JSON [`ClientMessage`]s to subscribe to book and trade
+//! channels per symbol and to submit or cancel orders, and receive
+//! [`ServerMessage`]s. A book subscription starts with a snapshot followed by
+//! deltas of the levels that changed; a level with zero quantity has been
+//! removed. Deltas cover the engine's snapshot depth, and `seq` goes up by one
+//! per book message so a client can tell it missed one.
+
+use crate::engine::bus::{BackpressurePolicy, RecvError, Subscriber};
+use crate::engine::matching::MatchingEngine;
+use crate::gateway::levels::diff_levels;
+use crate::gateway::request::OrderRequest;
+use crate::utils::types::{Order, OrderBookLevel, OrderBookSnapshot, Trade};
+use chrono::{DateTime, Utc};
+use futures::{SinkExt, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use tokio::net::{TcpListener, TcpStream};
+use tokio_tungstenite::accept_async;
+use tokio_tungstenite::tungstenite::Message;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+/// Trades queued per connection before it is cut off and told it lagged.
+const TRADE_CAPACITY: usize = 1024;
+/// Execution reports queued per connection; only the latest book state
+/// matters, so the oldest are dropped when a client falls behind.
+const REPORT_CAPACITY: usize = 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Channel {
+    Book,
+    Trades,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum ClientMessage {
+    Subscribe {
+        channel: Channel,
+        symbol: String,
+    },
+    Unsubscribe {
+        channel: Channel,
+        symbol: String,
+    },
+    SubmitOrder {
+        #[serde(default)]
+        request_id: Option<String>,
+        order: OrderRequest,
+    },
+    CancelOrder {
+        #[serde(default)]
+        request_id: Option<String>,
+        symbol: String,
+        order_id: Uuid,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerMessage {
+    Subscribed {
+        channel: Channel,
+        symbol: String,
+    },
+    Unsubscribed {
+        channel: Channel,
+        symbol: String,
+    },
+    Snapshot {
+        seq: u64,
+        snapshot: OrderBookSnapshot,
+    },
+    Delta {
+        symbol: String,
+        seq: u64,
+        bids: Vec<OrderBookLevel>,
+        asks: Vec<OrderBookLevel>,
+        timestamp: DateTime<Utc>,
+    },
+    Trade {
+        trade: Trade,
+    },
+    /// The client fell behind and `dropped` events of `channel` were lost.
+    Lagged {
+        channel: Channel,
+        dropped: u64,
+    },
+    /// The order as it stood after matching.
+    OrderSubmitted {
+        request_id: Option<String>,
+        order: Order,
+    },
+    OrderCancelled {
+        request_id: Option<String>,
+        order: Order,
+    },
+    Error {
+        request_id: Option<String>,
+        message: String,
+    },
+}
+
+/// Book levels last sent to a client.
+struct BookView {
+    seq: u64,
+    bids: Vec<OrderBookLevel>,
+    asks: Vec<OrderBookLevel>,
+}
+
+/// Subscriptions of one connection.
+#[derive(Default)]
+struct Subscriptions {
+    books: HashMap<String, BookView>,
+    trades: HashSet<String>,
+    /// Engine trade feed, held only while a trades channel is subscribed so
+    /// book-only clients are never cut off for trades they do not read.
+    trade_feed: Option<Subscriber<Trade>>,
+}
+
+pub struct WsServer {
+    engine: Arc<MatchingEngine>,
+}
+
+impl WsServer {
+    pub fn new(engine: Arc<MatchingEngine>) -> Self {
+        Self { engine }
+    }
+
+    /// Binds `addr` and serves WebSocket clients until the listener fails.
+    pub async fn run(self: Arc<Self>, addr: &str) -> anyhow::Result<()> {
+        let listener = TcpListener::bind(addr).await?;
+        info!("WebSocket server listening on {}", addr);
+        self.serve(listener).await
+    }
+
+    /// Serves WebSocket clients on an already bound listener.
+    pub async fn serve(self: Arc<Self>, listener: TcpListener) -> anyhow::Result<()> {
+        loop {
+            let (stream, peer) = listener.accept().await?;
+            let server = self.clone();
+            tokio::spawn(async move {
+                if let Err(e) = server.handle(stream).await {
+                    warn!("WebSocket connection from {} closed: {}", peer, e);
+                }
+            });
+        }
+    }
+
+    async fn handle(&self, stream: TcpStream) -> anyhow::Result<()> {
+        let mut ws = accept_async(stream).await?;
+        // Every book change comes with an execution report
+        let mut reports = self
+            .engine
+            .subscribe_executions(REPORT_CAPACITY, BackpressurePolicy::DropOldest);
+        let mut subscriptions = Subscriptions::default();
+
+        loop {
+            let outgoing = tokio::select! {
+                message = ws.next() => match message {
+                    None => break,
+                    Some(message) => match message? {
+                        Message::Text(text) => self.on_request(&mut subscriptions, &text).await,
+                        Message::Binary(_) => vec![ServerMessage::Error {
+                            request_id: None,
+                            message: "binary messages are not supported".to_string(),
+                        }],
+                        Message::Close(_) => break,
+                        _ => Vec::new(),
+                    },
+                },
+                trade = next_trade(&mut subscriptions.trade_feed) => match trade {
+                    Ok(trade) if subscriptions.trades.contains(&trade.symbol) => {
+                        vec![ServerMessage::Trade { trade }]
+                    }
+                    Ok(_) => Vec::new(),
+                    Err(RecvError::Lagged { dropped }) => {
+                        if let Some(feed) = subscriptions.trade_feed.as_mut() {
+                            feed.resync();
+                        }
+                        vec![ServerMessage::Lagged { channel: Channel::Trades, dropped }]
+                    }
+                    Err(RecvError::Closed) => break,
+                },
+                report = reports.recv() => match report {
+                    Ok(report) => self.book_delta(&mut subscriptions, &report.symbol).into_iter().collect(),
+                    Err(RecvError::Lagged { .. }) => {
+                        reports.resync();
+                        Vec::new()
+                    }
+                    Err(RecvError::Closed) => break,
+                },
+            };
+            for message in outgoing {
+                ws.send(Message::Text(serde_json::to_string(&message)?))
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn on_request(
+        &self,
+        subscriptions: &mut Subscriptions,
+        text: &str,
+    ) -> Vec<ServerMessage> {
+        let request = match serde_json::from_str::<ClientMessage>(text) {
+            Ok(request) => request,
+            Err(e) => {
+                return vec![ServerMessage::Error {
+                    request_id: None,
+                    message: format!("invalid request: {}", e),
+                }]
+            }
+        };
+
+        match request {
+            ClientMessage::Subscribe {
+                channel: Channel::Book,
+                symbol,
+            } => {
+                let snapshot = self.snapshot(&symbol);
+                let view = BookView {
+                    seq: 0,
+                    bids: snapshot.bids.clone(),
+                    asks: snapshot.asks.clone(),
+                };
+                subscriptions.books.insert(symbol.clone(), view);
+                vec![
+                    ServerMessage::Subscribed {
+                        channel: Channel::Book,
+                        symbol,
+                    },
+                    ServerMessage::Snapshot { seq: 0, snapshot },
+                ]
+            }
+            ClientMessage::Subscribe {
+                channel: Channel::Trades,
+                symbol,
+            } => {
+                if subscriptions.trade_feed.is_none() {
+                    subscriptions.trade_feed = Some(
+                        self.engine
+                            .subscribe_trades(TRADE_CAPACITY, BackpressurePolicy::Disconnect),
+                    );
+                }
+                subscriptions.trades.insert(symbol.clone());
+                vec![ServerMessage::Subscribed {
+                    channel: Channel::Trades,
+                    symbol,
+                }]
+            }
+            ClientMessage::Unsubscribe { channel, symbol } => {
+                let removed = match channel {
+                    Channel::Book => subscriptions.books.remove(&symbol).is_some(),
+                    Channel::Trades => subscriptions.trades.remove(&symbol),
+                };
+                if subscriptions.trades.is_empty() {
+                    subscriptions.trade_feed = None;
+                }
+                if removed {
+                    vec![ServerMessage::Unsubscribed { channel, symbol }]
+                } else {
+                    vec![ServerMessage::Error {
+                        request_id: None,
+                        message: format!("not subscribed to {:?} for {}", channel, symbol),
+                    }]
+                }
+            }
+            ClientMessage::SubmitOrder { request_id, order } => {
+                match self.engine.submit_order(order.into_order()).await {
+                    Ok(order) => vec![ServerMessage::OrderSubmitted { request_id, order }],
+                    Err(e) => vec![ServerMessage::Error {
+                        request_id,
+                        message: e.to_string(),
+                    }],
+                }
+            }
+            ClientMessage::CancelOrder {
+                request_id,
+                symbol,
+                order_id,
+            } => match self.engine.cancel_order(order_id, &symbol).await {
+                Ok(order) => vec![ServerMessage::OrderCancelled { request_id, order }],
+                Err(e) => vec![ServerMessage::Error {
+                    request_id,
+                    message: e.to_string(),
+                }],
+            },
+        }
+    }
+
+    /// Changes to a subscribed book since the client last saw it.
+    fn book_delta(&self, subscriptions: &mut Subscriptions, symbol: &str) -> Option<ServerMessage> {
+        let view = subscriptions.books.get_mut(symbol)?;
+        let snapshot = self.snapshot(symbol);
+        let bids = diff_levels(&view.bids, &snapshot.bids);
+        let asks = diff_levels(&view.asks, &snapshot.asks);
+        if bids.is_empty() && asks.is_empty() {
+            return None;
+        }
+
+        view.seq += 1;
+        view.bids = snapshot.bids;
+        view.asks = snapshot.asks;
+        Some(ServerMessage::Delta {
+            symbol: symbol.to_string(),
+            seq: view.seq,
+            bids,
+            asks,
+            timestamp: snapshot.timestamp,
+        })
+    }
+
+    /// Snapshot of `symbol`, empty for symbols the engine has not seen yet.
+    fn snapshot(&self, symbol: &str) -> OrderBookSnapshot {
+        self.engine
+            .get_orderbook_snapshot(symbol)
+            .unwrap_or_else(|| OrderBookSnapshot {
+                symbol: symbol.to_string(),
+                bids: Vec::new(),
+                asks: Vec::new(),
+                timestamp: Utc::now(),
+            })
+    }
+}
+
+/// Next trade from the connection's feed; never resolves while it has none.
+async fn next_trade(feed: &mut Option<Subscriber<Trade>>) -> Result<Trade, RecvError> {
+    match feed {
+        Some(feed) => feed.recv().await,
+        None => std::future::pending().await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal::Decimal;
+
+    #[test]
+    fn test_client_message_json() {
+        let request: ClientMessage = serde_json::from_str(
+            r#"{"op":"submit_order","request_id":"r1","order":{"symbol":"BTCUSD","side":"Buy","order_type":"Limit","price":100,"quantity":2}}"#,
+        )
+        .unwrap();
+        let ClientMessage::SubmitOrder { request_id, order } = request else {
+            panic!("expected a submit_order request");
+        };
+        assert_eq!(request_id.as_deref(), Some("r1"));
+        let order = order.into_order();
+        assert_eq!(order.price, Decimal::from(100));
+        assert_eq!(order.stop_price, None);
+
+        let request: ClientMessage =
+            serde_json::from_str(r#"{"op":"subscribe","channel":"book","symbol":"BTCUSD"}"#)
+                .unwrap();
+        assert!(matches!(
+            request,
+            ClientMessage::Subscribe {
+                channel: Channel::Book,
+                ..
+            }
+        ));
+    }
+}
@@ -8,13 +8,15 @@
 //!
 //! - [`engine`] -- Matching engine and order book with price-time priority
 //! - [`risk`] -- Position tracking, risk limits, and circuit breaker
-//! - [`connectors`] -- Exchange WebSocket connectors (Binance)
+//! - [`connectors`] -- Exchange connectors behind the `ExchangeConnector` trait (Binance, Coinbase)
+//! - [`gateway`] -- Order-entry and market data gateways onto the matching engine (FIX 4.4, binary, WebSocket, REST) and a UDP market data feed
 //! - [`backtest`] -- Historical backtesting with performance metrics
 //! - [`utils`] -- Shared types: Order, Trade, Ticker, OrderBookSnapshot
 
 pub mod backtest;
 pub mod connectors;
 pub mod engine;
+pub mod gateway;
 pub mod risk;
 pub mod utils;
 
@@ -1,16 +1,28 @@
+use chrono::{NaiveDate, NaiveTime, Utc};
 use clap::{Parser, Subcommand};
 use quantumflow::{
     backtest::engine::BacktestEngine,
-    connectors::binance::BinanceConnector,
-    engine::matching::MatchingEngine,
+    connectors::{
+        binance::BinanceConnector,
+        coinbase::CoinbaseConnector,
+        exchange::ExchangeConnector,
+        market::{subscription_channel, Channel, Kline, KlineInterval, MarketEvent, Subscription},
+        reconnect::StreamEvent,
+    },
+    engine::{bus::BackpressurePolicy, matching::MatchingEngine},
+    gateway::{
+        binary::BinaryServer,
+        feed::{FeedConfig, FeedPublisher},
+        fix::{FixAcceptor, FixConfig},
+        rest::RestServer,
+        websocket::WsServer,
+    },
     risk::manager::{RiskLimits, RiskManager},
-    Order, OrderType, Side,
+    MarketTrade, Order, OrderBookSnapshot, OrderType, Side, Ticker,
 };
 use rust_decimal::Decimal;
 use std::sync::Arc;
-use tokio::sync::mpsc;
-use tracing::{info, Level};
-use tracing_subscriber;
+use tracing::{error, info, warn, Level};
 
 #[derive(Parser)]
 #[command(name = "QuantumFlow")]
@@ -28,12 +40,15 @@ enum Commands {
         #[arg(short, long, default_value = "BTCUSD")]
         symbol: String,
     },
-    /// Stream market data from Binance
+    /// Stream market data from an exchange
     Stream {
-        /// Trading symbol
+        /// Exchange to stream from (binance or coinbase)
+        #[arg(short, long, default_value = "binance")]
+        exchange: String,
+        /// Trading symbol, or several comma-separated
         #[arg(short, long, default_value = "btcusdt")]
         symbol: String,
-        /// Stream type (ticker or orderbook)
+        /// Stream type (ticker, orderbook, trade, aggtrade or kline_<interval>), or several comma-separated
         #[arg(short, long, default_value = "ticker")]
         stream_type: String,
     },
@@ -43,8 +58,62 @@ enum Commands {
         #[arg(short, long)]
         file: String,
     },
+    /// Download historical Binance klines as backtest CSV
+    Klines {
+        /// Trading symbol
+        #[arg(short, long, default_value = "btcusdt")]
+        symbol: String,
+        /// Bar interval, e.g. 1m, 1h or 1d
+        #[arg(short, long, default_value = "1h")]
+        interval: String,
+        /// First day to fetch (YYYY-MM-DD, UTC)
+        #[arg(long)]
+        start: String,
+        /// Last day to fetch, inclusive (YYYY-MM-DD, UTC); defaults to now
+        #[arg(long)]
+        end: Option<String>,
+        /// CSV file to write
+        #[arg(short, long, default_value = "klines.csv")]
+        output: String,
+    },
     /// Run demo trading
     Demo,
+    /// Accept FIX 4.4 order entry on the matching engine
+    Fix {
+        /// Address to listen on
+        #[arg(short, long, default_value = "0.0.0.0:9878")]
+        bind: String,
+        /// Our SenderCompID
+        #[arg(short, long, default_value = "QUANTUMFLOW")]
+        comp_id: String,
+        /// Directory for persistent sequence numbers and resend messages
+        #[arg(long)]
+        store: Option<String>,
+    },
+    /// Accept binary order entry on the matching engine
+    Binary {
+        /// Address to listen on
+        #[arg(short, long, default_value = "0.0.0.0:9200")]
+        bind: String,
+        /// Also publish the UDP market data feed to this address
+        #[arg(long)]
+        feed: Option<String>,
+        /// Address of the feed's TCP recovery service
+        #[arg(long, default_value = "0.0.0.0:9201")]
+        recovery: String,
+    },
+    /// Serve order entry and market data over WebSocket
+    Ws {
+        /// Address to listen on
+        #[arg(short, long, default_value = "0.0.0.0:9001")]
+        bind: String,
+    },
+    /// Serve the HTTP/JSON API for orders, books, positions and risk
+    Rest {
+        /// Address to listen on
+        #[arg(short, long, default_value = "0.0.0.0:8080")]
+        bind: String,
+    },
 }
 
 #[tokio::main]
@@ -60,29 +129,74 @@ async fn main() -> anyhow::Result<()> {
         Commands::Match { symbol } => {
             run_matching_engine(&symbol).await?;
         }
-        Commands::Stream { symbol, stream_type } => {
-            run_stream(&symbol, &stream_type).await?;
+        Commands::Stream {
+            exchange,
+            symbol,
+            stream_type,
+        } => {
+            run_stream(&exchange, &symbol, &stream_type).await?;
         }
         Commands::Backtest { file } => {
             run_backtest(&file).await?;
         }
+        Commands::Klines {
+            symbol,
+            interval,
+            start,
+            end,
+            output,
+        } => {
+            run_klines(&symbol, &interval, &start, end.as_deref(), &output).await?;
+        }
         Commands::Demo => {
             run_demo().await?;
         }
+        Commands::Fix {
+            bind,
+            comp_id,
+            store,
+        } => {
+            run_fix(&bind, &comp_id, store).await?;
+        }
+        Commands::Binary {
+            bind,
+            feed,
+            recovery,
+        } => {
+            run_binary(&bind, feed.as_deref(), &recovery).await?;
+        }
+        Commands::Ws { bind } => {
+            run_ws(&bind).await?;
+        }
+        Commands::Rest { bind } => {
+            run_rest(&bind).await?;
+        }
     }
 
     Ok(())
 }
 
+/// A matching engine expiring good-till-date orders on wall-clock time.
+fn spawn_engine() -> Arc<MatchingEngine> {
+    let engine = Arc::new(MatchingEngine::new());
+    let expiry_engine = engine.clone();
+    tokio::spawn(async move {
+        expiry_engine
+            .run_expiry(std::time::Duration::from_millis(100))
+            .await;
+    });
+    engine
+}
+
 async fn run_matching_engine(symbol: &str) -> anyhow::Result<()> {
     info!("Starting matching engine for {}", symbol);
 
-    let (trade_tx, mut trade_rx) = mpsc::unbounded_channel();
-    let engine = Arc::new(MatchingEngine::new(trade_tx));
+    let engine = spawn_engine();
+    let mut trade_rx = engine.subscribe_trades(1024, BackpressurePolicy::DropOldest);
 
     // Spawn task to handle trades
     tokio::spawn(async move {
-        while let Some(trade) = trade_rx.recv().await {
+        while let Ok(trade) = trade_rx.recv().await {
             info!(
                 "Trade: {} {} @ {} qty {}",
                 trade.symbol, trade.id, trade.price, trade.quantity
@@ -122,45 +236,211 @@ async fn run_matching_engine(symbol: &str) -> anyhow::Result<()> {
     Ok(())
 }
 
-async fn run_stream(symbol: &str, stream_type: &str) -> anyhow::Result<()> {
-    info!("Starting {} stream for {}", stream_type, symbol);
-
-    let connector = BinanceConnector::new();
-
-    match stream_type {
-        "ticker" => {
-            connector
-                .stream_ticker(symbol, |ticker| {
-                    info!(
-                        "Ticker: {} | Bid: {} | Ask: {} | Last: {}",
-                        ticker.symbol, ticker.bid, ticker.ask, ticker.last
-                    );
-                })
-                .await?;
-        }
-        "orderbook" => {
-            connector
-                .stream_orderbook(symbol, |snapshot| {
-                    if let (Some(best_bid), Some(best_ask)) = (
-                        snapshot.bids.first(),
-                        snapshot.asks.first(),
-                    ) {
-                        info!(
-                            "OrderBook: {} | Best Bid: {} | Best Ask: {} | Spread: {}",
-                            snapshot.symbol,
-                            best_bid.price,
-                            best_ask.price,
-                            best_ask.price - best_bid.price
-                        );
-                    }
-                })
-                .await?;
+async fn run_fix(bind: &str, comp_id: &str, store: Option<String>) -> anyhow::Result<()> {
+    info!("Starting FIX acceptor {} on {}", comp_id, bind);
+
+    let engine = spawn_engine();
+
+    let config = FixConfig {
+        store_dir: store.map(Into::into),
+        ..FixConfig::new(comp_id)
+    };
+    Arc::new(FixAcceptor::new(engine, config)).run(bind).await
+}
+
+async fn run_binary(bind: &str, feed: Option<&str>, recovery: &str) -> anyhow::Result<()> {
+    info!("Starting binary order entry on {}", bind);
+
+    let engine = spawn_engine();
+
+    if let Some(feed) = feed {
+        let publisher = Arc::new(FeedPublisher::new(
+            engine.clone(),
+            FeedConfig::new(feed.parse()?),
+        ));
+        let recovery = recovery.to_string();
+        tokio::spawn(async move {
+            if let Err(e) = publisher.run(&recovery).await {
+                error!("Market data feed stopped: {}", e);
+            }
+        });
+    }
+
+    Arc::new(BinaryServer::new(engine)).run(bind).await
+}
+
+async fn run_ws(bind: &str) -> anyhow::Result<()> {
+    info!("Starting WebSocket server on {}", bind);
+
+    let engine = spawn_engine();
+
+    Arc::new(WsServer::new(engine)).run(bind).await
+}
+
+async fn run_rest(bind: &str) -> anyhow::Result<()> {
+    info!("Starting REST API on {}", bind);
+
+    let engine = spawn_engine();
+    let risk = Arc::new(RiskManager::new(RiskLimits::default()));
+
+    Arc::new(RestServer::new(engine, risk)).run(bind).await
+}
+
+async fn run_stream(exchange: &str, symbol: &str, stream_type: &str) -> anyhow::Result<()> {
+    info!(
+        "Starting {} {} stream for {}",
+        exchange, stream_type, symbol
+    );
+
+    let connector = connector(exchange)?;
+
+    let mut subscriptions = Vec::new();
+    for kind in stream_type.split(',') {
+        let channel = match kind {
+            "ticker" => Channel::Ticker,
+            "orderbook" => Channel::Depth,
+            "trade" => Channel::Trade,
+            "aggtrade" => Channel::AggTrade,
+            _ => match kline_interval(kind) {
+                Some(interval) => Channel::Kline(interval),
+                None => {
+                    eprintln!("Unknown stream type: {}", kind);
+                    return Ok(());
+                }
+            },
+        };
+        for symbol in symbol.split(',') {
+            subscriptions.push(Subscription::new(symbol, channel));
         }
-        _ => {
-            eprintln!("Unknown stream type: {}", stream_type);
+    }
+
+    // Nothing changes the subscriptions from the command line
+    let (_handle, commands) = subscription_channel();
+    connector
+        .stream_market_data(
+            &subscriptions,
+            commands,
+            Box::new(|event| match event {
+                StreamEvent::Data { data, .. } => match data {
+                    MarketEvent::Ticker(ticker) => log_ticker(&ticker),
+                    MarketEvent::Book(snapshot) => log_book(&snapshot),
+                    MarketEvent::Trade(trade) => log_trade(&trade),
+                    MarketEvent::Kline(kline) => log_kline(&kline),
+                },
+                event => log_stream_event(&event),
+            }),
+        )
+        .await
+}
+
+/// The connector for a venue named on the command line.
+fn connector(exchange: &str) -> anyhow::Result<Box<dyn ExchangeConnector>> {
+    match exchange {
+        "binance" => Ok(Box::new(BinanceConnector::new())),
+        "coinbase" => Ok(Box::new(CoinbaseConnector::new())),
+        _ => anyhow::bail!("unknown exchange {}", exchange),
+    }
+}
+
+fn log_ticker(ticker: &Ticker) {
+    info!(
+        "Ticker: {} | Bid: {} | Ask: {} | Last: {}",
+        ticker.symbol, ticker.bid, ticker.ask, ticker.last
+    );
+}
+
+/// Interval of a `kline_<interval>` stream type.
+fn kline_interval(stream_type: &str) -> Option<KlineInterval> {
+    stream_type.strip_prefix("kline_")?.parse().ok()
+}
+
+fn log_kline(kline: &Kline) {
+    info!(
+        "Kline: {} {} {} | O: {} H: {} L: {} C: {} V: {}{}",
+        kline.symbol,
+        kline.interval,
+        kline.bar.timestamp,
+        kline.bar.open,
+        kline.bar.high,
+        kline.bar.low,
+        kline.bar.close,
+        kline.bar.volume,
+        if kline.closed { " | closed" } else { "" }
+    );
+}
+
+fn log_trade(trade: &MarketTrade) {
+    info!(
+        "Trade: {} | #{} | {} {} @ {}",
+        trade.symbol,
+        trade.trade_id,
+        trade.aggressor(),
+        trade.quantity,
+        trade.price
+    );
+}
+
+fn log_book(snapshot: &OrderBookSnapshot) {
+    if let (Some(best_bid), Some(best_ask)) = (snapshot.bids.first(), snapshot.asks.first()) {
+        info!(
+            "OrderBook: {} | Best Bid: {} | Best Ask: {} | Spread: {}",
+            snapshot.symbol,
+            best_bid.price,
+            best_ask.price,
+            best_ask.price - best_bid.price
+        );
+    }
+}
+
+fn log_stream_event<T>(event: &StreamEvent<T>) {
+    match event {
+        StreamEvent::Data { .. } => {}
+        StreamEvent::Stale {
+            symbol: Some(symbol),
+            silent_for,
+        } => warn!("No {} market data for {:?}", symbol, silent_for),
+        StreamEvent::Stale {
+            symbol: None,
+            silent_for,
+        } => warn!("No market data for {:?}", silent_for),
+        StreamEvent::Connected => info!("Stream connected"),
+        StreamEvent::Disconnected { reason } => warn!("Stream disconnected: {}", reason),
+        StreamEvent::Reconnecting { attempt, delay } => {
+            info!("Reconnecting (attempt {}) in {:?}", attempt, delay);
         }
     }
+}
+
+async fn run_klines(
+    symbol: &str,
+    interval: &str,
+    start: &str,
+    end: Option<&str>,
+    output: &str,
+) -> anyhow::Result<()> {
+    let interval: KlineInterval = interval.parse()?;
+    let day = |date: &str| -> anyhow::Result<NaiveDate> {
+        NaiveDate::parse_from_str(date, "%Y-%m-%d")
+            .map_err(|e| anyhow::anyhow!("invalid date {}: {}", date, e))
+    };
+    let start = day(start)?.and_time(NaiveTime::MIN).and_utc();
+    let end = match end {
+        // Through the last millisecond of the given day
+        Some(end) => {
+            let next_day = day(end)? + chrono::Duration::days(1);
+            next_day.and_time(NaiveTime::MIN).and_utc() - chrono::Duration::milliseconds(1)
+        }
+        None => Utc::now(),
+    };
+    info!(
+        "Downloading {} {} klines from {} to {}",
+        symbol, interval, start, end
+    );
 
+    let written = BinanceConnector::new()
+        .download_klines(symbol, interval, start, end, output)
+        .await?;
+    info!("Wrote {} bars to {}", written, output);
     Ok(())
 }
 
@@ -177,13 +457,11 @@ async fn run_backtest(file: &str) -> anyhow::Result<()> {
                 .from_reader(reader);
 
             let mut loaded_prices = Vec::new();
-            for result in rdr.records() {
-                if let Ok(record) = result {
-                    // Expect "close" price in column index 4 (timestamp,open,high,low,close,volume)
-                    if let Some(close_str) = record.get(4) {
-                        if let Ok(price) = close_str.trim().parse::<Decimal>() {
-                            loaded_prices.push(price);
-                        }
+            for record in rdr.records().flatten() {
+                // Expect "close" price in column index 4 (timestamp,open,high,low,close,volume)
+                if let Some(close_str) = record.get(4) {
+                    if let Ok(price) = close_str.trim().parse::<Decimal>() {
+                        loaded_prices.push(price);
                     }
                 }
             }
@@ -251,14 +529,14 @@ async fn run_backtest(file: &str) -> anyhow::Result<()> {
 async fn run_demo() -> anyhow::Result<()> {
     info!("Running demo trading simulation");
 
-    let (trade_tx, mut trade_rx) = mpsc::unbounded_channel();
-    let engine = Arc::new(MatchingEngine::new(trade_tx));
+    let engine = Arc::new(MatchingEngine::new());
+    let mut trade_rx = engine.subscribe_trades(1024, BackpressurePolicy::DropOldest);
     let risk_manager = Arc::new(RiskManager::new(RiskLimits::default()));
 
     // Spawn task to handle trades
     let rm = risk_manager.clone();
     tokio::spawn(async move {
-        while let Some(trade) = trade_rx.recv().await {
+        while let Ok(trade) = trade_rx.recv().await {
             info!(
                 "Trade executed: {} @ {} qty {}",
                 trade.symbol, trade.price, trade.quantity
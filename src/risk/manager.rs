@@ -1,6 +1,7 @@
-use crate::utils::types::{Order, Side};
-use dashmap::DashMap;
+use crate::utils::types::{ExecType, ExecutionReport, Order, Side};
+use dashmap::{DashMap, DashSet};
 use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 use tracing::{info, warn};
 
@@ -23,7 +24,7 @@ impl Default for RiskLimits {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Position {
     pub symbol: String,
     pub quantity: Decimal,
@@ -76,6 +77,10 @@ pub struct RiskManager {
     limits: RiskLimits,
     positions: Arc<DashMap<String, Position>>,
     daily_pnl: Arc<parking_lot::RwLock<Decimal>>,
+    /// Symbols whose market data feed is currently stale.
+    stale_market_data: Arc<DashSet<String>>,
+    /// Holdings per asset as last reported by the exchange, free plus locked.
+    balances: Arc<DashMap<String, Decimal>>,
 }
 
 impl RiskManager {
@@ -84,10 +89,17 @@ impl RiskManager {
             limits,
             positions: Arc::new(DashMap::new()),
             daily_pnl: Arc::new(parking_lot::RwLock::new(Decimal::ZERO)),
+            stale_market_data: Arc::new(DashSet::new()),
+            balances: Arc::new(DashMap::new()),
         }
     }
 
     pub fn check_order(&self, order: &Order) -> Result<(), String> {
+        // Never quote against a market view that is out of date
+        if self.is_market_data_stale(&order.symbol) {
+            return Err(format!("Market data for {} is stale", order.symbol));
+        }
+
         // Check order size
         if order.quantity > self.limits.max_order_size {
             return Err(format!(
@@ -143,6 +155,28 @@ impl RiskManager {
         );
     }
 
+    /// Applies the fill an execution report describes to its symbol's
+    /// position. Reports without a fill leave positions unchanged.
+    pub fn on_execution(&self, report: &ExecutionReport) {
+        if let (ExecType::Trade, Some(price), Some(quantity)) =
+            (report.exec_type, report.last_price, report.last_quantity)
+        {
+            self.update_position(&report.symbol, report.side, price, quantity);
+        }
+    }
+
+    /// Records the exchange-reported holdings of `asset`.
+    pub fn set_balance(&self, asset: &str, total: Decimal) {
+        self.balances.insert(asset.to_string(), total);
+    }
+
+    pub fn get_balance(&self, asset: &str) -> Decimal {
+        self.balances
+            .get(asset)
+            .map(|balance| *balance)
+            .unwrap_or(Decimal::ZERO)
+    }
+
     pub fn get_position(&self, symbol: &str) -> Position {
         self.positions
             .get(symbol)
@@ -174,6 +208,25 @@ impl RiskManager {
             .sum()
     }
 
+    /// Marks the market data for `symbol` as stale or current again, e.g.
+    /// from a connector's [`StreamEvent::staleness`]. Orders for a symbol
+    /// with stale data are rejected.
+    ///
+    /// [`StreamEvent::staleness`]: crate::connectors::reconnect::StreamEvent::staleness
+    pub fn set_market_data_stale(&self, symbol: &str, stale: bool) {
+        if stale {
+            if self.stale_market_data.insert(symbol.to_string()) {
+                warn!("Market data for {} is stale, rejecting orders", symbol);
+            }
+        } else if self.stale_market_data.remove(symbol).is_some() {
+            info!("Market data for {} is current again", symbol);
+        }
+    }
+
+    pub fn is_market_data_stale(&self, symbol: &str) -> bool {
+        self.stale_market_data.contains(symbol)
+    }
+
     pub fn check_circuit_breaker(&self) -> bool {
         let daily_pnl = *self.daily_pnl.read();
         if daily_pnl < -self.limits.max_daily_loss {
@@ -225,4 +278,50 @@ mod tests {
         let result = manager.check_order(&order);
         assert!(result.is_err());
     }
+
+    #[test]
+    fn test_rejects_orders_on_stale_market_data() {
+        let manager = RiskManager::new(RiskLimits::default());
+        let order = Order::new(
+            "BTCUSD".to_string(),
+            Side::Buy,
+            crate::utils::types::OrderType::Limit,
+            Decimal::from(50000),
+            Decimal::from(1),
+        );
+
+        manager.set_market_data_stale("BTCUSD", true);
+        assert!(manager.check_order(&order).unwrap_err().contains("stale"));
+        manager.set_market_data_stale("BTCUSD", false);
+        assert!(manager.check_order(&order).is_ok());
+    }
+
+    #[test]
+    fn test_applies_fills_from_execution_reports() {
+        let manager = RiskManager::new(RiskLimits::default());
+        let order = Order::new(
+            "BTCUSD".to_string(),
+            Side::Buy,
+            crate::utils::types::OrderType::Limit,
+            Decimal::from(50000),
+            Decimal::from(2),
+        );
+
+        manager.on_execution(&ExecutionReport::new(
+            &order,
+            ExecType::New,
+            chrono::Utc::now(),
+        ));
+        assert_eq!(manager.get_position("BTCUSD").quantity, Decimal::ZERO);
+
+        let fill = ExecutionReport {
+            last_price: Some(Decimal::from(49990)),
+            last_quantity: Some(Decimal::from(1)),
+            ..ExecutionReport::new(&order, ExecType::Trade, chrono::Utc::now())
+        };
+        manager.on_execution(&fill);
+        let position = manager.get_position("BTCUSD");
+        assert_eq!(position.quantity, Decimal::from(1));
+        assert_eq!(position.average_price, Decimal::from(49990));
+    }
 }
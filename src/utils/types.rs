@@ -47,6 +47,7 @@ pub enum OrderStatus {
     Filled,
     Cancelled,
     Rejected,
+    Expired,
 }
 
 /// Represents a trading order with all metadata required for matching.
@@ -62,6 +63,18 @@ pub struct Order {
     pub status: OrderStatus,
     pub timestamp: DateTime<Utc>,
     pub client_id: Option<String>,
+    /// Trigger price for `StopLimit` and `StopMarket` orders.
+    #[serde(default)]
+    pub stop_price: Option<Decimal>,
+    /// Makes a stop order trail the market instead of keeping a fixed stop price.
+    #[serde(default)]
+    pub trailing: Option<TrailingStop>,
+    /// Makes a limit order follow the best bid and offer instead of its own price.
+    #[serde(default)]
+    pub peg: Option<PegInstruction>,
+    /// Good-till-date: the order is cancelled once the engine clock reaches this time.
+    #[serde(default)]
+    pub expires_at: Option<DateTime<Utc>>,
 }
 
 impl Order {
@@ -84,6 +97,10 @@ impl Order {
             status: OrderStatus::Pending,
             timestamp: Utc::now(),
             client_id: None,
+            stop_price: None,
+            trailing: None,
+            peg: None,
+            expires_at: None,
         }
     }
 
@@ -96,6 +113,68 @@ impl Order {
     pub fn is_fully_filled(&self) -> bool {
         self.filled_quantity >= self.quantity
     }
+
+    /// Returns `true` for order types that wait for a trigger price.
+    pub fn is_stop(&self) -> bool {
+        matches!(
+            self.order_type,
+            OrderType::StopLimit | OrderType::StopMarket
+        )
+    }
+}
+
+/// Distance a trailing stop keeps from its reference price.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TrailingOffset {
+    /// A fixed price distance.
+    Amount(Decimal),
+    /// A percentage of the reference price (`1.5` is 1.5%).
+    Percent(Decimal),
+}
+
+/// Price a trailing stop follows and triggers on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TrailReference {
+    /// Best bid for sell stops, best ask for buy stops.
+    BestPrice,
+    /// Last trade price.
+    LastTrade,
+}
+
+/// Trailing behaviour of a stop order.
+///
+/// A sell stop ratchets up to `reference - offset` as the reference rises and
+/// never moves down; a buy stop ratchets down to `reference + offset`. A
+/// trailing `StopLimit` moves its limit price by the same amount as its stop.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TrailingStop {
+    pub offset: TrailingOffset,
+    pub reference: TrailReference,
+}
+
+/// Book price a pegged order follows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PegReference {
+    /// Same-side best: best bid for buys, best offer for sells.
+    Primary,
+    /// Opposite-side best: best offer for buys, best bid for sells.
+    Market,
+    /// Midpoint of the best bid and offer, which may fall on a half tick.
+    Midpoint,
+}
+
+/// Pricing of a pegged order.
+///
+/// The order's price is `reference + offset`, capped at `limit` (no higher for
+/// buys, no lower for sells). While the reference is missing the order is
+/// inactive and cannot trade.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PegInstruction {
+    pub reference: PegReference,
+    #[serde(default)]
+    pub offset: Decimal,
+    #[serde(default)]
+    pub limit: Option<Decimal>,
 }
 
 /// Represents a completed trade between a buyer and a seller.
@@ -111,13 +190,14 @@ pub struct Trade {
 }
 
 impl Trade {
-    /// Creates a new trade record with a random UUID.
+    /// Creates a new trade record with a random UUID, executed at `timestamp`.
     pub fn new(
         symbol: String,
         price: Decimal,
         quantity: Decimal,
         buy_order_id: Uuid,
         sell_order_id: Uuid,
+        timestamp: DateTime<Utc>,
     ) -> Self {
         Self {
             id: Uuid::new_v4(),
@@ -126,13 +206,92 @@ impl Trade {
             quantity,
             buy_order_id,
             sell_order_id,
-            timestamp: Utc::now(),
+            timestamp,
         }
     }
 }
 
-/// Real-time ticker data from an exchange.
+/// Kind of event an [`ExecutionReport`] describes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ExecType {
+    /// The order was accepted (stop orders are accepted as `Pending`).
+    New,
+    /// The order traded; `last_price`/`last_quantity` describe the fill.
+    Trade,
+    /// The order, or its unfilled remainder, was cancelled.
+    Cancelled,
+    /// The order, or its unfilled remainder, was rejected.
+    Rejected,
+    /// A stop order's trigger price was reached and it became active.
+    Triggered,
+    /// The order reached its expiry time and was removed from the book.
+    Expired,
+}
+
+/// Per-order execution event emitted by the matching engine.
 #[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExecutionReport {
+    pub order_id: Uuid,
+    pub client_id: Option<String>,
+    pub symbol: String,
+    pub side: Side,
+    pub order_type: OrderType,
+    pub exec_type: ExecType,
+    pub status: OrderStatus,
+    pub price: Decimal,
+    pub quantity: Decimal,
+    pub filled_quantity: Decimal,
+    pub last_price: Option<Decimal>,
+    pub last_quantity: Option<Decimal>,
+    pub trade_id: Option<Uuid>,
+    pub timestamp: DateTime<Utc>,
+}
+
+impl ExecutionReport {
+    /// Creates a report describing the state of `order` at `timestamp`.
+    pub fn new(order: &Order, exec_type: ExecType, timestamp: DateTime<Utc>) -> Self {
+        Self {
+            order_id: order.id,
+            client_id: order.client_id.clone(),
+            symbol: order.symbol.clone(),
+            side: order.side,
+            order_type: order.order_type,
+            exec_type,
+            status: order.status,
+            price: order.price,
+            quantity: order.quantity,
+            filled_quantity: order.filled_quantity,
+            last_price: None,
+            last_quantity: None,
+            trade_id: None,
+            timestamp,
+        }
+    }
+
+    /// Creates a `Trade` report for `order` as it stands after `trade`.
+    pub fn fill(order: &Order, trade: &Trade) -> Self {
+        Self {
+            last_price: Some(trade.price),
+            last_quantity: Some(trade.quantity),
+            trade_id: Some(trade.id),
+            ..Self::new(order, ExecType::Trade, trade.timestamp)
+        }
+    }
+
+    /// Returns the quantity still open after this event.
+    pub fn leaves_quantity(&self) -> Decimal {
+        match self.status {
+            OrderStatus::Filled
+            | OrderStatus::Cancelled
+            | OrderStatus::Rejected
+            | OrderStatus::Expired => Decimal::ZERO,
+            _ => self.quantity - self.filled_quantity,
+        }
+    }
+}
+
+/// Real-time ticker data from an exchange.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Ticker {
     pub symbol: String,
     pub bid: Decimal,
@@ -142,15 +301,45 @@ pub struct Ticker {
     pub timestamp: DateTime<Utc>,
 }
 
+/// A trade printed on an exchange's public tape.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MarketTrade {
+    pub symbol: String,
+    /// Exchange trade id, or the aggregate id for aggregated trades.
+    pub trade_id: u64,
+    pub price: Decimal,
+    pub quantity: Decimal,
+    /// The buyer was the resting order, so the seller took liquidity.
+    pub buyer_maker: bool,
+    /// Exchange event time.
+    pub timestamp: DateTime<Utc>,
+}
+
+impl MarketTrade {
+    /// Side of the order that took liquidity.
+    pub fn aggressor(&self) -> Side {
+        if self.buyer_maker {
+            Side::Sell
+        } else {
+            Side::Buy
+        }
+    }
+}
+
 /// A single price level in an order book with aggregated quantity.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+///
+/// `order_count` is `0` when the source (e.g. an exchange depth feed) does not
+/// report how many orders make up the level.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct OrderBookLevel {
     pub price: Decimal,
     pub quantity: Decimal,
+    #[serde(default)]
+    pub order_count: usize,
 }
 
 /// Point-in-time snapshot of an order book with top bid/ask levels.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct OrderBookSnapshot {
     pub symbol: String,
     pub bids: Vec<OrderBookLevel>,
@@ -0,0 +1,311 @@
+//! Binance combined streams against a local stand-in that checks the
+//! subscription requests it receives.
+
+use futures::{SinkExt, StreamExt};
+use quantumflow::connectors::binance::combined::{subscription_channel, MarketEvent, Subscription};
+use quantumflow::connectors::binance::{BinanceConfig, BinanceConnector};
+use quantumflow::connectors::reconnect::{ReconnectPolicy, StreamEvent};
+use rust_decimal::Decimal;
+use serde_json::{json, Value};
+use std::time::Duration;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::WebSocketStream;
+
+fn event(stream: &str, data: Value) -> Message {
+    Message::Text(json!({"stream": stream, "data": data}).to_string())
+}
+
+fn ticker(symbol: &str, last: &str) -> Message {
+    event(
+        &format!("{}@ticker", symbol.to_lowercase()),
+        json!({
+            "e": "24hrTicker", "E": chrono::Utc::now().timestamp_millis(), "s": symbol,
+            "b": "1", "a": "2", "c": last, "v": "10",
+        }),
+    )
+}
+
+/// Reads the next subscription request and acknowledges it.
+async fn request(ws: &mut WebSocketStream<TcpStream>) -> Value {
+    let Message::Text(text) = ws.next().await.unwrap().unwrap() else {
+        panic!("expected a text request");
+    };
+    let request: Value = serde_json::from_str(&text).unwrap();
+    ws.send(Message::Text(
+        json!({"result": null, "id": request["id"]}).to_string(),
+    ))
+    .await
+    .unwrap();
+    request
+}
+
+#[tokio::test]
+async fn test_routes_many_streams_and_changes_subscriptions_live() {
+    let mut server = mockito::Server::new_async().await;
+    let depth = server
+        .mock("GET", "/api/v3/depth")
+        .match_query(mockito::Matcher::UrlEncoded(
+            "symbol".into(),
+            "BTCUSDT".into(),
+        ))
+        .with_body(r#"{"lastUpdateId": 10, "bids": [["100", "1"]], "asks": [["101", "2"]]}"#)
+        .create_async()
+        .await;
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let ws_url = format!("ws://{}/stream", listener.local_addr().unwrap());
+    let (requests_tx, mut requests) = mpsc::unbounded_channel();
+    let (next_tx, mut next) = mpsc::unbounded_channel::<Vec<Message>>();
+    tokio::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        let mut ws = tokio_tungstenite::accept_async(stream).await.unwrap();
+        // Each batch of messages is sent once the next request has arrived
+        while let Some(messages) = next.recv().await {
+            requests_tx.send(request(&mut ws).await).unwrap();
+            for message in messages {
+                ws.send(message).await.unwrap();
+            }
+        }
+    });
+
+    let connector = BinanceConnector::with_config(BinanceConfig {
+        combined_ws_url: ws_url,
+        rest_url: server.url(),
+        ..BinanceConfig::new()
+    });
+    let (handle, commands) = subscription_channel();
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let stream = tokio::spawn(async move {
+        connector
+            .stream_combined(
+                &[
+                    Subscription::depth("BTCUSDT"),
+                    Subscription::ticker("ETHUSDT"),
+                ],
+                commands,
+                move |event| {
+                    if let StreamEvent::Data { data, .. } = event {
+                        let _ = tx.send(data);
+                    }
+                },
+            )
+            .await
+    });
+    let mut recv = async || {
+        tokio::time::timeout(Duration::from_secs(5), rx.recv())
+            .await
+            .expect("no event before the timeout")
+            .unwrap()
+    };
+
+    // One connection, one request for everything
+    next_tx
+        .send(vec![
+            event(
+                "btcusdt@depth@100ms",
+                json!({"e": "depthUpdate", "E": 1, "s": "BTCUSDT", "U": 11, "u": 11,
+                       "b": [["99", "3"]], "a": []}),
+            ),
+            ticker("ETHUSDT", "2000"),
+        ])
+        .unwrap();
+    let subscribe = requests.recv().await.unwrap();
+    assert_eq!(subscribe["method"], "SUBSCRIBE");
+    assert_eq!(
+        subscribe["params"],
+        json!(["btcusdt@depth@100ms", "ethusdt@ticker"])
+    );
+
+    let mut events = [recv().await, recv().await];
+    events.sort_by_key(|e| e.symbol().to_string());
+    let MarketEvent::Book(book) = &events[0] else {
+        panic!("expected a book, got {:?}", events[0]);
+    };
+    assert_eq!(book.symbol, "BTCUSDT");
+    assert_eq!(book.bids.len(), 2);
+    assert!(matches!(&events[1], MarketEvent::Ticker(t) if t.last == Decimal::from(2000)));
+    depth.assert_async().await;
+
+    // Add a symbol on the live connection
+    next_tx.send(vec![ticker("SOLUSDT", "150")]).unwrap();
+    handle
+        .subscribe(&[Subscription::ticker("solusdt")])
+        .unwrap();
+    let subscribe = requests.recv().await.unwrap();
+    assert_eq!(subscribe["params"], json!(["solusdt@ticker"]));
+    assert!(matches!(recv().await, MarketEvent::Ticker(t) if t.symbol == "SOLUSDT"));
+
+    // A payload already in flight for a dropped stream is not delivered
+    next_tx
+        .send(vec![ticker("ETHUSDT", "2001"), ticker("SOLUSDT", "151")])
+        .unwrap();
+    handle
+        .unsubscribe(&[Subscription::ticker("ETHUSDT")])
+        .unwrap();
+    let unsubscribe = requests.recv().await.unwrap();
+    assert_eq!(unsubscribe["method"], "UNSUBSCRIBE");
+    assert_eq!(unsubscribe["params"], json!(["ethusdt@ticker"]));
+    assert!(matches!(
+        recv().await,
+        MarketEvent::Ticker(t) if t.symbol == "SOLUSDT" && t.last == Decimal::from(151)
+    ));
+    stream.abort();
+}
+
+#[tokio::test]
+async fn test_retries_a_failed_snapshot_without_dropping_the_connection() {
+    let mut server = mockito::Server::new_async().await;
+    // Preferred while it has hits left, so only the first fetch fails
+    let failed = server
+        .mock("GET", "/api/v3/depth")
+        .match_query(mockito::Matcher::Any)
+        .with_status(500)
+        .expect(1)
+        .create_async()
+        .await;
+    server
+        .mock("GET", "/api/v3/depth")
+        .match_query(mockito::Matcher::Any)
+        .with_body(r#"{"lastUpdateId": 10, "bids": [["100", "1"]], "asks": [["101", "2"]]}"#)
+        .create_async()
+        .await;
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let ws_url = format!("ws://{}/stream", listener.local_addr().unwrap());
+    tokio::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        let mut ws = tokio_tungstenite::accept_async(stream).await.unwrap();
+        request(&mut ws).await;
+        ws.send(event(
+            "btcusdt@depth@100ms",
+            json!({"e": "depthUpdate", "E": 1, "s": "BTCUSDT", "U": 11, "u": 11,
+                   "b": [["99", "3"]], "a": []}),
+        ))
+        .await
+        .unwrap();
+        ws.send(ticker("ETHUSDT", "2000")).await.unwrap();
+        // Only one connection is ever accepted
+        std::future::pending::<()>().await;
+    });
+
+    let connector = BinanceConnector::with_config(BinanceConfig {
+        combined_ws_url: ws_url,
+        rest_url: server.url(),
+        reconnect: ReconnectPolicy {
+            initial_backoff: Duration::from_millis(50),
+            ..ReconnectPolicy::new()
+        },
+        ..BinanceConfig::new()
+    });
+    let (_handle, commands) = subscription_channel();
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let stream = tokio::spawn(async move {
+        connector
+            .stream_combined(
+                &[
+                    Subscription::depth("BTCUSDT"),
+                    Subscription::ticker("ETHUSDT"),
+                ],
+                commands,
+                move |event| {
+                    let _ = tx.send(event);
+                },
+            )
+            .await
+    });
+
+    let book = tokio::time::timeout(Duration::from_secs(5), async {
+        loop {
+            match rx.recv().await.unwrap() {
+                StreamEvent::Data {
+                    data: MarketEvent::Book(book),
+                    ..
+                } => return book,
+                StreamEvent::Disconnected { reason } => panic!("disconnected: {}", reason),
+                _ => {}
+            }
+        }
+    })
+    .await
+    .expect("no book before the timeout");
+    assert_eq!(book.bids.len(), 2);
+    failed.assert_async().await;
+    stream.abort();
+}
+
+#[tokio::test]
+async fn test_flags_a_quiet_symbol_on_a_busy_connection() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let ws_url = format!("ws://{}/stream", listener.local_addr().unwrap());
+    let (resume_tx, mut resume) = mpsc::unbounded_channel::<()>();
+    tokio::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        let mut ws = tokio_tungstenite::accept_async(stream).await.unwrap();
+        request(&mut ws).await;
+        // ETHUSDT keeps the connection busy while BTCUSDT says nothing
+        loop {
+            ws.send(ticker("ETHUSDT", "2000")).await.unwrap();
+            tokio::select! {
+                _ = tokio::time::sleep(Duration::from_millis(20)) => {}
+                _ = resume.recv() => ws.send(ticker("BTCUSDT", "100")).await.unwrap(),
+            }
+        }
+    });
+
+    let connector = BinanceConnector::with_config(BinanceConfig {
+        combined_ws_url: ws_url,
+        reconnect: ReconnectPolicy {
+            stale_after: Duration::from_millis(200),
+            ..ReconnectPolicy::new()
+        },
+        ..BinanceConfig::new()
+    });
+    let (_handle, commands) = subscription_channel();
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let stream = tokio::spawn(async move {
+        connector
+            .stream_combined(
+                &[
+                    Subscription::ticker("BTCUSDT"),
+                    Subscription::ticker("ETHUSDT"),
+                ],
+                commands,
+                move |event| {
+                    let _ = tx.send(event);
+                },
+            )
+            .await
+    });
+
+    // Next event concerning BTCUSDT; the connection itself never goes quiet
+    let mut next_btc = async || {
+        tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                let event = rx.recv().await.unwrap();
+                match event.symbol() {
+                    Some("BTCUSDT") => return event,
+                    Some(_) => {}
+                    None => assert_eq!(event, StreamEvent::Connected),
+                }
+            }
+        })
+        .await
+        .expect("no BTCUSDT event before the timeout")
+    };
+
+    let stale = next_btc().await;
+    let StreamEvent::Stale {
+        symbol: Some(_),
+        silent_for,
+    } = stale
+    else {
+        panic!("expected BTCUSDT to go stale, got {:?}", stale);
+    };
+    assert!(silent_for >= Duration::from_millis(200));
+
+    resume_tx.send(()).unwrap();
+    assert_eq!(next_btc().await.staleness(), Some(false));
+    stream.abort();
+}
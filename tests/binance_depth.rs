@@ -0,0 +1,208 @@
+//! Binance order book sync against a mockito REST API and a local WebSocket
+//! stand-in for the diff stream.
+
+use futures::SinkExt;
+use quantumflow::connectors::binance::{BinanceConfig, BinanceConnector};
+use quantumflow::connectors::reconnect::{ReconnectPolicy, StreamEvent};
+use quantumflow::OrderBookSnapshot;
+use rust_decimal::Decimal;
+use serde_json::json;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio::sync::mpsc;
+use tokio_tungstenite::tungstenite::handshake::server::{Request, Response};
+use tokio_tungstenite::tungstenite::Message;
+
+fn diff(first: u64, last: u64, bids: &[[&str; 2]], asks: &[[&str; 2]]) -> Message {
+    Message::Text(
+        json!({
+            "e": "depthUpdate",
+            "E": 1_700_000_000_000u64 + last,
+            "s": "BTCUSDT",
+            "U": first,
+            "u": last,
+            "b": bids,
+            "a": asks,
+        })
+        .to_string(),
+    )
+}
+
+/// Serves one connection, sends `messages` and holds the socket open.
+async fn diff_stream(messages: Vec<Message>) -> (String, Arc<Mutex<String>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("ws://{}/ws", listener.local_addr().unwrap());
+    let path = Arc::new(Mutex::new(String::new()));
+    let seen = path.clone();
+    tokio::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        // The handshake callback's error type is fixed by tungstenite
+        #[allow(clippy::result_large_err)]
+        let record_path = |req: &Request, res: Response| {
+            *seen.lock().unwrap() = req.uri().path().to_string();
+            Ok(res)
+        };
+        let mut ws = tokio_tungstenite::accept_hdr_async(stream, record_path)
+            .await
+            .unwrap();
+        for message in messages {
+            ws.send(message).await.unwrap();
+        }
+        tokio::time::sleep(Duration::from_secs(30)).await;
+    });
+    (url, path)
+}
+
+fn pairs(levels: &[quantumflow::OrderBookLevel]) -> Vec<(Decimal, Decimal)> {
+    levels.iter().map(|l| (l.price, l.quantity)).collect()
+}
+
+fn d(s: &str) -> Decimal {
+    Decimal::from_str(s).unwrap()
+}
+
+#[tokio::test]
+async fn test_maintains_book_across_stale_diffs_and_gaps() {
+    // The first snapshot predates the gap, the second one covers it
+    let mut server = mockito::Server::new_async().await;
+    let calls = AtomicUsize::new(0);
+    let depth = server
+        .mock("GET", "/api/v3/depth")
+        .match_query(mockito::Matcher::AllOf(vec![
+            mockito::Matcher::UrlEncoded("symbol".into(), "BTCUSDT".into()),
+            mockito::Matcher::UrlEncoded("limit".into(), "1000".into()),
+        ]))
+        .with_body_from_request(move |_| {
+            let body = match calls.fetch_add(1, Ordering::SeqCst) {
+                0 => json!({
+                    "lastUpdateId": 100,
+                    "bids": [["100", "1"], ["99", "2"]],
+                    "asks": [["101", "1"]],
+                }),
+                _ => json!({
+                    "lastUpdateId": 120,
+                    "bids": [["100", "2"], ["97", "1"]],
+                    "asks": [["103", "5"]],
+                }),
+            };
+            body.to_string().into_bytes()
+        })
+        .expect(2)
+        .create_async()
+        .await;
+
+    let (ws_url, path) = diff_stream(vec![
+        // Already in the first snapshot
+        diff(95, 100, &[["100", "5"]], &[]),
+        diff(99, 102, &[["98", "3"]], &[["101", "0"], ["102", "4"]]),
+        diff(103, 105, &[["99", "0"]], &[]),
+        // Updates 106..=109 are lost
+        diff(110, 112, &[["96", "1"]], &[]),
+        diff(113, 121, &[], &[["104", "1"]]),
+        diff(122, 123, &[["100", "0"]], &[]),
+    ])
+    .await;
+
+    let connector = BinanceConnector::with_config(BinanceConfig {
+        ws_url,
+        rest_url: server.url(),
+        ..BinanceConfig::new()
+    });
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let stream = tokio::spawn(async move {
+        connector
+            .stream_orderbook("btcusdt", move |event| {
+                if let StreamEvent::Data { data: book, .. } = event {
+                    let _ = tx.send(book);
+                }
+            })
+            .await
+    });
+
+    let mut books = Vec::new();
+    let synced = tokio::time::timeout(Duration::from_secs(5), async {
+        while let Some(book) = rx.recv().await {
+            let done = pairs(&book.bids) == vec![(d("97"), d("1"))]
+                && pairs(&book.asks) == vec![(d("103"), d("5")), (d("104"), d("1"))];
+            books.push(book);
+            if done {
+                return;
+            }
+        }
+    })
+    .await;
+    stream.abort();
+    assert!(synced.is_ok(), "book never converged: {:?}", books.last());
+
+    assert_eq!(*path.lock().unwrap(), "/ws/btcusdt@depth@100ms");
+    depth.assert_async().await;
+    // Diffs already covered by a snapshot were never applied
+    let ever_bid = |level| {
+        books
+            .iter()
+            .any(|b: &OrderBookSnapshot| pairs(&b.bids).contains(&level))
+    };
+    assert!(!ever_bid((d("100"), d("5"))));
+    assert!(!ever_bid((d("96"), d("1"))));
+    assert!(books.iter().all(|b| b.symbol == "BTCUSDT"));
+}
+
+#[tokio::test]
+async fn test_waits_before_refetching_a_stale_snapshot() {
+    // The first snapshot predates the first diff, the second one covers it
+    let mut server = mockito::Server::new_async().await;
+    let calls = AtomicUsize::new(0);
+    let depth = server
+        .mock("GET", "/api/v3/depth")
+        .match_query(mockito::Matcher::Any)
+        .with_body_from_request(move |_| {
+            let last_update_id = match calls.fetch_add(1, Ordering::SeqCst) {
+                0 => 100,
+                _ => 150,
+            };
+            json!({"lastUpdateId": last_update_id, "bids": [["100", "1"]], "asks": []})
+                .to_string()
+                .into_bytes()
+        })
+        .expect(2)
+        .create_async()
+        .await;
+    let (ws_url, _) = diff_stream(vec![diff(150, 151, &[["99", "2"]], &[])]).await;
+
+    let connector = BinanceConnector::with_config(BinanceConfig {
+        ws_url,
+        rest_url: server.url(),
+        reconnect: ReconnectPolicy {
+            initial_backoff: Duration::from_millis(300),
+            jitter: 0.0,
+            ..ReconnectPolicy::new()
+        },
+        ..BinanceConfig::new()
+    });
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let started = tokio::time::Instant::now();
+    let stream = tokio::spawn(async move {
+        connector
+            .stream_orderbook("btcusdt", move |event| {
+                if let StreamEvent::Data { data: book, .. } = event {
+                    let _ = tx.send(book);
+                }
+            })
+            .await
+    });
+
+    let book = tokio::time::timeout(Duration::from_secs(5), rx.recv())
+        .await
+        .expect("book never synchronised")
+        .unwrap();
+    stream.abort();
+    assert!(started.elapsed() >= Duration::from_millis(300));
+    assert_eq!(
+        pairs(&book.bids),
+        vec![(d("100"), d("1")), (d("99"), d("2"))]
+    );
+    depth.assert_async().await;
+}
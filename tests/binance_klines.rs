@@ -0,0 +1,191 @@
+//! Binance klines: paginated REST download against mockito and the kline
+//! stream against a local WebSocket stand-in.
+
+use futures::{SinkExt, StreamExt};
+use mockito::Matcher;
+use quantumflow::connectors::binance::klines::KlineInterval;
+use quantumflow::connectors::binance::{BinanceConfig, BinanceConnector};
+use quantumflow::connectors::reconnect::StreamEvent;
+use rust_decimal::Decimal;
+use serde_json::json;
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio::sync::mpsc;
+use tokio_tungstenite::tungstenite::Message;
+
+const HOUR: i64 = 60 * 60 * 1000;
+const START: i64 = 1_700_000_000_000 - 1_700_000_000_000 % HOUR;
+
+fn row(hour: i64, close: &str) -> serde_json::Value {
+    let open_time = START + hour * HOUR;
+    json!([
+        open_time,
+        "100",
+        "110",
+        "90",
+        close,
+        "5",
+        open_time + HOUR - 1,
+        "500",
+        42,
+        "2",
+        "200",
+        "0",
+    ])
+}
+
+fn query(start_time: i64) -> Matcher {
+    Matcher::AllOf(vec![
+        Matcher::UrlEncoded("symbol".into(), "BTCUSDT".into()),
+        Matcher::UrlEncoded("interval".into(), "1h".into()),
+        Matcher::UrlEncoded("startTime".into(), start_time.to_string()),
+        Matcher::UrlEncoded("limit".into(), "2".into()),
+    ])
+}
+
+#[tokio::test]
+async fn test_downloads_paginated_klines_as_backtest_csv() {
+    let mut server = mockito::Server::new_async().await;
+    // Each page starts just after the last bar of the previous one
+    let first = server
+        .mock("GET", "/api/v3/klines")
+        .match_query(query(START))
+        .with_body(json!([row(0, "101"), row(1, "102.5")]).to_string())
+        .create_async()
+        .await;
+    let second = server
+        .mock("GET", "/api/v3/klines")
+        .match_query(query(START + HOUR + 1))
+        .with_body(json!([row(2, "103")]).to_string())
+        .create_async()
+        .await;
+
+    let connector = BinanceConnector::with_config(BinanceConfig {
+        rest_url: server.url(),
+        kline_limit: 2,
+        ..BinanceConfig::new()
+    });
+    let path =
+        std::env::temp_dir().join(format!("quantumflow-klines-{}.csv", uuid::Uuid::new_v4()));
+    let start = chrono::DateTime::from_timestamp_millis(START).unwrap();
+    let written = connector
+        .download_klines(
+            "btcusdt",
+            KlineInterval::Hour1,
+            start,
+            start + chrono::Duration::hours(3),
+            &path,
+        )
+        .await
+        .unwrap();
+    first.assert_async().await;
+    second.assert_async().await;
+    assert_eq!(written, 3);
+
+    // The layout run_backtest reads: header, close price in column 4
+    let mut reader = csv::Reader::from_path(&path).unwrap();
+    assert_eq!(
+        reader.headers().unwrap(),
+        vec!["timestamp", "open", "high", "low", "close", "volume"]
+    );
+    let records: Vec<_> = reader.records().map(Result::unwrap).collect();
+    std::fs::remove_file(&path).unwrap();
+    let closes: Vec<_> = records
+        .iter()
+        .map(|r| r[4].parse::<Decimal>().unwrap())
+        .collect();
+    assert_eq!(
+        closes,
+        ["101", "102.5", "103"].map(|c| c.parse::<Decimal>().unwrap())
+    );
+    let first_bar: chrono::DateTime<chrono::Utc> = records[0][0].parse().unwrap();
+    assert_eq!(first_bar, start);
+}
+
+#[tokio::test]
+async fn test_stops_paging_on_an_empty_page() {
+    let mut server = mockito::Server::new_async().await;
+    let empty = server
+        .mock("GET", "/api/v3/klines")
+        .match_query(Matcher::Any)
+        .with_body("[]")
+        .expect(1)
+        .create_async()
+        .await;
+
+    let connector = BinanceConnector::with_config(BinanceConfig {
+        rest_url: server.url(),
+        ..BinanceConfig::new()
+    });
+    let start = chrono::DateTime::from_timestamp_millis(START).unwrap();
+    let bars = connector
+        .fetch_klines("BTCUSDT", KlineInterval::Day1, start, start)
+        .await
+        .unwrap();
+    assert!(bars.is_empty());
+    empty.assert_async().await;
+}
+
+#[tokio::test]
+async fn test_streams_open_and_closed_bars() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let ws_url = format!("ws://{}/ws", listener.local_addr().unwrap());
+    tokio::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        let mut ws = tokio_tungstenite::accept_async(stream).await.unwrap();
+        for (close, closed) in [("100.5", false), ("101", true)] {
+            let event = json!({
+                "e": "kline", "E": chrono::Utc::now().timestamp_millis(), "s": "BTCUSDT",
+                "k": {
+                    "t": START, "T": START + 60_000 - 1, "s": "BTCUSDT", "i": "1m",
+                    "o": "100", "h": "101", "l": "99", "c": close, "v": "3", "x": closed,
+                },
+            });
+            ws.send(Message::Text(event.to_string())).await.unwrap();
+        }
+        let _ = ws.next().await;
+    });
+
+    let connector = BinanceConnector::with_config(BinanceConfig {
+        ws_url,
+        ..BinanceConfig::new()
+    });
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let stream = tokio::spawn(async move {
+        connector
+            .stream_klines("BTCUSDT", KlineInterval::Minute1, move |event| {
+                let _ = tx.send(event);
+            })
+            .await
+    });
+
+    let mut klines = Vec::new();
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while klines.len() < 2 {
+            match rx.recv().await.unwrap() {
+                // Fresh event times even though the bar opened long ago
+                StreamEvent::Data { data, stale } => {
+                    assert!(!stale);
+                    klines.push(data);
+                }
+                StreamEvent::Connected => {}
+                event => panic!("unexpected {:?}", event),
+            }
+        }
+    })
+    .await
+    .expect("no klines before the timeout");
+    stream.abort();
+
+    assert_eq!(
+        klines
+            .iter()
+            .map(|k| (k.bar.close, k.closed))
+            .collect::<Vec<_>>(),
+        vec![
+            ("100.5".parse().unwrap(), false),
+            (Decimal::from(101), true)
+        ]
+    );
+    assert_eq!(klines[1].bar.timestamp.timestamp_millis(), START);
+}
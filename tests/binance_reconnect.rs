@@ -0,0 +1,257 @@
+//! Binance stream supervision against local WebSocket stand-ins that drop,
+//! refuse or outlive their connections.
+
+use futures::SinkExt;
+use quantumflow::connectors::binance::{BinanceConfig, BinanceConnector};
+use quantumflow::connectors::reconnect::{ReconnectPolicy, StreamEvent};
+use quantumflow::{OrderBookSnapshot, Ticker};
+use rust_decimal::Decimal;
+use serde_json::json;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{mpsc, oneshot};
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::WebSocketStream;
+
+fn connector(ws_url: String, rest_url: String, policy: ReconnectPolicy) -> BinanceConnector {
+    BinanceConnector::with_config(BinanceConfig {
+        ws_url,
+        rest_url,
+        reconnect: policy,
+        ..BinanceConfig::new()
+    })
+}
+
+fn policy() -> ReconnectPolicy {
+    ReconnectPolicy {
+        initial_backoff: Duration::from_millis(10),
+        jitter: 0.0,
+        ..ReconnectPolicy::new()
+    }
+}
+
+async fn accept(listener: &TcpListener) -> WebSocketStream<TcpStream> {
+    let (stream, _) = listener.accept().await.unwrap();
+    tokio_tungstenite::accept_async(stream).await.unwrap()
+}
+
+fn diff(update_id: u64, bids: &[[&str; 2]], asks: &[[&str; 2]]) -> Message {
+    Message::Text(
+        json!({
+            "e": "depthUpdate",
+            "E": 1_700_000_000_000u64,
+            "s": "BTCUSDT",
+            "U": update_id,
+            "u": update_id,
+            "b": bids,
+            "a": asks,
+        })
+        .to_string(),
+    )
+}
+
+fn ticker(last: &str) -> Message {
+    Message::Text(
+        json!({
+            "e": "24hrTicker",
+            "E": chrono::Utc::now().timestamp_millis(),
+            "s": "BTCUSDT",
+            "b": "99",
+            "a": "101",
+            "c": last,
+            "v": "10",
+        })
+        .to_string(),
+    )
+}
+
+/// Events up to and including the next data event.
+async fn until_data<T>(rx: &mut mpsc::UnboundedReceiver<StreamEvent<T>>) -> Vec<StreamEvent<T>> {
+    let mut events = Vec::new();
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while let Some(event) = rx.recv().await {
+            let data = matches!(event, StreamEvent::Data { .. });
+            events.push(event);
+            if data {
+                return;
+            }
+        }
+    })
+    .await
+    .expect("no data before the timeout");
+    events
+}
+
+fn best(book: &OrderBookSnapshot) -> (Decimal, Decimal) {
+    (book.bids[0].price, book.asks[0].price)
+}
+
+#[tokio::test]
+async fn test_resyncs_book_after_reconnect() {
+    let mut server = mockito::Server::new_async().await;
+    let calls = AtomicUsize::new(0);
+    let depth = server
+        .mock("GET", "/api/v3/depth")
+        .match_query(mockito::Matcher::Any)
+        .with_body_from_request(move |_| {
+            let body = match calls.fetch_add(1, Ordering::SeqCst) {
+                0 => json!({"lastUpdateId": 100, "bids": [["100", "1"]], "asks": [["101", "1"]]}),
+                _ => json!({"lastUpdateId": 120, "bids": [["99", "1"]], "asks": [["102", "1"]]}),
+            };
+            body.to_string().into_bytes()
+        })
+        .expect(2)
+        .create_async()
+        .await;
+
+    // The first connection is closed by the server once its book is out
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let ws_url = format!("ws://{}/ws", listener.local_addr().unwrap());
+    let (close_tx, close_rx) = oneshot::channel::<()>();
+    tokio::spawn(async move {
+        let mut first = accept(&listener).await;
+        first.send(diff(101, &[["98", "1"]], &[])).await.unwrap();
+        close_rx.await.unwrap();
+        first.close(None).await.unwrap();
+
+        let mut second = accept(&listener).await;
+        second.send(diff(121, &[], &[["101", "0"]])).await.unwrap();
+        tokio::time::sleep(Duration::from_secs(30)).await;
+    });
+
+    let connector = connector(ws_url, server.url(), policy());
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let stream = tokio::spawn(async move {
+        connector
+            .stream_orderbook("BTCUSDT", move |event| {
+                let _ = tx.send(event);
+            })
+            .await
+    });
+
+    let events = until_data(&mut rx).await;
+    assert_eq!(events[0], StreamEvent::Connected);
+    let StreamEvent::Data { data: book, .. } = &events[1] else {
+        panic!("expected a book, got {:?}", events[1]);
+    };
+    assert_eq!(book.bids.len(), 2);
+    assert_eq!(best(book), (Decimal::from(100), Decimal::from(101)));
+
+    close_tx.send(()).unwrap();
+    let events = until_data(&mut rx).await;
+    stream.abort();
+    assert_eq!(
+        events[..3],
+        [
+            StreamEvent::Disconnected {
+                reason: "connection closed".to_string()
+            },
+            StreamEvent::Reconnecting {
+                attempt: 1,
+                delay: Duration::from_millis(10)
+            },
+            StreamEvent::Connected,
+        ]
+    );
+    // Rebuilt from the new connection's snapshot, nothing carried over
+    let StreamEvent::Data { data: book, .. } = &events[3] else {
+        panic!("expected a book, got {:?}", events[3]);
+    };
+    assert_eq!(book.bids.len(), 1);
+    assert_eq!(best(book), (Decimal::from(99), Decimal::from(102)));
+    depth.assert_async().await;
+}
+
+#[tokio::test]
+async fn test_gives_up_after_max_retries() {
+    let unused = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let ws_url = format!("ws://{}/ws", unused.local_addr().unwrap());
+    drop(unused);
+
+    let connector = connector(
+        ws_url,
+        "http://127.0.0.1:1".to_string(),
+        ReconnectPolicy {
+            max_retries: Some(2),
+            ..policy()
+        },
+    );
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let result = connector
+        .stream_ticker("BTCUSDT", move |event| {
+            let _ = tx.send(event);
+        })
+        .await;
+    let mut events = Vec::new();
+    while let Ok(event) = rx.try_recv() {
+        events.push(event);
+    }
+
+    let error = result.unwrap_err().to_string();
+    assert!(error.contains("Giving up"), "{}", error);
+    assert_eq!(
+        events,
+        vec![
+            StreamEvent::<Ticker>::Reconnecting {
+                attempt: 1,
+                delay: Duration::from_millis(10)
+            },
+            StreamEvent::Reconnecting {
+                attempt: 2,
+                delay: Duration::from_millis(20)
+            },
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_renews_connection_at_age_limit() {
+    // Stands in for Binance's 24 hour limit without waiting for it
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let ws_url = format!("ws://{}/ws", listener.local_addr().unwrap());
+    tokio::spawn(async move {
+        for last in ["100", "101"] {
+            let mut ws = accept(&listener).await;
+            tokio::spawn(async move {
+                ws.send(ticker(last)).await.unwrap();
+                tokio::time::sleep(Duration::from_secs(30)).await;
+            });
+        }
+    });
+
+    let connector = connector(
+        ws_url,
+        "http://127.0.0.1:1".to_string(),
+        ReconnectPolicy {
+            max_connection_age: Duration::from_millis(200),
+            ..policy()
+        },
+    );
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let stream = tokio::spawn(async move {
+        connector
+            .stream_ticker("BTCUSDT", move |event| {
+                let _ = tx.send(event);
+            })
+            .await
+    });
+
+    let first = until_data(&mut rx).await;
+    let renewed = until_data(&mut rx).await;
+    stream.abort();
+    assert_eq!(first[0], StreamEvent::Connected);
+    assert_eq!(
+        renewed[..2],
+        [
+            StreamEvent::Disconnected {
+                reason: "connection age limit reached".to_string()
+            },
+            StreamEvent::Connected,
+        ]
+    );
+    let StreamEvent::Data { data: ticker, .. } = &renewed[2] else {
+        panic!("expected a ticker, got {:?}", renewed[2]);
+    };
+    assert_eq!(ticker.last, Decimal::from(101));
+}
@@ -0,0 +1,149 @@
+//! Binance trade tape streams against local WebSocket stand-ins.
+
+use futures::{SinkExt, StreamExt};
+use quantumflow::connectors::binance::combined::{subscription_channel, MarketEvent, Subscription};
+use quantumflow::connectors::binance::{BinanceConfig, BinanceConnector};
+use quantumflow::connectors::reconnect::StreamEvent;
+use quantumflow::{MarketTrade, Side};
+use rust_decimal::Decimal;
+use serde_json::{json, Value};
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio::sync::{mpsc, oneshot};
+use tokio_tungstenite::tungstenite::handshake::server::{Request, Response};
+use tokio_tungstenite::tungstenite::Message;
+
+fn trade(id: u64, price: &str, buyer_maker: bool) -> Value {
+    json!({
+        "e": "trade", "E": 1_700_000_000_100u64 + id, "s": "BTCUSDT", "t": id,
+        "p": price, "q": "0.5", "T": 1_700_000_000_000u64, "m": buyer_maker, "M": true,
+    })
+}
+
+fn agg_trade(id: u64, price: &str) -> Value {
+    json!({
+        "e": "aggTrade", "E": 1_700_000_000_200u64, "s": "ETHUSDT", "a": id,
+        "p": price, "q": "2", "f": 10, "l": 12, "T": 1_700_000_000_000u64, "m": false, "M": true,
+    })
+}
+
+async fn recv<T>(rx: &mut mpsc::UnboundedReceiver<T>) -> T {
+    tokio::time::timeout(Duration::from_secs(5), rx.recv())
+        .await
+        .expect("no trade before the timeout")
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_streams_trades_in_tape_order() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let ws_url = format!("ws://{}/ws", listener.local_addr().unwrap());
+    let (path_tx, path_rx) = oneshot::channel();
+    tokio::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        #[allow(clippy::result_large_err)]
+        let record_path = |request: &Request, response: Response| {
+            path_tx.send(request.uri().path().to_string()).unwrap();
+            Ok(response)
+        };
+        let mut ws = tokio_tungstenite::accept_hdr_async(stream, record_path)
+            .await
+            .unwrap();
+        for message in [
+            trade(1, "43000.5", true).to_string(),
+            "{\"e\":\"trade\",\"t\":2,\"p\":\"garbled\"}".to_string(),
+            trade(3, "43001", false).to_string(),
+        ] {
+            ws.send(Message::Text(message)).await.unwrap();
+        }
+        let _ = ws.next().await;
+    });
+
+    let connector = BinanceConnector::with_config(BinanceConfig {
+        ws_url,
+        ..BinanceConfig::new()
+    });
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let stream = tokio::spawn(async move {
+        connector
+            .stream_trades("BTCUSDT", move |event| {
+                if let StreamEvent::Data { data, .. } = event {
+                    let _ = tx.send(data);
+                }
+            })
+            .await
+    });
+
+    let first: MarketTrade = recv(&mut rx).await;
+    let second = recv(&mut rx).await;
+    stream.abort();
+    assert_eq!(path_rx.await.unwrap(), "/ws/btcusdt@trade");
+    // The malformed print is skipped rather than surfacing as a zero price
+    assert_eq!((first.trade_id, second.trade_id), (1, 3));
+    assert_eq!(first.price, "43000.5".parse::<Decimal>().unwrap());
+    assert_eq!(first.aggressor(), Side::Sell);
+    assert_eq!(second.aggressor(), Side::Buy);
+    assert_eq!(second.timestamp.timestamp_millis(), 1_700_000_000_103);
+}
+
+#[tokio::test]
+async fn test_routes_trades_and_agg_trades_on_combined_stream() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let ws_url = format!("ws://{}/stream", listener.local_addr().unwrap());
+    let (request_tx, request_rx) = oneshot::channel();
+    tokio::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        let mut ws = tokio_tungstenite::accept_async(stream).await.unwrap();
+        let Some(Ok(Message::Text(request))) = ws.next().await else {
+            panic!("expected a subscription request");
+        };
+        request_tx
+            .send(serde_json::from_str::<Value>(&request).unwrap())
+            .unwrap();
+        for (stream, data) in [
+            ("btcusdt@trade", trade(7, "43000", true)),
+            ("ethusdt@aggTrade", agg_trade(99, "2200.25")),
+        ] {
+            let message = json!({"stream": stream, "data": data}).to_string();
+            ws.send(Message::Text(message)).await.unwrap();
+        }
+        let _ = ws.next().await;
+    });
+
+    let connector = BinanceConnector::with_config(BinanceConfig {
+        combined_ws_url: ws_url,
+        ..BinanceConfig::new()
+    });
+    let (_handle, commands) = subscription_channel();
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let stream = tokio::spawn(async move {
+        connector
+            .stream_combined(
+                &[
+                    Subscription::trade("BTCUSDT"),
+                    Subscription::agg_trade("ETHUSDT"),
+                ],
+                commands,
+                move |event| {
+                    if let StreamEvent::Data { data, .. } = event {
+                        let _ = tx.send(data);
+                    }
+                },
+            )
+            .await
+    });
+
+    let trade = recv(&mut rx).await;
+    let agg = recv(&mut rx).await;
+    stream.abort();
+    assert_eq!(
+        request_rx.await.unwrap()["params"],
+        json!(["btcusdt@trade", "ethusdt@aggTrade"])
+    );
+    let (MarketEvent::Trade(trade), MarketEvent::Trade(agg)) = (trade, agg) else {
+        panic!("expected two trades");
+    };
+    assert_eq!((trade.symbol.as_str(), trade.trade_id), ("BTCUSDT", 7));
+    assert_eq!((agg.symbol.as_str(), agg.trade_id), ("ETHUSDT", 99));
+    assert_eq!(agg.price, "2200.25".parse::<Decimal>().unwrap());
+}
@@ -0,0 +1,330 @@
+//! Signed Binance REST trading against mockito stand-ins.
+
+use mockito::{Matcher, Mock, Request, ServerGuard};
+use quantumflow::connectors::binance::trading::{
+    BinanceError, Credentials, OrderRef, TradingClient,
+};
+use quantumflow::connectors::binance::BinanceConfig;
+use quantumflow::{
+    Order, OrderStatus, OrderType, PegInstruction, PegReference, Side, TrailReference,
+    TrailingOffset, TrailingStop,
+};
+use rust_decimal::Decimal;
+use serde_json::json;
+use std::time::Duration;
+
+const API_KEY: &str = "test-key";
+const SECRET: &str = "test-secret";
+
+fn client(server: &ServerGuard) -> TradingClient {
+    TradingClient::new(
+        &BinanceConfig {
+            rest_url: server.url(),
+            recv_window: Duration::from_millis(2500),
+            ..BinanceConfig::new()
+        },
+        Credentials::new(API_KEY, SECRET),
+    )
+}
+
+/// Whether the request carries our key and a valid signature over the rest
+/// of its query.
+fn signed(request: &Request) -> bool {
+    let Some((_, query)) = request.path_and_query().split_once('?') else {
+        return false;
+    };
+    let Some((payload, signature)) = query.rsplit_once("&signature=") else {
+        return false;
+    };
+    let key = request.header("X-MBX-APIKEY");
+    key.len() == 1
+        && key[0] == API_KEY
+        && Credentials::new(API_KEY, SECRET).sign(payload) == signature
+}
+
+fn timestamp(request: &Request) -> i64 {
+    let query = request.path_and_query().split_once('?').unwrap().1;
+    url::form_urlencoded::parse(query.as_bytes())
+        .find(|(key, _)| key == "timestamp")
+        .unwrap()
+        .1
+        .parse()
+        .unwrap()
+}
+
+fn order_body(status: &str, executed: &str) -> String {
+    json!({
+        "symbol": "BTCUSDT", "orderId": 28, "orderListId": -1,
+        "clientOrderId": "strategy-1", "transactTime": 1_507_725_176_595u64,
+        "price": "43000.00000000", "origQty": "0.50000000", "executedQty": executed,
+        "cummulativeQuoteQty": "0", "status": status, "timeInForce": "GTC",
+        "type": "LIMIT", "side": "BUY",
+    })
+    .to_string()
+}
+
+async fn error_mock(server: &mut ServerGuard, status: usize, code: i64) -> Mock {
+    server
+        .mock("GET", "/api/v3/order")
+        .match_query(Matcher::Any)
+        .with_status(status)
+        .with_body(json!({"code": code, "msg": "from the stand-in"}).to_string())
+        .create_async()
+        .await
+}
+
+#[tokio::test]
+async fn test_places_signed_limit_order() {
+    let mut server = mockito::Server::new_async().await;
+    let place = server
+        .mock("POST", "/api/v3/order")
+        .match_query(Matcher::AllOf(vec![
+            Matcher::UrlEncoded("symbol".into(), "BTCUSDT".into()),
+            Matcher::UrlEncoded("side".into(), "BUY".into()),
+            Matcher::UrlEncoded("type".into(), "LIMIT".into()),
+            Matcher::UrlEncoded("timeInForce".into(), "GTC".into()),
+            Matcher::UrlEncoded("price".into(), "43000".into()),
+            Matcher::UrlEncoded("quantity".into(), "0.5".into()),
+            Matcher::UrlEncoded("newClientOrderId".into(), "strategy-1".into()),
+            Matcher::UrlEncoded("recvWindow".into(), "2500".into()),
+        ]))
+        .match_request(signed)
+        .with_body(order_body("NEW", "0.00000000"))
+        .create_async()
+        .await;
+
+    let mut order = Order::new(
+        "btcusdt".to_string(),
+        Side::Buy,
+        OrderType::Limit,
+        Decimal::from(43000),
+        "0.50".parse().unwrap(),
+    );
+    order.client_id = Some("strategy-1".to_string());
+    let placed = client(&server).place_order(&order).await.unwrap();
+    place.assert_async().await;
+    assert_eq!(placed.order_id, 28);
+    assert_eq!(placed.status, OrderStatus::Open);
+    assert_eq!(placed.side, Side::Buy);
+    assert_eq!(placed.quantity, "0.5".parse::<Decimal>().unwrap());
+}
+
+#[tokio::test]
+async fn test_refuses_orders_binance_cannot_express() {
+    let mut server = mockito::Server::new_async().await;
+    let place = server
+        .mock("POST", "/api/v3/order")
+        .match_query(Matcher::Any)
+        .expect(0)
+        .create_async()
+        .await;
+    let client = client(&server);
+    let order = |order_type| {
+        Order::new(
+            "BTCUSDT".to_string(),
+            Side::Sell,
+            order_type,
+            Decimal::from(43000),
+            Decimal::ONE,
+        )
+    };
+
+    let mut pegged = order(OrderType::Limit);
+    pegged.peg = Some(PegInstruction {
+        reference: PegReference::Primary,
+        offset: Decimal::ZERO,
+        limit: None,
+    });
+    let mut trailing = order(OrderType::StopMarket);
+    trailing.stop_price = Some(Decimal::from(42000));
+    trailing.trailing = Some(TrailingStop {
+        offset: TrailingOffset::Percent(Decimal::ONE),
+        reference: TrailReference::LastTrade,
+    });
+    let mut expiring = order(OrderType::Limit);
+    expiring.expires_at = Some(chrono::Utc::now() + chrono::Duration::hours(1));
+    let no_stop_price = order(OrderType::StopLimit);
+
+    for order in [pegged, trailing, expiring, no_stop_price] {
+        assert!(matches!(
+            client.place_order(&order).await,
+            Err(BinanceError::InvalidOrder(_))
+        ));
+    }
+    place.assert_async().await;
+}
+
+#[tokio::test]
+async fn test_cancels_queries_and_lists_orders() {
+    let mut server = mockito::Server::new_async().await;
+    let cancel = server
+        .mock("DELETE", "/api/v3/order")
+        .match_query(Matcher::UrlEncoded(
+            "origClientOrderId".into(),
+            "strategy-1".into(),
+        ))
+        .match_request(signed)
+        .with_body(
+            json!({
+                "symbol": "BTCUSDT", "origClientOrderId": "strategy-1", "orderId": 28,
+                "orderListId": -1, "clientOrderId": "cancel-request-7",
+                "price": "43000", "origQty": "0.5", "executedQty": "0.2",
+                "cummulativeQuoteQty": "8600", "status": "CANCELED",
+                "timeInForce": "GTC", "type": "LIMIT", "side": "BUY",
+            })
+            .to_string(),
+        )
+        .create_async()
+        .await;
+    let query = server
+        .mock("GET", "/api/v3/order")
+        .match_query(Matcher::UrlEncoded("orderId".into(), "28".into()))
+        .match_request(signed)
+        .with_body(order_body("PARTIALLY_FILLED", "0.2"))
+        .create_async()
+        .await;
+    let open = server
+        .mock("GET", "/api/v3/openOrders")
+        .match_query(Matcher::Any)
+        .match_request(|request| signed(request) && !request.path_and_query().contains("symbol="))
+        .with_body(format!("[{}]", order_body("NEW", "0")))
+        .create_async()
+        .await;
+    let account = server
+        .mock("GET", "/api/v3/account")
+        .match_query(Matcher::Any)
+        .match_request(signed)
+        .with_body(
+            json!({"balances": [
+                {"asset": "BTC", "free": "0.5", "locked": "0.1"},
+                {"asset": "BNB", "free": "0.00000000", "locked": "0.00000000"},
+                {"asset": "USDT", "free": "1000", "locked": "0"},
+            ]})
+            .to_string(),
+        )
+        .create_async()
+        .await;
+
+    let client = client(&server);
+    let cancelled = client
+        .cancel_order("BTCUSDT", &OrderRef::ClientId("strategy-1".to_string()))
+        .await
+        .unwrap();
+    assert_eq!(cancelled.client_order_id, "strategy-1");
+    assert_eq!(cancelled.status, OrderStatus::Cancelled);
+
+    let queried = client
+        .query_order("BTCUSDT", &OrderRef::Id(28))
+        .await
+        .unwrap();
+    assert_eq!(queried.status, OrderStatus::PartiallyFilled);
+    assert_eq!(queried.executed_quantity, "0.2".parse::<Decimal>().unwrap());
+
+    assert_eq!(client.open_orders(None).await.unwrap().len(), 1);
+    let balances = client.balances().await.unwrap();
+    assert_eq!(
+        balances
+            .iter()
+            .map(|b| b.asset.as_str())
+            .collect::<Vec<_>>(),
+        ["BTC", "USDT"]
+    );
+    for mock in [cancel, query, open, account] {
+        mock.assert_async().await;
+    }
+}
+
+#[tokio::test]
+async fn test_corrects_timestamps_by_server_offset() {
+    let mut server = mockito::Server::new_async().await;
+    let ahead = 60_000;
+    let _time = server
+        .mock("GET", "/api/v3/time")
+        .with_body_from_request(move |_| {
+            let now = chrono::Utc::now().timestamp_millis();
+            json!({"serverTime": now + ahead}).to_string().into_bytes()
+        })
+        .create_async()
+        .await;
+    // Only requests stamped on the server's clock are accepted
+    let in_window = move |request: &Request| {
+        (timestamp(request) - chrono::Utc::now().timestamp_millis() - ahead).abs() < 2500
+    };
+    let accepted = server
+        .mock("GET", "/api/v3/order")
+        .match_query(Matcher::Any)
+        .match_request(move |request| signed(request) && in_window(request))
+        .with_body(order_body("FILLED", "0.5"))
+        .expect(1)
+        .create_async()
+        .await;
+    let rejected = server
+        .mock("GET", "/api/v3/order")
+        .match_query(Matcher::Any)
+        .match_request(move |request| !in_window(request))
+        .with_status(400)
+        .with_body(json!({"code": -1021, "msg": "Timestamp outside of recvWindow."}).to_string())
+        .expect(1)
+        .create_async()
+        .await;
+
+    // The first attempt is rejected, the clock resynced and the retry accepted
+    let client = client(&server);
+    let order = client
+        .query_order("BTCUSDT", &OrderRef::Id(28))
+        .await
+        .unwrap();
+    assert_eq!(order.status, OrderStatus::Filled);
+    assert!((client.time_offset() - ahead).abs() < 1000);
+    rejected.assert_async().await;
+    accepted.assert_async().await;
+}
+
+#[tokio::test]
+async fn test_maps_failures_to_typed_errors() {
+    let mut server = mockito::Server::new_async().await;
+    let client = client(&server);
+    let query = || client.query_order("BTCUSDT", &OrderRef::Id(1));
+
+    let mock = error_mock(&mut server, 400, -2013).await;
+    assert!(matches!(query().await, Err(BinanceError::UnknownOrder(_))));
+    mock.remove_async().await;
+
+    let mock = error_mock(&mut server, 401, -2015).await;
+    assert!(matches!(query().await, Err(BinanceError::Unauthorized(_))));
+    mock.remove_async().await;
+
+    let mock = error_mock(&mut server, 400, -1102).await;
+    assert!(matches!(
+        query().await,
+        Err(BinanceError::InvalidRequest { code: -1102, .. })
+    ));
+    mock.remove_async().await;
+
+    let mock = server
+        .mock("GET", "/api/v3/order")
+        .match_query(Matcher::Any)
+        .with_status(429)
+        .with_header("Retry-After", "7")
+        .create_async()
+        .await;
+    assert!(matches!(
+        query().await,
+        Err(BinanceError::RateLimited {
+            retry_after: Some(delay)
+        }) if delay == Duration::from_secs(7)
+    ));
+    mock.remove_async().await;
+
+    let _mock = server
+        .mock("GET", "/api/v3/order")
+        .match_query(Matcher::Any)
+        .with_status(502)
+        .with_body("<html>bad gateway</html>")
+        .create_async()
+        .await;
+    assert!(matches!(
+        query().await,
+        Err(BinanceError::Http { status: 502, .. })
+    ));
+}
@@ -0,0 +1,258 @@
+//! Binance user-data stream: listen key lifecycle against mockito and
+//! account events from a local WebSocket stand-in feeding the risk manager.
+
+use futures::{SinkExt, StreamExt};
+use mockito::Matcher;
+use quantumflow::connectors::binance::trading::{Credentials, TradingClient};
+use quantumflow::connectors::binance::user_data::UserDataEvent;
+use quantumflow::connectors::binance::BinanceConfig;
+use quantumflow::connectors::reconnect::{ReconnectPolicy, StreamEvent};
+use quantumflow::{ExecType, RiskLimits, RiskManager};
+use rust_decimal::Decimal;
+use serde_json::json;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio::sync::mpsc;
+use tokio_tungstenite::tungstenite::handshake::server::{Request, Response};
+use tokio_tungstenite::tungstenite::Message;
+
+fn fill(order_id: u64, exec_type: &str, last_quantity: &str, filled: &str) -> Message {
+    Message::Text(
+        json!({
+            "e": "executionReport", "E": chrono::Utc::now().timestamp_millis(),
+            "s": "ETHUSDT", "c": "web_8f2a", "S": "BUY", "o": "LIMIT", "f": "GTC",
+            "q": "1.00000000", "p": "2000.00000000", "P": "0", "x": exec_type,
+            "X": if filled == "0" { "NEW" } else { "PARTIALLY_FILLED" }, "r": "NONE",
+            "i": order_id, "l": last_quantity, "z": filled, "L": "2000.00000000",
+            "n": "0", "N": null, "T": chrono::Utc::now().timestamp_millis(),
+            "t": if exec_type == "TRADE" { order_id as i64 * 10 } else { -1 },
+        })
+        .to_string(),
+    )
+}
+
+#[tokio::test]
+async fn test_feeds_account_events_into_risk_and_renews_expired_keys() {
+    let mut server = mockito::Server::new_async().await;
+    let created = AtomicUsize::new(0);
+    let create = server
+        .mock("POST", "/api/v3/userDataStream")
+        .match_header("X-MBX-APIKEY", "test-key")
+        .with_body_from_request(move |_| {
+            let n = created.fetch_add(1, Ordering::SeqCst) + 1;
+            json!({"listenKey": format!("key-{}", n)})
+                .to_string()
+                .into_bytes()
+        })
+        .expect(2)
+        .create_async()
+        .await;
+    let keepalive = server
+        .mock("PUT", "/api/v3/userDataStream")
+        .match_query(Matcher::UrlEncoded("listenKey".into(), "key-1".into()))
+        .match_header("X-MBX-APIKEY", "test-key")
+        .with_body("{}")
+        .expect_at_least(1)
+        .create_async()
+        .await;
+
+    // The first key expires after a keepalive; the second connection must
+    // use a fresh one
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let ws_url = format!("ws://{}/ws", listener.local_addr().unwrap());
+    let (path_tx, mut paths) = mpsc::unbounded_channel();
+    tokio::spawn(async move {
+        for round in 0..2 {
+            let (stream, _) = listener.accept().await.unwrap();
+            let path_tx = path_tx.clone();
+            #[allow(clippy::result_large_err)]
+            let record_path = move |request: &Request, response: Response| {
+                path_tx.send(request.uri().path().to_string()).unwrap();
+                Ok(response)
+            };
+            let mut ws = tokio_tungstenite::accept_hdr_async(stream, record_path)
+                .await
+                .unwrap();
+            if round == 0 {
+                ws.send(fill(1, "NEW", "0", "0")).await.unwrap();
+                ws.send(fill(1, "TRADE", "0.5", "0.5")).await.unwrap();
+                let balances = json!({
+                    "e": "outboundAccountPosition", "E": chrono::Utc::now().timestamp_millis(),
+                    "u": 1, "B": [{"a": "ETH", "f": "9.5", "l": "0.5"}],
+                });
+                ws.send(Message::Text(balances.to_string())).await.unwrap();
+                tokio::time::sleep(Duration::from_millis(300)).await;
+                let expired = json!({"e": "listenKeyExpired", "E": 1, "listenKey": "key-1"});
+                ws.send(Message::Text(expired.to_string())).await.unwrap();
+                let _ = ws.next().await;
+            } else {
+                ws.send(fill(2, "TRADE", "0.25", "0.25")).await.unwrap();
+                tokio::time::sleep(Duration::from_secs(30)).await;
+            }
+        }
+    });
+
+    let client = TradingClient::new(
+        &BinanceConfig {
+            ws_url,
+            rest_url: server.url(),
+            listen_key_keepalive: Duration::from_millis(100),
+            reconnect: ReconnectPolicy {
+                initial_backoff: Duration::from_millis(10),
+                jitter: 0.0,
+                ..ReconnectPolicy::new()
+            },
+            ..BinanceConfig::new()
+        },
+        Credentials::new("test-key", "test-secret"),
+    );
+    let risk = Arc::new(RiskManager::new(RiskLimits::default()));
+    let stream_risk = risk.clone();
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let stream = tokio::spawn(async move {
+        client
+            .stream_user_data(stream_risk, move |event| {
+                if let StreamEvent::Data { data, .. } = event {
+                    let _ = tx.send(data);
+                }
+            })
+            .await
+    });
+
+    let mut events = Vec::new();
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while events.len() < 4 {
+            events.push(rx.recv().await.unwrap());
+        }
+    })
+    .await
+    .expect("not every event arrived");
+    stream.abort();
+
+    let exec_types: Vec<_> = events
+        .iter()
+        .filter_map(|event| match event {
+            UserDataEvent::Execution(report) => Some(report.exec_type),
+            UserDataEvent::Account { .. } => None,
+        })
+        .collect();
+    assert_eq!(
+        exec_types,
+        [ExecType::New, ExecType::Trade, ExecType::Trade]
+    );
+    assert_eq!(
+        risk.get_position("ETHUSDT").quantity,
+        "0.75".parse::<Decimal>().unwrap()
+    );
+    assert_eq!(risk.get_balance("ETH"), Decimal::from(10));
+    assert_eq!(paths.recv().await.unwrap(), "/ws/key-1");
+    assert_eq!(paths.recv().await.unwrap(), "/ws/key-2");
+    create.assert_async().await;
+    keepalive.assert_async().await;
+}
+
+#[tokio::test]
+async fn test_closes_listen_key() {
+    let mut server = mockito::Server::new_async().await;
+    let close = server
+        .mock("DELETE", "/api/v3/userDataStream")
+        .match_query(Matcher::UrlEncoded("listenKey".into(), "key-1".into()))
+        .match_header("X-MBX-APIKEY", "test-key")
+        .with_body("{}")
+        .create_async()
+        .await;
+    let client = TradingClient::new(
+        &BinanceConfig {
+            rest_url: server.url(),
+            ..BinanceConfig::new()
+        },
+        Credentials::new("test-key", "test-secret"),
+    );
+    client.close_listen_key("key-1").await.unwrap();
+    close.assert_async().await;
+}
+
+#[tokio::test]
+async fn test_reports_orders_placed_under_a_client_id_by_their_own_id() {
+    let mut server = mockito::Server::new_async().await;
+    server
+        .mock("POST", "/api/v3/userDataStream")
+        .with_body(json!({"listenKey": "key-1"}).to_string())
+        .create_async()
+        .await;
+    let place = server
+        .mock("POST", "/api/v3/order")
+        .match_query(Matcher::UrlEncoded("newClientOrderId".into(), "abc".into()))
+        .with_body(
+            json!({
+                "symbol": "ETHUSDT", "orderId": 7, "clientOrderId": "abc",
+                "transactTime": 1_700_000_000_000u64, "price": "2000.00000000",
+                "origQty": "1.00000000", "executedQty": "0.00000000",
+                "status": "NEW", "timeInForce": "GTC", "type": "LIMIT", "side": "BUY",
+            })
+            .to_string(),
+        )
+        .create_async()
+        .await;
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let ws_url = format!("ws://{}/ws", listener.local_addr().unwrap());
+    let (placed_tx, placed) = tokio::sync::oneshot::channel::<()>();
+    tokio::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        let mut ws = tokio_tungstenite::accept_async(stream).await.unwrap();
+        placed.await.unwrap();
+        let mut report = fill(7, "NEW", "0", "0");
+        if let Message::Text(text) = &mut report {
+            *text = text.replace("web_8f2a", "abc");
+        }
+        ws.send(report).await.unwrap();
+        tokio::time::sleep(Duration::from_secs(30)).await;
+    });
+
+    let client = Arc::new(TradingClient::new(
+        &BinanceConfig {
+            ws_url,
+            rest_url: server.url(),
+            ..BinanceConfig::new()
+        },
+        Credentials::new("test-key", "test-secret"),
+    ));
+    let mut order = quantumflow::Order::new(
+        "ETHUSDT".to_string(),
+        quantumflow::Side::Buy,
+        quantumflow::OrderType::Limit,
+        Decimal::from(2000),
+        Decimal::ONE,
+    );
+    order.client_id = Some("abc".to_string());
+    client.place_order(&order).await.unwrap();
+    place.assert_async().await;
+    placed_tx.send(()).unwrap();
+
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let stream_client = client.clone();
+    let risk = Arc::new(RiskManager::new(RiskLimits::default()));
+    let stream = tokio::spawn(async move {
+        stream_client
+            .stream_user_data(risk, move |event| {
+                if let StreamEvent::Data { data, .. } = event {
+                    let _ = tx.send(data);
+                }
+            })
+            .await
+    });
+    let event = tokio::time::timeout(Duration::from_secs(5), rx.recv())
+        .await
+        .expect("no report arrived")
+        .unwrap();
+    stream.abort();
+
+    let UserDataEvent::Execution(report) = event else {
+        panic!("expected an execution report");
+    };
+    assert_eq!(report.order_id, order.id);
+    assert_eq!(report.client_id.as_deref(), Some("abc"));
+}
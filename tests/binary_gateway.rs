@@ -0,0 +1,261 @@
+//! Binary order-entry server tests with the Rust client.
+
+use quantumflow::engine::matching::MatchingEngine;
+use quantumflow::gateway::binary::client::BinaryClient;
+use quantumflow::gateway::binary::codec::{
+    CancelReason, ClientMessage, EnterOrder, RejectReason, ServerMessage,
+};
+use quantumflow::gateway::binary::BinaryServer;
+use quantumflow::{OrderType, Side};
+use rust_decimal::Decimal;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpListener;
+
+async fn start() -> (Arc<MatchingEngine>, SocketAddr) {
+    let engine = Arc::new(MatchingEngine::new());
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(Arc::new(BinaryServer::new(engine.clone())).serve(listener));
+    (engine, addr)
+}
+
+async fn recv(client: &mut BinaryClient) -> ServerMessage {
+    tokio::time::timeout(Duration::from_secs(5), client.recv())
+        .await
+        .expect("no message from server")
+        .unwrap()
+        .expect("server closed the connection")
+}
+
+#[tokio::test]
+async fn test_enter_execute_and_cancel() {
+    let (engine, addr) = start().await;
+    let mut maker = BinaryClient::connect(addr).await.unwrap();
+    let mut taker = BinaryClient::connect(addr).await.unwrap();
+
+    let bid = maker
+        .enter_order(
+            "BTCUSD",
+            Side::Buy,
+            OrderType::Limit,
+            Decimal::from(3),
+            Decimal::new(1005, 1),
+        )
+        .await
+        .unwrap();
+    let ServerMessage::Accepted(accepted) = recv(&mut maker).await else {
+        panic!("expected the order to be accepted");
+    };
+    assert_eq!(accepted.token, bid);
+    assert_eq!(accepted.price, Decimal::new(1005, 1));
+
+    taker
+        .enter_order(
+            "BTCUSD",
+            Side::Sell,
+            OrderType::Market,
+            Decimal::from(2),
+            Decimal::ZERO,
+        )
+        .await
+        .unwrap();
+    assert!(matches!(recv(&mut taker).await, ServerMessage::Accepted(_)));
+    let ServerMessage::Executed(taken) = recv(&mut taker).await else {
+        panic!("expected the taker to be executed");
+    };
+    let ServerMessage::Executed(made) = recv(&mut maker).await else {
+        panic!("expected the maker to be executed");
+    };
+    assert_eq!(made.token, bid);
+    assert_eq!(made.quantity, Decimal::from(2));
+    assert_eq!(made.match_id, taken.match_id);
+
+    maker.cancel_order(bid).await.unwrap();
+    let ServerMessage::Cancelled(cancelled) = recv(&mut maker).await else {
+        panic!("expected the rest to be cancelled");
+    };
+    assert_eq!(cancelled.quantity, Decimal::from(1));
+    assert_eq!(cancelled.reason, CancelReason::User);
+
+    maker.cancel_order(bid).await.unwrap();
+    let ServerMessage::Rejected(rejected) = recv(&mut maker).await else {
+        panic!("expected the second cancel to be rejected");
+    };
+    assert_eq!(rejected.reason, RejectReason::UnknownToken);
+
+    // Orders left open are cancelled when the connection closes
+    maker
+        .enter_order(
+            "BTCUSD",
+            Side::Buy,
+            OrderType::Limit,
+            Decimal::from(1),
+            Decimal::from(99),
+        )
+        .await
+        .unwrap();
+    recv(&mut maker).await;
+    drop(maker);
+    for _ in 0..50 {
+        if engine
+            .get_orderbook_snapshot("BTCUSD")
+            .unwrap()
+            .bids
+            .is_empty()
+        {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    panic!("the disconnected session's bid is still open");
+}
+
+#[tokio::test]
+async fn test_replace_and_token_checks() {
+    let (engine, addr) = start().await;
+    let mut client = BinaryClient::connect(addr).await.unwrap();
+
+    let ask = client
+        .enter_order(
+            "ETHUSD",
+            Side::Sell,
+            OrderType::Limit,
+            Decimal::from(5),
+            Decimal::from(2000),
+        )
+        .await
+        .unwrap();
+    recv(&mut client).await;
+
+    let replacement = client
+        .replace_order(ask, Decimal::from(4), Decimal::from(2010))
+        .await
+        .unwrap();
+    let ServerMessage::Cancelled(cancelled) = recv(&mut client).await else {
+        panic!("expected the original to be cancelled");
+    };
+    assert_eq!(cancelled.token, ask);
+    assert_eq!(cancelled.reason, CancelReason::Replaced);
+    let ServerMessage::Accepted(accepted) = recv(&mut client).await else {
+        panic!("expected the replacement to be accepted");
+    };
+    assert_eq!(accepted.token, replacement);
+    assert_eq!(accepted.side, Side::Sell);
+    assert_eq!(accepted.quantity, Decimal::from(4));
+
+    let snapshot = engine.get_orderbook_snapshot("ETHUSD").unwrap();
+    assert_eq!(snapshot.asks.len(), 1);
+    assert_eq!(snapshot.asks[0].price, Decimal::from(2010));
+
+    // The replaced token no longer names an order
+    let again = client
+        .replace_order(ask, Decimal::from(1), Decimal::from(2020))
+        .await
+        .unwrap();
+    let ServerMessage::Rejected(rejected) = recv(&mut client).await else {
+        panic!("expected the replace to be rejected");
+    };
+    assert_eq!(rejected.token, again);
+    assert_eq!(rejected.reason, RejectReason::UnknownToken);
+
+    // Tokens must keep increasing
+    let reused = ClientMessage::EnterOrder(EnterOrder {
+        token: replacement,
+        side: Side::Buy,
+        order_type: OrderType::Limit,
+        symbol: "ETHUSD".to_string(),
+        quantity: Decimal::from(1),
+        price: Decimal::from(1990),
+    });
+    client.send(&reused).await.unwrap();
+    let ServerMessage::Rejected(rejected) = recv(&mut client).await else {
+        panic!("expected the reused token to be rejected");
+    };
+    assert_eq!(rejected.reason, RejectReason::DuplicateToken);
+    assert!(engine
+        .get_orderbook_snapshot("ETHUSD")
+        .unwrap()
+        .bids
+        .is_empty());
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_replace_racing_a_trade_never_rests_twice() {
+    let (engine, addr) = start().await;
+    let mut maker = BinaryClient::connect(addr).await.unwrap();
+    let mut taker = BinaryClient::connect(addr).await.unwrap();
+
+    for _ in 0..50 {
+        let ask = maker
+            .enter_order(
+                "BTCUSD",
+                Side::Sell,
+                OrderType::Limit,
+                Decimal::from(1),
+                Decimal::from(100),
+            )
+            .await
+            .unwrap();
+        assert!(matches!(recv(&mut maker).await, ServerMessage::Accepted(_)));
+
+        // The taker lifts the ask while the maker moves it away
+        let (replacement, bid) = tokio::join!(
+            maker.replace_order(ask, Decimal::from(1), Decimal::from(101)),
+            taker.enter_order(
+                "BTCUSD",
+                Side::Buy,
+                OrderType::Limit,
+                Decimal::from(1),
+                Decimal::from(100),
+            )
+        );
+        let (replacement, bid) = (replacement.unwrap(), bid.unwrap());
+
+        let mut executed = false;
+        let mut replaced = false;
+        for _ in 0..2 {
+            match recv(&mut maker).await {
+                ServerMessage::Executed(_) => executed = true,
+                ServerMessage::Rejected(rejected) => {
+                    assert_eq!(rejected.token, replacement);
+                    // Depending on whether the fill reached the session first
+                    assert!(matches!(
+                        rejected.reason,
+                        RejectReason::TooLate | RejectReason::UnknownToken
+                    ));
+                }
+                ServerMessage::Cancelled(cancelled) => {
+                    assert_eq!(cancelled.reason, CancelReason::Replaced)
+                }
+                ServerMessage::Accepted(accepted) => {
+                    assert_eq!(accepted.token, replacement);
+                    replaced = true;
+                }
+            }
+        }
+        assert_ne!(executed, replaced, "the ask both traded and was replaced");
+        assert!(matches!(recv(&mut taker).await, ServerMessage::Accepted(_)));
+
+        let snapshot = engine.get_orderbook_snapshot("BTCUSD").unwrap();
+        if executed {
+            assert!(matches!(recv(&mut taker).await, ServerMessage::Executed(_)));
+            assert!(snapshot.asks.is_empty());
+            assert!(snapshot.bids.is_empty());
+        } else {
+            assert_eq!(snapshot.asks.len(), 1);
+            assert_eq!(snapshot.asks[0].price, Decimal::from(101));
+            maker.cancel_order(replacement).await.unwrap();
+            assert!(matches!(
+                recv(&mut maker).await,
+                ServerMessage::Cancelled(_)
+            ));
+            taker.cancel_order(bid).await.unwrap();
+            assert!(matches!(
+                recv(&mut taker).await,
+                ServerMessage::Cancelled(_)
+            ));
+        }
+    }
+}
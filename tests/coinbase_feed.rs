@@ -0,0 +1,221 @@
+//! Coinbase feed replayed from recorded messages by a local stand-in.
+
+use futures::{SinkExt, StreamExt};
+use quantumflow::connectors::coinbase::trading::Credentials;
+use quantumflow::connectors::coinbase::{CoinbaseConfig, CoinbaseConnector};
+use quantumflow::connectors::exchange::ExchangeConnector;
+use quantumflow::connectors::market::{subscription_channel, MarketEvent, Subscription};
+use quantumflow::connectors::reconnect::StreamEvent;
+use quantumflow::Side;
+use rust_decimal::Decimal;
+use serde_json::{json, Value};
+use std::time::Duration;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::WebSocketStream;
+
+const FEED: &str = include_str!("fixtures/coinbase/feed.jsonl");
+
+fn decimal(s: &str) -> Decimal {
+    s.parse().unwrap()
+}
+
+async fn next_request(ws: &mut WebSocketStream<TcpStream>) -> Value {
+    let Some(Ok(Message::Text(text))) = ws.next().await else {
+        panic!("expected a subscription request");
+    };
+    serde_json::from_str(&text).unwrap()
+}
+
+/// Serves one connection, handing each request to the test and answering
+/// it with the next batch of messages.
+async fn stand_in() -> (
+    String,
+    mpsc::UnboundedReceiver<Value>,
+    mpsc::UnboundedSender<Vec<String>>,
+) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("ws://{}", listener.local_addr().unwrap());
+    let (requests_tx, requests) = mpsc::unbounded_channel();
+    let (replies, mut replies_rx) = mpsc::unbounded_channel::<Vec<String>>();
+    tokio::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        let mut ws = tokio_tungstenite::accept_async(stream).await.unwrap();
+        while let Some(messages) = replies_rx.recv().await {
+            requests_tx.send(next_request(&mut ws).await).unwrap();
+            for message in messages {
+                ws.send(Message::Text(message)).await.unwrap();
+            }
+        }
+        tokio::time::sleep(Duration::from_secs(30)).await;
+    });
+    (url, requests, replies)
+}
+
+async fn collect(rx: &mut mpsc::UnboundedReceiver<MarketEvent>, count: usize) -> Vec<MarketEvent> {
+    let mut events = Vec::new();
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while events.len() < count {
+            events.push(rx.recv().await.unwrap());
+        }
+    })
+    .await
+    .expect("not every event arrived");
+    events
+}
+
+fn forward(tx: mpsc::UnboundedSender<MarketEvent>) -> impl FnMut(StreamEvent<MarketEvent>) {
+    move |event| {
+        if let StreamEvent::Data { data, .. } = event {
+            let _ = tx.send(data);
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_replays_recorded_feed_into_books_tickers_and_trades() {
+    let (ws_url, mut requests, replies) = stand_in().await;
+    replies
+        .send(FEED.lines().map(str::to_string).collect())
+        .unwrap();
+
+    let connector = CoinbaseConnector::with_config(CoinbaseConfig {
+        ws_url,
+        ..CoinbaseConfig::new()
+    });
+    let subscriptions = [
+        Subscription::depth("BTC-USD"),
+        Subscription::ticker("BTC-USD"),
+        Subscription::trade("BTC-USD"),
+    ];
+    let (_handle, commands) = subscription_channel();
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let stream = tokio::spawn(async move {
+        connector
+            .stream_feed(&subscriptions, commands, forward(tx))
+            .await
+    });
+
+    // Unauthenticated books come from the public batched channel
+    assert_eq!(
+        requests.recv().await.unwrap(),
+        json!({"type": "subscribe", "channels": [
+            {"name": "level2_batch", "product_ids": ["BTC-USD"]},
+            {"name": "matches", "product_ids": ["BTC-USD"]},
+            {"name": "ticker", "product_ids": ["BTC-USD"]},
+        ]})
+    );
+
+    // The last match before subscribing and the unsubscribed ETH-USD
+    // ticker are not emitted
+    let events = collect(&mut rx, 5).await;
+    stream.abort();
+    let [MarketEvent::Book(snapshot), MarketEvent::Book(updated), MarketEvent::Ticker(ticker), MarketEvent::Trade(trade), MarketEvent::Book(last)] =
+        &events[..]
+    else {
+        panic!("unexpected events {:?}", events);
+    };
+    assert_eq!(snapshot.bids[0].price, decimal("64250.01"));
+    assert_eq!(snapshot.asks.len(), 2);
+    assert_eq!(updated.bids[0].price, decimal("64250.00"));
+    assert_eq!(updated.asks[0].price, decimal("64250.25"));
+    assert_eq!(updated.asks[0].quantity, decimal("0.3"));
+    assert_eq!(ticker.bid, decimal("64250.00"));
+    assert_eq!(ticker.ask, decimal("64250.25"));
+    assert_eq!(ticker.volume_24h, decimal("12345.678"));
+    assert_eq!(trade.trade_id, 612345556);
+    assert_eq!(trade.aggressor(), Side::Buy);
+    assert_eq!(trade.quantity, decimal("0.3"));
+    assert_eq!(last.asks[0].price, decimal("64250.50"));
+    assert!(rx.try_recv().is_err());
+}
+
+#[tokio::test]
+async fn test_signs_subscriptions_and_changes_them_live() {
+    let (ws_url, mut requests, replies) = stand_in().await;
+    let connector = CoinbaseConnector::with_credentials(
+        CoinbaseConfig {
+            ws_url,
+            ..CoinbaseConfig::new()
+        },
+        Credentials::new("test-key", "c2VjcmV0", "test-passphrase").unwrap(),
+    );
+    let (handle, commands) = subscription_channel();
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let stream = tokio::spawn(async move {
+        connector
+            .stream_feed(&[Subscription::depth("ETH-USD")], commands, forward(tx))
+            .await
+    });
+
+    let snapshot = json!({
+        "type": "snapshot", "product_id": "ETH-USD",
+        "bids": [["3010.10", "4"]], "asks": [["3010.12", "2.5"]],
+    });
+    replies.send(vec![snapshot.to_string()]).unwrap();
+    let request = requests.recv().await.unwrap();
+    assert_eq!(
+        request["channels"],
+        json!([{"name": "level2", "product_ids": ["ETH-USD"]}])
+    );
+    assert_eq!(request["key"], "test-key");
+    assert_eq!(request["passphrase"], "test-passphrase");
+    let signed = Credentials::new("test-key", "c2VjcmV0", "test-passphrase")
+        .unwrap()
+        .sign(
+            request["timestamp"].as_str().unwrap(),
+            "GET",
+            "/users/self/verify",
+            "",
+        );
+    assert_eq!(request["signature"], signed);
+    assert!(matches!(
+        collect(&mut rx, 1).await[..],
+        [MarketEvent::Book(_)]
+    ));
+
+    // A ticker subscribed while running arrives alongside book updates
+    let ticker = FEED.lines().last().unwrap().to_string();
+    let update = json!({
+        "type": "l2update", "product_id": "ETH-USD",
+        "time": "2024-05-01T12:00:01.000000Z", "changes": [["buy", "3010.11", "1"]],
+    });
+    replies.send(vec![ticker, update.to_string()]).unwrap();
+    handle
+        .subscribe(&[Subscription::ticker("ETH-USD")])
+        .unwrap();
+    assert_eq!(
+        requests.recv().await.unwrap()["channels"],
+        json!([{"name": "ticker", "product_ids": ["ETH-USD"]}])
+    );
+    replies.send(vec![]).unwrap();
+    let events = collect(&mut rx, 2).await;
+    assert!(matches!(events[0], MarketEvent::Ticker(_)));
+    assert!(matches!(events[1], MarketEvent::Book(_)));
+
+    handle
+        .unsubscribe(&[Subscription::depth("ETH-USD")])
+        .unwrap();
+    assert_eq!(requests.recv().await.unwrap()["type"], "unsubscribe");
+    stream.abort();
+}
+
+#[tokio::test]
+async fn test_refuses_to_stream_channels_coinbase_lacks() {
+    // Fails before connecting anywhere
+    let connector: Box<dyn ExchangeConnector> = Box::new(CoinbaseConnector::new());
+    let (_handle, commands) = subscription_channel();
+    let streamed = connector
+        .stream_market_data(
+            &[
+                Subscription::ticker("BTC-USD"),
+                Subscription::agg_trade("BTC-USD"),
+            ],
+            commands,
+            Box::new(|_| {}),
+        )
+        .await;
+    let error = streamed.unwrap_err().to_string();
+    assert!(error.contains("AggTrade"), "{}", error);
+}
@@ -0,0 +1,325 @@
+//! Signed Coinbase REST trading against mockito stand-ins serving recorded
+//! responses.
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use mockito::{Matcher, Request, ServerGuard};
+use quantumflow::connectors::coinbase::trading::{CoinbaseError, Credentials, TradingClient};
+use quantumflow::connectors::coinbase::{CoinbaseConfig, CoinbaseConnector};
+use quantumflow::connectors::exchange::{ExchangeConnector, OrderRef};
+use quantumflow::{
+    Order, OrderStatus, OrderType, PegInstruction, PegReference, Side, TrailReference,
+    TrailingOffset, TrailingStop,
+};
+use rust_decimal::Decimal;
+use serde_json::{json, Value};
+use std::time::Duration;
+
+const API_KEY: &str = "test-key";
+const PASSPHRASE: &str = "test-passphrase";
+const ORDER_ID: &str = "d0c5340b-6d6c-49d9-b567-48c4bfca13d2";
+const CLIENT_OID: &str = "b4b6c1f8-5a0f-4a2e-9c64-0f1b2d3c4e5f";
+
+fn credentials() -> Credentials {
+    Credentials::new(API_KEY, &BASE64.encode(b"test-secret"), PASSPHRASE).unwrap()
+}
+
+fn config(server: &ServerGuard) -> CoinbaseConfig {
+    CoinbaseConfig {
+        rest_url: server.url(),
+        ..CoinbaseConfig::new()
+    }
+}
+
+fn fixture(key: &str) -> String {
+    let orders: Value =
+        serde_json::from_str(include_str!("fixtures/coinbase/orders.json")).unwrap();
+    orders[key].to_string()
+}
+
+/// Whether the request carries our key and passphrase and a valid signature
+/// over its timestamp, method, path and body.
+fn signed(request: &Request) -> bool {
+    let header = |name: &str| {
+        request
+            .header(name)
+            .first()
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string)
+    };
+    let (Some(key), Some(passphrase), Some(timestamp), Some(signature)) = (
+        header("CB-ACCESS-KEY"),
+        header("CB-ACCESS-PASSPHRASE"),
+        header("CB-ACCESS-TIMESTAMP"),
+        header("CB-ACCESS-SIGN"),
+    ) else {
+        return false;
+    };
+    let body = request.utf8_lossy_body().unwrap_or_default();
+    key == API_KEY
+        && passphrase == PASSPHRASE
+        && credentials().sign(
+            &timestamp,
+            request.method(),
+            request.path_and_query(),
+            &body,
+        ) == signature
+}
+
+#[tokio::test]
+async fn test_places_signed_limit_order() {
+    let mut server = mockito::Server::new_async().await;
+    let place = server
+        .mock("POST", "/orders")
+        .match_body(Matcher::Json(json!({
+            "client_oid": CLIENT_OID,
+            "product_id": "BTC-USD",
+            "side": "buy",
+            "type": "limit",
+            "price": "64000",
+            "size": "0.01",
+            "time_in_force": "GTC",
+        })))
+        .match_request(signed)
+        .with_body(fixture("placed"))
+        .create_async()
+        .await;
+
+    let mut order = Order::new(
+        "btc-usd".to_string(),
+        Side::Buy,
+        OrderType::Limit,
+        Decimal::from(64000),
+        "0.010".parse().unwrap(),
+    );
+    order.client_id = Some(CLIENT_OID.to_string());
+    let placed = TradingClient::new(&config(&server), credentials())
+        .place_order(&order)
+        .await
+        .unwrap();
+    place.assert_async().await;
+    assert_eq!(placed.id, ORDER_ID);
+    assert_eq!(placed.order_status(), OrderStatus::Pending);
+    assert_eq!(placed.size, Some("0.01".parse().unwrap()));
+}
+
+#[tokio::test]
+async fn test_refuses_orders_coinbase_cannot_express() {
+    let mut server = mockito::Server::new_async().await;
+    let place = server
+        .mock("POST", "/orders")
+        .expect(0)
+        .create_async()
+        .await;
+    let client = TradingClient::new(&config(&server), credentials());
+    let order = |order_type| {
+        Order::new(
+            "BTC-USD".to_string(),
+            Side::Sell,
+            order_type,
+            Decimal::from(64000),
+            Decimal::ONE,
+        )
+    };
+
+    let mut pegged = order(OrderType::Limit);
+    pegged.peg = Some(PegInstruction {
+        reference: PegReference::Midpoint,
+        offset: Decimal::ZERO,
+        limit: None,
+    });
+    let mut trailing = order(OrderType::StopMarket);
+    trailing.stop_price = Some(Decimal::from(63000));
+    trailing.trailing = Some(TrailingStop {
+        offset: TrailingOffset::Amount(Decimal::from(100)),
+        reference: TrailReference::BestPrice,
+    });
+    let mut expiring = order(OrderType::Limit);
+    expiring.expires_at = Some(chrono::Utc::now() + chrono::Duration::minutes(90));
+    let no_stop_price = order(OrderType::StopMarket);
+
+    for order in [pegged, trailing, expiring, no_stop_price] {
+        assert!(matches!(
+            client.place_order(&order).await,
+            Err(CoinbaseError::InvalidOrder(_))
+        ));
+    }
+    place.assert_async().await;
+}
+
+#[tokio::test]
+async fn test_trades_through_the_connector_trait() {
+    let mut server = mockito::Server::new_async().await;
+    // Read before and after the cancel
+    let query = server
+        .mock("GET", format!("/orders/client:{}", CLIENT_OID).as_str())
+        .match_request(signed)
+        .with_body(fixture("open"))
+        .expect(2)
+        .create_async()
+        .await;
+    let cancel = server
+        .mock("DELETE", format!("/orders/client:{}", CLIENT_OID).as_str())
+        .match_request(signed)
+        .with_body(json!(ORDER_ID).to_string())
+        .create_async()
+        .await;
+    // Two pages, chained by the CB-AFTER cursor
+    let first_page = server
+        .mock("GET", "/orders")
+        .match_query(Matcher::Regex(
+            "^status=open&status=pending&status=active&limit=1000&product_id=BTC-USD$".to_string(),
+        ))
+        .match_request(signed)
+        .with_header("CB-AFTER", "cursor-1")
+        .with_body(format!("[{}]", fixture("open")))
+        .create_async()
+        .await;
+    let second_page = server
+        .mock("GET", "/orders")
+        .match_query(Matcher::UrlEncoded("after".into(), "cursor-1".into()))
+        .match_request(signed)
+        .with_body(format!("[{}]", fixture("stop")))
+        .create_async()
+        .await;
+    let accounts = server
+        .mock("GET", "/accounts")
+        .match_request(signed)
+        .with_body(fixture("accounts"))
+        .create_async()
+        .await;
+
+    let connector: Box<dyn ExchangeConnector> = Box::new(CoinbaseConnector::with_credentials(
+        config(&server),
+        credentials(),
+    ));
+    assert!(connector.capabilities().order_entry);
+
+    let cancelled = connector
+        .cancel_order("BTC-USD", &OrderRef::ClientId(CLIENT_OID.to_string()))
+        .await
+        .unwrap();
+    assert_eq!(cancelled.order_id, ORDER_ID);
+    assert_eq!(cancelled.status, OrderStatus::Cancelled);
+    assert_eq!(
+        cancelled.filled_quantity,
+        "0.004".parse::<Decimal>().unwrap()
+    );
+
+    let open = connector.open_orders(Some("btc-usd")).await.unwrap();
+    assert_eq!(open.len(), 2);
+    assert_eq!(open[0].status, OrderStatus::PartiallyFilled);
+    assert_eq!(open[1].order_type, Some(OrderType::StopLimit));
+    assert_eq!(open[1].side, Side::Sell);
+    assert_eq!(open[1].client_id, None);
+
+    // The empty USD account is left out
+    let balances = connector.balances().await.unwrap();
+    assert_eq!(balances.len(), 1);
+    assert_eq!(balances[0].asset, "BTC");
+    assert_eq!(balances[0].total(), "0.25".parse::<Decimal>().unwrap());
+
+    for mock in [query, cancel, first_page, second_page, accounts] {
+        mock.assert_async().await;
+    }
+}
+
+#[tokio::test]
+async fn test_cancel_reports_fills_made_before_it() {
+    let mut server = mockito::Server::new_async().await;
+    let client = TradingClient::new(&config(&server), credentials());
+    let order = OrderRef::Id(ORDER_ID.to_string());
+    let path = format!("/orders/{}", ORDER_ID);
+    let _cancel = server
+        .mock("DELETE", path.as_str())
+        .with_body(json!(ORDER_ID).to_string())
+        .create_async()
+        .await;
+
+    // Filled further between the first read and the cancel
+    let mut later: Value = serde_json::from_str(&fixture("open")).unwrap();
+    later["filled_size"] = json!("0.00600000");
+    later["status"] = json!("done");
+    later["done_reason"] = json!("canceled");
+    let before = server
+        .mock("GET", path.as_str())
+        .with_body(fixture("open"))
+        .expect(1)
+        .create_async()
+        .await;
+    let after = server
+        .mock("GET", path.as_str())
+        .with_body(later.to_string())
+        .create_async()
+        .await;
+    let cancelled = client.cancel_order(&order).await.unwrap();
+    assert_eq!(cancelled.order_status(), OrderStatus::Cancelled);
+    assert_eq!(cancelled.filled_size, "0.006".parse::<Decimal>().unwrap());
+    before.remove_async().await;
+    after.remove_async().await;
+
+    // Forgotten once cancelled, so reported as first read
+    let _before = server
+        .mock("GET", path.as_str())
+        .with_body(fixture("open"))
+        .expect(1)
+        .create_async()
+        .await;
+    let _gone = server
+        .mock("GET", path.as_str())
+        .with_status(404)
+        .with_body(json!({"message": "NotFound"}).to_string())
+        .create_async()
+        .await;
+    let cancelled = client.cancel_order(&order).await.unwrap();
+    assert_eq!(cancelled.order_status(), OrderStatus::Cancelled);
+    assert_eq!(cancelled.filled_size, "0.004".parse::<Decimal>().unwrap());
+}
+
+#[tokio::test]
+async fn test_maps_failures_to_typed_errors() {
+    let mut server = mockito::Server::new_async().await;
+    let client = TradingClient::new(&config(&server), credentials());
+    let order = OrderRef::Id(ORDER_ID.to_string());
+    let query = || client.query_order(&order);
+    let path = format!("/orders/{}", ORDER_ID);
+
+    for (status, message) in [
+        (400, "Insufficient funds"),
+        (401, "invalid signature"),
+        (404, "NotFound"),
+    ] {
+        let mock = server
+            .mock("GET", path.as_str())
+            .with_status(status)
+            .with_body(json!({ "message": message }).to_string())
+            .create_async()
+            .await;
+        match (status, query().await) {
+            (400, Err(CoinbaseError::BadRequest(m)))
+            | (401, Err(CoinbaseError::Unauthorized(m)))
+            | (404, Err(CoinbaseError::NotFound(m))) => assert_eq!(m, message),
+            (_, other) => panic!("unexpected result for {}: {:?}", status, other),
+        }
+        mock.remove_async().await;
+    }
+
+    let mock = server
+        .mock("GET", path.as_str())
+        .with_status(429)
+        .with_header("Retry-After", "3")
+        .create_async()
+        .await;
+    assert!(matches!(
+        query().await,
+        Err(CoinbaseError::RateLimited {
+            retry_after: Some(delay)
+        }) if delay == Duration::from_secs(3)
+    ));
+    mock.remove_async().await;
+
+    // Without credentials the connector refuses to trade
+    let connector = CoinbaseConnector::with_config(config(&server));
+    assert!(!connector.capabilities().order_entry);
+    assert!(connector.balances().await.is_err());
+}
@@ -0,0 +1,157 @@
+//! Binance driven only through the venue-neutral `ExchangeConnector` trait.
+
+use futures::{SinkExt, StreamExt};
+use mockito::Matcher;
+use quantumflow::connectors::binance::trading::Credentials;
+use quantumflow::connectors::binance::{BinanceConfig, BinanceConnector};
+use quantumflow::connectors::exchange::{check_subscriptions, ExchangeConnector, OrderRef};
+use quantumflow::connectors::market::{subscription_channel, MarketEvent, Subscription};
+use quantumflow::connectors::reconnect::StreamEvent;
+use quantumflow::{Order, OrderStatus, OrderType, Side};
+use rust_decimal::Decimal;
+use serde_json::{json, Value};
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio::sync::mpsc;
+use tokio_tungstenite::tungstenite::Message;
+
+fn config(rest_url: String) -> BinanceConfig {
+    BinanceConfig {
+        rest_url,
+        ..BinanceConfig::new()
+    }
+}
+
+#[tokio::test]
+async fn test_trades_through_the_trait() {
+    let mut server = mockito::Server::new_async().await;
+    let order = json!({
+        "symbol": "BTCUSDT", "orderId": 28, "clientOrderId": "strategy-1",
+        "price": "43000", "origQty": "0.5", "executedQty": "0.2",
+        "status": "PARTIALLY_FILLED", "type": "LIMIT", "side": "BUY",
+    });
+    let place = server
+        .mock("POST", "/api/v3/order")
+        .match_query(Matcher::UrlEncoded(
+            "newClientOrderId".into(),
+            "strategy-1".into(),
+        ))
+        .with_body(order.to_string())
+        .create_async()
+        .await;
+    let query = server
+        .mock("GET", "/api/v3/order")
+        .match_query(Matcher::UrlEncoded("orderId".into(), "28".into()))
+        .with_body(order.to_string())
+        .create_async()
+        .await;
+    let account = server
+        .mock("GET", "/api/v3/account")
+        .match_query(Matcher::Any)
+        .with_body(
+            json!({"balances": [{"asset": "BTC", "free": "0.5", "locked": "0.1"}]}).to_string(),
+        )
+        .create_async()
+        .await;
+
+    let connector: Box<dyn ExchangeConnector> = Box::new(BinanceConnector::with_credentials(
+        config(server.url()),
+        Credentials::new("test-key", "test-secret"),
+    ));
+    assert!(connector.capabilities().order_entry);
+
+    let mut new = Order::new(
+        "BTCUSDT".to_string(),
+        Side::Buy,
+        OrderType::Limit,
+        Decimal::from(43000),
+        "0.5".parse().unwrap(),
+    );
+    new.client_id = Some("strategy-1".to_string());
+    let placed = connector.place_order(&new).await.unwrap();
+    assert_eq!(placed.order_id, "28");
+    assert_eq!(placed.order_type, Some(OrderType::Limit));
+    assert_eq!(placed.status, OrderStatus::PartiallyFilled);
+    assert_eq!(placed.filled_quantity, "0.2".parse::<Decimal>().unwrap());
+
+    let queried = connector
+        .query_order("BTCUSDT", &OrderRef::Id(placed.order_id))
+        .await
+        .unwrap();
+    assert_eq!(queried.client_id.as_deref(), Some("strategy-1"));
+    assert!(connector
+        .query_order("BTCUSDT", &OrderRef::Id("not-a-number".to_string()))
+        .await
+        .is_err());
+
+    let balances = connector.balances().await.unwrap();
+    assert_eq!(balances[0].total(), "0.6".parse::<Decimal>().unwrap());
+    for mock in [place, query, account] {
+        mock.assert_async().await;
+    }
+}
+
+#[tokio::test]
+async fn test_refuses_order_entry_without_credentials() {
+    let connector: Box<dyn ExchangeConnector> = Box::new(BinanceConnector::new());
+    let capabilities = connector.capabilities();
+    assert!(capabilities.order_book && capabilities.live_subscriptions);
+    assert!(!capabilities.order_entry && !capabilities.account);
+    assert!(connector.balances().await.is_err());
+    assert!(check_subscriptions(connector.as_ref(), &[Subscription::agg_trade("BTCUSDT")]).is_ok());
+}
+
+#[tokio::test]
+async fn test_streams_market_data_through_the_trait() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let ws_url = format!("ws://{}/stream", listener.local_addr().unwrap());
+    tokio::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        let mut ws = tokio_tungstenite::accept_async(stream).await.unwrap();
+        let Some(Ok(Message::Text(text))) = ws.next().await else {
+            panic!("expected a subscription request");
+        };
+        let request: Value = serde_json::from_str(&text).unwrap();
+        assert_eq!(request["params"], json!(["ethusdt@trade"]));
+        let trade = json!({
+            "e": "trade", "E": chrono::Utc::now().timestamp_millis(), "s": "ETHUSDT",
+            "t": 12345, "p": "2000.5", "q": "0.25", "T": chrono::Utc::now().timestamp_millis(),
+            "m": true, "M": true,
+        });
+        let event = json!({"stream": "ethusdt@trade", "data": trade});
+        ws.send(Message::Text(event.to_string())).await.unwrap();
+        tokio::time::sleep(Duration::from_secs(30)).await;
+    });
+
+    let connector: Box<dyn ExchangeConnector> =
+        Box::new(BinanceConnector::with_config(BinanceConfig {
+            combined_ws_url: ws_url,
+            ..BinanceConfig::new()
+        }));
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let (_handle, commands) = subscription_channel();
+    let stream = tokio::spawn(async move {
+        connector
+            .stream_market_data(
+                &[Subscription::trade("ETHUSDT")],
+                commands,
+                Box::new(move |event| {
+                    if let StreamEvent::Data { data, .. } = event {
+                        let _ = tx.send(data);
+                    }
+                }),
+            )
+            .await
+    });
+
+    let event = tokio::time::timeout(Duration::from_secs(5), rx.recv())
+        .await
+        .unwrap()
+        .unwrap();
+    stream.abort();
+    let MarketEvent::Trade(trade) = event else {
+        panic!("expected a trade, got {:?}", event);
+    };
+    assert_eq!(trade.trade_id, 12345);
+    assert_eq!(trade.aggressor(), Side::Sell);
+}
@@ -0,0 +1,318 @@
+//! FIX acceptor tests against a minimal local initiator.
+
+use quantumflow::engine::matching::MatchingEngine;
+use quantumflow::gateway::fix::message::{format_timestamp, msg_type, tags, FixMessage};
+use quantumflow::gateway::fix::{FixAcceptor, FixConfig};
+use quantumflow::Side;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+const ACCEPTOR: &str = "QUANTUMFLOW";
+
+async fn start(config: FixConfig) -> (Arc<MatchingEngine>, SocketAddr) {
+    let engine = Arc::new(MatchingEngine::new());
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let acceptor = Arc::new(FixAcceptor::new(engine.clone(), config));
+    tokio::spawn(acceptor.serve(listener));
+    (engine, addr)
+}
+
+struct Initiator {
+    stream: TcpStream,
+    buf: Vec<u8>,
+    comp_id: String,
+    next_seq: u64,
+}
+
+impl Initiator {
+    async fn connect(addr: SocketAddr, comp_id: &str, next_seq: u64) -> Self {
+        Self {
+            stream: TcpStream::connect(addr).await.unwrap(),
+            buf: Vec::new(),
+            comp_id: comp_id.to_string(),
+            next_seq,
+        }
+    }
+
+    async fn send_as(&mut self, mut message: FixMessage, seq: u64) {
+        message.set(tags::SENDER_COMP_ID, &self.comp_id);
+        message.set(tags::TARGET_COMP_ID, ACCEPTOR);
+        message.set(tags::MSG_SEQ_NUM, seq);
+        message.set(tags::SENDING_TIME, format_timestamp(chrono::Utc::now()));
+        self.stream.write_all(&message.encode()).await.unwrap();
+    }
+
+    async fn send(&mut self, message: FixMessage) {
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        self.send_as(message, seq).await;
+    }
+
+    async fn recv(&mut self) -> FixMessage {
+        tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                if let Some((message, used)) = FixMessage::decode(&self.buf).unwrap() {
+                    self.buf.drain(..used);
+                    return message;
+                }
+                let read = self.stream.read_buf(&mut self.buf).await.unwrap();
+                assert!(read > 0, "acceptor closed the connection");
+            }
+        })
+        .await
+        .expect("no message from acceptor")
+    }
+
+    /// Next message other than a heartbeat.
+    async fn expect(&mut self, msg_type: &str) -> FixMessage {
+        loop {
+            let message = self.recv().await;
+            if message.msg_type() != msg_type::HEARTBEAT || msg_type == msg_type::HEARTBEAT {
+                assert_eq!(message.msg_type(), msg_type, "unexpected {:?}", message);
+                return message;
+            }
+        }
+    }
+
+    async fn logon(&mut self, reset: bool) -> FixMessage {
+        let mut logon = FixMessage::new(msg_type::LOGON)
+            .with(tags::ENCRYPT_METHOD, 0)
+            .with(tags::HEART_BT_INT, 30);
+        if reset {
+            logon = logon.with(tags::RESET_SEQ_NUM_FLAG, "Y");
+        }
+        self.send(logon).await;
+        self.expect(msg_type::LOGON).await
+    }
+}
+
+fn new_order(cl_ord_id: &str, side: &str, quantity: u32, price: u32) -> FixMessage {
+    FixMessage::new(msg_type::NEW_ORDER_SINGLE)
+        .with(tags::CL_ORD_ID, cl_ord_id)
+        .with(tags::SYMBOL, "BTCUSD")
+        .with(tags::SIDE, side)
+        .with(tags::ORDER_QTY, quantity)
+        .with(tags::ORD_TYPE, 2)
+        .with(tags::PRICE, price)
+        .with(tags::TRANSACT_TIME, format_timestamp(chrono::Utc::now()))
+}
+
+#[tokio::test]
+async fn test_orders_trade_between_sessions() {
+    let (_engine, addr) = start(FixConfig::new(ACCEPTOR)).await;
+    let mut buyer = Initiator::connect(addr, "BUYER", 1).await;
+    let mut seller = Initiator::connect(addr, "SELLER", 1).await;
+    buyer.logon(true).await;
+    seller.logon(true).await;
+
+    buyer.send(new_order("b1", "1", 2, 100)).await;
+    let ack = buyer.expect(msg_type::EXECUTION_REPORT).await;
+    assert_eq!(ack.get(tags::EXEC_TYPE), Some("0"));
+    assert_eq!(ack.get(tags::CL_ORD_ID), Some("b1"));
+
+    seller.send(new_order("s1", "2", 1, 100)).await;
+    let ack = seller.expect(msg_type::EXECUTION_REPORT).await;
+    assert_eq!(ack.get(tags::EXEC_TYPE), Some("0"));
+    let fill = seller.expect(msg_type::EXECUTION_REPORT).await;
+    assert_eq!(fill.get(tags::EXEC_TYPE), Some("F"));
+    assert_eq!(fill.get(tags::ORD_STATUS), Some("2"));
+    assert_eq!(fill.get(tags::LAST_PX), Some("100"));
+
+    let fill = buyer.expect(msg_type::EXECUTION_REPORT).await;
+    assert_eq!(fill.get(tags::CL_ORD_ID), Some("b1"));
+    assert_eq!(fill.get(tags::ORD_STATUS), Some("1"));
+    assert_eq!(fill.get(tags::CUM_QTY), Some("1"));
+    assert_eq!(fill.get(tags::LEAVES_QTY), Some("1"));
+    assert_eq!(fill.get(tags::AVG_PX), Some("100"));
+
+    buyer
+        .send(FixMessage::new(msg_type::TEST_REQUEST).with(tags::TEST_REQ_ID, "ping"))
+        .await;
+    let heartbeat = buyer.expect(msg_type::HEARTBEAT).await;
+    assert_eq!(heartbeat.get(tags::TEST_REQ_ID), Some("ping"));
+
+    buyer.send(FixMessage::new(msg_type::LOGOUT)).await;
+    buyer.expect(msg_type::LOGOUT).await;
+}
+
+#[tokio::test]
+async fn test_cancel_and_replace() {
+    let (engine, addr) = start(FixConfig::new(ACCEPTOR)).await;
+    let mut client = Initiator::connect(addr, "OMS", 1).await;
+    client.logon(true).await;
+
+    client.send(new_order("c1", "1", 5, 100)).await;
+    client.expect(msg_type::EXECUTION_REPORT).await;
+
+    let replace = FixMessage::new(msg_type::ORDER_CANCEL_REPLACE_REQUEST)
+        .with(tags::CL_ORD_ID, "c2")
+        .with(tags::ORIG_CL_ORD_ID, "c1")
+        .with(tags::SYMBOL, "BTCUSD")
+        .with(tags::SIDE, "1")
+        .with(tags::ORDER_QTY, 3)
+        .with(tags::ORD_TYPE, 2)
+        .with(tags::PRICE, 101);
+    client.send(replace).await;
+    let replaced = client.expect(msg_type::EXECUTION_REPORT).await;
+    assert_eq!(replaced.get(tags::EXEC_TYPE), Some("5"));
+    assert_eq!(replaced.get(tags::CL_ORD_ID), Some("c2"));
+    assert_eq!(replaced.get(tags::ORIG_CL_ORD_ID), Some("c1"));
+    assert_eq!(replaced.get(tags::PRICE), Some("101"));
+    assert_eq!(replaced.get(tags::ORDER_QTY), Some("3"));
+
+    let cancel = FixMessage::new(msg_type::ORDER_CANCEL_REQUEST)
+        .with(tags::CL_ORD_ID, "c3")
+        .with(tags::ORIG_CL_ORD_ID, "c2")
+        .with(tags::SYMBOL, "BTCUSD")
+        .with(tags::SIDE, "1");
+    client.send(cancel).await;
+    let cancelled = client.expect(msg_type::EXECUTION_REPORT).await;
+    assert_eq!(cancelled.get(tags::EXEC_TYPE), Some("4"));
+    assert_eq!(cancelled.get(tags::CL_ORD_ID), Some("c3"));
+    assert_eq!(cancelled.get(tags::ORIG_CL_ORD_ID), Some("c2"));
+    assert!(engine
+        .get_orderbook_snapshot("BTCUSD")
+        .unwrap()
+        .bids
+        .is_empty());
+
+    let unknown = FixMessage::new(msg_type::ORDER_CANCEL_REQUEST)
+        .with(tags::CL_ORD_ID, "c4")
+        .with(tags::ORIG_CL_ORD_ID, "c1")
+        .with(tags::SYMBOL, "BTCUSD")
+        .with(tags::SIDE, "1");
+    client.send(unknown).await;
+    let reject = client.expect(msg_type::ORDER_CANCEL_REJECT).await;
+    assert_eq!(reject.get(tags::CXL_REJ_REASON), Some("1"));
+
+    // Missing OrderQty is a session-level reject
+    let mut incomplete = new_order("c5", "1", 1, 100);
+    incomplete.remove(tags::ORDER_QTY);
+    client.send(incomplete).await;
+    let reject = client.expect(msg_type::REJECT).await;
+    assert_eq!(reject.get(tags::REF_TAG_ID), Some("38"));
+}
+
+#[tokio::test]
+async fn test_sequence_numbers_persist_and_resend() {
+    let dir = std::env::temp_dir().join(format!("quantumflow-fix-{}", uuid::Uuid::new_v4()));
+    let config = FixConfig {
+        store_dir: Some(dir.clone()),
+        ..FixConfig::new(ACCEPTOR)
+    };
+
+    let (_engine, addr) = start(config.clone()).await;
+    let mut client = Initiator::connect(addr, "OMS", 1).await;
+    let logon = client.logon(true).await;
+    assert_eq!(logon.get(tags::MSG_SEQ_NUM), Some("1"));
+    client.send(new_order("c1", "1", 1, 100)).await;
+    let ack = client.expect(msg_type::EXECUTION_REPORT).await;
+    assert_eq!(ack.get(tags::MSG_SEQ_NUM), Some("2"));
+    client.send(FixMessage::new(msg_type::LOGOUT)).await;
+    client.expect(msg_type::LOGOUT).await;
+
+    // A restarted gateway continues both sequences
+    let (_engine, addr) = start(config).await;
+    let mut client = Initiator::connect(addr, "OMS", client.next_seq).await;
+    let logon = client.logon(false).await;
+    assert_eq!(logon.get(tags::MSG_SEQ_NUM), Some("4"));
+
+    client
+        .send(
+            FixMessage::new(msg_type::RESEND_REQUEST)
+                .with(tags::BEGIN_SEQ_NO, 1)
+                .with(tags::END_SEQ_NO, 0),
+        )
+        .await;
+    let gap_fill = client.expect(msg_type::SEQUENCE_RESET).await;
+    assert_eq!(gap_fill.get(tags::MSG_SEQ_NUM), Some("1"));
+    assert_eq!(gap_fill.get(tags::NEW_SEQ_NO), Some("2"));
+    let resent = client.expect(msg_type::EXECUTION_REPORT).await;
+    assert_eq!(resent.get(tags::MSG_SEQ_NUM), Some("2"));
+    assert_eq!(resent.get(tags::POSS_DUP_FLAG), Some("Y"));
+    assert_eq!(resent.get(tags::CL_ORD_ID), Some("c1"));
+    assert!(resent.get(tags::ORIG_SENDING_TIME).is_some());
+    let gap_fill = client.expect(msg_type::SEQUENCE_RESET).await;
+    assert_eq!(gap_fill.get(tags::MSG_SEQ_NUM), Some("3"));
+    assert_eq!(gap_fill.get(tags::NEW_SEQ_NO), Some("5"));
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_sequence_gap_is_resent_before_processing() {
+    let (_engine, addr) = start(FixConfig::new(ACCEPTOR)).await;
+    let mut client = Initiator::connect(addr, "OMS", 1).await;
+    client.logon(true).await;
+
+    // Messages 2..=4 never arrive
+    client
+        .send_as(
+            FixMessage::new(msg_type::TEST_REQUEST).with(tags::TEST_REQ_ID, "late"),
+            5,
+        )
+        .await;
+    let resend = client.expect(msg_type::RESEND_REQUEST).await;
+    assert_eq!(resend.get(tags::BEGIN_SEQ_NO), Some("2"));
+    assert_eq!(resend.get(tags::END_SEQ_NO), Some("0"));
+
+    let gap_fill = FixMessage::new(msg_type::SEQUENCE_RESET)
+        .with(tags::GAP_FILL_FLAG, "Y")
+        .with(tags::NEW_SEQ_NO, 5)
+        .with(tags::POSS_DUP_FLAG, "Y");
+    client.send_as(gap_fill, 2).await;
+    // The queued test request is answered once the gap is filled
+    let heartbeat = client.expect(msg_type::HEARTBEAT).await;
+    assert_eq!(heartbeat.get(tags::TEST_REQ_ID), Some("late"));
+
+    client
+        .send_as(FixMessage::new(msg_type::HEARTBEAT), 3)
+        .await;
+    let logout = client.expect(msg_type::LOGOUT).await;
+    assert!(logout
+        .get(tags::TEXT)
+        .unwrap()
+        .contains("MsgSeqNum too low"));
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_concurrent_sessions_keep_every_order() {
+    const ORDERS: u32 = 50;
+    let (engine, addr) = start(FixConfig::new(ACCEPTOR)).await;
+
+    // Both sessions hammer the same book at prices that never cross
+    let mut sessions = Vec::new();
+    for (comp_id, base) in [("OMS1", 100), ("OMS2", 100 + ORDERS)] {
+        sessions.push(tokio::spawn(async move {
+            let mut client = Initiator::connect(addr, comp_id, 1).await;
+            client.logon(true).await;
+            for n in 0..ORDERS {
+                let cl_ord_id = format!("{}-{}", comp_id, n);
+                client.send(new_order(&cl_ord_id, "1", 1, base + n)).await;
+            }
+            for n in 0..ORDERS {
+                let ack = client.expect(msg_type::EXECUTION_REPORT).await;
+                assert_eq!(ack.get(tags::EXEC_TYPE), Some("0"));
+                assert_eq!(
+                    ack.get(tags::CL_ORD_ID),
+                    Some(format!("{}-{}", comp_id, n).as_str())
+                );
+            }
+            // Held open, since a closed session's orders are cancelled
+            client
+        }));
+    }
+    let mut clients = Vec::new();
+    for session in sessions {
+        clients.push(session.await.unwrap());
+    }
+
+    let book = engine.get_or_create_orderbook("BTCUSD");
+    let bids = book.get_depth(Side::Buy, 2 * ORDERS as usize);
+    assert_eq!(bids.len(), 2 * ORDERS as usize);
+    assert_eq!(book.check_invariants(), Ok(()));
+}
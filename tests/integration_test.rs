@@ -1,16 +1,15 @@
 use quantumflow::{
     backtest::engine::BacktestEngine,
-    engine::matching::MatchingEngine,
+    engine::{bus::BackpressurePolicy, matching::MatchingEngine},
     risk::manager::{RiskLimits, RiskManager},
     Order, OrderStatus, OrderType, Side,
 };
 use rust_decimal::Decimal;
-use tokio::sync::mpsc;
 
 #[tokio::test]
 async fn test_full_trading_flow() {
-    let (tx, mut rx) = mpsc::unbounded_channel();
-    let engine = MatchingEngine::new(tx);
+    let engine = MatchingEngine::new();
+    let mut rx = engine.subscribe_trades(16, BackpressurePolicy::Block);
     let risk_manager = RiskManager::new(RiskLimits::default());
 
     // Create and submit buy order
@@ -41,7 +40,7 @@ async fn test_full_trading_flow() {
 
     // Verify trade was executed
     let trade = rx.recv().await;
-    assert!(trade.is_some());
+    assert!(trade.is_ok());
 
     let trade = trade.unwrap();
     assert_eq!(trade.symbol, "BTCUSD");
@@ -84,8 +83,7 @@ async fn test_risk_manager_limits() {
 
 #[tokio::test]
 async fn test_orderbook_snapshot() {
-    let (tx, _rx) = mpsc::unbounded_channel();
-    let engine = MatchingEngine::new(tx);
+    let engine = MatchingEngine::new();
 
     // Add orders
     for i in 0..10 {
@@ -120,8 +118,8 @@ async fn test_orderbook_snapshot() {
 
 #[tokio::test]
 async fn test_partial_fill() {
-    let (tx, mut rx) = mpsc::unbounded_channel();
-    let engine = MatchingEngine::new(tx);
+    let engine = MatchingEngine::new();
+    let mut rx = engine.subscribe_trades(16, BackpressurePolicy::Block);
 
     // Submit a buy order for 5 units
     let buy_order = Order::new(
@@ -157,8 +155,7 @@ async fn test_partial_fill() {
 
 #[tokio::test]
 async fn test_order_cancellation_flow() {
-    let (tx, _rx) = mpsc::unbounded_channel();
-    let engine = MatchingEngine::new(tx);
+    let engine = MatchingEngine::new();
 
     // Submit a buy order
     let order = Order::new(
@@ -185,8 +182,8 @@ async fn test_order_cancellation_flow() {
 
 #[tokio::test]
 async fn test_multi_symbol_routing() {
-    let (tx, mut rx) = mpsc::unbounded_channel();
-    let engine = MatchingEngine::new(tx);
+    let engine = MatchingEngine::new();
+    let mut rx = engine.subscribe_trades(16, BackpressurePolicy::Block);
 
     // Submit orders for BTCUSD
     let btc_buy = Order::new(
@@ -367,3 +364,64 @@ fn test_backtest_max_drawdown() {
     let results = engine.get_results();
     assert!(results.max_drawdown > Decimal::ZERO);
 }
+
+#[tokio::test]
+async fn test_trade_fanout_with_slow_subscriber() {
+    let engine = MatchingEngine::new();
+    let mut fast = engine.subscribe_trades(16, BackpressurePolicy::Block);
+    let slow = engine.subscribe_trades(1, BackpressurePolicy::DropOldest);
+
+    for _ in 0..3 {
+        let buy = Order::new(
+            "BTCUSD".to_string(),
+            Side::Buy,
+            OrderType::Limit,
+            Decimal::from(50000),
+            Decimal::from(1),
+        );
+        engine.submit_order(buy).await.unwrap();
+
+        let sell = Order::new(
+            "BTCUSD".to_string(),
+            Side::Sell,
+            OrderType::Limit,
+            Decimal::from(50000),
+            Decimal::from(1),
+        );
+        engine.submit_order(sell).await.unwrap();
+    }
+
+    for _ in 0..3 {
+        assert!(fast.recv().await.is_ok());
+    }
+
+    let metrics = engine.trade_subscriber_metrics();
+    let slow_metrics = metrics.iter().find(|m| m.id == slow.id()).unwrap();
+    assert_eq!(slow_metrics.depth, 1);
+    assert_eq!(slow_metrics.dropped, 2);
+}
+
+#[tokio::test]
+async fn test_orderbook_snapshot_depth_is_configurable() {
+    let engine = MatchingEngine::new();
+    engine.set_snapshot_depth("BTCUSD", 5);
+
+    for i in 0..10 {
+        for _ in 0..2 {
+            let buy_order = Order::new(
+                "BTCUSD".to_string(),
+                Side::Buy,
+                OrderType::Limit,
+                Decimal::from(50000 - i * 100),
+                Decimal::from(1),
+            );
+            engine.submit_order(buy_order).await.unwrap();
+        }
+    }
+
+    let snapshot = engine.get_orderbook_snapshot("BTCUSD").unwrap();
+    assert_eq!(snapshot.bids.len(), 5);
+    assert_eq!(snapshot.bids[0].price, Decimal::from(50000));
+    assert_eq!(snapshot.bids[0].quantity, Decimal::from(2));
+    assert_eq!(snapshot.bids[0].order_count, 2);
+}
@@ -0,0 +1,168 @@
+//! Market data feed tests with the reference subscriber behind a lossy relay.
+
+use quantumflow::engine::matching::MatchingEngine;
+use quantumflow::gateway::feed::codec::{FeedMessage, HEADER_LEN};
+use quantumflow::gateway::feed::subscriber::FeedSubscriber;
+use quantumflow::gateway::feed::{FeedConfig, FeedPublisher};
+use quantumflow::{Order, OrderType, Side};
+use rust_decimal::Decimal;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::{TcpListener, UdpSocket};
+
+struct Feed {
+    engine: Arc<MatchingEngine>,
+    subscriber: FeedSubscriber,
+    /// Drops the next packet with messages on its way to the subscriber.
+    drop_next: Arc<AtomicBool>,
+}
+
+/// Publisher -> relay -> subscriber, all on loopback.
+async fn start(history: usize) -> Feed {
+    let engine = Arc::new(MatchingEngine::new());
+    let relay = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let recovery = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let subscriber = FeedSubscriber::bind(
+        "127.0.0.1:0".parse().unwrap(),
+        recovery.local_addr().unwrap(),
+        "BTCUSD",
+    )
+    .await
+    .unwrap();
+
+    let config = FeedConfig {
+        history,
+        heartbeat: Duration::from_millis(20),
+        ..FeedConfig::new(relay.local_addr().unwrap())
+    };
+    let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    tokio::spawn(Arc::new(FeedPublisher::new(engine.clone(), config)).serve(socket, recovery));
+
+    let drop_next = Arc::new(AtomicBool::new(false));
+    let relay_drop = drop_next.clone();
+    let target = subscriber.local_addr().unwrap();
+    tokio::spawn(async move {
+        let mut buf = vec![0; 65536];
+        loop {
+            let len = relay.recv(&mut buf).await.unwrap();
+            let heartbeat = len == HEADER_LEN;
+            if heartbeat || !relay_drop.swap(false, Ordering::SeqCst) {
+                relay.send_to(&buf[..len], target).await.unwrap();
+            }
+        }
+    });
+
+    Feed {
+        engine,
+        subscriber,
+        drop_next,
+    }
+}
+
+async fn submit(engine: &MatchingEngine, side: Side, price: i64, quantity: i64) -> Order {
+    let order = Order::new(
+        "BTCUSD".to_string(),
+        side,
+        OrderType::Limit,
+        Decimal::from(price),
+        Decimal::from(quantity),
+    );
+    engine.submit_order(order).await.unwrap()
+}
+
+/// Follows the feed until the rebuilt book matches the engine and `trades`
+/// trades have been seen, returning the messages applied on the way.
+async fn converge(feed: &mut Feed, trades: usize) -> Vec<FeedMessage> {
+    let mut applied = Vec::new();
+    let result = tokio::time::timeout(Duration::from_secs(5), async {
+        loop {
+            applied.extend(feed.subscriber.recv().await.unwrap());
+            let seen = applied
+                .iter()
+                .filter(|m| matches!(m, FeedMessage::Trade(_)))
+                .count();
+            if seen >= trades && feed.subscriber.verify(&feed.engine).is_ok() {
+                return;
+            }
+        }
+    })
+    .await;
+    if result.is_err() {
+        panic!(
+            "feed did not converge: {:?}",
+            feed.subscriber.verify(&feed.engine)
+        );
+    }
+    applied
+}
+
+#[tokio::test]
+async fn test_rebuilds_book_and_retransmits_gaps() {
+    let mut feed = start(1000).await;
+
+    submit(&feed.engine, Side::Buy, 100, 2).await;
+    submit(&feed.engine, Side::Sell, 105, 1).await;
+    converge(&mut feed, 0).await;
+    let synced_at = feed.subscriber.next_sequence().unwrap();
+
+    // Lose the packet carrying the trade and its level update
+    feed.drop_next.store(true, Ordering::SeqCst);
+    submit(&feed.engine, Side::Sell, 100, 1).await;
+    submit(&feed.engine, Side::Buy, 99, 3).await;
+    let applied = converge(&mut feed, 1).await;
+
+    // Recovered by retransmission, in order and without a new snapshot
+    assert_eq!(
+        applied.len() as u64,
+        feed.subscriber.next_sequence().unwrap() - synced_at
+    );
+    let FeedMessage::Trade(trade) = applied
+        .iter()
+        .find(|m| matches!(m, FeedMessage::Trade(_)))
+        .unwrap()
+    else {
+        unreachable!()
+    };
+    assert_eq!(trade.price, Decimal::from(100));
+    let bids = feed.subscriber.book().get_depth(Side::Buy, 10);
+    assert_eq!(bids[0].quantity, Decimal::from(1));
+    assert_eq!(bids[1].price, Decimal::from(99));
+}
+
+#[tokio::test]
+async fn test_resnapshots_when_history_is_gone() {
+    let mut feed = start(1).await;
+
+    let resting = submit(&feed.engine, Side::Buy, 100, 2).await;
+    converge(&mut feed, 0).await;
+
+    // More than one message is lost, so the publisher can no longer resend them
+    feed.drop_next.store(true, Ordering::SeqCst);
+    feed.engine
+        .cancel_order(resting.id, "BTCUSD")
+        .await
+        .unwrap();
+    submit(&feed.engine, Side::Buy, 98, 1).await;
+    submit(&feed.engine, Side::Sell, 110, 4).await;
+    converge(&mut feed, 0).await;
+
+    let book = feed.subscriber.book();
+    assert_eq!(book.get_best_bid(), Some(Decimal::from(98)));
+    assert_eq!(book.get_best_ask(), Some(Decimal::from(110)));
+}
+
+#[tokio::test]
+async fn test_publishes_levels_beyond_the_snapshot_depth() {
+    let mut feed = start(1000).await;
+
+    for price in 1..=25 {
+        submit(&feed.engine, Side::Buy, price, 1).await;
+    }
+    converge(&mut feed, 0).await;
+
+    // The lowest bids are below the engine's snapshot depth but still resting
+    let bids = feed.subscriber.book().get_depth(Side::Buy, 100);
+    assert_eq!(bids.len(), 25);
+    assert_eq!(bids[24].price, Decimal::from(1));
+}
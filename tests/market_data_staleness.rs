@@ -0,0 +1,119 @@
+//! Ping handling and staleness detection on a Binance stream, with the
+//! staleness driving the risk manager.
+
+use futures::{SinkExt, StreamExt};
+use quantumflow::connectors::binance::{BinanceConfig, BinanceConnector};
+use quantumflow::connectors::reconnect::{ReconnectPolicy, StreamEvent};
+use quantumflow::{Order, OrderType, RiskLimits, RiskManager, Side, Ticker};
+use rust_decimal::Decimal;
+use serde_json::json;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio::sync::{mpsc, oneshot};
+use tokio_tungstenite::tungstenite::Message;
+
+fn ticker(age: chrono::Duration) -> Message {
+    Message::Text(
+        json!({
+            "e": "24hrTicker",
+            "E": (chrono::Utc::now() - age).timestamp_millis(),
+            "s": "BTCUSDT",
+            "b": "99",
+            "a": "101",
+            "c": "100",
+            "v": "10",
+        })
+        .to_string(),
+    )
+}
+
+#[tokio::test]
+async fn test_answers_pings_and_flags_stale_data() {
+    // Pings first, then a late ticker, a current one and silence
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let ws_url = format!("ws://{}/ws", listener.local_addr().unwrap());
+    let (pong_tx, pong_rx) = oneshot::channel();
+    tokio::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        let mut ws = tokio_tungstenite::accept_async(stream).await.unwrap();
+        ws.send(Message::Ping(b"heartbeat".to_vec())).await.unwrap();
+        let reply = ws.next().await.unwrap().unwrap();
+        pong_tx.send(reply).unwrap();
+
+        ws.send(ticker(chrono::Duration::seconds(60)))
+            .await
+            .unwrap();
+        ws.send(ticker(chrono::Duration::zero())).await.unwrap();
+        tokio::time::sleep(Duration::from_secs(30)).await;
+    });
+
+    let connector = BinanceConnector::with_config(BinanceConfig {
+        ws_url,
+        reconnect: ReconnectPolicy {
+            stale_after: Duration::from_millis(200),
+            ..ReconnectPolicy::new()
+        },
+        ..BinanceConfig::new()
+    });
+    let risk = Arc::new(RiskManager::new(RiskLimits::default()));
+    let stream_risk = risk.clone();
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let stream = tokio::spawn(async move {
+        connector
+            .stream_ticker("BTCUSDT", move |event| {
+                if let Some(stale) = event.staleness() {
+                    stream_risk.set_market_data_stale("BTCUSDT", stale);
+                }
+                let _ = tx.send((event, stream_risk.is_market_data_stale("BTCUSDT")));
+            })
+            .await
+    });
+
+    assert_eq!(pong_rx.await.unwrap(), Message::Pong(b"heartbeat".to_vec()));
+
+    let mut events: Vec<(StreamEvent<Ticker>, bool)> = Vec::new();
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while let Some(event) = rx.recv().await {
+            let silent = matches!(event.0, StreamEvent::Stale { .. });
+            events.push(event);
+            if silent {
+                return;
+            }
+        }
+    })
+    .await
+    .expect("stream never went stale");
+    stream.abort();
+
+    let flags: Vec<_> = events
+        .iter()
+        .map(|(event, risk_stale)| (event.staleness(), *risk_stale))
+        .collect();
+    assert_eq!(
+        flags,
+        vec![
+            (None, false),
+            (Some(true), true),
+            (Some(false), false),
+            (Some(true), true),
+        ]
+    );
+    let StreamEvent::Stale {
+        symbol: None,
+        silent_for,
+    } = events[3].0
+    else {
+        unreachable!()
+    };
+    assert!(silent_for >= Duration::from_millis(200));
+
+    let order = Order::new(
+        "BTCUSDT".to_string(),
+        Side::Buy,
+        OrderType::Limit,
+        Decimal::from(100),
+        Decimal::from(1),
+    );
+    assert!(risk.check_order(&order).is_err());
+}
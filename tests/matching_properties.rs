@@ -0,0 +1,273 @@
+//! Property tests comparing `MatchingEngine` against a naive reference matcher.
+
+use proptest::prelude::*;
+use proptest::sample::Index;
+use quantumflow::engine::bus::BackpressurePolicy;
+use quantumflow::engine::matching::MatchingEngine;
+use quantumflow::{Order, OrderType, Side};
+use rust_decimal::Decimal;
+use uuid::Uuid;
+
+const SYMBOL: &str = "BTCUSD";
+
+#[derive(Debug, Clone)]
+enum Command {
+    Limit {
+        side: Side,
+        price: i64,
+        quantity: i64,
+    },
+    Market {
+        side: Side,
+        quantity: i64,
+    },
+    Cancel {
+        pick: Index,
+    },
+}
+
+fn side() -> impl Strategy<Value = Side> {
+    prop_oneof![Just(Side::Buy), Just(Side::Sell)]
+}
+
+fn command() -> impl Strategy<Value = Command> {
+    prop_oneof![
+        6 => (side(), 95i64..=105, 1i64..=10)
+            .prop_map(|(side, price, quantity)| Command::Limit { side, price, quantity }),
+        1 => (side(), 1i64..=15).prop_map(|(side, quantity)| Command::Market { side, quantity }),
+        2 => any::<Index>().prop_map(|pick| Command::Cancel { pick }),
+    ]
+}
+
+/// A resting order in the reference book.
+struct RefOrder {
+    id: Uuid,
+    side: Side,
+    price: i64,
+    leaves: i64,
+    seq: u64,
+}
+
+/// Price-time matching the slow, obvious way: scan every order for the best one.
+#[derive(Default)]
+struct ReferenceBook {
+    orders: Vec<RefOrder>,
+    seq: u64,
+}
+
+/// `(price, quantity, buy order, sell order)`
+type RefTrade = (i64, i64, Uuid, Uuid);
+
+impl ReferenceBook {
+    fn submit(&mut self, id: Uuid, side: Side, limit: Option<i64>, quantity: i64) -> Vec<RefTrade> {
+        let mut trades = Vec::new();
+        let mut leaves = quantity;
+
+        while leaves > 0 {
+            let best = self
+                .orders
+                .iter()
+                .enumerate()
+                .filter(|(_, o)| o.side != side)
+                .filter(|(_, o)| match (side, limit) {
+                    (_, None) => true,
+                    (Side::Buy, Some(limit)) => o.price <= limit,
+                    (Side::Sell, Some(limit)) => o.price >= limit,
+                })
+                .min_by_key(|(_, o)| {
+                    let price = match side {
+                        Side::Buy => o.price,
+                        Side::Sell => -o.price,
+                    };
+                    (price, o.seq)
+                })
+                .map(|(i, _)| i);
+            let Some(i) = best else {
+                break;
+            };
+
+            let resting = &mut self.orders[i];
+            let quantity = leaves.min(resting.leaves);
+            let (buy, sell) = match side {
+                Side::Buy => (id, resting.id),
+                Side::Sell => (resting.id, id),
+            };
+            trades.push((resting.price, quantity, buy, sell));
+            leaves -= quantity;
+            resting.leaves -= quantity;
+            if resting.leaves == 0 {
+                self.orders.remove(i);
+            }
+        }
+
+        if let (Some(price), true) = (limit, leaves > 0) {
+            self.orders.push(RefOrder {
+                id,
+                side,
+                price,
+                leaves,
+                seq: self.seq,
+            });
+            self.seq += 1;
+        }
+        trades
+    }
+
+    fn cancel(&mut self, id: Uuid) -> Option<i64> {
+        let i = self.orders.iter().position(|o| o.id == id)?;
+        Some(self.orders.remove(i).leaves)
+    }
+
+    /// `(price, quantity, order count)` per level, best first.
+    fn depth(&self, side: Side) -> Vec<(i64, i64, usize)> {
+        let mut levels: Vec<(i64, i64, usize)> = Vec::new();
+        let mut prices: Vec<i64> = self
+            .orders
+            .iter()
+            .filter(|o| o.side == side)
+            .map(|o| o.price)
+            .collect();
+        prices.sort_unstable();
+        prices.dedup();
+        if side == Side::Buy {
+            prices.reverse();
+        }
+        for price in prices {
+            let at_price = self
+                .orders
+                .iter()
+                .filter(|o| o.side == side && o.price == price);
+            levels.push((
+                price,
+                at_price.clone().map(|o| o.leaves).sum(),
+                at_price.count(),
+            ));
+        }
+        levels
+    }
+}
+
+fn engine_depth(engine: &MatchingEngine, side: Side) -> Vec<(i64, i64, usize)> {
+    let Some(snapshot) = engine.get_orderbook_snapshot(SYMBOL) else {
+        return Vec::new();
+    };
+    let levels = match side {
+        Side::Buy => snapshot.bids,
+        Side::Sell => snapshot.asks,
+    };
+    levels
+        .iter()
+        .map(|l| {
+            (
+                i64::try_from(l.price.mantissa()).unwrap() / 10i64.pow(l.price.scale()),
+                i64::try_from(l.quantity.mantissa()).unwrap() / 10i64.pow(l.quantity.scale()),
+                l.order_count,
+            )
+        })
+        .collect()
+}
+
+fn run(commands: Vec<Command>) -> Result<(), TestCaseError> {
+    tokio_test::block_on(async {
+        let engine = MatchingEngine::new();
+        let mut trade_rx = engine.subscribe_trades(1024, BackpressurePolicy::Block);
+        let mut reference = ReferenceBook::default();
+        let mut submitted: Vec<Uuid> = Vec::new();
+
+        // Quantity conservation: everything submitted is either traded (on both
+        // sides of a trade), resting, or cancelled
+        let mut total_submitted = 0i64;
+        let mut total_traded = 0i64;
+        let mut total_cancelled = 0i64;
+
+        for command in commands {
+            let expected = match command {
+                Command::Limit {
+                    side,
+                    price,
+                    quantity,
+                } => {
+                    let order = Order::new(
+                        SYMBOL.to_string(),
+                        side,
+                        OrderType::Limit,
+                        Decimal::from(price),
+                        Decimal::from(quantity),
+                    );
+                    submitted.push(order.id);
+                    total_submitted += quantity;
+                    let expected = reference.submit(order.id, side, Some(price), quantity);
+                    engine.submit_order(order).await.unwrap();
+                    expected
+                }
+                Command::Market { side, quantity } => {
+                    let order = Order::new(
+                        SYMBOL.to_string(),
+                        side,
+                        OrderType::Market,
+                        Decimal::ZERO,
+                        Decimal::from(quantity),
+                    );
+                    total_submitted += quantity;
+                    let expected = reference.submit(order.id, side, None, quantity);
+                    let filled: i64 = expected.iter().map(|t| t.1).sum();
+                    let result = engine.submit_order(order).await.unwrap();
+                    prop_assert_eq!(result.filled_quantity, Decimal::from(filled));
+                    total_cancelled += quantity - filled;
+                    expected
+                }
+                Command::Cancel { pick } => {
+                    if submitted.is_empty() {
+                        continue;
+                    }
+                    let id = submitted[pick.index(submitted.len())];
+                    let expected = reference.cancel(id);
+                    let result = engine.cancel_order(id, SYMBOL).await;
+                    prop_assert_eq!(result.is_ok(), expected.is_some());
+                    if let (Ok(order), Some(leaves)) = (result, expected) {
+                        prop_assert_eq!(order.remaining_quantity(), Decimal::from(leaves));
+                        total_cancelled += leaves;
+                    }
+                    Vec::new()
+                }
+            };
+
+            let mut actual = Vec::new();
+            while let Ok(Some(trade)) = trade_rx.try_recv() {
+                actual.push(trade);
+            }
+            prop_assert_eq!(actual.len(), expected.len());
+            for (trade, (price, quantity, buy, sell)) in actual.iter().zip(&expected) {
+                prop_assert_eq!(trade.price, Decimal::from(*price));
+                prop_assert_eq!(trade.quantity, Decimal::from(*quantity));
+                prop_assert_eq!(trade.buy_order_id, *buy);
+                prop_assert_eq!(trade.sell_order_id, *sell);
+                total_traded += quantity;
+            }
+
+            for side in [Side::Buy, Side::Sell] {
+                prop_assert_eq!(engine_depth(&engine, side), reference.depth(side));
+            }
+            let book = engine.get_or_create_orderbook(SYMBOL);
+            prop_assert_eq!(book.check_invariants(), Ok(()));
+
+            let resting: i64 = [Side::Buy, Side::Sell]
+                .into_iter()
+                .flat_map(|side| reference.depth(side))
+                .map(|(_, quantity, _)| quantity)
+                .sum();
+            prop_assert_eq!(
+                total_submitted,
+                2 * total_traded + resting + total_cancelled
+            );
+        }
+        Ok(())
+    })
+}
+
+proptest! {
+    #[test]
+    fn engine_matches_reference(commands in prop::collection::vec(command(), 1..80)) {
+        run(commands)?;
+    }
+}
@@ -0,0 +1,252 @@
+//! REST API tests over a local HTTP listener.
+
+use quantumflow::engine::matching::MatchingEngine;
+use quantumflow::gateway::rest::{RestServer, RiskStatus};
+use quantumflow::risk::manager::{Position, RiskLimits, RiskManager};
+use quantumflow::{Order, OrderBookSnapshot, OrderStatus, Side};
+use reqwest::{Client, StatusCode};
+use rust_decimal::Decimal;
+use serde_json::{json, Value};
+use std::sync::Arc;
+use tokio::net::TcpListener;
+
+async fn start_with_engine() -> (Arc<MatchingEngine>, String) {
+    let engine = Arc::new(MatchingEngine::new());
+    let risk = Arc::new(RiskManager::new(RiskLimits::default()));
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(Arc::new(RestServer::new(engine.clone(), risk)).serve(listener));
+    (engine, format!("http://{}", addr))
+}
+
+async fn start() -> String {
+    start_with_engine().await.1
+}
+
+async fn submit(client: &Client, base: &str, side: &str, price: u32, quantity: u32) -> Order {
+    let response = client
+        .post(format!("{}/orders", base))
+        .json(&json!({
+            "symbol": "BTCUSD",
+            "side": side,
+            "order_type": "Limit",
+            "price": price,
+            "quantity": quantity,
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    response.json().await.unwrap()
+}
+
+#[tokio::test]
+async fn test_orders_books_and_positions() {
+    let base = start().await;
+    let client = Client::new();
+
+    let bid = submit(&client, &base, "Buy", 100, 2).await;
+    assert_eq!(bid.status, OrderStatus::Open);
+    submit(&client, &base, "Buy", 99, 1).await;
+
+    let book: OrderBookSnapshot = client
+        .get(format!("{}/books/BTCUSD?depth=1", base))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(book.bids.len(), 1);
+    assert_eq!(book.bids[0].price, Decimal::from(100));
+
+    let ask = submit(&client, &base, "Sell", 100, 2).await;
+    assert_eq!(ask.status, OrderStatus::Filled);
+
+    // The resting bid picks up its fill from the execution reports
+    let mut filled = None;
+    for _ in 0..50 {
+        let order: Order = client
+            .get(format!("{}/orders/{}", base, bid.id))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        if order.status == OrderStatus::Filled {
+            filled = Some(order);
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    }
+    assert_eq!(filled.unwrap().filled_quantity, Decimal::from(2));
+
+    let orders: Vec<Order> = client
+        .get(format!("{}/orders?symbol=BTCUSD", base))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(orders.len(), 3);
+
+    let positions: Vec<Position> = client
+        .get(format!("{}/positions", base))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(positions[0].symbol, "BTCUSD");
+
+    let risk: RiskStatus = client
+        .get(format!("{}/risk", base))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert!(!risk.circuit_breaker);
+}
+
+#[tokio::test]
+async fn test_cancel_and_json_errors() {
+    let base = start().await;
+    let client = Client::new();
+
+    let order = submit(&client, &base, "Sell", 105, 1).await;
+    let response = client
+        .delete(format!("{}/orders/{}", base, order.id))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let cancelled: Order = response.json().await.unwrap();
+    assert_eq!(cancelled.status, OrderStatus::Cancelled);
+
+    let cases = [
+        (
+            client.delete(format!("{}/orders/{}", base, order.id)),
+            StatusCode::UNPROCESSABLE_ENTITY,
+        ),
+        (
+            client.get(format!("{}/orders/not-an-id", base)),
+            StatusCode::BAD_REQUEST,
+        ),
+        (
+            client.get(format!("{}/books/ETHUSD", base)),
+            StatusCode::NOT_FOUND,
+        ),
+        (
+            client.put(format!("{}/positions", base)),
+            StatusCode::METHOD_NOT_ALLOWED,
+        ),
+        (
+            client.post(format!("{}/orders", base)).body("{"),
+            StatusCode::BAD_REQUEST,
+        ),
+        // Larger than the default maximum order size
+        (
+            client.post(format!("{}/orders", base)).json(&json!({
+                "symbol": "BTCUSD",
+                "side": "Buy",
+                "order_type": "Limit",
+                "price": 100,
+                "quantity": 50,
+            })),
+            StatusCode::FORBIDDEN,
+        ),
+    ];
+    for (request, status) in cases {
+        let response = request.send().await.unwrap();
+        assert_eq!(response.status(), status);
+        let body: Value = response.json().await.unwrap();
+        assert!(body["error"].is_string());
+    }
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_concurrent_posts_keep_every_order() {
+    const ORDERS: u32 = 40;
+    let (engine, base) = start_with_engine().await;
+
+    // Both clients hammer the same book at prices that never cross
+    let mut clients = Vec::new();
+    for start in [100, 100 + ORDERS] {
+        let base = base.clone();
+        clients.push(tokio::spawn(async move {
+            let client = Client::new();
+            for price in start..start + ORDERS {
+                let order = submit(&client, &base, "Buy", price, 1).await;
+                assert_eq!(order.status, OrderStatus::Open);
+            }
+        }));
+    }
+    for client in clients {
+        client.await.unwrap();
+    }
+
+    let book = engine.get_or_create_orderbook("BTCUSD");
+    let bids = book.get_depth(Side::Buy, 2 * ORDERS as usize);
+    assert_eq!(bids.len(), 2 * ORDERS as usize);
+    assert_eq!(book.check_invariants(), Ok(()));
+
+    let orders: Vec<Order> = Client::new()
+        .get(format!("{}/orders", base))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(orders.len(), 2 * ORDERS as usize);
+    assert!(orders.iter().all(|o| o.status == OrderStatus::Open));
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_positions_include_fills_once_posts_return() {
+    const ORDERS: u32 = 20;
+    let (engine, base) = start_with_engine().await;
+    for _ in 0..2 * ORDERS {
+        engine
+            .submit_order(Order::new(
+                "BTCUSD".to_string(),
+                Side::Sell,
+                quantumflow::OrderType::Limit,
+                Decimal::from(100),
+                Decimal::ONE,
+            ))
+            .await
+            .unwrap();
+    }
+
+    let mut clients = Vec::new();
+    for _ in 0..2 {
+        let base = base.clone();
+        clients.push(tokio::spawn(async move {
+            let client = Client::new();
+            for _ in 0..ORDERS {
+                let order = submit(&client, &base, "Buy", 100, 1).await;
+                assert_eq!(order.status, OrderStatus::Filled);
+            }
+        }));
+    }
+    for client in clients {
+        client.await.unwrap();
+    }
+
+    // No waiting: a returned POST has already reached the positions
+    let positions: Vec<Position> = Client::new()
+        .get(format!("{}/positions", base))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(positions[0].quantity, Decimal::from(2 * ORDERS));
+}
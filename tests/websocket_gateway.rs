@@ -0,0 +1,192 @@
+//! WebSocket server tests with a local client.
+
+use futures::{SinkExt, StreamExt};
+use quantumflow::engine::matching::MatchingEngine;
+use quantumflow::gateway::websocket::{Channel, ServerMessage, WsServer};
+use quantumflow::{OrderStatus, Side};
+use rust_decimal::Decimal;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::{TcpListener, TcpStream};
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::{connect_async, MaybeTlsStream, WebSocketStream};
+
+type Client = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+async fn start() -> (Arc<MatchingEngine>, SocketAddr) {
+    let engine = Arc::new(MatchingEngine::new());
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(Arc::new(WsServer::new(engine.clone())).serve(listener));
+    (engine, addr)
+}
+
+async fn connect_to(addr: SocketAddr) -> Client {
+    let (client, _) = connect_async(format!("ws://{}", addr)).await.unwrap();
+    client
+}
+
+async fn connect() -> Client {
+    let (_engine, addr) = start().await;
+    connect_to(addr).await
+}
+
+async fn send(client: &mut Client, json: &str) {
+    client.send(Message::Text(json.to_string())).await.unwrap();
+}
+
+async fn recv(client: &mut Client) -> ServerMessage {
+    let message = tokio::time::timeout(Duration::from_secs(5), client.next())
+        .await
+        .expect("no message from server")
+        .unwrap()
+        .unwrap();
+    serde_json::from_str(message.to_text().unwrap()).unwrap()
+}
+
+fn submit_order(request_id: &str, side: &str, price: u32, quantity: u32) -> String {
+    format!(
+        r#"{{"op":"submit_order","request_id":"{}","order":{{"symbol":"BTCUSD","side":"{}","order_type":"Limit","price":{},"quantity":{}}}}}"#,
+        request_id, side, price, quantity
+    )
+}
+
+#[tokio::test]
+async fn test_book_snapshot_deltas_and_trades() {
+    let (engine, addr) = start().await;
+    let mut client = connect_to(addr).await;
+
+    send(
+        &mut client,
+        r#"{"op":"subscribe","channel":"book","symbol":"BTCUSD"}"#,
+    )
+    .await;
+    assert!(matches!(
+        recv(&mut client).await,
+        ServerMessage::Subscribed {
+            channel: Channel::Book,
+            ..
+        }
+    ));
+    let ServerMessage::Snapshot { seq, snapshot } = recv(&mut client).await else {
+        panic!("expected a snapshot");
+    };
+    assert_eq!(seq, 0);
+    assert!(snapshot.bids.is_empty());
+    // A book-only client has no trade feed to fall behind on
+    assert!(engine.trade_subscriber_metrics().is_empty());
+
+    send(
+        &mut client,
+        r#"{"op":"subscribe","channel":"trades","symbol":"BTCUSD"}"#,
+    )
+    .await;
+    recv(&mut client).await;
+    assert_eq!(engine.trade_subscriber_metrics().len(), 1);
+
+    send(&mut client, &submit_order("r1", "Buy", 100, 2)).await;
+    let ServerMessage::OrderSubmitted { request_id, order } = recv(&mut client).await else {
+        panic!("expected the submitted order");
+    };
+    assert_eq!(request_id.as_deref(), Some("r1"));
+    assert_eq!(order.status, OrderStatus::Open);
+    let ServerMessage::Delta {
+        seq, bids, asks, ..
+    } = recv(&mut client).await
+    else {
+        panic!("expected a book delta");
+    };
+    assert_eq!(seq, 1);
+    assert_eq!(bids[0].quantity, Decimal::from(2));
+    assert!(asks.is_empty());
+
+    send(&mut client, &submit_order("r2", "Sell", 100, 2)).await;
+    let mut trade = None;
+    let mut delta = None;
+    for _ in 0..3 {
+        match recv(&mut client).await {
+            ServerMessage::Trade { trade: t } => trade = Some(t),
+            ServerMessage::Delta { seq, bids, .. } => delta = Some((seq, bids)),
+            ServerMessage::OrderSubmitted { order, .. } => {
+                assert_eq!(order.status, OrderStatus::Filled)
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+    assert_eq!(trade.unwrap().price, Decimal::from(100));
+    // The filled bid level is removed
+    let (seq, bids) = delta.unwrap();
+    assert_eq!(seq, 2);
+    assert_eq!(bids[0].quantity, Decimal::ZERO);
+}
+
+#[tokio::test]
+async fn test_cancel_and_errors() {
+    let mut client = connect().await;
+
+    send(&mut client, &submit_order("r1", "Sell", 105, 1)).await;
+    let ServerMessage::OrderSubmitted { order, .. } = recv(&mut client).await else {
+        panic!("expected the submitted order");
+    };
+
+    let cancel = format!(
+        r#"{{"op":"cancel_order","request_id":"c1","symbol":"BTCUSD","order_id":"{}"}}"#,
+        order.id
+    );
+    send(&mut client, &cancel).await;
+    let ServerMessage::OrderCancelled {
+        order: cancelled, ..
+    } = recv(&mut client).await
+    else {
+        panic!("expected the cancelled order");
+    };
+    assert_eq!(cancelled.id, order.id);
+
+    // Cancelling again fails and echoes the request id
+    send(&mut client, &cancel).await;
+    let ServerMessage::Error { request_id, .. } = recv(&mut client).await else {
+        panic!("expected an error");
+    };
+    assert_eq!(request_id.as_deref(), Some("c1"));
+
+    send(&mut client, r#"{"op":"launch"}"#).await;
+    assert!(matches!(
+        recv(&mut client).await,
+        ServerMessage::Error { .. }
+    ));
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_concurrent_clients_keep_every_order() {
+    const ORDERS: u32 = 50;
+    let (engine, addr) = start().await;
+
+    // Both clients hammer the same book at prices that never cross
+    let mut clients = Vec::new();
+    for (name, base) in [("a", 100), ("b", 100 + ORDERS)] {
+        clients.push(tokio::spawn(async move {
+            let mut client = connect_to(addr).await;
+            for n in 0..ORDERS {
+                let request_id = format!("{}-{}", name, n);
+                send(&mut client, &submit_order(&request_id, "Buy", base + n, 1)).await;
+            }
+            for n in 0..ORDERS {
+                let ServerMessage::OrderSubmitted { request_id, order } = recv(&mut client).await
+                else {
+                    panic!("expected the submitted order");
+                };
+                assert_eq!(request_id, Some(format!("{}-{}", name, n)));
+                assert_eq!(order.status, OrderStatus::Open);
+            }
+        }));
+    }
+    for client in clients {
+        client.await.unwrap();
+    }
+
+    let book = engine.get_or_create_orderbook("BTCUSD");
+    let bids = book.get_depth(Side::Buy, 2 * ORDERS as usize);
+    assert_eq!(bids.len(), 2 * ORDERS as usize);
+    assert_eq!(book.check_invariants(), Ok(()));
+}